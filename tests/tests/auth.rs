@@ -1,7 +1,7 @@
 use common::{
     encoding::base64_encode,
     endpoints,
-    http::{self, gen_challenge_response, FormResponse},
+    http::{self, gen_challenge_response, FormResponse, KeyResponse, TokenResponse},
     matchers::{have_field, JsonString},
 };
 use ed25519_dalek::Signer;
@@ -96,3 +96,108 @@ async fn challenge_token_can_not_be_used_as_access_token() -> anyhow::Result<()>
 
     Ok(())
 }
+
+#[tokio::test]
+async fn refresh_token_can_be_exchanged_for_access_token() -> anyhow::Result<()> {
+    let FormResponse {
+        form_id,
+        client_key_id,
+        signing_key,
+    } = http::create_form().await?;
+
+    let TokenResponse { refresh_token, .. } =
+        http::request_tokens(&form_id, &client_key_id, &signing_key).await?;
+
+    let resp = endpoints::post_refresh_token()
+        .json(&json!({ "refresh_token": refresh_token }))
+        .send()
+        .await?;
+
+    expect!(resp.status()).to(equal(StatusCode::OK));
+
+    let body = expect!(resp.json::<JsonValue>().await)
+        .to(be_ok())
+        .into_inner();
+
+    let new_refresh_token = expect!(body.clone())
+        .to(have_field::<JsonString>("refresh_token"))
+        .into_inner();
+
+    expect!(new_refresh_token).to_not(equal(refresh_token));
+
+    let token = expect!(body)
+        .to(have_field::<JsonString>("token"))
+        .into_inner();
+
+    let resp = endpoints::get_submissions(&form_id)
+        .bearer_auth(&token)
+        .send()
+        .await?;
+
+    expect!(resp.status()).to(equal(StatusCode::OK));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn refresh_token_can_only_be_used_once() -> anyhow::Result<()> {
+    let FormResponse {
+        form_id,
+        client_key_id,
+        signing_key,
+    } = http::create_form().await?;
+
+    let TokenResponse { refresh_token, .. } =
+        http::request_tokens(&form_id, &client_key_id, &signing_key).await?;
+
+    let resp = endpoints::post_refresh_token()
+        .json(&json!({ "refresh_token": refresh_token }))
+        .send()
+        .await?;
+
+    expect!(resp.status()).to(equal(StatusCode::OK));
+
+    let resp = endpoints::post_refresh_token()
+        .json(&json!({ "refresh_token": refresh_token }))
+        .send()
+        .await?;
+
+    expect!(resp.status()).to(equal(StatusCode::UNAUTHORIZED));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn refresh_token_is_revoked_with_its_key() -> anyhow::Result<()> {
+    let FormResponse {
+        form_id,
+        client_key_id,
+        signing_key,
+    } = http::create_form().await?;
+
+    let auth_token = http::authenticate(&form_id, &client_key_id, &signing_key).await?;
+
+    let KeyResponse {
+        client_key_id: read_key_id,
+        signing_key: read_signing_key,
+    } = http::create_key(&form_id, &auth_token, "read").await?;
+
+    let TokenResponse { refresh_token, .. } =
+        http::request_tokens(&form_id, &read_key_id, &read_signing_key).await?;
+
+    let resp = endpoints::delete_key(&form_id, &read_key_id)
+        .bearer_auth(&auth_token)
+        .send()
+        .await?;
+
+    expect!(resp.status()).to(equal(StatusCode::NO_CONTENT));
+
+    let resp = endpoints::post_refresh_token()
+        .json(&json!({ "refresh_token": refresh_token }))
+        .send()
+        .await?;
+
+    expect!(resp.status()).to(equal(StatusCode::UNAUTHORIZED));
+
+    Ok(())
+}
@@ -32,3 +32,107 @@ pub fn get_challenge(form_id: &str, client_key_id: &str) -> RequestBuilder {
 pub fn post_token() -> RequestBuilder {
     http::client().post(http::path("/tokens"))
 }
+
+pub fn post_key(form_id: &str) -> RequestBuilder {
+    http::client().post(http::path(&format!("/keys/{}", form_id)))
+}
+
+pub fn patch_key(form_id: &str, client_key_id: &str) -> RequestBuilder {
+    http::client().patch(http::path(&format!("/keys/{}/{}", form_id, client_key_id)))
+}
+
+pub fn delete_key(form_id: &str, client_key_id: &str) -> RequestBuilder {
+    http::client().delete(http::path(&format!("/keys/{}/{}", form_id, client_key_id)))
+}
+
+pub fn revoke_token() -> RequestBuilder {
+    http::client().post(http::path("/tokens/revoke"))
+}
+
+pub fn get_sessions(form_id: &str, client_key_id: &str) -> RequestBuilder {
+    http::client().get(http::path(&format!(
+        "/keys/{}/{}/sessions",
+        form_id, client_key_id
+    )))
+}
+
+pub fn delete_sessions(form_id: &str, client_key_id: &str) -> RequestBuilder {
+    http::client().delete(http::path(&format!(
+        "/keys/{}/{}/sessions",
+        form_id, client_key_id
+    )))
+}
+
+pub fn post_refresh_token() -> RequestBuilder {
+    http::client().post(http::path("/tokens/refresh"))
+}
+
+pub fn post_primary_key(form_id: &str) -> RequestBuilder {
+    http::client().post(http::path(&format!("/primary-keys/{}", form_id)))
+}
+
+pub fn put_submission(form_id: &str, submission_id: &str) -> RequestBuilder {
+    http::client().put(http::path(&format!(
+        "/submissions/{}/{}",
+        form_id, submission_id
+    )))
+}
+
+pub fn get_jwks() -> RequestBuilder {
+    http::client().get(http::path("/.well-known/jwks.json"))
+}
+
+pub fn get_health() -> RequestBuilder {
+    http::client().get(http::path("/health"))
+}
+
+pub fn get_metrics() -> RequestBuilder {
+    http::client().get(http::path("/metrics"))
+}
+
+pub fn get_form_stats(form_id: &str) -> RequestBuilder {
+    http::client().get(http::path(&format!("/forms/{}/stats", form_id)))
+}
+
+pub fn post_webhook(form_id: &str) -> RequestBuilder {
+    http::client().post(http::path(&format!("/webhooks/{}", form_id)))
+}
+
+pub fn get_webhooks(form_id: &str) -> RequestBuilder {
+    http::client().get(http::path(&format!("/webhooks/{}", form_id)))
+}
+
+pub fn delete_webhook(form_id: &str, webhook_id: &str) -> RequestBuilder {
+    http::client().delete(http::path(&format!("/webhooks/{}/{}", form_id, webhook_id)))
+}
+
+pub fn get_vapid_key() -> RequestBuilder {
+    http::client().get(http::path("/push/vapid-key"))
+}
+
+pub fn post_push_subscription(form_id: &str, client_key_id: &str) -> RequestBuilder {
+    http::client().post(http::path(&format!("/push/{}/{}", form_id, client_key_id)))
+}
+
+pub fn delete_push_subscriptions(form_id: &str, client_key_id: &str) -> RequestBuilder {
+    http::client().delete(http::path(&format!("/push/{}/{}", form_id, client_key_id)))
+}
+
+pub fn post_digest_subscription(form_id: &str, client_key_id: &str) -> RequestBuilder {
+    http::client().post(http::path(&format!(
+        "/digests/{}/{}",
+        form_id, client_key_id
+    )))
+}
+
+pub fn delete_digest_subscription(form_id: &str, client_key_id: &str) -> RequestBuilder {
+    http::client().delete(http::path(&format!(
+        "/digests/{}/{}",
+        form_id, client_key_id
+    )))
+}
+
+// Only available when the worker is run with `wrangler dev --test-scheduled`.
+pub fn trigger_scheduled() -> RequestBuilder {
+    http::client().get(http::path("/__scheduled?cron=0+0+*+*+*"))
+}
@@ -12,6 +12,10 @@ use super::{
 
 const DEFAULT_API_URL: &str = "http://localhost:8787";
 
+// The server encrypts audit events with the form's public primary key, so these must be valid keys.
+pub const PUBLIC_PRIMARY_KEY: &str = "AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=";
+pub const NEW_PUBLIC_PRIMARY_KEY: &str = "AgICAgICAgICAgICAgICAgICAgICAgICAgICAgICAgI=";
+
 pub fn api_url() -> String {
     dotenv::var("API_URL").unwrap_or_else(|_| DEFAULT_API_URL.to_string())
 }
@@ -36,11 +40,12 @@ pub async fn create_form() -> anyhow::Result<FormResponse> {
 
     let resp = endpoints::post_form()
         .json(&json!({
-            "public_primary_key": "<public_primary_key>",
+            "public_primary_key": PUBLIC_PRIMARY_KEY,
             "public_signing_key": public_signing_key,
             "org_name": "<org_name>",
             "description": "<description>",
-            "contact_methods": ["<contact_method>"]
+            "contact_methods": ["<contact_method>"],
+            "roles": []
         }))
         .send()
         .await?;
@@ -88,11 +93,17 @@ pub async fn gen_challenge_response(
     respond_challenge(&challenge, signing_key)
 }
 
-pub async fn authenticate(
+#[derive(Debug)]
+pub struct TokenResponse {
+    pub token: String,
+    pub refresh_token: String,
+}
+
+pub async fn request_tokens(
     form_id: &str,
     client_key_id: &str,
     signing_key: &ed25519::SigningKey,
-) -> anyhow::Result<String> {
+) -> anyhow::Result<TokenResponse> {
     let challenge_response = gen_challenge_response(form_id, client_key_id, signing_key).await?;
 
     let resp = endpoints::post_token()
@@ -106,9 +117,64 @@ pub async fn authenticate(
         .to(be_ok())
         .into_inner();
 
-    let token = expect!(body)
+    let token = expect!(body.clone())
         .to(have_field::<JsonString>("token"))
         .into_inner();
 
-    Ok(token)
+    let refresh_token = expect!(body)
+        .to(have_field::<JsonString>("refresh_token"))
+        .into_inner();
+
+    Ok(TokenResponse {
+        token,
+        refresh_token,
+    })
+}
+
+pub async fn authenticate(
+    form_id: &str,
+    client_key_id: &str,
+    signing_key: &ed25519::SigningKey,
+) -> anyhow::Result<String> {
+    Ok(request_tokens(form_id, client_key_id, signing_key)
+        .await?
+        .token)
+}
+
+#[derive(Debug)]
+pub struct KeyResponse {
+    pub client_key_id: String,
+    pub signing_key: ed25519::SigningKey,
+}
+
+pub async fn create_key(
+    form_id: &str,
+    auth_token: &str,
+    role: &str,
+) -> anyhow::Result<KeyResponse> {
+    let signing_key = ed25519::SigningKey::generate(&mut rand::thread_rng());
+    let public_signing_key = BASE64_STANDARD.encode(signing_key.as_ref().to_bytes());
+
+    let resp = endpoints::post_key(form_id)
+        .bearer_auth(auth_token)
+        .json(&json!({
+            "public_signing_key": public_signing_key,
+            "wrapped_private_primary_key": "<wrapped_private_primary_key>",
+            "encrypted_comment": "<encrypted_comment>",
+            "role": role,
+        }))
+        .send()
+        .await?;
+
+    expect!(resp.status()).to(equal(StatusCode::CREATED));
+
+    let client_key_id = expect!(resp.json::<JsonValue>().await)
+        .to(be_ok())
+        .to(have_field::<JsonString>("client_key_id"))
+        .into_inner();
+
+    Ok(KeyResponse {
+        client_key_id,
+        signing_key,
+    })
 }
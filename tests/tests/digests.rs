@@ -0,0 +1,168 @@
+use std::time::Duration;
+
+use axum::{extract::State, http::StatusCode as AxumStatusCode, routing::post, Json, Router};
+use rand::distributions::{Alphanumeric, DistString};
+use reqwest::StatusCode;
+use serde_json::{json, Value as JsonValue};
+use tokio::{net::TcpListener, sync::mpsc};
+use xpct::{be_some, contain_substr, equal, expect};
+
+use common::{
+    endpoints,
+    http::{self, FormResponse, KeyResponse},
+};
+
+mod common;
+
+// This must match the port in `MAIL_RELAY_URL` in the worker's `.dev.vars`.
+const DEFAULT_MAIL_RELAY_PORT: &str = "8025";
+
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(30);
+
+async fn relay(
+    State(tx): State<mpsc::UnboundedSender<JsonValue>>,
+    Json(message): Json<JsonValue>,
+) -> AxumStatusCode {
+    let _ = tx.send(message);
+    AxumStatusCode::ACCEPTED
+}
+
+// Start a local stand-in for the mail relay that forwards every message it receives.
+async fn start_relay() -> anyhow::Result<mpsc::UnboundedReceiver<JsonValue>> {
+    let (tx, rx) = mpsc::unbounded_channel();
+
+    let port =
+        dotenv::var("MAIL_RELAY_PORT").unwrap_or_else(|_| DEFAULT_MAIL_RELAY_PORT.to_string());
+    let listener = TcpListener::bind(format!("0.0.0.0:{}", port)).await?;
+
+    let app = Router::new().route("/", post(relay)).with_state(tx);
+
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    Ok(rx)
+}
+
+// Each test needs its own address so it can pick its digest out from the others.
+fn email_address() -> String {
+    format!(
+        "{}@example.com",
+        Alphanumeric
+            .sample_string(&mut rand::thread_rng(), 16)
+            .to_lowercase()
+    )
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn digest_counts_new_submissions() -> anyhow::Result<()> {
+    let mut messages = start_relay().await?;
+
+    let FormResponse {
+        form_id,
+        client_key_id,
+        signing_key,
+    } = http::create_form().await?;
+
+    let auth_token = http::authenticate(&form_id, &client_key_id, &signing_key).await?;
+
+    let address = email_address();
+
+    let resp = endpoints::post_digest_subscription(&form_id, &client_key_id)
+        .bearer_auth(&auth_token)
+        .json(&json!({ "email": address }))
+        .send()
+        .await?;
+
+    expect!(resp.status()).to(equal(StatusCode::CREATED));
+
+    for _ in 0..2 {
+        endpoints::post_submission(&form_id)
+            .json(&json!({
+                "encrypted_body": "<encrypted_body>",
+            }))
+            .send()
+            .await?
+            .error_for_status()?;
+    }
+
+    // Submissions from the current second are left for the next digest.
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+
+    endpoints::trigger_scheduled()
+        .send()
+        .await?
+        .error_for_status()?;
+
+    let message = expect!(tokio::time::timeout(DELIVERY_TIMEOUT, async {
+        while let Some(message) = messages.recv().await {
+            if message["to"] == json!(address) {
+                return Some(message);
+            }
+        }
+
+        None
+    })
+    .await
+    .ok()
+    .flatten())
+    .to(be_some())
+    .into_inner();
+
+    expect!(message["text"].as_str().unwrap_or_default().to_string())
+        .to(contain_substr("2 new responses"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn cannot_subscribe_another_key_to_digest() -> anyhow::Result<()> {
+    let FormResponse {
+        form_id,
+        client_key_id,
+        signing_key,
+    } = http::create_form().await?;
+
+    let auth_token = http::authenticate(&form_id, &client_key_id, &signing_key).await?;
+
+    let KeyResponse {
+        client_key_id: other_client_key_id,
+        ..
+    } = http::create_key(&form_id, &auth_token, "read").await?;
+
+    let resp = endpoints::post_digest_subscription(&form_id, &other_client_key_id)
+        .bearer_auth(&auth_token)
+        .json(&json!({ "email": email_address() }))
+        .send()
+        .await?;
+
+    expect!(resp.status()).to(equal(StatusCode::FORBIDDEN));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn digest_email_must_be_valid() -> anyhow::Result<()> {
+    let FormResponse {
+        form_id,
+        client_key_id,
+        signing_key,
+    } = http::create_form().await?;
+
+    let auth_token = http::authenticate(&form_id, &client_key_id, &signing_key).await?;
+
+    let resp = endpoints::post_digest_subscription(&form_id, &client_key_id)
+        .bearer_auth(&auth_token)
+        .json(&json!({ "email": "someone@example.com, someone-else@example.com" }))
+        .send()
+        .await?;
+
+    expect!(resp.status()).to(equal(StatusCode::BAD_REQUEST));
+
+    let resp = endpoints::delete_digest_subscription(&form_id, &client_key_id)
+        .bearer_auth(&auth_token)
+        .send()
+        .await?;
+
+    expect!(resp.status()).to(equal(StatusCode::NO_CONTENT));
+
+    Ok(())
+}
@@ -0,0 +1,26 @@
+use reqwest::StatusCode;
+use serde_json::Value as JsonValue;
+use xpct::{be_ok, equal, expect};
+
+use common::{
+    endpoints, http,
+    matchers::{have_field, JsonString},
+};
+
+mod common;
+
+#[tokio::test]
+async fn health_check_succeeds() -> anyhow::Result<()> {
+    let resp = endpoints::get_health().send().await?;
+
+    expect!(resp.status()).to(equal(StatusCode::OK));
+
+    let body = resp.json::<JsonValue>().await;
+
+    expect!(body)
+        .to(be_ok())
+        .to(have_field::<JsonString>("d1"))
+        .to(equal("ok"));
+
+    Ok(())
+}
@@ -0,0 +1,77 @@
+use base64::prelude::*;
+use ed25519_dalek::{Signature, VerifyingKey};
+use reqwest::StatusCode;
+use serde_json::{json, Value as JsonValue};
+use xpct::{be_ok, be_some, equal, expect};
+
+use common::{
+    endpoints, http,
+    matchers::{have_field, JsonArray},
+};
+
+mod common;
+
+async fn get_jwks() -> anyhow::Result<Vec<JsonValue>> {
+    let resp = endpoints::get_jwks().send().await?;
+
+    expect!(resp.status()).to(equal(StatusCode::OK));
+
+    let keys = expect!(resp.json::<JsonValue>().await)
+        .to(be_ok())
+        .to(have_field::<JsonArray>("keys"))
+        .into_inner();
+
+    Ok(keys)
+}
+
+#[tokio::test]
+async fn jwks_endpoint_lists_keys() -> anyhow::Result<()> {
+    let keys = get_jwks().await?;
+
+    for key in keys {
+        expect!(&key["kty"]).to(equal(&json!("OKP")));
+        expect!(&key["crv"]).to(equal(&json!("Ed25519")));
+        expect!(key["kid"].as_str()).to(be_some());
+        expect!(key["x"].as_str()).to(be_some());
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn receipt_verifies_against_listed_key() -> anyhow::Result<()> {
+    let form = http::create_form().await?;
+
+    let resp = endpoints::post_submission(&form.form_id)
+        .json(&json!({
+            "encrypted_body": "<encrypted_body>",
+        }))
+        .send()
+        .await?;
+
+    expect!(resp.status()).to(equal(StatusCode::CREATED));
+
+    let body = resp.json::<JsonValue>().await?;
+    let receipt = expect!(body["receipt"].as_str()).to(be_some()).into_inner();
+
+    let (signing_input, signature) = expect!(receipt.rsplit_once('.')).to(be_some()).into_inner();
+    let (header, _) = expect!(signing_input.split_once('.'))
+        .to(be_some())
+        .into_inner();
+    let header = serde_json::from_slice::<JsonValue>(&BASE64_URL_SAFE_NO_PAD.decode(header)?)?;
+
+    expect!(&header["alg"]).to(equal(&json!("EdDSA")));
+
+    let keys = get_jwks().await?;
+    let key = expect!(keys.iter().find(|key| key["kid"] == header["kid"]))
+        .to(be_some())
+        .into_inner();
+
+    let x = BASE64_URL_SAFE_NO_PAD.decode(key["x"].as_str().unwrap_or_default())?;
+    let verifying_key = VerifyingKey::from_bytes(x.as_slice().try_into()?)?;
+    let signature = Signature::from_slice(&BASE64_URL_SAFE_NO_PAD.decode(signature)?)?;
+
+    expect!(verifying_key.verify_strict(signing_input.as_bytes(), &signature)).to(be_ok());
+
+    Ok(())
+}
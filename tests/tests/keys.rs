@@ -0,0 +1,108 @@
+use reqwest::StatusCode;
+use serde_json::json;
+use xpct::{equal, expect};
+
+use common::{
+    endpoints,
+    http::{self, FormResponse, KeyResponse},
+};
+
+mod common;
+
+#[tokio::test]
+async fn demoting_last_admin_key_is_conflict() -> anyhow::Result<()> {
+    let FormResponse {
+        form_id,
+        client_key_id,
+        signing_key,
+    } = http::create_form().await?;
+
+    let auth_token = http::authenticate(&form_id, &client_key_id, &signing_key).await?;
+
+    let resp = endpoints::patch_key(&form_id, &client_key_id)
+        .bearer_auth(&auth_token)
+        .json(&json!({ "role": "read" }))
+        .send()
+        .await?;
+
+    expect!(resp.status()).to(equal(StatusCode::CONFLICT));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn deleting_last_admin_key_is_conflict() -> anyhow::Result<()> {
+    let FormResponse {
+        form_id,
+        client_key_id,
+        signing_key,
+    } = http::create_form().await?;
+
+    let auth_token = http::authenticate(&form_id, &client_key_id, &signing_key).await?;
+
+    let resp = endpoints::delete_key(&form_id, &client_key_id)
+        .bearer_auth(&auth_token)
+        .send()
+        .await?;
+
+    expect!(resp.status()).to(equal(StatusCode::CONFLICT));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn promoted_key_can_demote_original_admin_key() -> anyhow::Result<()> {
+    let FormResponse {
+        form_id,
+        client_key_id,
+        signing_key,
+    } = http::create_form().await?;
+
+    let auth_token = http::authenticate(&form_id, &client_key_id, &signing_key).await?;
+
+    let KeyResponse {
+        client_key_id: read_key_id,
+        signing_key: read_signing_key,
+    } = http::create_key(&form_id, &auth_token, "read").await?;
+
+    let resp = endpoints::patch_key(&form_id, &read_key_id)
+        .bearer_auth(&auth_token)
+        .json(&json!({ "role": "admin" }))
+        .send()
+        .await?;
+
+    expect!(resp.status()).to(equal(StatusCode::NO_CONTENT));
+
+    let promoted_auth_token = http::authenticate(&form_id, &read_key_id, &read_signing_key).await?;
+
+    let resp = endpoints::patch_key(&form_id, &client_key_id)
+        .bearer_auth(&promoted_auth_token)
+        .json(&json!({ "role": "read" }))
+        .send()
+        .await?;
+
+    expect!(resp.status()).to(equal(StatusCode::NO_CONTENT));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn updating_nonexistent_key_is_not_found() -> anyhow::Result<()> {
+    let FormResponse {
+        form_id,
+        client_key_id,
+        signing_key,
+    } = http::create_form().await?;
+
+    let auth_token = http::authenticate(&form_id, &client_key_id, &signing_key).await?;
+
+    let resp = endpoints::patch_key(&form_id, "999")
+        .bearer_auth(&auth_token)
+        .json(&json!({ "role": "read" }))
+        .send()
+        .await?;
+
+    expect!(resp.status()).to(equal(StatusCode::NOT_FOUND));
+
+    Ok(())
+}
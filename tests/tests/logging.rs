@@ -0,0 +1,38 @@
+use reqwest::StatusCode;
+use xpct::{be_some, equal, expect};
+
+use common::{endpoints, http};
+
+mod common;
+
+#[tokio::test]
+async fn responses_include_request_id() -> anyhow::Result<()> {
+    let resp = endpoints::get_health().send().await?;
+
+    expect!(resp.headers().get("x-request-id")).to(be_some());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn not_found_responses_include_request_id() -> anyhow::Result<()> {
+    let resp = http::client()
+        .get(http::path("/does-not-exist"))
+        .send()
+        .await?;
+
+    expect!(resp.status()).to(equal(StatusCode::NOT_FOUND));
+    expect!(resp.headers().get("x-request-id")).to(be_some());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn unauthorized_responses_include_request_id() -> anyhow::Result<()> {
+    let resp = endpoints::get_submissions("invalid-form-id").send().await?;
+
+    expect!(resp.status()).to(equal(StatusCode::UNAUTHORIZED));
+    expect!(resp.headers().get("x-request-id")).to(be_some());
+
+    Ok(())
+}
@@ -0,0 +1,18 @@
+use reqwest::StatusCode;
+use xpct::{equal, expect};
+
+use common::{endpoints, http};
+
+mod common;
+
+#[tokio::test]
+async fn metrics_require_operator_token() -> anyhow::Result<()> {
+    let resp = endpoints::get_metrics()
+        .bearer_auth("not-the-operator-token")
+        .send()
+        .await?;
+
+    expect!(resp.status()).to_not(equal(StatusCode::OK));
+
+    Ok(())
+}
@@ -0,0 +1,186 @@
+use reqwest::StatusCode;
+use serde_json::{json, Value as JsonValue};
+use xpct::{all, be_ok, equal, expect, have_len, match_elements};
+
+use common::{
+    endpoints,
+    http::{self, FormResponse, KeyResponse},
+    matchers::{have_field, have_type, JsonArray, JsonString},
+};
+
+mod common;
+
+#[tokio::test]
+async fn rotating_primary_key_increments_key_epoch() -> anyhow::Result<()> {
+    let FormResponse {
+        form_id,
+        client_key_id,
+        signing_key,
+    } = http::create_form().await?;
+
+    let auth_token = http::authenticate(&form_id, &client_key_id, &signing_key).await?;
+
+    let resp = endpoints::post_primary_key(&form_id)
+        .bearer_auth(&auth_token)
+        .json(&json!({
+            "public_primary_key": http::NEW_PUBLIC_PRIMARY_KEY,
+            "key_epoch": 0,
+            "wrapped_keys": [{
+                "client_key_id": client_key_id,
+                "wrapped_private_primary_key": "<new_wrapped_private_primary_key>",
+            }],
+        }))
+        .send()
+        .await?;
+
+    expect!(resp.status()).to(equal(StatusCode::OK));
+
+    let resp = endpoints::get_form(&form_id).send().await?;
+
+    let body = expect!(resp.json::<JsonValue>().await)
+        .to(be_ok())
+        .into_inner();
+
+    expect!(body.clone())
+        .to(have_field::<JsonString>("public_primary_key"))
+        .to(equal(http::NEW_PUBLIC_PRIMARY_KEY));
+
+    expect!(body.get("key_epoch").cloned()).to(equal(Some(json!(1))));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn rotating_primary_key_with_stale_key_epoch_is_conflict() -> anyhow::Result<()> {
+    let FormResponse {
+        form_id,
+        client_key_id,
+        signing_key,
+    } = http::create_form().await?;
+
+    let auth_token = http::authenticate(&form_id, &client_key_id, &signing_key).await?;
+
+    let resp = endpoints::post_primary_key(&form_id)
+        .bearer_auth(&auth_token)
+        .json(&json!({
+            "public_primary_key": http::NEW_PUBLIC_PRIMARY_KEY,
+            "key_epoch": 1,
+            "wrapped_keys": [{
+                "client_key_id": client_key_id,
+                "wrapped_private_primary_key": "<new_wrapped_private_primary_key>",
+            }],
+        }))
+        .send()
+        .await?;
+
+    expect!(resp.status()).to(equal(StatusCode::CONFLICT));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn rotating_primary_key_without_every_client_key_is_conflict() -> anyhow::Result<()> {
+    let FormResponse {
+        form_id,
+        client_key_id,
+        signing_key,
+    } = http::create_form().await?;
+
+    let auth_token = http::authenticate(&form_id, &client_key_id, &signing_key).await?;
+
+    let KeyResponse { .. } = http::create_key(&form_id, &auth_token, "read").await?;
+
+    let resp = endpoints::post_primary_key(&form_id)
+        .bearer_auth(&auth_token)
+        .json(&json!({
+            "public_primary_key": http::NEW_PUBLIC_PRIMARY_KEY,
+            "key_epoch": 0,
+            "wrapped_keys": [{
+                "client_key_id": client_key_id,
+                "wrapped_private_primary_key": "<new_wrapped_private_primary_key>",
+            }],
+        }))
+        .send()
+        .await?;
+
+    expect!(resp.status()).to(equal(StatusCode::CONFLICT));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn re_encrypted_submission_is_tagged_with_new_key_epoch() -> anyhow::Result<()> {
+    let FormResponse {
+        form_id,
+        client_key_id,
+        signing_key,
+    } = http::create_form().await?;
+
+    let auth_token = http::authenticate(&form_id, &client_key_id, &signing_key).await?;
+
+    let resp = endpoints::post_submission(&form_id)
+        .json(&json!({
+            "encrypted_body": "<encrypted_body>",
+        }))
+        .send()
+        .await?;
+
+    expect!(resp.status()).to(equal(StatusCode::CREATED));
+
+    let resp = endpoints::post_primary_key(&form_id)
+        .bearer_auth(&auth_token)
+        .json(&json!({
+            "public_primary_key": http::NEW_PUBLIC_PRIMARY_KEY,
+            "key_epoch": 0,
+            "wrapped_keys": [{
+                "client_key_id": client_key_id,
+                "wrapped_private_primary_key": "<new_wrapped_private_primary_key>",
+            }],
+        }))
+        .send()
+        .await?;
+
+    expect!(resp.status()).to(equal(StatusCode::OK));
+
+    let resp = endpoints::get_submissions(&form_id)
+        .bearer_auth(&auth_token)
+        .send()
+        .await?;
+
+    let submission_id = expect!(resp.json::<JsonValue>().await)
+        .to(be_ok())
+        .to(have_type::<JsonArray>())
+        .to(have_len(1))
+        .map(|submissions| submissions[0].clone())
+        .to(have_field::<JsonString>("submission_id"))
+        .into_inner();
+
+    let resp = endpoints::put_submission(&form_id, &submission_id)
+        .bearer_auth(&auth_token)
+        .json(&json!({
+            "encrypted_body": "<re_encrypted_body>",
+            "key_epoch": 1,
+        }))
+        .send()
+        .await?;
+
+    expect!(resp.status()).to(equal(StatusCode::NO_CONTENT));
+
+    let resp = endpoints::get_submissions(&form_id)
+        .bearer_auth(&auth_token)
+        .send()
+        .await?;
+
+    let body = expect!(resp.json::<JsonValue>().await)
+        .to(be_ok())
+        .into_inner();
+
+    expect!(body)
+        .to(have_type::<JsonArray>())
+        .to(match_elements([all(|ctx| {
+            ctx.map(|submission: JsonValue| submission.get("key_epoch").cloned())
+                .to(equal(Some(json!(1))))
+        })]));
+
+    Ok(())
+}
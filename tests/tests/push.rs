@@ -0,0 +1,130 @@
+use base64::prelude::*;
+use rand::distributions::{Alphanumeric, DistString};
+use reqwest::StatusCode;
+use serde_json::{json, Value as JsonValue};
+use xpct::{be_ok, equal, expect};
+
+use common::{
+    endpoints,
+    http::{self, FormResponse, KeyResponse},
+    matchers::{have_field, JsonString},
+};
+
+mod common;
+
+// A valid subscription key and auth secret, taken from the example in RFC 8291.
+const P256DH: &str =
+    "BCVxsr7N_eNgVRqvHtD0zTZsEc6-VV-JvLexhqUzORcxaOzi6-AYWXvTBHm4bjyPjs7Vd8pZGH6SRpkNtoIAiw4";
+const AUTH: &str = "BTBZMqHH6r4Tts7J_aSIgg";
+
+// Endpoints are unique, so each test needs its own.
+fn push_endpoint() -> String {
+    format!(
+        "https://push.example.com/{}",
+        Alphanumeric.sample_string(&mut rand::thread_rng(), 16)
+    )
+}
+
+fn subscription(endpoint: &str) -> JsonValue {
+    json!({
+        "endpoint": endpoint,
+        "keys": {
+            "p256dh": P256DH,
+            "auth": AUTH,
+        },
+    })
+}
+
+#[tokio::test]
+async fn vapid_key_is_uncompressed_point() -> anyhow::Result<()> {
+    let resp = endpoints::get_vapid_key().send().await?;
+
+    expect!(resp.status()).to(equal(StatusCode::OK));
+
+    let public_key = expect!(resp.json::<JsonValue>().await)
+        .to(be_ok())
+        .to(have_field::<JsonString>("public_key"))
+        .into_inner();
+
+    let decoded = BASE64_URL_SAFE_NO_PAD.decode(public_key)?;
+
+    expect!(decoded.len()).to(equal(65));
+    expect!(decoded[0]).to(equal(0x04));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn subscribe_and_unsubscribe_own_key() -> anyhow::Result<()> {
+    let FormResponse {
+        form_id,
+        client_key_id,
+        signing_key,
+    } = http::create_form().await?;
+
+    let auth_token = http::authenticate(&form_id, &client_key_id, &signing_key).await?;
+
+    let resp = endpoints::post_push_subscription(&form_id, &client_key_id)
+        .bearer_auth(&auth_token)
+        .json(&subscription(&push_endpoint()))
+        .send()
+        .await?;
+
+    expect!(resp.status()).to(equal(StatusCode::CREATED));
+
+    let resp = endpoints::delete_push_subscriptions(&form_id, &client_key_id)
+        .bearer_auth(&auth_token)
+        .send()
+        .await?;
+
+    expect!(resp.status()).to(equal(StatusCode::NO_CONTENT));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn cannot_subscribe_another_key() -> anyhow::Result<()> {
+    let FormResponse {
+        form_id,
+        client_key_id,
+        signing_key,
+    } = http::create_form().await?;
+
+    let auth_token = http::authenticate(&form_id, &client_key_id, &signing_key).await?;
+
+    let KeyResponse {
+        client_key_id: other_client_key_id,
+        ..
+    } = http::create_key(&form_id, &auth_token, "read").await?;
+
+    let resp = endpoints::post_push_subscription(&form_id, &other_client_key_id)
+        .bearer_auth(&auth_token)
+        .json(&subscription(&push_endpoint()))
+        .send()
+        .await?;
+
+    expect!(resp.status()).to(equal(StatusCode::FORBIDDEN));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn push_endpoint_must_use_https() -> anyhow::Result<()> {
+    let FormResponse {
+        form_id,
+        client_key_id,
+        signing_key,
+    } = http::create_form().await?;
+
+    let auth_token = http::authenticate(&form_id, &client_key_id, &signing_key).await?;
+
+    let resp = endpoints::post_push_subscription(&form_id, &client_key_id)
+        .bearer_auth(&auth_token)
+        .json(&subscription("http://push.example.com/insecure"))
+        .send()
+        .await?;
+
+    expect!(resp.status()).to(equal(StatusCode::BAD_REQUEST));
+
+    Ok(())
+}
@@ -0,0 +1,105 @@
+use reqwest::StatusCode;
+use serde_json::Value as JsonValue;
+use xpct::{be_ok, equal, expect, have_len};
+
+use common::{
+    endpoints,
+    http::{self, FormResponse, KeyResponse},
+    matchers::{have_type, JsonArray},
+};
+
+mod common;
+
+#[tokio::test]
+async fn revoked_access_token_is_unauthorized() -> anyhow::Result<()> {
+    let FormResponse {
+        form_id,
+        client_key_id,
+        signing_key,
+    } = http::create_form().await?;
+
+    let auth_token = http::authenticate(&form_id, &client_key_id, &signing_key).await?;
+
+    let resp = endpoints::revoke_token()
+        .bearer_auth(&auth_token)
+        .send()
+        .await?;
+
+    expect!(resp.status()).to(equal(StatusCode::NO_CONTENT));
+
+    let resp = endpoints::get_submissions(&form_id)
+        .bearer_auth(&auth_token)
+        .send()
+        .await?;
+
+    expect!(resp.status()).to(equal(StatusCode::UNAUTHORIZED));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn admin_can_list_sessions() -> anyhow::Result<()> {
+    let FormResponse {
+        form_id,
+        client_key_id,
+        signing_key,
+    } = http::create_form().await?;
+
+    let auth_token = http::authenticate(&form_id, &client_key_id, &signing_key).await?;
+
+    let KeyResponse {
+        client_key_id: read_key_id,
+        signing_key: read_signing_key,
+    } = http::create_key(&form_id, &auth_token, "read").await?;
+
+    http::authenticate(&form_id, &read_key_id, &read_signing_key).await?;
+    http::authenticate(&form_id, &read_key_id, &read_signing_key).await?;
+
+    let resp = endpoints::get_sessions(&form_id, &read_key_id)
+        .bearer_auth(&auth_token)
+        .send()
+        .await?;
+
+    expect!(resp.status()).to(equal(StatusCode::OK));
+
+    expect!(resp.json::<JsonValue>().await)
+        .to(be_ok())
+        .to(have_type::<JsonArray>())
+        .to(have_len(2));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn admin_can_end_sessions() -> anyhow::Result<()> {
+    let FormResponse {
+        form_id,
+        client_key_id,
+        signing_key,
+    } = http::create_form().await?;
+
+    let auth_token = http::authenticate(&form_id, &client_key_id, &signing_key).await?;
+
+    let KeyResponse {
+        client_key_id: read_key_id,
+        signing_key: read_signing_key,
+    } = http::create_key(&form_id, &auth_token, "read").await?;
+
+    let read_auth_token = http::authenticate(&form_id, &read_key_id, &read_signing_key).await?;
+
+    let resp = endpoints::delete_sessions(&form_id, &read_key_id)
+        .bearer_auth(&auth_token)
+        .send()
+        .await?;
+
+    expect!(resp.status()).to(equal(StatusCode::NO_CONTENT));
+
+    let resp = endpoints::get_submissions(&form_id)
+        .bearer_auth(&read_auth_token)
+        .send()
+        .await?;
+
+    expect!(resp.status()).to(equal(StatusCode::UNAUTHORIZED));
+
+    Ok(())
+}
@@ -0,0 +1,57 @@
+use reqwest::StatusCode;
+use serde_json::{json, Value as JsonValue};
+use xpct::{equal, expect};
+
+use common::{
+    endpoints,
+    http::{self, FormResponse},
+};
+
+mod common;
+
+#[tokio::test]
+async fn form_stats_count_submissions() -> anyhow::Result<()> {
+    let FormResponse {
+        form_id,
+        client_key_id,
+        signing_key,
+    } = http::create_form().await?;
+
+    let auth_token = http::authenticate(&form_id, &client_key_id, &signing_key).await?;
+
+    for _ in 0..2 {
+        endpoints::post_submission(&form_id)
+            .json(&json!({
+                "encrypted_body": "<encrypted_body>",
+            }))
+            .send()
+            .await?
+            .error_for_status()?;
+    }
+
+    let resp = endpoints::get_form_stats(&form_id)
+        .bearer_auth(&auth_token)
+        .send()
+        .await?;
+
+    expect!(resp.status()).to(equal(StatusCode::OK));
+
+    let body = resp.json::<JsonValue>().await?;
+
+    expect!(body["submission_count"].clone()).to(equal(json!(2)));
+    expect!(body["key_count"].clone()).to(equal(json!(1)));
+    expect!(body["submissions_per_period"][0]["count"].clone()).to(equal(json!(2)));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn form_stats_require_auth() -> anyhow::Result<()> {
+    let FormResponse { form_id, .. } = http::create_form().await?;
+
+    let resp = endpoints::get_form_stats(&form_id).send().await?;
+
+    expect!(resp.status()).to(equal(StatusCode::UNAUTHORIZED));
+
+    Ok(())
+}
@@ -0,0 +1,223 @@
+use std::time::Duration;
+
+use axum::{body::Bytes, extract::State, http::HeaderMap, routing::post, Router};
+use hmac::{Hmac, Mac};
+use reqwest::StatusCode;
+use serde_json::{json, Value as JsonValue};
+use sha2::Sha256;
+use tokio::{net::TcpListener, sync::mpsc};
+use xpct::{be_some, equal, expect};
+
+use common::{
+    endpoints,
+    http::{self, FormResponse, KeyResponse},
+};
+
+mod common;
+
+const DEFAULT_WEBHOOK_RECEIVER_HOST: &str = "127.0.0.1";
+
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug)]
+struct ReceivedEvent {
+    headers: HeaderMap,
+    body: String,
+}
+
+// The host the worker should use to reach the receiver, which may differ from localhost if the
+// worker runs somewhere else.
+fn receiver_host() -> String {
+    dotenv::var("WEBHOOK_RECEIVER_HOST")
+        .unwrap_or_else(|_| DEFAULT_WEBHOOK_RECEIVER_HOST.to_string())
+}
+
+async fn receive(
+    State(tx): State<mpsc::UnboundedSender<ReceivedEvent>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> StatusCode {
+    let _ = tx.send(ReceivedEvent {
+        headers,
+        body: String::from_utf8_lossy(&body).into_owned(),
+    });
+
+    StatusCode::NO_CONTENT
+}
+
+// Start a local HTTP server that forwards every event it receives, returning its URL.
+async fn start_receiver() -> anyhow::Result<(String, mpsc::UnboundedReceiver<ReceivedEvent>)> {
+    let (tx, rx) = mpsc::unbounded_channel();
+
+    let listener = TcpListener::bind("0.0.0.0:0").await?;
+    let port = listener.local_addr()?.port();
+
+    let app = Router::new().route("/", post(receive)).with_state(tx);
+
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    Ok((format!("http://{}:{}/", receiver_host(), port), rx))
+}
+
+fn sign(secret: &str, timestamp: &str, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(format!("{}.{}", timestamp, body).as_bytes());
+
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+async fn register_webhook(form_id: &str, auth_token: &str, url: &str) -> anyhow::Result<JsonValue> {
+    let resp = endpoints::post_webhook(form_id)
+        .bearer_auth(auth_token)
+        .json(&json!({ "url": url }))
+        .send()
+        .await?;
+
+    expect!(resp.status()).to(equal(StatusCode::CREATED));
+
+    Ok(resp.json::<JsonValue>().await?)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn webhook_receives_signed_submission_event() -> anyhow::Result<()> {
+    let FormResponse {
+        form_id,
+        client_key_id,
+        signing_key,
+    } = http::create_form().await?;
+
+    let auth_token = http::authenticate(&form_id, &client_key_id, &signing_key).await?;
+
+    let (url, mut events) = start_receiver().await?;
+
+    let webhook = register_webhook(&form_id, &auth_token, &url).await?;
+    let secret = webhook["secret"].as_str().unwrap_or_default().to_string();
+
+    endpoints::post_submission(&form_id)
+        .json(&json!({
+            "encrypted_body": "<encrypted_body>",
+        }))
+        .send()
+        .await?
+        .error_for_status()?;
+
+    let event = expect!(tokio::time::timeout(DELIVERY_TIMEOUT, events.recv())
+        .await
+        .ok()
+        .flatten())
+    .to(be_some())
+    .into_inner();
+
+    let timestamp = event.headers["x-webhook-timestamp"].to_str()?;
+    let signature = event.headers["x-webhook-signature"].to_str()?;
+
+    expect!(signature.to_string()).to(equal(format!(
+        "sha256={}",
+        sign(&secret, timestamp, &event.body)
+    )));
+
+    let body = serde_json::from_str::<JsonValue>(&event.body)?;
+
+    expect!(body["type"].clone()).to(equal(json!("submission.created")));
+    expect!(body["form_id"].clone()).to(equal(json!(form_id)));
+    expect!(body.get("encrypted_body").is_none()).to(equal(true));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn list_and_delete_webhooks() -> anyhow::Result<()> {
+    let FormResponse {
+        form_id,
+        client_key_id,
+        signing_key,
+    } = http::create_form().await?;
+
+    let auth_token = http::authenticate(&form_id, &client_key_id, &signing_key).await?;
+
+    let webhook = register_webhook(&form_id, &auth_token, "https://example.com/webhook").await?;
+    let webhook_id = webhook["webhook_id"].as_str().unwrap_or_default();
+
+    let resp = endpoints::get_webhooks(&form_id)
+        .bearer_auth(&auth_token)
+        .send()
+        .await?;
+
+    expect!(resp.status()).to(equal(StatusCode::OK));
+
+    let body = resp.json::<JsonValue>().await?;
+
+    expect!(body[0]["webhook_id"].clone()).to(equal(json!(webhook_id)));
+    expect!(body[0].get("secret").is_none()).to(equal(true));
+
+    let resp = endpoints::delete_webhook(&form_id, webhook_id)
+        .bearer_auth(&auth_token)
+        .send()
+        .await?;
+
+    expect!(resp.status()).to(equal(StatusCode::NO_CONTENT));
+
+    let body = endpoints::get_webhooks(&form_id)
+        .bearer_auth(&auth_token)
+        .send()
+        .await?
+        .json::<JsonValue>()
+        .await?;
+
+    expect!(body).to(equal(json!([])));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn registering_webhook_requires_admin() -> anyhow::Result<()> {
+    let FormResponse {
+        form_id,
+        client_key_id,
+        signing_key,
+    } = http::create_form().await?;
+
+    let auth_token = http::authenticate(&form_id, &client_key_id, &signing_key).await?;
+
+    let KeyResponse {
+        client_key_id,
+        signing_key,
+    } = http::create_key(&form_id, &auth_token, "read").await?;
+
+    let read_token = http::authenticate(&form_id, &client_key_id, &signing_key).await?;
+
+    let resp = endpoints::post_webhook(&form_id)
+        .bearer_auth(&read_token)
+        .json(&json!({ "url": "https://example.com/webhook" }))
+        .send()
+        .await?;
+
+    expect!(resp.status()).to(equal(StatusCode::FORBIDDEN));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn webhook_url_must_be_valid() -> anyhow::Result<()> {
+    let FormResponse {
+        form_id,
+        client_key_id,
+        signing_key,
+    } = http::create_form().await?;
+
+    let auth_token = http::authenticate(&form_id, &client_key_id, &signing_key).await?;
+
+    let resp = endpoints::post_webhook(&form_id)
+        .bearer_auth(&auth_token)
+        .json(&json!({ "url": "not a url" }))
+        .send()
+        .await?;
+
+    expect!(resp.status()).to(equal(StatusCode::BAD_REQUEST));
+
+    Ok(())
+}
@@ -0,0 +1,8 @@
+-- Migration number: 0005 	 2026-10-18T09:12:40.318Z
+CREATE TABLE "sessions" (
+  "id" integer PRIMARY KEY,
+  "key" integer REFERENCES "keys" ("id") ON DELETE CASCADE,
+  "server_key_id" text NOT NULL UNIQUE,
+  "created_at" text NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  "expires_at" text NOT NULL
+);
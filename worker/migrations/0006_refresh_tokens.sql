@@ -0,0 +1,10 @@
+-- Migration number: 0006 	 2026-10-18T11:40:07.925Z
+CREATE TABLE "refresh_tokens" (
+  "id" integer PRIMARY KEY,
+  "key" integer REFERENCES "keys" ("id") ON DELETE CASCADE,
+  "token_hash" text NOT NULL UNIQUE,
+  "server_key_id" text NOT NULL,
+  "origin" text,
+  "created_at" text NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  "expires_at" text NOT NULL
+);
@@ -0,0 +1,6 @@
+-- Migration number: 0007 	 2026-10-18T14:03:51.662Z
+ALTER TABLE "forms"
+ADD COLUMN "key_epoch" integer NOT NULL DEFAULT 0;
+
+ALTER TABLE "submissions"
+ADD COLUMN "key_epoch" integer NOT NULL DEFAULT 0;
@@ -0,0 +1,11 @@
+-- Migration number: 0008 	 2026-10-18T16:21:18.044Z
+-- `private_key` is encrypted at rest with `SERVER_KEY_ENCRYPTION_KEY`, and cleared once the key is
+-- retired. We keep the public key so that receipts and tree heads signed with it can still be
+-- verified.
+CREATE TABLE "server_signing_keys" (
+  "id" integer PRIMARY KEY,
+  "key_id" text NOT NULL UNIQUE,
+  "private_key" text,
+  "public_key" text NOT NULL,
+  "created_at" text NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
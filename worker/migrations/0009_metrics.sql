@@ -0,0 +1,9 @@
+-- Migration number: 0009 	 2026-10-18T18:12:09.551Z
+CREATE TABLE "metrics" (
+  "day" text NOT NULL,
+  "name" text NOT NULL,
+  "route" text NOT NULL,
+  "status" text NOT NULL,
+  "value" integer NOT NULL DEFAULT 0,
+  PRIMARY KEY ("day", "name", "route", "status")
+);
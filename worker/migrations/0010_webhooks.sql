@@ -0,0 +1,10 @@
+-- Migration number: 0010 	 2026-10-18T19:04:52.730Z
+CREATE TABLE "webhooks" (
+  "id" integer PRIMARY KEY,
+  "form" integer REFERENCES "forms" ("id") ON DELETE CASCADE,
+  "webhook_id" text NOT NULL UNIQUE,
+  "url" text NOT NULL,
+  "secret" text NOT NULL,
+  "include_ciphertext" integer NOT NULL DEFAULT 0,
+  "created_at" text NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,10 @@
+-- Migration number: 0011 	 2026-10-18T19:41:07.215Z
+CREATE TABLE "push_subscriptions" (
+  "id" integer PRIMARY KEY,
+  "key" integer REFERENCES "keys" ("id") ON DELETE CASCADE,
+  "subscription_id" text NOT NULL UNIQUE,
+  "endpoint" text NOT NULL UNIQUE,
+  "p256dh" text NOT NULL,
+  "auth" text NOT NULL,
+  "created_at" text NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
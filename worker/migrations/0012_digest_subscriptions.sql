@@ -0,0 +1,9 @@
+-- Migration number: 0012 	 2026-10-18T20:26:51.904Z
+CREATE TABLE "digest_subscriptions" (
+  "id" integer PRIMARY KEY,
+  "key" integer NOT NULL UNIQUE REFERENCES "keys" ("id") ON DELETE CASCADE,
+  "origin" text NOT NULL,
+  "encrypted_email" text NOT NULL,
+  "last_sent_at" text NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  "created_at" text NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
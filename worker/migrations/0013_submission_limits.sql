@@ -0,0 +1,3 @@
+-- Migration number: 0013 	 2026-10-18T21:04:17.512Z
+ALTER TABLE "forms"
+ADD COLUMN "max_submission_len" integer;
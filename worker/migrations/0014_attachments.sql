@@ -0,0 +1,26 @@
+-- Migration number: 0014 	 2026-10-18T22:15:32.604Z
+CREATE TABLE "attachments" (
+  "id" integer PRIMARY KEY,
+  "form" integer NOT NULL REFERENCES "forms" ("id") ON DELETE CASCADE,
+  "submission" integer REFERENCES "submissions" ("id") ON DELETE CASCADE,
+  "attachment_id" text NOT NULL UNIQUE,
+  "chunk_count" integer NOT NULL DEFAULT 0,
+  "len" integer NOT NULL DEFAULT 0,
+  "created_at" text NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- The chunks of an attachment are stored outside of the database, so when its row is deleted,
+-- including by a cascading delete, we record it here until the chunks are deleted too.
+CREATE TABLE "deleted_attachments" (
+  "id" integer PRIMARY KEY,
+  "attachment_id" text NOT NULL,
+  "chunk_count" integer NOT NULL
+);
+
+CREATE TRIGGER "record_deleted_attachment" AFTER DELETE ON "attachments" FOR EACH ROW BEGIN
+INSERT INTO
+  "deleted_attachments" ("attachment_id", "chunk_count")
+VALUES
+  (OLD."attachment_id", OLD."chunk_count");
+
+END;
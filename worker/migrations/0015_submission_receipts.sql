@@ -0,0 +1,6 @@
+-- Migration number: 0015 	 2026-10-18T23:02:47.318Z
+ALTER TABLE "submissions"
+ADD COLUMN "receipt_public_key" text;
+
+ALTER TABLE "submissions"
+ADD COLUMN "revision" integer NOT NULL DEFAULT 0;
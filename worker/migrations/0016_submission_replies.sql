@@ -0,0 +1,17 @@
+-- Migration number: 0016 	 2026-10-18T23:41:09.527Z
+ALTER TABLE "submissions"
+ADD COLUMN "reply_public_key" text;
+
+ALTER TABLE "submissions"
+ADD COLUMN "mailbox_token_hash" text;
+
+-- Columns with a UNIQUE constraint can't be added to an existing table.
+CREATE UNIQUE INDEX "submissions_mailbox_token_hash" ON "submissions" ("mailbox_token_hash");
+
+CREATE TABLE "replies" (
+  "id" integer PRIMARY KEY,
+  "submission" integer NOT NULL REFERENCES "submissions" ("id") ON DELETE CASCADE,
+  "reply_id" text NOT NULL UNIQUE,
+  "encrypted_body" text NOT NULL,
+  "created_at" text NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
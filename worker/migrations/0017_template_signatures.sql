@@ -0,0 +1,6 @@
+-- Migration number: 0017 	 2026-10-19T00:12:54.861Z
+ALTER TABLE "forms"
+ADD COLUMN "template_signing_key" text;
+
+ALTER TABLE "forms"
+ADD COLUMN "template_signature" text;
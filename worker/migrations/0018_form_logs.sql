@@ -0,0 +1,157 @@
+-- Migration number: 0018 	 2026-10-19T00:47:21.093Z
+-- The base64-encoded SHA-256 hash of the encrypted body, which the log records instead of the
+-- ciphertext itself.
+ALTER TABLE "submissions"
+ADD COLUMN "body_hash" text;
+
+-- When the form's log started recording changes. This is only set for the forms which existed
+-- before the log did, whose existing submissions and keys were never logged. Every other form has
+-- logged every change since it was created.
+ALTER TABLE "forms"
+ADD COLUMN "log_started_at" text;
+
+UPDATE "forms"
+SET
+  "log_started_at" = CURRENT_TIMESTAMP;
+
+-- An append-only log of the changes to each form's submissions and keys. Entries are only ever
+-- added by the triggers below, and the leaves of the form's Merkle tree are the hashes of `entry`.
+CREATE TABLE "log_entries" (
+  "id" integer PRIMARY KEY,
+  "form" integer NOT NULL REFERENCES "forms" ("id") ON DELETE CASCADE,
+  "entry_index" integer NOT NULL,
+  "entry" text NOT NULL,
+  "created_at" text NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  UNIQUE ("form", "entry_index")
+);
+
+CREATE TRIGGER "log_submission_added" AFTER INSERT ON "submissions" FOR EACH ROW BEGIN
+INSERT INTO
+  "log_entries" ("form", "entry_index", "entry")
+VALUES
+  (
+    NEW."form",
+    (
+      SELECT
+        COUNT("id")
+      FROM
+        "log_entries"
+      WHERE
+        "form" = NEW."form"
+    ),
+    'submission_added:' || NEW."submission_id" || ':' || NEW."body_hash"
+  );
+
+END;
+
+CREATE TRIGGER "log_submission_changed" AFTER
+UPDATE OF "body_hash" ON "submissions" FOR EACH ROW WHEN OLD."body_hash" IS NOT NEW."body_hash" BEGIN
+INSERT INTO
+  "log_entries" ("form", "entry_index", "entry")
+VALUES
+  (
+    NEW."form",
+    (
+      SELECT
+        COUNT("id")
+      FROM
+        "log_entries"
+      WHERE
+        "form" = NEW."form"
+    ),
+    'submission_changed:' || NEW."submission_id" || ':' || NEW."body_hash"
+  );
+
+END;
+
+-- When the whole form is deleted, its log is deleted too, so there's nothing to record.
+CREATE TRIGGER "log_submission_deleted" AFTER DELETE ON "submissions" FOR EACH ROW WHEN EXISTS (
+  SELECT
+    "id"
+  FROM
+    "forms"
+  WHERE
+    "id" = OLD."form"
+) BEGIN
+INSERT INTO
+  "log_entries" ("form", "entry_index", "entry")
+VALUES
+  (
+    OLD."form",
+    (
+      SELECT
+        COUNT("id")
+      FROM
+        "log_entries"
+      WHERE
+        "form" = OLD."form"
+    ),
+    'submission_deleted:' || OLD."submission_id"
+  );
+
+END;
+
+CREATE TRIGGER "log_key_added" AFTER INSERT ON "keys" FOR EACH ROW BEGIN
+INSERT INTO
+  "log_entries" ("form", "entry_index", "entry")
+VALUES
+  (
+    NEW."form",
+    (
+      SELECT
+        COUNT("id")
+      FROM
+        "log_entries"
+      WHERE
+        "form" = NEW."form"
+    ),
+    'key_added:' || NEW."key_index" || ':' || NEW."public_signing_key" || ':' || NEW."role"
+  );
+
+END;
+
+CREATE TRIGGER "log_key_role_changed" AFTER
+UPDATE OF "role" ON "keys" FOR EACH ROW WHEN OLD."role" != NEW."role" BEGIN
+INSERT INTO
+  "log_entries" ("form", "entry_index", "entry")
+VALUES
+  (
+    NEW."form",
+    (
+      SELECT
+        COUNT("id")
+      FROM
+        "log_entries"
+      WHERE
+        "form" = NEW."form"
+    ),
+    'key_role_changed:' || NEW."key_index" || ':' || NEW."role"
+  );
+
+END;
+
+CREATE TRIGGER "log_key_revoked" AFTER DELETE ON "keys" FOR EACH ROW WHEN EXISTS (
+  SELECT
+    "id"
+  FROM
+    "forms"
+  WHERE
+    "id" = OLD."form"
+) BEGIN
+INSERT INTO
+  "log_entries" ("form", "entry_index", "entry")
+VALUES
+  (
+    OLD."form",
+    (
+      SELECT
+        COUNT("id")
+      FROM
+        "log_entries"
+      WHERE
+        "form" = OLD."form"
+    ),
+    'key_revoked:' || OLD."key_index"
+  );
+
+END;
@@ -0,0 +1,15 @@
+-- Migration number: 0019 	 2026-10-19T02:13:48.531Z
+-- Changes made to a form by its admins. The acting key and the action are in plaintext, but the
+-- details are encrypted with the form's public primary key as of `key_epoch`. The actor isn't a
+-- foreign key, since the event should outlive the key that made it.
+CREATE TABLE "audit_events" (
+  "id" integer PRIMARY KEY,
+  "form" integer NOT NULL REFERENCES "forms" ("id") ON DELETE CASCADE,
+  "event_index" integer NOT NULL,
+  "actor" integer NOT NULL,
+  "action" text NOT NULL,
+  "key_epoch" integer NOT NULL,
+  "encrypted_details" text NOT NULL,
+  "created_at" text NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  UNIQUE ("form", "event_index")
+);
@@ -0,0 +1,10 @@
+-- Migration number: 0020 	 2026-10-19T05:27:44.906Z
+-- Forms deleted by their admins. A form's audit log is deleted along with it, so this records which
+-- key deleted it and when. Neither column is a foreign key, since this must outlive the form and
+-- the key.
+CREATE TABLE "form_deletions" (
+  "id" integer PRIMARY KEY,
+  "form_id" text NOT NULL,
+  "actor" integer NOT NULL,
+  "deleted_at" text NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -2,11 +2,22 @@ use serde::{Deserialize, Serialize};
 
 use crate::{
     auth::{AccessRole, ApiChallengeResponse, SignedApiAccessToken, SignedApiChallenge},
-    keys::{ClientNonceSignature, PublicPrimaryKey, PublicSigningKey, WrappedPrivatePrimaryKey},
+    config::{self, WorkerEnv},
+    keys::{
+        ClientNonceSignature, MailboxToken, PublicPrimaryKey, PublicSigningKey,
+        PushSubscriptionAuth, PushSubscriptionKey, ReceiptSignature, RefreshToken, ReplyPublicKey,
+        ServerVerifyingKey, TemplateSignature, VapidPublicKey, WebhookSecret,
+        WrappedPrivatePrimaryKey,
+    },
     models::{
-        ClientKeyId, ClientKeys, EncryptedKeyComment, EncryptedSubmissionBody, FormData, FormId,
-        OrgRole, SecretLinkPasswordNonce, SecretLinkPasswordSalt, Submission,
+        Attachment, AttachmentId, AuditAction, AuditEvent, ClientKeyId, ClientKeys,
+        EncryptedAuditDetails, EncryptedKeyComment, EncryptedReplyBody, EncryptedSubmissionBody,
+        FormData, FormId, FormStats, KeyEpoch, LogEntry, OrgRole, Reply, ReplyId,
+        SecretLinkPasswordNonce, SecretLinkPasswordSalt, ServerKeyId, ServerSigningKeyId, Session,
+        StatsPeriod, Submission, SubmissionId, Webhook, WebhookId,
     },
+    receipts::SignedReceipt,
+    transparency::SignedTreeHead,
 };
 
 #[derive(Debug, Serialize)]
@@ -15,8 +26,15 @@ pub struct GetFormResponse {
     pub description: String,
     pub contact_methods: Vec<String>,
     pub public_primary_key: PublicPrimaryKey,
+    pub key_epoch: KeyEpoch,
     pub expires_at: Option<String>,
     pub roles: Vec<OrgRole>,
+    // The maximum length of an encrypted submission, in bytes.
+    pub max_submission_len: usize,
+    // Respondents should check this signature if the sharing link includes the fingerprint of the
+    // template signing key.
+    pub template_signing_key: Option<PublicSigningKey>,
+    pub template_signature: Option<TemplateSignature>,
 }
 
 impl From<FormData> for GetFormResponse {
@@ -26,8 +44,12 @@ impl From<FormData> for GetFormResponse {
             description: data.template.description,
             contact_methods: data.template.contact_methods,
             public_primary_key: data.public_primary_key,
+            key_epoch: data.key_epoch,
             expires_at: data.expires_at.map(|dt| dt.to_rfc3339()),
             roles: data.template.roles,
+            max_submission_len: config::max_submission_len(data.max_submission_len),
+            template_signing_key: data.template_signing_key,
+            template_signature: data.template_signature,
         }
     }
 }
@@ -41,6 +63,14 @@ pub struct PostFormRequest {
     pub contact_methods: Vec<String>,
     pub expires_at: Option<String>,
     pub roles: Vec<OrgRole>,
+    // If this is unset, the form gets the operator's default limit.
+    #[serde(default)]
+    pub max_submission_len: Option<usize>,
+    // These must be sent together. Forms created without them can never have a signed template.
+    #[serde(default)]
+    pub template_signing_key: Option<PublicSigningKey>,
+    #[serde(default)]
+    pub template_signature: Option<TemplateSignature>,
 }
 
 #[derive(Debug, Serialize)]
@@ -56,28 +86,230 @@ pub struct PatchFormRequest {
     pub contact_methods: Vec<String>,
     pub expires_at: Option<String>,
     pub roles: Vec<OrgRole>,
+    // Unlike the other fields, leaving this unset keeps the current limit, so clients which
+    // predate submission limits don't reset it.
+    #[serde(default)]
+    pub max_submission_len: Option<usize>,
+    // Required if the form has a template signing key, since the old signature won't match the
+    // new template.
+    #[serde(default)]
+    pub template_signature: Option<TemplateSignature>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct PostSubmissionRequest {
     pub encrypted_body: EncryptedSubmissionBody,
+    // Clients which predate key rotation won't send this, in which case we assume the submission
+    // was encrypted with the current public primary key.
+    #[serde(default)]
+    pub key_epoch: Option<KeyEpoch>,
+    // Attachments which were uploaded for this submission and have all their chunks.
+    #[serde(default)]
+    pub attachment_ids: Vec<AttachmentId>,
+    // Respondents who send this can later withdraw or amend the submission.
+    #[serde(default)]
+    pub receipt_public_key: Option<PublicSigningKey>,
+    // Respondents who send this can receive replies from the organizers.
+    #[serde(default)]
+    pub reply_public_key: Option<ReplyPublicKey>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PostSubmissionResponse {
+    pub submission_id: SubmissionId,
+    pub receipt: SignedReceipt,
+    // Only present if the respondent sent a reply public key.
+    pub mailbox_token: Option<MailboxToken>,
+}
+
+// These are signed by the respondent with the private key matching the `receipt_public_key` they
+// made the submission with.
+#[derive(Debug, Deserialize)]
+pub struct PutReceiptRequest {
+    pub encrypted_body: EncryptedSubmissionBody,
+    #[serde(default)]
+    pub key_epoch: Option<KeyEpoch>,
+    pub revision: u32,
+    pub signature: ReceiptSignature,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PutReceiptResponse {
+    pub revision: u32,
+    pub receipt: SignedReceipt,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteReceiptRequest {
+    pub revision: u32,
+    pub signature: ReceiptSignature,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PutSubmissionRequest {
+    pub encrypted_body: EncryptedSubmissionBody,
+    pub key_epoch: KeyEpoch,
 }
 
 #[derive(Debug, Serialize)]
 pub struct ListSubmissionsResponse {
+    pub submission_id: SubmissionId,
     pub encrypted_body: EncryptedSubmissionBody,
+    pub key_epoch: KeyEpoch,
     pub created_at: String,
+    pub revision: u32,
+    pub attachments: Vec<Attachment>,
+    pub reply_public_key: Option<ReplyPublicKey>,
 }
 
 impl From<Submission> for ListSubmissionsResponse {
     fn from(submission: Submission) -> Self {
         Self {
+            submission_id: submission.id,
             encrypted_body: submission.encrypted_body,
+            key_epoch: submission.key_epoch,
             created_at: submission.created_at.to_rfc3339(),
+            revision: submission.revision,
+            attachments: submission.attachments,
+            reply_public_key: submission.reply_public_key,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PostReplyRequest {
+    pub encrypted_body: EncryptedReplyBody,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PostReplyResponse {
+    pub reply_id: ReplyId,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GetMailboxResponse {
+    pub reply_id: ReplyId,
+    pub encrypted_body: EncryptedReplyBody,
+    pub created_at: String,
+}
+
+impl From<Reply> for GetMailboxResponse {
+    fn from(reply: Reply) -> Self {
+        Self {
+            reply_id: reply.id,
+            encrypted_body: reply.encrypted_body,
+            created_at: reply.created_at.to_rfc3339(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct GetLogResponse {
+    pub tree_size: u64,
+    pub root_hash: String,
+    pub tree_head: SignedTreeHead,
+    // Every change to the form's submissions and keys since this time is in the log. This is when
+    // the form was created, unless the form predates the log.
+    pub started_at: String,
+}
+
+// The range of entries to list, where `end` is exclusive. If `end` is unset, this lists every
+// entry from `start` onward.
+#[derive(Debug, Deserialize)]
+pub struct ListLogEntriesQuery {
+    #[serde(default)]
+    pub start: u64,
+    #[serde(default)]
+    pub end: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ListLogEntriesResponse {
+    pub index: u64,
+    pub entry: String,
+    pub created_at: String,
+}
+
+impl From<LogEntry> for ListLogEntriesResponse {
+    fn from(entry: LogEntry) -> Self {
+        Self {
+            index: entry.index,
+            entry: entry.entry,
+            created_at: entry.created_at.to_rfc3339(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GetInclusionProofQuery {
+    pub index: u64,
+    pub tree_size: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GetInclusionProofResponse {
+    pub index: u64,
+    pub tree_size: u64,
+    pub audit_path: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GetConsistencyProofQuery {
+    pub first: u64,
+    pub second: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GetConsistencyProofResponse {
+    pub first: u64,
+    pub second: u64,
+    pub proof: Vec<String>,
+}
+
+// A page of the audit log, newest first. To get the next page, pass the `next_before` from the
+// response as `before`.
+#[derive(Debug, Deserialize)]
+pub struct ListAuditEventsQuery {
+    #[serde(default)]
+    pub before: Option<u64>,
+    #[serde(default)]
+    pub limit: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuditEventResponse {
+    pub index: u64,
+    pub actor: ClientKeyId,
+    pub action: AuditAction,
+    pub key_epoch: KeyEpoch,
+    pub encrypted_details: EncryptedAuditDetails,
+    pub created_at: String,
+}
+
+impl From<AuditEvent> for AuditEventResponse {
+    fn from(event: AuditEvent) -> Self {
+        Self {
+            index: event.index,
+            actor: event.actor,
+            action: event.action,
+            key_epoch: event.key_epoch,
+            encrypted_details: event.encrypted_details,
+            created_at: event.created_at.to_rfc3339(),
         }
     }
 }
 
+#[derive(Debug, Serialize)]
+pub struct ListAuditEventsResponse {
+    pub events: Vec<AuditEventResponse>,
+    pub next_before: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PostAttachmentResponse {
+    pub attachment_id: AttachmentId,
+}
+
 #[derive(Debug, Serialize)]
 pub struct GetKeyResponse {
     pub wrapped_private_primary_key: Option<WrappedPrivatePrimaryKey>,
@@ -121,6 +353,31 @@ pub struct PostKeyResponse {
 pub struct PatchKeyRequest {
     pub wrapped_private_primary_key: Option<WrappedPrivatePrimaryKey>,
     pub encrypted_comment: Option<EncryptedKeyComment>,
+    pub role: Option<AccessRole>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RotatedWrappedKey {
+    pub client_key_id: ClientKeyId,
+    pub wrapped_private_primary_key: WrappedPrivatePrimaryKey,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PostPrimaryKeyRequest {
+    pub public_primary_key: PublicPrimaryKey,
+    // The key epoch being rotated away from. If the key has been rotated since the client last
+    // fetched the form, the request is rejected.
+    pub key_epoch: KeyEpoch,
+    pub wrapped_keys: Vec<RotatedWrappedKey>,
+    // Required if the form has a template signing key, since the signature covers the public
+    // primary key.
+    #[serde(default)]
+    pub template_signature: Option<TemplateSignature>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PostPrimaryKeyResponse {
+    pub key_epoch: KeyEpoch,
 }
 
 #[derive(Debug, Serialize)]
@@ -146,6 +403,59 @@ impl From<PostTokenRequest> for ApiChallengeResponse {
 #[derive(Debug, Serialize)]
 pub struct PostTokenResponse {
     pub token: SignedApiAccessToken,
+    pub refresh_token: RefreshToken,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PostRefreshTokenRequest {
+    pub refresh_token: RefreshToken,
+}
+
+// An Ed25519 public key in the JWK format (RFC 8037).
+#[derive(Debug, Serialize)]
+pub struct Jwk {
+    pub kty: &'static str,
+    pub crv: &'static str,
+    pub alg: &'static str,
+    #[serde(rename = "use")]
+    pub key_use: &'static str,
+    pub kid: ServerSigningKeyId,
+    pub x: ServerVerifyingKey,
+}
+
+impl Jwk {
+    pub fn new(kid: ServerSigningKeyId, x: ServerVerifyingKey) -> Self {
+        Self {
+            kty: "OKP",
+            crv: "Ed25519",
+            alg: "EdDSA",
+            key_use: "sig",
+            kid,
+            x,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct GetJwksResponse {
+    pub keys: Vec<Jwk>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ListSessionsResponse {
+    pub session_id: ServerKeyId,
+    pub created_at: String,
+    pub expires_at: String,
+}
+
+impl From<Session> for ListSessionsResponse {
+    fn from(session: Session) -> Self {
+        Self {
+            session_id: session.id,
+            created_at: session.created_at.to_rfc3339(),
+            expires_at: session.expires_at.to_rfc3339(),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -159,3 +469,119 @@ pub struct GetPasswordResponse {
     pub salt: SecretLinkPasswordSalt,
     pub nonce: SecretLinkPasswordNonce,
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ComponentHealth {
+    Ok,
+    Error,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GetHealthResponse {
+    pub version: &'static str,
+    pub env: WorkerEnv,
+    pub d1: ComponentHealth,
+    pub kv: ComponentHealth,
+    pub expected_schema_version: u32,
+    pub applied_schema_version: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GetFormStatsQuery {
+    #[serde(default)]
+    pub period: StatsPeriod,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SubmissionCountResponse {
+    pub period_start: String,
+    pub count: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GetFormStatsResponse {
+    pub submission_count: u64,
+    pub newest_submission_at: Option<String>,
+    pub period: StatsPeriod,
+    pub submissions_per_period: Vec<SubmissionCountResponse>,
+    pub key_count: u64,
+    pub access_count: u64,
+    pub last_accessed_at: Option<String>,
+}
+
+impl GetFormStatsResponse {
+    pub fn new(stats: FormStats, period: StatsPeriod) -> Self {
+        Self {
+            submission_count: stats.submission_count,
+            newest_submission_at: stats.newest_submission_at.map(|date| date.to_rfc3339()),
+            period,
+            submissions_per_period: stats
+                .submissions_per_period
+                .into_iter()
+                .map(|count| SubmissionCountResponse {
+                    period_start: count.period_start.to_string(),
+                    count: count.count,
+                })
+                .collect(),
+            key_count: stats.key_count,
+            access_count: stats.access_count,
+            last_accessed_at: stats.last_accessed_at.map(|date| date.to_rfc3339()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PostWebhookRequest {
+    pub url: String,
+    #[serde(default)]
+    pub include_ciphertext: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PostWebhookResponse {
+    pub webhook_id: WebhookId,
+    pub secret: WebhookSecret,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ListWebhooksResponse {
+    pub webhook_id: WebhookId,
+    pub url: String,
+    pub include_ciphertext: bool,
+    pub created_at: String,
+}
+
+impl From<Webhook> for ListWebhooksResponse {
+    fn from(webhook: Webhook) -> Self {
+        Self {
+            webhook_id: webhook.id,
+            url: webhook.url,
+            include_ciphertext: webhook.include_ciphertext,
+            created_at: webhook.created_at.to_rfc3339(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct GetVapidKeyResponse {
+    pub public_key: VapidPublicKey,
+}
+
+// This matches the shape of `PushSubscription.toJSON()` in the browser.
+#[derive(Debug, Deserialize)]
+pub struct PostPushSubscriptionRequest {
+    pub endpoint: String,
+    pub keys: PushSubscriptionKeys,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PushSubscriptionKeys {
+    pub p256dh: PushSubscriptionKey,
+    pub auth: PushSubscriptionAuth,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PostDigestSubscriptionRequest {
+    pub email: String,
+}
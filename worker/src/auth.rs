@@ -10,12 +10,14 @@ use futures::future::{BoxFuture, FutureExt};
 use jsonwebtoken as jwt;
 use serde::{Deserialize, Serialize};
 use tower_http::auth::AsyncRequireAuthorizationLayer;
-use worker::Date;
 
 use crate::{
-    config,
-    keys::{ApiChallengeNonce, ClientNonceSignature},
+    config::Tenant,
+    keys::{ApiChallengeNonce, ClientNonceSignature, RefreshToken},
+    logging::RequestLog,
     models::{ChallengeId, ClientKeyId, FormId, ServerKeyId},
+    runtime,
+    signing::{self, TokenSigningKey},
     store::{Store, UnauthenticatedStore},
 };
 
@@ -28,27 +30,43 @@ use crate::{
 
 const BEARER_PREFIX: &str = "Bearer ";
 
-const JWT_ALGORITHM: jwt::Algorithm = jwt::Algorithm::HS256;
-
 fn unix_timestamp() -> u64 {
-    let datetime = Date::now();
-    Duration::from_millis(datetime.as_millis()).as_secs()
+    Duration::from_millis(runtime::now_millis()).as_secs()
 }
 
-fn new_jwt_validation() -> jwt::Validation {
-    let mut validation = jwt::Validation::new(JWT_ALGORITHM);
+// We only accept the algorithm the token's header claims it was signed with, and only after we've
+// found a key for that algorithm, so a token can't be verified with the wrong kind of key.
+//
+// Tokens are only valid for the tenant whose origin issued them.
+fn new_jwt_validation(alg: jwt::Algorithm, tenant: &Tenant) -> jwt::Validation {
+    let mut validation = jwt::Validation::new(alg);
 
     validation.required_spec_claims = ["exp", "sub", "aud", "iss"]
         .iter()
         .map(|claim| claim.to_string())
         .collect();
-    validation.aud = Some(config::api_allowed_origins().into_iter().collect());
-    validation.iss = Some(config::api_allowed_origins().into_iter().collect());
-    validation.algorithms = vec![JWT_ALGORITHM];
+    validation.aud = Some([tenant.origin.clone()].into_iter().collect());
+    validation.iss = Some([tenant.origin.clone()].into_iter().collect());
+    validation.algorithms = vec![alg];
 
     validation
 }
 
+// Tokens identify the session they belong to with the `sid` claim. Tokens issued before we
+// supported EdDSA lack this claim, but they were always signed with their session's ephemeral
+// server key, so the `kid` identifies the session instead.
+fn session_id(header: &jwt::Header, sid: Option<ServerKeyId>) -> anyhow::Result<ServerKeyId> {
+    match (sid, header.alg) {
+        (Some(sid), _) => Ok(sid),
+        (None, jwt::Algorithm::HS256) => Ok(header
+            .kid
+            .as_deref()
+            .ok_or_else(|| anyhow!("Token is missing the `kid` claim."))?
+            .parse()?),
+        (None, _) => bail!("Token is missing the `sid` claim."),
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum AuthErrorType {
     Unauthorized,
@@ -164,6 +182,8 @@ struct ApiAccessTokenClaims {
     token_type: ApiTokenType,
     role: AccessRole,
     sub: ApiTokenJwtSub,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sid: Option<ServerKeyId>,
     aud: String,
     iss: String,
     iat: u64,
@@ -172,16 +192,44 @@ struct ApiAccessTokenClaims {
 
 #[derive(Debug, Clone, Serialize)]
 #[serde(transparent)]
-pub struct SignedApiAccessToken(String);
+pub struct SignedApiAccessToken {
+    token: String,
+    // Where to record the role of this token once it's been validated.
+    #[serde(skip)]
+    log: Option<RequestLog>,
+}
+
+impl SignedApiAccessToken {
+    fn new(token: String) -> Self {
+        Self { token, log: None }
+    }
+}
 
 impl SignedApiAccessToken {
     pub async fn validate<'a>(
         self,
         store: &'a UnauthenticatedStore,
+        tenant: &Tenant,
         form_id: &'a FormId,
         needs_role: AccessRole,
     ) -> Result<&'a Store, AuthError> {
-        self.validate_with(store, form_id, |_, role| {
+        let (store, _) = self
+            .validate_actor(store, tenant, form_id, needs_role)
+            .await?;
+
+        Ok(store)
+    }
+
+    // Like `validate`, but also returns the ID of the client key the token belongs to, for
+    // handlers which need to record who made a change.
+    pub async fn validate_actor<'a>(
+        self,
+        store: &'a UnauthenticatedStore,
+        tenant: &Tenant,
+        form_id: &'a FormId,
+        needs_role: AccessRole,
+    ) -> Result<(&'a Store, ClientKeyId), AuthError> {
+        self.validate_with(store, tenant, form_id, |_, role| {
             if role.includes(needs_role) {
                 Ok(())
             } else {
@@ -196,45 +244,13 @@ impl SignedApiAccessToken {
     pub async fn validate_with<'a>(
         self,
         store: &'a UnauthenticatedStore,
+        tenant: &Tenant,
         form_id: &'a FormId,
         role_validator: impl Fn(ClientKeyId, AccessRole) -> Result<(), AuthError>,
-    ) -> Result<&'a Store, AuthError> {
+    ) -> Result<(&'a Store, ClientKeyId), AuthError> {
         let store = store.without_authenticating();
 
-        let header =
-            jwt::decode_header(&self.0).map_err(|err| AuthError::unauthorized(err.to_string()))?;
-
-        let server_key_id = header
-            .kid
-            .ok_or_else(|| AuthError::unauthorized("Access token is missing the `kid` claim."))?
-            .parse()
-            .map_err(|_| AuthError::unauthorized("Could not parse header key ID."))?;
-
-        let ephemeral_server_key = store
-            .get_ephemeral_server_key(&server_key_id)
-            .await
-            .map_err(|err| AuthError::unauthorized(err.to_string()))?
-            .ok_or_else(|| {
-                AuthError::unauthorized(
-                    "Ephemeral server key for access token `kid` does not exist.",
-                )
-            })?;
-
-        let token_claims = jwt::decode::<ApiAccessTokenClaims>(
-            &self.0,
-            &ephemeral_server_key.decoding_key(),
-            &new_jwt_validation(),
-        )
-        .map_err(|err| AuthError::unauthorized(err.to_string()))?
-        .claims;
-
-        // If we don't do this check, there would be nothing stopping a user from authenticating
-        // with a challenge token, since they're also signed by the ephemeral server key.
-        if token_claims.token_type != ApiTokenType::Access {
-            return Err(AuthError::unauthorized(
-                "Attempted to use a challenge token as an access token.",
-            ));
-        }
+        let (server_key_id, alg, token_claims) = self.decode(store, tenant).await?;
 
         if &token_claims.sub.form_id != form_id {
             return Err(AuthError::forbidden(
@@ -242,13 +258,36 @@ impl SignedApiAccessToken {
             ));
         }
 
+        // Ending a session deletes its ephemeral server key, which is enough to invalidate tokens
+        // signed via HS256. Server signing keys are shared between sessions, so for tokens signed
+        // via EdDSA, we check that the session still exists. Verifying the signature alone doesn't
+        // need D1, but we read the client key from D1 here anyway.
+        if alg == jwt::Algorithm::EdDSA {
+            let has_session = store
+                .has_session(&server_key_id)
+                .await
+                .map_err(|err| AuthError::unauthorized(err.to_string()))?;
+
+            if !has_session {
+                return Err(AuthError::unauthorized(
+                    "The session for this access token has been ended.",
+                ));
+            }
+        }
+
         let client_keys = store
             .get_client_keys(&token_claims.sub.form_id, &token_claims.sub.client_key_id)
             .await
             .map_err(|err| AuthError::unauthorized(err.to_string()))?;
 
         match client_keys {
-            Some(keys) => role_validator(keys.id, keys.role)?,
+            Some(keys) => {
+                if let Some(log) = &self.log {
+                    log.set_role(keys.role);
+                }
+
+                role_validator(keys.id, keys.role)?
+            }
             None => {
                 return Err(AuthError::unauthorized(
                     "Client key in access token `sub` does not exist or has been revoked.",
@@ -261,13 +300,79 @@ impl SignedApiAccessToken {
             .await
             .map_err(|err| AuthError::unauthorized(err.to_string()))?;
 
-        Ok(store)
+        Ok((store, token_claims.sub.client_key_id))
+    }
+
+    // The form and client key this token was issued to, once its signature has been checked. This
+    // doesn't check that the client key still exists or what it's allowed to do; handlers still
+    // need to call `validate`.
+    pub async fn verified_subject(
+        &self,
+        store: &UnauthenticatedStore,
+        tenant: &Tenant,
+    ) -> Result<(FormId, ClientKeyId), AuthError> {
+        let (_, _, token_claims) = self.decode(store.without_authenticating(), tenant).await?;
+
+        Ok((token_claims.sub.form_id, token_claims.sub.client_key_id))
+    }
+
+    // End the session this access token belongs to. The holder of a valid access token can always
+    // end their own session, regardless of their role.
+    pub async fn revoke(
+        self,
+        store: &UnauthenticatedStore,
+        tenant: &Tenant,
+    ) -> Result<(), AuthError> {
+        let store = store.without_authenticating();
+
+        let (server_key_id, _, _) = self.decode(store, tenant).await?;
+
+        store
+            .delete_session(&server_key_id)
+            .await
+            .map_err(|err| AuthError::unauthorized(err.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn decode(
+        &self,
+        store: &Store,
+        tenant: &Tenant,
+    ) -> Result<(ServerKeyId, jwt::Algorithm, ApiAccessTokenClaims), AuthError> {
+        let header = jwt::decode_header(&self.token)
+            .map_err(|err| AuthError::unauthorized(err.to_string()))?;
+
+        let decoding_key = signing::decoding_key(store, &header)
+            .await
+            .map_err(|err| AuthError::unauthorized(err.to_string()))?;
+
+        let token_claims = jwt::decode::<ApiAccessTokenClaims>(
+            &self.token,
+            &decoding_key,
+            &new_jwt_validation(header.alg, tenant),
+        )
+        .map_err(|err| AuthError::unauthorized(err.to_string()))?
+        .claims;
+
+        // If we don't do this check, there would be nothing stopping a user from authenticating
+        // with a challenge token, since they're also signed by the same server key.
+        if token_claims.token_type != ApiTokenType::Access {
+            return Err(AuthError::unauthorized(
+                "Attempted to use a challenge token as an access token.",
+            ));
+        }
+
+        let server_key_id = session_id(&header, token_claims.sid.clone())
+            .map_err(|err| AuthError::unauthorized(err.to_string()))?;
+
+        Ok((server_key_id, header.alg, token_claims))
     }
 }
 
 impl fmt::Display for SignedApiAccessToken {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.0)
+        write!(f, "{}", self.token)
     }
 }
 
@@ -287,6 +392,8 @@ struct ApiChallengeClaims {
     #[serde(rename = "type")]
     token_type: ApiTokenType,
     sub: ApiTokenJwtSub,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sid: Option<ServerKeyId>,
     aud: String,
     iss: String,
     iat: u64,
@@ -296,10 +403,7 @@ struct ApiChallengeClaims {
 }
 
 impl ApiChallenge {
-    pub fn encode(&self, key: &jwt::EncodingKey) -> anyhow::Result<SignedApiChallenge> {
-        let mut header = jwt::Header::new(JWT_ALGORITHM);
-        header.kid = Some(self.server_key_id.to_string());
-
+    pub fn encode(&self, key: &TokenSigningKey) -> anyhow::Result<SignedApiChallenge> {
         let secs_since_epoch = unix_timestamp();
 
         let claims = ApiChallengeClaims {
@@ -308,6 +412,7 @@ impl ApiChallenge {
                 form_id: self.form_id.clone(),
                 client_key_id: self.client_key_id,
             },
+            sid: Some(self.server_key_id.clone()),
             aud: self.origin.clone(),
             iss: self.origin.clone(),
             iat: secs_since_epoch,
@@ -316,7 +421,11 @@ impl ApiChallenge {
             nonce: self.nonce.clone(),
         };
 
-        Ok(SignedApiChallenge(jwt::encode(&header, &claims, key)?))
+        Ok(SignedApiChallenge(jwt::encode(
+            &key.header(),
+            &claims,
+            &key.encoding_key()?,
+        )?))
     }
 }
 
@@ -325,25 +434,19 @@ impl ApiChallenge {
 pub struct SignedApiChallenge(String);
 
 impl SignedApiChallenge {
-    async fn validate(&self, store: &Store) -> anyhow::Result<ValidatedApiChallenge> {
+    async fn validate(
+        &self,
+        store: &Store,
+        tenant: &Tenant,
+    ) -> anyhow::Result<ValidatedApiChallenge> {
         let header = jwt::decode_header(&self.0)?;
 
-        let server_key_id: ServerKeyId = header
-            .kid
-            .ok_or_else(|| anyhow!("Challenge token is missing the `kid` claim."))?
-            .parse()?;
-
-        let ephemeral_server_key = store
-            .get_ephemeral_server_key(&server_key_id)
-            .await?
-            .ok_or_else(|| {
-                anyhow!("Ephemeral server key for challenge token `kid` does not exist.")
-            })?;
+        let decoding_key = signing::decoding_key(store, &header).await?;
 
         let claims = jwt::decode::<ApiChallengeClaims>(
             &self.0,
-            &ephemeral_server_key.decoding_key(),
-            &new_jwt_validation(),
+            &decoding_key,
+            &new_jwt_validation(header.alg, tenant),
         )?
         .claims;
 
@@ -357,6 +460,8 @@ impl SignedApiChallenge {
 
         store.delete_challenge_id(&claims.jti).await?;
 
+        let server_key_id = session_id(&header, claims.sid)?;
+
         Ok(ValidatedApiChallenge(ApiChallenge {
             server_key_id,
             form_id: claims.sub.form_id,
@@ -382,8 +487,12 @@ pub struct ApiChallengeResponse {
 }
 
 impl ApiChallengeResponse {
-    pub async fn validate(&self, store: &Store) -> anyhow::Result<ValidatedApiChallengeResponse> {
-        let challenge = self.challenge.validate(store).await?.0;
+    pub async fn validate(
+        &self,
+        store: &Store,
+        tenant: &Tenant,
+    ) -> anyhow::Result<ValidatedApiChallengeResponse> {
+        let challenge = self.challenge.validate(store, tenant).await?.0;
 
         let client_keys = store
             .get_client_keys(&challenge.form_id, &challenge.client_key_id)
@@ -423,35 +532,130 @@ impl ValidatedApiChallengeResponse {
         self.challenge.server_key_id.clone()
     }
 
+    pub fn form_id(&self) -> FormId {
+        self.challenge.form_id.clone()
+    }
+
+    pub fn client_key_id(&self) -> ClientKeyId {
+        self.challenge.client_key_id
+    }
+
     pub fn into_access_token(
         self,
-        key: &jwt::EncodingKey,
+        key: &TokenSigningKey,
         exp: Duration,
     ) -> anyhow::Result<SignedApiAccessToken> {
         let challenge = self.challenge;
 
-        let mut header = jwt::Header::new(JWT_ALGORITHM);
-        header.kid = Some(challenge.server_key_id.to_string());
+        encode_access_token(
+            &challenge.server_key_id,
+            &challenge.form_id,
+            challenge.client_key_id,
+            self.role,
+            &challenge.origin,
+            key,
+            exp,
+        )
+    }
+}
 
-        let secs_since_epoch = unix_timestamp();
+// A refresh token can be exchanged for an access token without completing a new API challenge.
+// Because we look up the client keys again, revoking a secret link or changing its role takes
+// effect the next time its refresh token is used.
+#[derive(Debug, Clone)]
+pub struct ValidatedRefreshToken {
+    form_id: FormId,
+    client_key_id: ClientKeyId,
+    role: AccessRole,
+    origin: String,
+}
 
-        let claims = ApiAccessTokenClaims {
-            token_type: ApiTokenType::Access,
-            role: self.role,
-            sub: ApiTokenJwtSub {
-                form_id: challenge.form_id.clone(),
-                client_key_id: challenge.client_key_id,
-            },
-            aud: challenge.origin.clone(),
-            iss: challenge.origin.clone(),
-            iat: secs_since_epoch,
-            exp: secs_since_epoch + exp.as_secs(),
-        };
+impl ValidatedRefreshToken {
+    // This consumes the refresh token, so it can only be redeemed once, and only with the tenant
+    // it was issued by.
+    pub async fn redeem(
+        refresh_token: &RefreshToken,
+        store: &Store,
+        tenant: &Tenant,
+    ) -> anyhow::Result<Self> {
+        let (form_id, client_key_id) = store
+            .consume_refresh_token(&refresh_token.hash(), &tenant.origin)
+            .await?
+            .ok_or_else(|| anyhow!("Refresh token does not exist, has expired, or was used."))?;
+
+        let client_keys = store
+            .get_client_keys(&form_id, &client_key_id)
+            .await?
+            .ok_or_else(|| {
+                anyhow!("Client key for this refresh token does not exist or has been revoked.")
+            })?;
+
+        Ok(Self {
+            form_id,
+            client_key_id,
+            role: client_keys.role,
+            origin: tenant.origin.clone(),
+        })
+    }
+
+    pub fn form_id(&self) -> FormId {
+        self.form_id.clone()
+    }
+
+    pub fn client_key_id(&self) -> ClientKeyId {
+        self.client_key_id
+    }
 
-        Ok(SignedApiAccessToken(jwt::encode(&header, &claims, key)?))
+    pub fn into_access_token(
+        self,
+        server_key_id: &ServerKeyId,
+        key: &TokenSigningKey,
+        exp: Duration,
+    ) -> anyhow::Result<SignedApiAccessToken> {
+        encode_access_token(
+            server_key_id,
+            &self.form_id,
+            self.client_key_id,
+            self.role,
+            &self.origin,
+            key,
+            exp,
+        )
     }
 }
 
+fn encode_access_token(
+    server_key_id: &ServerKeyId,
+    form_id: &FormId,
+    client_key_id: ClientKeyId,
+    role: AccessRole,
+    origin: &str,
+    key: &TokenSigningKey,
+    exp: Duration,
+) -> anyhow::Result<SignedApiAccessToken> {
+    let secs_since_epoch = unix_timestamp();
+
+    let claims = ApiAccessTokenClaims {
+        token_type: ApiTokenType::Access,
+        role,
+        sub: ApiTokenJwtSub {
+            form_id: form_id.clone(),
+            client_key_id,
+        },
+        sid: Some(server_key_id.clone()),
+        aud: origin.to_string(),
+        iss: origin.to_string(),
+        iat: secs_since_epoch,
+        exp: secs_since_epoch + exp.as_secs(),
+    };
+
+    Ok(SignedApiAccessToken::new(jwt::encode(
+        &key.header(),
+        &claims,
+        &key.encoding_key()?,
+    )?))
+}
+
 type BoxFutureResponseResult<'a> = BoxFuture<'a, Result<Request<Body>, Response<Body>>>;
 
 // Extract the bearer token from the Authorization header and insert it into the request
@@ -469,11 +673,13 @@ pub fn auth_layer<'a>(
                 .to_str()
                 .map_err(|_| StatusCode::UNAUTHORIZED.into_response())?;
 
-            let token = auth_header_value
+            let mut token = auth_header_value
                 .strip_prefix(BEARER_PREFIX)
-                .map(|token| SignedApiAccessToken(token.to_string()))
+                .map(|token| SignedApiAccessToken::new(token.to_string()))
                 .ok_or_else(|| StatusCode::UNAUTHORIZED.into_response())?;
 
+            token.log = req.extensions().get::<RequestLog>().cloned();
+
             req.extensions_mut().insert(token);
 
             Ok(req)
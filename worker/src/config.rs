@@ -1,68 +1,617 @@
-use std::{sync::OnceLock, time::Duration};
+use std::{fmt, ops::RangeInclusive, str::FromStr, sync::OnceLock, time::Duration};
 
-use worker::Env;
+use axum::http::HeaderValue;
+use serde::Serialize;
+use worker::{Env, Url};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+use crate::{
+    keys::{EmailEncryptionKey, ServerKeyEncryptionKey, VapidSigningKey},
+    runtime,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum WorkerEnv {
     Dev,
     Prod,
 }
 
+// The algorithm used to sign API challenges and access tokens.
+//
+// - `Hs256`: Each session gets its own ephemeral server key, stored in KV.
+// - `EdDsa`: Every session shares a long-lived server signing key pair, stored in D1 and rotated
+//   by the scheduled handler.
+//
+// Tokens signed with either algorithm are accepted regardless of this setting, so switching
+// between them doesn't end existing sessions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum JwtSigningAlgorithm {
+    Hs256,
+    EdDsa,
+}
+
+// An origin the API is served from, along with the frontend origins which are allowed to call it.
+// Tokens are only valid for the origin which issued them, so each tenant's sessions are isolated
+// from the others'.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tenant {
+    pub origin: String,
+    pub cors_allowed_origins: Vec<String>,
+}
+
+impl Tenant {
+    fn host(&self) -> Option<&str> {
+        let (_, authority) = self.origin.split_once("://")?;
+        Some(authority.split(':').next().unwrap_or(authority))
+    }
+
+    // The frontend we link to in emails, which is the first allowed CORS origin.
+    pub fn frontend_origin(&self) -> &str {
+        &self.cors_allowed_origins[0]
+    }
+}
+
+// Where and how we send emails. Emails are disabled unless the relay is configured.
+#[derive(Debug, Clone)]
+pub struct MailConfig {
+    pub relay_url: String,
+    pub relay_token: Option<String>,
+    pub from: String,
+    pub encryption_key: EmailEncryptionKey,
+}
+
+// Origins are compared as strings, both to pick the tenant and to match the `Origin` header, so we
+// only accept them in the form browsers send, like `https://example.com`.
+fn parse_origin(origin: &str) -> Result<String, String> {
+    let url =
+        Url::parse(origin).map_err(|err| format!("`{}` is not a valid origin: {}", origin, err))?;
+
+    if !matches!(url.scheme(), "http" | "https") || url.origin().ascii_serialization() != origin {
+        return Err(format!(
+            "`{}` is not an origin; origins must have the format `<scheme>://<host>[:<port>]`",
+            origin
+        ));
+    }
+
+    // We send these back in the `Access-Control-Allow-Origin` header.
+    if origin.parse::<HeaderValue>().is_err() {
+        return Err(format!("`{}` is not a valid header value", origin));
+    }
+
+    Ok(origin.to_string())
+}
+
+// Each line has the format `<origin> <cors_allowed_origin>...`.
+fn parse_tenants(value: &str) -> Result<Vec<Tenant>, String> {
+    let tenants = value
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let mut origins = line.split_whitespace().map(parse_origin);
+
+            let origin = origins.next().unwrap_or_else(|| Ok(String::new()))?;
+            let cors_allowed_origins = origins.collect::<Result<Vec<_>, _>>()?;
+
+            if cors_allowed_origins.is_empty() {
+                return Err(format!(
+                    "`{}` has no CORS allowed origins; each line must have the format `<origin> <cors_allowed_origin>...`",
+                    origin
+                ));
+            }
+
+            Ok(Tenant {
+                origin,
+                cors_allowed_origins,
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if tenants.is_empty() {
+        return Err("must list at least one origin".to_string());
+    }
+
+    Ok(tenants)
+}
+
 #[derive(Debug)]
 struct Config {
     env: WorkerEnv,
-    origin: String,
-    cors_allowed_origin: String,
+    tenants: Vec<Tenant>,
     access_token_exp: Duration,
     challenge_token_exp: Duration,
+    refresh_token_exp: Duration,
+    jwt_signing_algorithm: JwtSigningAlgorithm,
+    server_signing_key_rotation: Duration,
+    server_key_encryption_key: ServerKeyEncryptionKey,
     max_request_body_len: usize,
+    max_submission_len: usize,
+    max_submission_len_bounds: RangeInclusive<usize>,
+    idempotency_key_ttl: Duration,
+    max_attachment_len: usize,
+    max_form_attachments_len: usize,
+    max_form_pending_attachments_len: usize,
+    redact_logs: bool,
+    metrics_retention_days: u32,
+    webhook_allow_insecure_urls: bool,
+    operator_token: Option<String>,
+    vapid_signing_key: Option<VapidSigningKey>,
+    mail: Option<MailConfig>,
 }
 
 static CONFIG: OnceLock<Config> = OnceLock::new();
 
-pub fn init(env: &Env) -> anyhow::Result<()> {
-    CONFIG
-        .set(Config {
-            env: match env.var("ENV")?.to_string().as_str() {
-                "dev" => WorkerEnv::Dev,
-                "prod" => WorkerEnv::Prod,
-                _ => return Err(anyhow::anyhow!("invalid ENV")),
-            },
-            cors_allowed_origin: env.var("CORS_ALLOWED_ORIGIN")?.to_string(),
-            origin: env.var("ORIGIN")?.to_string(),
-            access_token_exp: Duration::from_secs(
-                env.var("ACCESS_TOKEN_EXP")?.to_string().parse()?,
-            ),
-            challenge_token_exp: Duration::from_secs(
-                env.var("CHALLENGE_TOKEN_EXP")?.to_string().parse()?,
-            ),
-            max_request_body_len: env.var("MAX_REQUEST_BODY_LEN")?.to_string().parse()?,
-        })
-        .ok();
+//
+// Defaults for the vars which are optional. These match what we use in prod; see
+// `wrangler.toml` for the rationale behind each one.
+//
+
+const DEFAULT_ACCESS_TOKEN_EXP: &str = "3600";
+const DEFAULT_CHALLENGE_TOKEN_EXP: &str = "60";
+const DEFAULT_REFRESH_TOKEN_EXP: &str = "43200";
+const DEFAULT_JWT_SIGNING_ALGORITHM: &str = "HS256";
+const DEFAULT_SERVER_SIGNING_KEY_ROTATION: &str = "604800";
+const DEFAULT_MAX_REQUEST_BODY_LEN: &str = "5120";
+const DEFAULT_MAX_SUBMISSION_LEN: &str = "5120";
+const DEFAULT_MAX_SUBMISSION_LEN_FLOOR: &str = "1024";
+const DEFAULT_MAX_SUBMISSION_LEN_CEILING: &str = "65536";
+const DEFAULT_IDEMPOTENCY_KEY_TTL: &str = "86400";
+const DEFAULT_MAX_ATTACHMENT_LEN: &str = "10485760";
+const DEFAULT_MAX_FORM_ATTACHMENTS_LEN: &str = "104857600";
+const DEFAULT_MAX_FORM_PENDING_ATTACHMENTS_LEN: &str = "20971520";
+const DEFAULT_REDACT_LOGS: &str = "true";
+const DEFAULT_METRICS_RETENTION_DAYS: &str = "90";
+const DEFAULT_WEBHOOK_ALLOW_INSECURE_URLS: &str = "false";
+const DEFAULT_MAIL_FROM: &str = "Not Without Help <noreply@notwithout.help>";
+
+// Every problem with the config, so they can all be fixed in one go.
+#[derive(Debug, Clone)]
+pub struct ConfigError {
+    problems: Vec<String>,
+}
+
+impl std::error::Error for ConfigError {}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid config: {}", self.problems.join("; "))
+    }
+}
+
+// Vars and secrets are both plain strings, so we look them up the same way.
+struct ConfigLoader<'a> {
+    lookup: &'a dyn Fn(&str) -> Option<String>,
+    problems: Vec<String>,
+}
+
+impl ConfigLoader<'_> {
+    // Returns `None` and records a problem if the var can't be parsed, or if it's missing and has
+    // no default.
+    fn var<T>(
+        &mut self,
+        name: &str,
+        default: Option<&str>,
+        parse: impl FnOnce(&str) -> Result<T, String>,
+    ) -> Option<T> {
+        let value = match ((self.lookup)(name), default) {
+            (Some(value), _) => value,
+            (None, Some(default)) => default.to_string(),
+            (None, None) => {
+                self.problems.push(format!("`{}` is required", name));
+                return None;
+            }
+        };
+
+        match parse(&value) {
+            Ok(parsed) => Some(parsed),
+            Err(err) => {
+                self.problems.push(format!("`{}` {}", name, err));
+                None
+            }
+        }
+    }
+
+    // Most secrets are optional; features which need them are disabled when they're unset.
+    fn secret(&self, name: &str) -> Option<String> {
+        (self.lookup)(name).filter(|secret| !secret.is_empty())
+    }
+
+    // Returns `None` and records a problem if the secret is missing or can't be parsed.
+    fn required_secret<T: FromStr<Err = anyhow::Error>>(&mut self, name: &str) -> Option<T> {
+        let Some(secret) = self.secret(name) else {
+            self.problems.push(format!("`{}` is required", name));
+            return None;
+        };
+
+        match secret.parse::<T>() {
+            Ok(parsed) => Some(parsed),
+            Err(err) => {
+                self.problems
+                    .push(format!("`{}` is invalid: {}", name, err));
+                None
+            }
+        }
+    }
+
+    fn check(&mut self, is_valid: bool, problem: &str) {
+        if !is_valid {
+            self.problems.push(problem.to_string());
+        }
+    }
+}
+
+fn parse_secs(value: &str) -> Result<Duration, String> {
+    match value.trim().parse::<u64>() {
+        Ok(0) => Err("must be greater than zero".to_string()),
+        Ok(secs) => Ok(Duration::from_secs(secs)),
+        Err(_) => Err(format!("must be a number of seconds, got `{}`", value)),
+    }
+}
+
+fn parse_bytes(value: &str) -> Result<usize, String> {
+    match value.trim().parse::<usize>() {
+        Ok(0) => Err("must be greater than zero".to_string()),
+        Ok(len) => Ok(len),
+        Err(_) => Err(format!("must be a number of bytes, got `{}`", value)),
+    }
+}
+
+fn load(lookup: &dyn Fn(&str) -> Option<String>) -> Result<Config, ConfigError> {
+    let mut loader = ConfigLoader {
+        lookup,
+        problems: Vec::new(),
+    };
+
+    let worker_env = loader.var("ENV", None, |value| match value {
+        "dev" => Ok(WorkerEnv::Dev),
+        "prod" => Ok(WorkerEnv::Prod),
+        _ => Err(format!("must be `dev` or `prod`, got `{}`", value)),
+    });
+    let tenants = loader.var("ORIGINS", None, parse_tenants);
+    let access_token_exp = loader.var(
+        "ACCESS_TOKEN_EXP",
+        Some(DEFAULT_ACCESS_TOKEN_EXP),
+        parse_secs,
+    );
+    let challenge_token_exp = loader.var(
+        "CHALLENGE_TOKEN_EXP",
+        Some(DEFAULT_CHALLENGE_TOKEN_EXP),
+        parse_secs,
+    );
+    let refresh_token_exp = loader.var(
+        "REFRESH_TOKEN_EXP",
+        Some(DEFAULT_REFRESH_TOKEN_EXP),
+        parse_secs,
+    );
+    let jwt_signing_algorithm = loader.var(
+        "JWT_SIGNING_ALGORITHM",
+        Some(DEFAULT_JWT_SIGNING_ALGORITHM),
+        |value| match value {
+            "HS256" => Ok(JwtSigningAlgorithm::Hs256),
+            "EdDSA" => Ok(JwtSigningAlgorithm::EdDsa),
+            _ => Err(format!("must be `HS256` or `EdDSA`, got `{}`", value)),
+        },
+    );
+    let server_signing_key_rotation = loader.var(
+        "SERVER_SIGNING_KEY_ROTATION",
+        Some(DEFAULT_SERVER_SIGNING_KEY_ROTATION),
+        parse_secs,
+    );
+    let max_request_body_len = loader.var(
+        "MAX_REQUEST_BODY_LEN",
+        Some(DEFAULT_MAX_REQUEST_BODY_LEN),
+        parse_bytes,
+    );
+    let max_submission_len = loader.var(
+        "MAX_SUBMISSION_LEN",
+        Some(DEFAULT_MAX_SUBMISSION_LEN),
+        parse_bytes,
+    );
+    let max_submission_len_floor = loader.var(
+        "MAX_SUBMISSION_LEN_FLOOR",
+        Some(DEFAULT_MAX_SUBMISSION_LEN_FLOOR),
+        parse_bytes,
+    );
+    let max_submission_len_ceiling = loader.var(
+        "MAX_SUBMISSION_LEN_CEILING",
+        Some(DEFAULT_MAX_SUBMISSION_LEN_CEILING),
+        parse_bytes,
+    );
+    let idempotency_key_ttl = loader.var(
+        "IDEMPOTENCY_KEY_TTL",
+        Some(DEFAULT_IDEMPOTENCY_KEY_TTL),
+        parse_secs,
+    );
+    let max_attachment_len = loader.var(
+        "MAX_ATTACHMENT_LEN",
+        Some(DEFAULT_MAX_ATTACHMENT_LEN),
+        parse_bytes,
+    );
+    let max_form_attachments_len = loader.var(
+        "MAX_FORM_ATTACHMENTS_LEN",
+        Some(DEFAULT_MAX_FORM_ATTACHMENTS_LEN),
+        parse_bytes,
+    );
+    let max_form_pending_attachments_len = loader.var(
+        "MAX_FORM_PENDING_ATTACHMENTS_LEN",
+        Some(DEFAULT_MAX_FORM_PENDING_ATTACHMENTS_LEN),
+        parse_bytes,
+    );
+    let redact_logs = loader.var(
+        "REDACT_LOGS",
+        Some(DEFAULT_REDACT_LOGS),
+        |value| match value {
+            "true" => Ok(true),
+            "false" => Ok(false),
+            _ => Err(format!("must be `true` or `false`, got `{}`", value)),
+        },
+    );
+    let metrics_retention_days = loader.var(
+        "METRICS_RETENTION_DAYS",
+        Some(DEFAULT_METRICS_RETENTION_DAYS),
+        |value| match value.trim().parse::<u32>() {
+            Ok(0) => Err("must be greater than zero".to_string()),
+            Ok(days) => Ok(days),
+            Err(_) => Err(format!("must be a number of days, got `{}`", value)),
+        },
+    );
+    let webhook_allow_insecure_urls = loader.var(
+        "WEBHOOK_ALLOW_INSECURE_URLS",
+        Some(DEFAULT_WEBHOOK_ALLOW_INSECURE_URLS),
+        |value| match value {
+            "true" => Ok(true),
+            "false" => Ok(false),
+            _ => Err(format!("must be `true` or `false`, got `{}`", value)),
+        },
+    );
+    let mail_from = loader.var("MAIL_FROM", Some(DEFAULT_MAIL_FROM), |value| {
+        Ok(value.to_string())
+    });
+    // Server signing keys are always needed, since they sign receipts.
+    let server_key_encryption_key =
+        loader.required_secret::<ServerKeyEncryptionKey>("SERVER_KEY_ENCRYPTION_KEY");
+    let operator_token = loader.secret("OPERATOR_TOKEN");
+    let vapid_signing_key = loader.secret("VAPID_PRIVATE_KEY").and_then(|secret| {
+        match secret.parse::<VapidSigningKey>() {
+            Ok(key) => Some(key),
+            Err(err) => {
+                loader
+                    .problems
+                    .push(format!("`VAPID_PRIVATE_KEY` is invalid: {}", err));
+                None
+            }
+        }
+    });
+
+    let mail_relay_url = loader.secret("MAIL_RELAY_URL");
+    let mail_relay_token = loader.secret("MAIL_RELAY_TOKEN");
+    let email_encryption_key = loader.secret("EMAIL_ENCRYPTION_KEY").and_then(|secret| {
+        match secret.parse::<EmailEncryptionKey>() {
+            Ok(key) => Some(key),
+            Err(err) => {
+                loader
+                    .problems
+                    .push(format!("`EMAIL_ENCRYPTION_KEY` is invalid: {}", err));
+                None
+            }
+        }
+    });
+
+    // We refuse to send emails if we'd have to store the addresses in plaintext.
+    loader.check(
+        mail_relay_url.is_none() || email_encryption_key.is_some(),
+        "`EMAIL_ENCRYPTION_KEY` is required when `MAIL_RELAY_URL` is set",
+    );
+
+    let mail = match (mail_relay_url, email_encryption_key, mail_from) {
+        (Some(relay_url), Some(encryption_key), Some(from)) => Some(MailConfig {
+            relay_url,
+            relay_token: mail_relay_token,
+            from,
+            encryption_key,
+        }),
+        _ => None,
+    };
+
+    // A challenge only needs to live long enough to be exchanged for an access token, and a
+    // refresh token is pointless if it expires before the access token it was issued alongside.
+    if let (Some(challenge), Some(access)) = (challenge_token_exp, access_token_exp) {
+        loader.check(
+            challenge < access,
+            "`CHALLENGE_TOKEN_EXP` must be shorter than `ACCESS_TOKEN_EXP`",
+        );
+    }
+
+    if let (Some(floor), Some(ceiling)) = (max_submission_len_floor, max_submission_len_ceiling) {
+        loader.check(
+            floor <= ceiling,
+            "`MAX_SUBMISSION_LEN_FLOOR` must not be greater than `MAX_SUBMISSION_LEN_CEILING`",
+        );
+    }
+
+    // Forms which don't choose their own limit get the default, so it has to be one they could
+    // have chosen.
+    let max_submission_len_bounds = match (max_submission_len_floor, max_submission_len_ceiling) {
+        (Some(floor), Some(ceiling)) => Some(floor..=ceiling),
+        _ => None,
+    };
+
+    if let (Some(default), Some(bounds)) = (max_submission_len, &max_submission_len_bounds) {
+        loader.check(
+            bounds.contains(&default),
+            "`MAX_SUBMISSION_LEN` must be between `MAX_SUBMISSION_LEN_FLOOR` and `MAX_SUBMISSION_LEN_CEILING`",
+        );
+    }
+
+    // Cached responses are stored in KV, which doesn't support shorter TTLs.
+    if let Some(ttl) = idempotency_key_ttl {
+        loader.check(
+            ttl >= Duration::from_secs(60),
+            "`IDEMPOTENCY_KEY_TTL` must be at least 60 seconds",
+        );
+    }
+
+    if let (Some(attachment), Some(form)) = (max_attachment_len, max_form_attachments_len) {
+        loader.check(
+            attachment <= form,
+            "`MAX_ATTACHMENT_LEN` must not be greater than `MAX_FORM_ATTACHMENTS_LEN`",
+        );
+    }
+
+    if let (Some(attachment), Some(pending), Some(form)) = (
+        max_attachment_len,
+        max_form_pending_attachments_len,
+        max_form_attachments_len,
+    ) {
+        loader.check(
+            attachment <= pending && pending <= form,
+            "`MAX_FORM_PENDING_ATTACHMENTS_LEN` must be between `MAX_ATTACHMENT_LEN` and `MAX_FORM_ATTACHMENTS_LEN`",
+        );
+    }
+
+    if let (Some(access), Some(refresh)) = (access_token_exp, refresh_token_exp) {
+        loader.check(
+            access < refresh,
+            "`ACCESS_TOKEN_EXP` must be shorter than `REFRESH_TOKEN_EXP`",
+        );
+    }
+
+    match (
+        worker_env,
+        tenants,
+        access_token_exp,
+        challenge_token_exp,
+        refresh_token_exp,
+        jwt_signing_algorithm,
+        server_signing_key_rotation,
+        server_key_encryption_key,
+        max_request_body_len,
+        max_submission_len,
+        max_submission_len_bounds,
+        idempotency_key_ttl,
+        max_attachment_len,
+        max_form_attachments_len,
+        max_form_pending_attachments_len,
+        redact_logs,
+        metrics_retention_days,
+        webhook_allow_insecure_urls,
+    ) {
+        (
+            Some(env),
+            Some(tenants),
+            Some(access_token_exp),
+            Some(challenge_token_exp),
+            Some(refresh_token_exp),
+            Some(jwt_signing_algorithm),
+            Some(server_signing_key_rotation),
+            Some(server_key_encryption_key),
+            Some(max_request_body_len),
+            Some(max_submission_len),
+            Some(max_submission_len_bounds),
+            Some(idempotency_key_ttl),
+            Some(max_attachment_len),
+            Some(max_form_attachments_len),
+            Some(max_form_pending_attachments_len),
+            Some(redact_logs),
+            Some(metrics_retention_days),
+            Some(webhook_allow_insecure_urls),
+        ) if loader.problems.is_empty() => Ok(Config {
+            env,
+            tenants,
+            access_token_exp,
+            challenge_token_exp,
+            refresh_token_exp,
+            jwt_signing_algorithm,
+            server_signing_key_rotation,
+            server_key_encryption_key,
+            max_request_body_len,
+            max_submission_len,
+            max_submission_len_bounds,
+            idempotency_key_ttl,
+            max_attachment_len,
+            max_form_attachments_len,
+            max_form_pending_attachments_len,
+            redact_logs,
+            metrics_retention_days,
+            webhook_allow_insecure_urls,
+            operator_token,
+            vapid_signing_key,
+            mail,
+        }),
+        _ => Err(ConfigError {
+            problems: loader.problems,
+        }),
+    }
+}
+
+// Env vars can't change within an isolate, so we only need to load the config once. Later calls
+// are a no-op.
+pub fn init(env: &Env) -> Result<(), ConfigError> {
+    init_with(|name| env.var(name).ok().map(|value| value.to_string()))
+}
+
+// Load the config from somewhere other than the worker's bindings, like a map of vars in tests.
+pub fn init_with(lookup: impl Fn(&str) -> Option<String>) -> Result<(), ConfigError> {
+    if CONFIG.get().is_some() {
+        return Ok(());
+    }
+
+    let config = load(&lookup)?;
+
+    // If another request loaded the config first, it loaded the same config.
+    CONFIG.get_or_init(|| config);
 
     Ok(())
 }
 
+// Check a config without initializing it, so tests can see every problem with an invalid one.
+#[cfg(test)]
+pub fn validate(lookup: impl Fn(&str) -> Option<String>) -> Result<(), ConfigError> {
+    load(&lookup).map(|_| ())
+}
+
 fn get_config() -> &'static Config {
     CONFIG.get().expect("config not initialized")
 }
 
-// Currently unused.
-#[allow(dead_code)]
 pub fn env() -> WorkerEnv {
     get_config().env
 }
 
-pub fn current_origin() -> String {
-    get_config().origin.clone()
+// Get the tenant whose origin has the given host. Requests for any other host, such as when running
+// the worker locally, are served as the first tenant. We log when that happens, since in prod it
+// means `ORIGINS` is missing a line.
+pub fn tenant_for_host(host: Option<&str>) -> Tenant {
+    let tenants = &get_config().tenants;
+
+    match host.and_then(|host| tenants.iter().find(|tenant| tenant.host() == Some(host))) {
+        Some(tenant) => tenant.clone(),
+        None => {
+            runtime::log_error(&format!(
+                "No origin in `ORIGINS` has the host `{}`; serving the request as `{}`.",
+                host.unwrap_or_default(),
+                tenants[0].origin,
+            ));
+
+            tenants[0].clone()
+        }
+    }
 }
 
-pub fn api_allowed_origins() -> Vec<String> {
-    vec![current_origin()]
+// Falls back to the default tenant if the origin is no longer configured.
+pub fn tenant_for_origin(origin: &str) -> Tenant {
+    let tenants = &get_config().tenants;
+
+    tenants
+        .iter()
+        .find(|tenant| tenant.origin == origin)
+        .unwrap_or(&tenants[0])
+        .clone()
 }
 
-pub fn cors_allowed_origin() -> String {
-    get_config().cors_allowed_origin.clone()
+// The tenant which owned every session from before the worker supported multiple origins.
+pub fn default_tenant() -> Tenant {
+    get_config().tenants[0].clone()
 }
 
 pub fn access_token_exp() -> Duration {
@@ -73,6 +622,84 @@ pub fn challenge_token_exp() -> Duration {
     get_config().challenge_token_exp
 }
 
+pub fn refresh_token_exp() -> Duration {
+    get_config().refresh_token_exp
+}
+
+pub fn jwt_signing_algorithm() -> JwtSigningAlgorithm {
+    get_config().jwt_signing_algorithm
+}
+
+pub fn server_signing_key_rotation() -> Duration {
+    get_config().server_signing_key_rotation
+}
+
+pub fn server_key_encryption_key() -> ServerKeyEncryptionKey {
+    get_config().server_key_encryption_key.clone()
+}
+
 pub fn max_request_body_len() -> usize {
     get_config().max_request_body_len
 }
+
+// The limits admins can choose between for the length of their form's encrypted submissions.
+pub fn max_submission_len_bounds() -> RangeInclusive<usize> {
+    get_config().max_submission_len_bounds.clone()
+}
+
+// The limit on the length of a form's encrypted submissions, given the limit its admins chose, if
+// any. The chosen limit is clamped to the current bounds in case they've changed since.
+pub fn max_submission_len(form_limit: Option<usize>) -> usize {
+    let config = get_config();
+    let bounds = &config.max_submission_len_bounds;
+
+    form_limit
+        .map(|limit| limit.clamp(*bounds.start(), *bounds.end()))
+        .unwrap_or(config.max_submission_len)
+}
+
+pub fn idempotency_key_ttl() -> Duration {
+    get_config().idempotency_key_ttl
+}
+
+// The total length of an attachment's encrypted chunks.
+pub fn max_attachment_len() -> usize {
+    get_config().max_attachment_len
+}
+
+// The total length of all the attachments on a form, including ones still being uploaded.
+pub fn max_form_attachments_len() -> usize {
+    get_config().max_form_attachments_len
+}
+
+// The total length of the attachments on a form which aren't part of a submission yet. This is
+// lower than the form's quota, so abandoned uploads can't use up all of it.
+pub fn max_form_pending_attachments_len() -> usize {
+    get_config().max_form_pending_attachments_len
+}
+
+pub fn redact_logs() -> bool {
+    get_config().redact_logs
+}
+
+pub fn metrics_retention_days() -> u32 {
+    get_config().metrics_retention_days
+}
+
+pub fn webhook_allow_insecure_urls() -> bool {
+    get_config().webhook_allow_insecure_urls
+}
+
+// The bearer token operators use to access operator endpoints, which are disabled if it's unset.
+pub fn operator_token() -> Option<String> {
+    get_config().operator_token.clone()
+}
+
+// The key used to sign push notifications, which are disabled if it's unset.
+pub fn vapid_signing_key() -> Option<VapidSigningKey> {
+    get_config().vapid_signing_key.clone()
+}
+
+pub fn mail() -> Option<MailConfig> {
+    get_config().mail.clone()
+}
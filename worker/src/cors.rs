@@ -2,22 +2,38 @@ use axum::http::{
     header::{AUTHORIZATION, CONTENT_TYPE},
     HeaderName, HeaderValue, Method,
 };
-use tower_http::cors::CorsLayer;
+use tower_http::cors::{AllowOrigin, CorsLayer};
 
-use crate::config;
+use crate::{
+    config::Tenant,
+    idempotency::{IDEMPOTENCY_KEY_HEADER, IDEMPOTENT_REPLAYED_HEADER},
+    logging::REQUEST_ID_HEADER,
+};
 
 const CORS_ALLOWED_METHODS: [Method; 4] =
     [Method::GET, Method::POST, Method::PATCH, Method::DELETE];
 
-const CORS_ALLOWED_HEADERS: [HeaderName; 2] = [CONTENT_TYPE, AUTHORIZATION];
+const CORS_ALLOWED_HEADERS: [HeaderName; 3] = [CONTENT_TYPE, AUTHORIZATION, IDEMPOTENCY_KEY_HEADER];
 
-pub fn cors_layer() -> CorsLayer {
+fn base_cors_layer() -> CorsLayer {
     CorsLayer::new()
         .allow_methods(CORS_ALLOWED_METHODS)
         .allow_headers(CORS_ALLOWED_HEADERS)
-        .allow_origin(
-            config::cors_allowed_origin()
-                .parse::<HeaderValue>()
-                .unwrap(),
-        )
+        .expose_headers([REQUEST_ID_HEADER, IDEMPOTENT_REPLAYED_HEADER])
+}
+
+// The origins are validated when the config is parsed, so none of them should be skipped here.
+pub fn cors_layer(tenant: &Tenant) -> CorsLayer {
+    base_cors_layer().allow_origin(AllowOrigin::list(
+        tenant
+            .cors_allowed_origins
+            .iter()
+            .filter_map(|origin| origin.parse::<HeaderValue>().ok()),
+    ))
+}
+
+// When the config is invalid, we don't know which origins are allowed. The response only has a
+// diagnostic ID, so it's safe to let any origin read it, which lets the frontend show the error.
+pub fn config_error_cors_layer() -> CorsLayer {
+    base_cors_layer().allow_origin(AllowOrigin::any())
 }
@@ -0,0 +1,135 @@
+use anyhow::anyhow;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::{
+    config::{self, MailConfig},
+    models::PendingDigest,
+    runtime,
+    store::Store,
+};
+
+//
+// Organizers can opt in to a daily email digest for their secret link. The scheduled handler sends
+// one email per secret link with new submissions, through an HTTP mail relay. This works with any
+// relay which accepts a JSON message, including an SMTP relay behind a small HTTP bridge.
+//
+// Like push notifications, the email never names the form or contains anything from the
+// submissions, since it passes through the relay and the recipient's mail provider. Addresses are
+// encrypted at rest and only decrypted to send the email.
+//
+
+// The longest an email address can be, per RFC 5321.
+const MAX_EMAIL_LEN: usize = 254;
+
+const DIGEST_SUBJECT: &str = "New responses to your form";
+
+// Each digest takes a request to the mail relay and a write to D1, and the scheduled handler can
+// only make so many subrequests. Any digests past this are sent on the next run, oldest first.
+const MAX_DIGESTS_PER_RUN: u32 = 200;
+
+// The body we send to the mail relay.
+#[derive(Debug, Serialize)]
+struct MailMessage<'a> {
+    from: &'a str,
+    to: &'a str,
+    subject: &'a str,
+    text: String,
+}
+
+// We only need to be strict enough to keep the address from being interpreted as more than one
+// address or header by the relay; the relay will reject anything undeliverable.
+pub fn validate_email(address: &str) -> anyhow::Result<()> {
+    let is_valid = address.len() <= MAX_EMAIL_LEN
+        && address
+            .split_once('@')
+            .is_some_and(|(local, domain)| !local.is_empty() && domain.contains('.'))
+        && !address
+            .chars()
+            .any(|c| c.is_whitespace() || c.is_control() || matches!(c, ',' | ';' | '<' | '>'));
+
+    if is_valid {
+        Ok(())
+    } else {
+        Err(anyhow!("Email address is invalid."))
+    }
+}
+
+fn digest_text(digest: &PendingDigest) -> String {
+    let tenant = config::tenant_for_origin(&digest.origin);
+
+    let summary = match digest.new_submission_count {
+        1 => "Your form received 1 new response since the last digest.".to_string(),
+        count => format!(
+            "Your form received {} new responses since the last digest.",
+            count
+        ),
+    };
+
+    format!(
+        "{}\n\nOpen your secret link to read them:\n{}/view\n\nYou're receiving this because you \
+         turned on the daily digest for your secret link. You can turn it off from the same page.\n",
+        summary,
+        tenant.frontend_origin(),
+    )
+}
+
+async fn send(mail: &MailConfig, to: &str, text: String) -> anyhow::Result<()> {
+    let body = serde_json::to_string(&MailMessage {
+        from: &mail.from,
+        to,
+        subject: DIGEST_SUBJECT,
+        text,
+    })?;
+
+    let authorization = mail
+        .relay_token
+        .as_ref()
+        .map(|token| format!("Bearer {}", token));
+
+    let mut headers = vec![("Content-Type", "application/json")];
+
+    if let Some(authorization) = &authorization {
+        headers.push(("Authorization", authorization));
+    }
+
+    let status = runtime::post_to_operator(&mail.relay_url, &headers, body.into_bytes()).await?;
+
+    match status {
+        200..=299 => Ok(()),
+        status => Err(anyhow!("Mail relay responded with status {}.", status)),
+    }
+}
+
+async fn send_digest(
+    store: &Store,
+    mail: &MailConfig,
+    digest: &PendingDigest,
+    cutoff: DateTime<Utc>,
+) -> anyhow::Result<()> {
+    let address = mail.encryption_key.decrypt(&digest.encrypted_email)?;
+
+    send(mail, &address, digest_text(digest)).await?;
+
+    store
+        .mark_digest_sent(&digest.form_id, &digest.client_key_id, cutoff)
+        .await
+}
+
+// If a digest fails to send, we leave it for the next run, which will include the submissions
+// from both days. One digest failing doesn't keep the others from being sent.
+pub async fn send_digests(store: &Store, mail: &MailConfig) -> anyhow::Result<()> {
+    let cutoff = DateTime::<Utc>::from_timestamp_millis(runtime::now_millis() as i64)
+        .ok_or_else(|| anyhow!("Current time is out of range."))?;
+
+    for digest in store
+        .list_pending_digests(cutoff, MAX_DIGESTS_PER_RUN)
+        .await?
+    {
+        if let Err(err) = send_digest(store, mail, &digest, cutoff).await {
+            runtime::log_error(&format!("Error: Could not send email digest: {:#}", err));
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,261 @@
+use std::{sync::Arc, time::Duration};
+
+use axum::{
+    body::{self, Body, Bytes},
+    extract::{FromRequest, Request, State},
+    http::{header::CONTENT_TYPE, HeaderName, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{ErrorResponse, IntoResponse, Response},
+};
+use base64::prelude::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    auth::SignedApiAccessToken,
+    config,
+    keys::IdempotentResponseKey,
+    logging::{ErrorCode, LoggedError},
+    router::AppState,
+    runtime,
+};
+
+//
+// Clients can retry a request which creates something without creating it twice by sending an
+// `Idempotency-Key` header. The first response for a key is cached in KV and replayed verbatim for
+// retries with the same key and request body, while a different request body under the same key is
+// rejected. The cached response is encrypted with a key derived from the idempotency key and
+// request body, and KV only sees hashes of those, so clients should use random idempotency keys.
+// Because KV is eventually consistent, concurrent retries from different locations may both be
+// handled; this protects against retries on flaky connections, not against a determined client.
+//
+
+pub const IDEMPOTENCY_KEY_HEADER: HeaderName = HeaderName::from_static("idempotency-key");
+
+// Set on replayed responses, so clients can tell them apart.
+pub const IDEMPOTENT_REPLAYED_HEADER: HeaderName = HeaderName::from_static("idempotent-replayed");
+
+const MAX_IDEMPOTENCY_KEY_LEN: usize = 255;
+
+// A request that's still being handled blocks retries until it finishes. This is the minimum TTL
+// KV supports, so a request that never finishes only blocks retries briefly.
+const IN_PROGRESS_TTL_SECS: u64 = 60;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum IdempotencyRecord {
+    InProgress {
+        request_hash: String,
+    },
+    Completed {
+        request_hash: String,
+        status: u16,
+        content_type: Option<String>,
+        encrypted_body: String,
+    },
+}
+
+impl IdempotencyRecord {
+    fn request_hash(&self) -> &str {
+        match self {
+            Self::InProgress { request_hash } => request_hash,
+            Self::Completed { request_hash, .. } => request_hash,
+        }
+    }
+}
+
+fn hash(parts: &[&[u8]]) -> String {
+    let mut hasher = Sha256::new();
+
+    // Each part is length-prefixed so that different parts can't run together into the same input.
+    for part in parts {
+        hasher.update((part.len() as u64).to_be_bytes());
+        hasher.update(part);
+    }
+
+    BASE64_URL_SAFE_NO_PAD.encode(hasher.finalize())
+}
+
+fn internal_err(err: impl Into<anyhow::Error>) -> ErrorResponse {
+    LoggedError::new(ErrorCode::Internal, err.into())
+        .into_response(StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+fn replay(
+    key: &IdempotentResponseKey,
+    status: u16,
+    content_type: Option<&str>,
+    encrypted_body: &str,
+) -> anyhow::Result<Response> {
+    let body = key.decrypt(encrypted_body)?;
+
+    let mut builder = Response::builder()
+        .status(status)
+        .header(IDEMPOTENT_REPLAYED_HEADER, HeaderValue::from_static("true"));
+
+    if let Some(content_type) = content_type {
+        builder = builder.header(CONTENT_TYPE, content_type);
+    }
+
+    Ok(builder.body(Body::from(body))?)
+}
+
+pub async fn idempotency_layer(
+    State(state): State<Arc<AppState>>,
+    req: Request,
+    next: Next,
+) -> Result<Response, ErrorResponse> {
+    let idempotency_key = match req.headers().get(IDEMPOTENCY_KEY_HEADER) {
+        Some(value) => value.as_bytes().to_vec(),
+        None => return Ok(next.run(req).await),
+    };
+
+    if idempotency_key.is_empty() || idempotency_key.len() > MAX_IDEMPOTENCY_KEY_LEN {
+        return Err(LoggedError::new(
+            ErrorCode::InvalidIdempotencyKey,
+            format!(
+                "Idempotency keys must be between 1 and {} bytes long.",
+                MAX_IDEMPOTENCY_KEY_LEN
+            ),
+        )
+        .into_response(StatusCode::BAD_REQUEST));
+    }
+
+    let store = state.store.without_authenticating();
+
+    let (parts, req_body) = req.into_parts();
+
+    // This respects the body limit for the route, the same as the handler's extractors would.
+    let req_body = Bytes::from_request(Request::from_parts(parts.clone(), req_body), &())
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    // On authenticated endpoints, keys are also scoped to the client key the access token was
+    // issued to, so one organizer can't replay a response meant for another. The handler still
+    // validates the token fully.
+    let subject = match parts.extensions.get::<SignedApiAccessToken>() {
+        Some(token) => {
+            let (form_id, client_key_id) = token
+                .verified_subject(&state.store, &state.tenant)
+                .await
+                .map_err(|err| {
+                    LoggedError::new(ErrorCode::Unauthorized, err)
+                        .into_response(StatusCode::UNAUTHORIZED)
+                })?;
+
+            format!("{}/{}", form_id, client_key_id)
+        }
+        None => String::new(),
+    };
+
+    // Keys are scoped to the tenant and the endpoint, so the same key can't replay a response from
+    // somewhere else.
+    let scope = hash(&[
+        state.tenant.origin.as_bytes(),
+        parts.method.as_str().as_bytes(),
+        parts.uri.path().as_bytes(),
+        subject.as_bytes(),
+        &idempotency_key,
+    ]);
+    let request_hash = hash(&[&req_body]);
+    let response_key =
+        IdempotentResponseKey::derive(&idempotency_key, &req_body).map_err(internal_err)?;
+
+    match store
+        .get_idempotency_record(&scope)
+        .await
+        .map_err(internal_err)?
+    {
+        Some(record) if record.request_hash() != request_hash => {
+            return Err(LoggedError::new(
+                ErrorCode::IdempotencyKeyReused,
+                "This idempotency key was already used for a request with a different body.",
+            )
+            .into_response(StatusCode::UNPROCESSABLE_ENTITY));
+        }
+        Some(IdempotencyRecord::InProgress { .. }) => {
+            return Err(LoggedError::new(
+                ErrorCode::IdempotencyKeyInProgress,
+                "A request with this idempotency key is still being handled.",
+            )
+            .into_response(StatusCode::CONFLICT));
+        }
+        Some(IdempotencyRecord::Completed {
+            status,
+            content_type,
+            encrypted_body,
+            ..
+        }) => {
+            return replay(
+                &response_key,
+                status,
+                content_type.as_deref(),
+                &encrypted_body,
+            )
+            .map_err(internal_err);
+        }
+        None => {}
+    }
+
+    store
+        .store_idempotency_record(
+            &scope,
+            &IdempotencyRecord::InProgress {
+                request_hash: request_hash.clone(),
+            },
+            Duration::from_secs(IN_PROGRESS_TTL_SECS),
+        )
+        .await
+        .map_err(internal_err)?;
+
+    let resp = next
+        .run(Request::from_parts(parts, Body::from(req_body)))
+        .await;
+
+    // Errors on our end aren't cached, so the request can be retried once they're resolved.
+    if resp.status().is_server_error() {
+        if let Err(err) = store.delete_idempotency_record(&scope).await {
+            runtime::log_error(&format!(
+                "Error: failed to delete idempotency record: {:#}",
+                err
+            ));
+        }
+
+        return Ok(resp);
+    }
+
+    let (parts, resp_body) = resp.into_parts();
+    let resp_body = body::to_bytes(resp_body, usize::MAX)
+        .await
+        .map_err(internal_err)?;
+
+    // The request already succeeded, so failing it now would only make the client retry it.
+    let result = match response_key.encrypt(&resp_body) {
+        Ok(encrypted_body) => {
+            let record = IdempotencyRecord::Completed {
+                request_hash,
+                status: parts.status.as_u16(),
+                content_type: parts
+                    .headers
+                    .get(CONTENT_TYPE)
+                    .and_then(|value| value.to_str().ok())
+                    .map(str::to_string),
+                encrypted_body,
+            };
+
+            store
+                .store_idempotency_record(&scope, &record, config::idempotency_key_ttl())
+                .await
+        }
+        Err(err) => Err(err),
+    };
+
+    if let Err(err) = result {
+        runtime::log_error(&format!(
+            "Error: failed to cache idempotent response: {:#}",
+            err
+        ));
+    }
+
+    Ok(Response::from_parts(parts, Body::from(resp_body)))
+}
@@ -1,12 +1,15 @@
-use std::str::FromStr;
+use std::{fmt, str::FromStr};
 
 use anyhow::Context;
 use base64::prelude::*;
-use ed25519_dalek::{self as ed25519, Verifier};
+use ed25519_dalek::{self as ed25519, pkcs8::EncodePrivateKey, Verifier};
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
 use jsonwebtoken as jwt;
 use rand::RngCore;
 use secrecy::{ExposeSecret, SecretSlice, SecretString};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
 //
 // See the security architecture document for information on the purpose of these values and how
@@ -65,6 +68,247 @@ impl FromStr for EphemeralServerKey {
     }
 }
 
+// A long-lived key pair used to sign API challenges and access tokens via EdDSA, as an alternative
+// to per-session ephemeral server keys. Unlike ephemeral server keys, verifying a token signed with
+// this key only requires the public half, which we can cache and publish.
+#[derive(Debug, Clone)]
+pub struct ServerSigningKey {
+    key: ed25519::SigningKey,
+    encoded: SecretString,
+}
+
+impl ServerSigningKey {
+    pub fn generate() -> Self {
+        let mut rng = rand::thread_rng();
+        let mut bytes = [0u8; ed25519::SECRET_KEY_LENGTH];
+        rng.fill_bytes(&mut bytes);
+
+        let key = ed25519::SigningKey::from_bytes(&bytes);
+        let encoded = BASE64_STANDARD.encode(key.to_bytes());
+
+        Self {
+            key,
+            encoded: SecretString::from(encoded),
+        }
+    }
+
+    pub fn encoding_key(&self) -> anyhow::Result<jwt::EncodingKey> {
+        let der = self
+            .key
+            .to_pkcs8_der()
+            .map_err(|err| anyhow::anyhow!("Could not encode server signing key: {}", err))?;
+
+        Ok(jwt::EncodingKey::from_ed_der(der.as_bytes()))
+    }
+
+    pub fn verifying_key(&self) -> ServerVerifyingKey {
+        ServerVerifyingKey(self.key.verifying_key())
+    }
+}
+
+impl ExposeSecret<str> for ServerSigningKey {
+    fn expose_secret(&self) -> &str {
+        self.encoded.expose_secret()
+    }
+}
+
+impl FromStr for ServerSigningKey {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let decoded = BASE64_STANDARD.decode(s)?;
+
+        let bytes: [u8; ed25519::SECRET_KEY_LENGTH] = decoded
+            .as_slice()
+            .try_into()
+            .context("Server signing key is not the expected length.")?;
+
+        Ok(Self {
+            key: ed25519::SigningKey::from_bytes(&bytes),
+            encoded: SecretString::from(s),
+        })
+    }
+}
+
+// We encode this as unpadded base64url, which is how the `x` parameter of a JWK is encoded.
+#[derive(Debug, Clone)]
+pub struct ServerVerifyingKey(ed25519::VerifyingKey);
+
+impl ServerVerifyingKey {
+    pub fn decoding_key(&self) -> anyhow::Result<jwt::DecodingKey> {
+        Ok(jwt::DecodingKey::from_ed_components(&self.to_string())?)
+    }
+}
+
+impl fmt::Display for ServerVerifyingKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", BASE64_URL_SAFE_NO_PAD.encode(self.0.as_bytes()))
+    }
+}
+
+impl Serialize for ServerVerifyingKey {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        self.to_string().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ServerVerifyingKey {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        let decoded = BASE64_URL_SAFE_NO_PAD
+            .decode(s)
+            .context("Server verifying key is not a valid base64url-encoded string.")
+            .map_err(serde::de::Error::custom)?;
+
+        let bytes: [u8; ed25519::PUBLIC_KEY_LENGTH] = decoded
+            .as_slice()
+            .try_into()
+            .map_err(|_| serde::de::Error::custom("Server verifying key is not 32 bytes long."))?;
+
+        Ok(Self(
+            ed25519::VerifyingKey::from_bytes(&bytes).map_err(serde::de::Error::custom)?,
+        ))
+    }
+}
+
+// A random bearer secret, encoded as unpadded base64url so it can be put in a URL.
+#[derive(Debug, Clone)]
+struct RandomToken(SecretString);
+
+impl RandomToken {
+    const LEN: usize = 32;
+
+    fn generate() -> Self {
+        let mut rng = rand::thread_rng();
+        let mut buf = vec![0u8; Self::LEN];
+        rng.fill_bytes(&mut buf);
+
+        Self(SecretString::from(BASE64_URL_SAFE_NO_PAD.encode(&buf)))
+    }
+
+    // The base64-encoded SHA-256 hash of the token as it's encoded, which is what we store for
+    // tokens we don't need to know.
+    fn hash(&self) -> String {
+        BASE64_STANDARD.encode(Sha256::digest(self.0.expose_secret().as_bytes()))
+    }
+}
+
+impl ExposeSecret<str> for RandomToken {
+    fn expose_secret(&self) -> &str {
+        self.0.expose_secret()
+    }
+}
+
+impl Serialize for RandomToken {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        self.0.expose_secret().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for RandomToken {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        Ok(Self(SecretString::from(String::deserialize(deserializer)?)))
+    }
+}
+
+// Refresh tokens are bearer secrets, so we only ever store a hash of them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct RefreshToken(RandomToken);
+
+impl RefreshToken {
+    pub fn generate() -> Self {
+        Self(RandomToken::generate())
+    }
+
+    pub fn hash(&self) -> RefreshTokenHash {
+        RefreshTokenHash(self.0.hash())
+    }
+}
+
+impl ExposeSecret<str> for RefreshToken {
+    fn expose_secret(&self) -> &str {
+        self.0.expose_secret()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct RefreshTokenHash(String);
+
+// Mailbox tokens let a respondent read the replies to their submission without revealing anything
+// else about themselves. Like refresh tokens, they're bearer secrets, so we only store a hash.
+#[derive(Debug, Clone, Serialize)]
+#[serde(transparent)]
+pub struct MailboxToken(RandomToken);
+
+impl MailboxToken {
+    pub fn generate() -> Self {
+        Self(RandomToken::generate())
+    }
+
+    pub fn hash(&self) -> MailboxTokenHash {
+        MailboxTokenHash(self.0.hash())
+    }
+}
+
+impl From<&str> for MailboxToken {
+    fn from(s: &str) -> Self {
+        Self(RandomToken(SecretString::from(s.to_string())))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct MailboxTokenHash(String);
+
+// The secret used to sign webhook events. Unlike most secrets, the server needs to know this one,
+// since it's used to prove to the receiver that events came from us.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct WebhookSecret(RandomToken);
+
+impl WebhookSecret {
+    pub fn generate() -> Self {
+        Self(RandomToken::generate())
+    }
+
+    // The signature is the hex-encoded HMAC-SHA256 of `<timestamp>.<body>`, keyed with the
+    // secret as it's encoded.
+    pub fn sign(&self, timestamp: u64, body: &str) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.0.expose_secret().as_bytes())
+            .expect("HMAC can take a key of any size");
+
+        mac.update(timestamp.to_string().as_bytes());
+        mac.update(b".");
+        mac.update(body.as_bytes());
+
+        mac.finalize()
+            .into_bytes()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+}
+
+impl ExposeSecret<str> for WebhookSecret {
+    fn expose_secret(&self) -> &str {
+        self.0.expose_secret()
+    }
+}
+
 impl<'de> Deserialize<'de> for ApiChallengeNonce {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -128,6 +372,52 @@ impl<'de> Deserialize<'de> for ClientNonceSignature {
     }
 }
 
+// A respondent's signature over a request to withdraw or amend their submission.
+#[derive(Debug, Clone)]
+pub struct ReceiptSignature(Vec<u8>);
+
+impl<'de> Deserialize<'de> for ReceiptSignature {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        let decoded = BASE64_STANDARD
+            .decode(s)
+            .context("Receipt signature is not a valid base64-encoded string.")
+            .map_err(serde::de::Error::custom)?;
+        Ok(Self(decoded))
+    }
+}
+
+// An organizer's signature over a form's template and public primary key. Unlike a receipt
+// signature, we store this and pass it on to respondents, so it needs to be serialized too.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TemplateSignature(Vec<u8>);
+
+impl Serialize for TemplateSignature {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        BASE64_STANDARD.encode(&self.0).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for TemplateSignature {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        let decoded = BASE64_STANDARD
+            .decode(s)
+            .context("Template signature is not a valid base64-encoded string.")
+            .map_err(serde::de::Error::custom)?;
+        Ok(Self(decoded))
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct PublicSigningKey(ed25519::VerifyingKey);
 
@@ -142,6 +432,28 @@ impl PublicSigningKey {
 
         Ok(())
     }
+
+    pub fn verify_receipt(
+        &self,
+        message: &[u8],
+        signature: &ReceiptSignature,
+    ) -> Result<(), ed25519::SignatureError> {
+        self.0
+            .verify(message, &ed25519::Signature::from_slice(&signature.0)?)?;
+
+        Ok(())
+    }
+
+    pub fn verify_template(
+        &self,
+        message: &[u8],
+        signature: &TemplateSignature,
+    ) -> Result<(), ed25519::SignatureError> {
+        self.0
+            .verify(message, &ed25519::Signature::from_slice(&signature.0)?)?;
+
+        Ok(())
+    }
 }
 
 impl Serialize for PublicSigningKey {
@@ -185,7 +497,351 @@ impl<'de> Deserialize<'de> for PublicSigningKey {
 #[serde(transparent)]
 pub struct WrappedPrivatePrimaryKey(String);
 
-// This is opaque to the server, so no need to decode it.
+// The client generates this via `crypto_box_keypair`. The server only decodes it to encrypt audit
+// events for the organizers.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(transparent)]
 pub struct PublicPrimaryKey(String);
+
+impl PublicPrimaryKey {
+    // Encrypt a message such that only the private primary key can decrypt it. This is compatible
+    // with libsodium's `crypto_box_seal`, which the client uses to encrypt submissions.
+    pub fn seal(&self, message: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let bytes: [u8; crypto_box::KEY_SIZE] = BASE64_STANDARD
+            .decode(&self.0)
+            .context("Public primary key is not valid base64.")?
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Public primary key is not the correct length."))?;
+
+        crypto_box::PublicKey::from(bytes)
+            .seal(&mut rand::thread_rng(), message)
+            .map_err(|err| anyhow::anyhow!("Could not encrypt with public primary key: {}", err))
+    }
+}
+
+// The public key a respondent sends with their submission so organizers can encrypt replies to
+// them. This is opaque to the server, so no need to decode it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ReplyPublicKey(String);
+
+// The P-256 key pair the server uses to identify itself to push services, as described in RFC
+// 8292. It's configured as a secret, encoded as unpadded base64url, since it needs to stay the same
+// for as long as any push subscriptions made with its public key exist.
+#[derive(Debug, Clone)]
+pub struct VapidSigningKey(p256::ecdsa::SigningKey);
+
+impl VapidSigningKey {
+    pub fn sign(&self, message: &[u8]) -> Vec<u8> {
+        use p256::ecdsa::signature::Signer;
+
+        let signature: p256::ecdsa::Signature = self.0.sign(message);
+
+        signature.to_bytes().to_vec()
+    }
+
+    pub fn public_key(&self) -> VapidPublicKey {
+        VapidPublicKey(*self.0.verifying_key())
+    }
+}
+
+impl FromStr for VapidSigningKey {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let decoded = BASE64_URL_SAFE_NO_PAD
+            .decode(s.trim())
+            .context("VAPID private key is not a valid base64url-encoded string.")?;
+
+        Ok(Self(
+            p256::ecdsa::SigningKey::from_slice(&decoded)
+                .context("VAPID private key is not a valid P-256 private key.")?,
+        ))
+    }
+}
+
+// This is encoded as an uncompressed point in unpadded base64url, which is the format browsers
+// expect for the `applicationServerKey` when subscribing.
+#[derive(Debug, Clone)]
+pub struct VapidPublicKey(p256::ecdsa::VerifyingKey);
+
+impl fmt::Display for VapidPublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            BASE64_URL_SAFE_NO_PAD.encode(self.0.to_encoded_point(false).as_bytes())
+        )
+    }
+}
+
+impl Serialize for VapidPublicKey {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        self.to_string().serialize(serializer)
+    }
+}
+
+// The `p256dh` key of a push subscription, which the browser generates so that push messages can
+// be encrypted for it. It's encoded as an uncompressed point in base64url.
+#[derive(Debug, Clone)]
+pub struct PushSubscriptionKey(p256::PublicKey);
+
+impl PushSubscriptionKey {
+    pub fn public_key(&self) -> &p256::PublicKey {
+        &self.0
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        use p256::elliptic_curve::sec1::ToEncodedPoint;
+
+        self.0.to_encoded_point(false).as_bytes().to_vec()
+    }
+}
+
+impl Serialize for PushSubscriptionKey {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        BASE64_URL_SAFE_NO_PAD
+            .encode(self.to_bytes())
+            .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for PushSubscriptionKey {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        let decoded = BASE64_URL_SAFE_NO_PAD
+            .decode(s.trim_end_matches('='))
+            .context("Push subscription key is not a valid base64url-encoded string.")
+            .map_err(serde::de::Error::custom)?;
+
+        Ok(Self(
+            p256::PublicKey::from_sec1_bytes(&decoded)
+                .context("Push subscription key is not a valid P-256 public key.")
+                .map_err(serde::de::Error::custom)?,
+        ))
+    }
+}
+
+// The `auth` secret of a push subscription, which the browser generates and mixes into the key
+// used to encrypt push messages. It's encoded as base64url.
+#[derive(Debug, Clone)]
+pub struct PushSubscriptionAuth([u8; Self::LEN]);
+
+impl PushSubscriptionAuth {
+    pub const LEN: usize = 16;
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl Serialize for PushSubscriptionAuth {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        BASE64_URL_SAFE_NO_PAD.encode(self.0).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for PushSubscriptionAuth {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        let decoded = BASE64_URL_SAFE_NO_PAD
+            .decode(s.trim_end_matches('='))
+            .context("Push subscription auth secret is not a valid base64url-encoded string.")
+            .map_err(serde::de::Error::custom)?;
+
+        Ok(Self(decoded.as_slice().try_into().map_err(|_| {
+            serde::de::Error::custom(format!(
+                "Push subscription auth secret is not {} bytes long.",
+                Self::LEN,
+            ))
+        })?))
+    }
+}
+
+// A key the server uses to encrypt something at rest which it needs to be able to decrypt again,
+// via AES-256-GCM. The ciphertext is prefixed with its random nonce and encoded as base64. Keys are
+// configured as secrets, encoded as base64, so that what they encrypt can't be read from the
+// database alone.
+#[derive(Debug, Clone)]
+struct AtRestKey(SecretSlice<u8>);
+
+impl AtRestKey {
+    const LEN: usize = 32;
+
+    const NONCE_LEN: usize = 12;
+
+    fn parse(s: &str) -> anyhow::Result<Self> {
+        let decoded = BASE64_STANDARD
+            .decode(s.trim())
+            .context("Key is not a valid base64-encoded string.")?;
+
+        if decoded.len() != Self::LEN {
+            anyhow::bail!("Key is not {} bytes long.", Self::LEN);
+        }
+
+        Ok(Self(SecretSlice::from(decoded)))
+    }
+
+    fn cipher(&self) -> aes_gcm::Aes256Gcm {
+        use aes_gcm::KeyInit;
+
+        aes_gcm::Aes256Gcm::new_from_slice(self.0.expose_secret())
+            .expect("at-rest encryption key has the expected length")
+    }
+
+    fn encrypt(&self, plaintext: &[u8]) -> anyhow::Result<String> {
+        use aes_gcm::aead::Aead;
+
+        let mut nonce = [0u8; Self::NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce);
+
+        let ciphertext = self
+            .cipher()
+            .encrypt(aes_gcm::Nonce::from_slice(&nonce), plaintext)
+            .map_err(|err| anyhow::anyhow!("{}", err))?;
+
+        let mut encrypted = nonce.to_vec();
+        encrypted.extend_from_slice(&ciphertext);
+
+        Ok(BASE64_STANDARD.encode(encrypted))
+    }
+
+    fn decrypt(&self, encrypted: &str) -> anyhow::Result<Vec<u8>> {
+        use aes_gcm::aead::Aead;
+
+        let decoded = BASE64_STANDARD.decode(encrypted)?;
+
+        if decoded.len() < Self::NONCE_LEN {
+            anyhow::bail!("Ciphertext is too short.");
+        }
+
+        let (nonce, ciphertext) = decoded.split_at(Self::NONCE_LEN);
+
+        self.cipher()
+            .decrypt(aes_gcm::Nonce::from_slice(nonce), ciphertext)
+            .map_err(|err| anyhow::anyhow!("{}", err))
+    }
+}
+
+// The key the server uses to encrypt organizers' email addresses at rest. Unlike most secrets, the
+// server needs to be able to decrypt these, since it sends the emails.
+#[derive(Debug, Clone)]
+pub struct EmailEncryptionKey(AtRestKey);
+
+impl EmailEncryptionKey {
+    pub fn encrypt(&self, address: &str) -> anyhow::Result<EncryptedEmailAddress> {
+        Ok(EncryptedEmailAddress(
+            self.0
+                .encrypt(address.as_bytes())
+                .context("Could not encrypt email address.")?,
+        ))
+    }
+
+    pub fn decrypt(&self, encrypted: &EncryptedEmailAddress) -> anyhow::Result<String> {
+        let plaintext = self
+            .0
+            .decrypt(&encrypted.0)
+            .context("Could not decrypt email address.")?;
+
+        Ok(String::from_utf8(plaintext)?)
+    }
+}
+
+impl FromStr for EmailEncryptionKey {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self(AtRestKey::parse(s)?))
+    }
+}
+
+// The key the server uses to encrypt its own server signing keys at rest, so that someone who can
+// read the database can't sign access tokens or receipts.
+#[derive(Debug, Clone)]
+pub struct ServerKeyEncryptionKey(AtRestKey);
+
+impl ServerKeyEncryptionKey {
+    pub fn encrypt(&self, key: &ServerSigningKey) -> anyhow::Result<EncryptedServerSigningKey> {
+        Ok(EncryptedServerSigningKey(
+            self.0
+                .encrypt(key.expose_secret().as_bytes())
+                .context("Could not encrypt server signing key.")?,
+        ))
+    }
+
+    pub fn decrypt(
+        &self,
+        encrypted: &EncryptedServerSigningKey,
+    ) -> anyhow::Result<ServerSigningKey> {
+        let plaintext = self
+            .0
+            .decrypt(&encrypted.0)
+            .context("Could not decrypt server signing key.")?;
+
+        String::from_utf8(plaintext)?.parse()
+    }
+}
+
+impl FromStr for ServerKeyEncryptionKey {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self(AtRestKey::parse(s)?))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct EncryptedServerSigningKey(String);
+
+// The key a cached idempotent response is encrypted with. It's derived from the idempotency key and
+// the request body, which only the client has, so capability tokens in the response, like a
+// mailbox token, can't be read from KV alone.
+#[derive(Debug, Clone)]
+pub struct IdempotentResponseKey(AtRestKey);
+
+impl IdempotentResponseKey {
+    const INFO: &'static [u8] = b"notwithouthelp idempotent response";
+
+    pub fn derive(idempotency_key: &[u8], request_body: &[u8]) -> anyhow::Result<Self> {
+        let mut key = vec![0u8; AtRestKey::LEN];
+
+        Hkdf::<Sha256>::new(Some(request_body), idempotency_key)
+            .expand(Self::INFO, &mut key)
+            .map_err(|err| anyhow::anyhow!("Could not derive idempotent response key: {}", err))?;
+
+        Ok(Self(AtRestKey(SecretSlice::from(key))))
+    }
+
+    pub fn encrypt(&self, body: &[u8]) -> anyhow::Result<String> {
+        self.0
+            .encrypt(body)
+            .context("Could not encrypt idempotent response.")
+    }
+
+    pub fn decrypt(&self, encrypted: &str) -> anyhow::Result<Vec<u8>> {
+        self.0
+            .decrypt(encrypted)
+            .context("Could not decrypt idempotent response.")
+    }
+}
+
+// The nonce and ciphertext of an email address, encoded as base64.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct EncryptedEmailAddress(String);
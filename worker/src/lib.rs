@@ -4,28 +4,90 @@ mod api;
 mod auth;
 mod config;
 mod cors;
+mod digests;
+mod idempotency;
 mod keys;
+mod logging;
+mod metrics;
 mod models;
+mod notifications;
+mod push;
+mod receipts;
 mod router;
+mod runtime;
+#[cfg(all(not(target_arch = "wasm32"), feature = "server"))]
+pub mod server;
+mod signing;
+mod storage;
 mod store;
+mod templates;
+#[cfg(test)]
+mod tests;
+mod transparency;
+mod webhooks;
 
-use axum::{body::Body, http::Response};
+use std::{sync::Arc, time::Duration};
+
+use anyhow::Context as _;
+use axum::{
+    body::Body,
+    http::{Response, StatusCode},
+    Json, Router,
+};
 use router::AppState;
-use store::UnauthenticatedStore;
+use serde_json::json;
+use storage::{Blobs, D1Backend, Database, KeyValue, R2Backend, WorkersKvBackend};
+use store::{Store, UnauthenticatedStore};
 use tower_service::Service;
-use worker::{self, event, Context, Env, HttpRequest, ScheduleContext, ScheduledEvent};
+use uuid::Uuid;
+use worker::{
+    self, console_error, event, Context, Env, HttpRequest, MessageBatch, ScheduleContext,
+    ScheduledEvent,
+};
 
 const D1_BINDING: &str = "DB";
 const KV_BINDING: &str = "KV";
+const ATTACHMENTS_BINDING: &str = "ATTACHMENTS";
+const NOTIFICATIONS_QUEUE_BINDING: &str = "NOTIFICATIONS";
+
+fn open_store(env: &Env) -> worker::Result<UnauthenticatedStore> {
+    Ok(UnauthenticatedStore::new(
+        Database::new(D1Backend::new(env.d1(D1_BINDING)?)),
+        KeyValue::new(WorkersKvBackend::new(env.kv(KV_BINDING)?)),
+        Blobs::new(R2Backend::new(env.bucket(ATTACHMENTS_BINDING)?)),
+    ))
+}
+
+// We don't want to leak the details of the config in a response, so we log them along with an ID
+// that can be matched up with the response. Every request gets this response, including CORS
+// preflights, which the CORS layer answers itself.
+fn config_error_router(err: &config::ConfigError) -> Router {
+    let diagnostic_id = Uuid::new_v4();
+
+    runtime::log_error(&format!("Diagnostic ID {}: {}", diagnostic_id, err));
+
+    Router::new()
+        .fallback(move || async move {
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(json!({ "diagnostic_id": diagnostic_id })),
+            )
+        })
+        .layer(cors::config_error_cors_layer())
+}
 
 #[event(fetch)]
 async fn fetch(req: HttpRequest, env: Env, _ctx: Context) -> worker::Result<Response<Body>> {
     console_error_panic_hook::set_once();
 
-    config::init(&env).expect("failed to initialize config");
+    if let Err(err) = config::init(&env) {
+        return Ok(config_error_router(&err).call(req).await?);
+    }
 
     let state = AppState {
-        store: UnauthenticatedStore::new(env.d1(D1_BINDING)?, env.kv(KV_BINDING)?),
+        store: open_store(&env)?,
+        tenant: config::tenant_for_host(req.uri().host()),
+        notifications: Arc::new(env.queue(NOTIFICATIONS_QUEUE_BINDING)?),
     };
 
     Ok(router::new(state).call(req).await?)
@@ -35,16 +97,113 @@ async fn fetch(req: HttpRequest, env: Env, _ctx: Context) -> worker::Result<Resp
 async fn scheduled(_event: ScheduledEvent, env: Env, _ctx: ScheduleContext) {
     console_error_panic_hook::set_once();
 
-    config::init(&env).expect("failed to initialize config");
+    if let Err(err) = config::init(&env) {
+        console_error!("{}", err);
+        return;
+    }
+
+    let store = match open_store(&env) {
+        Ok(store) => store,
+        Err(err) => {
+            console_error!("failed to get D1, KV, and R2 bindings: {}", err);
+            return;
+        }
+    };
+
+    run_scheduled_tasks(store.without_authenticating()).await;
+}
+
+// Attachments are uploaded before the submission they're part of, so one which still isn't part of
+// a submission after this long was abandoned.
+const ABANDONED_ATTACHMENT_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+// Each task is independent of the others, so one failing shouldn't keep the rest from running.
+fn log_task_result(result: anyhow::Result<()>) {
+    if let Err(err) = result {
+        runtime::log_error(&format!("Error: {:#}", err));
+    }
+}
+
+// The work done by the cron trigger, which the standalone server does on a timer instead.
+async fn run_scheduled_tasks(store: &Store) {
+    log_task_result(
+        store
+            .delete_expired_forms()
+            .await
+            .context("failed to delete expired forms"),
+    );
+
+    log_task_result(
+        store
+            .delete_expired_sessions()
+            .await
+            .context("failed to delete expired sessions"),
+    );
+
+    log_task_result(
+        store
+            .delete_expired_refresh_tokens()
+            .await
+            .context("failed to delete expired refresh tokens"),
+    );
+
+    log_task_result(
+        store
+            .delete_old_metrics(config::metrics_retention_days())
+            .await
+            .context("failed to delete old metrics"),
+    );
+
+    log_task_result(
+        store
+            .delete_abandoned_attachments(ABANDONED_ATTACHMENT_AGE)
+            .await
+            .context("failed to delete abandoned attachments"),
+    );
+
+    // This also picks up any attachments whose chunks couldn't be deleted along with their form
+    // or submission.
+    log_task_result(
+        store
+            .purge_deleted_attachments()
+            .await
+            .context("failed to purge deleted attachments"),
+    );
+
+    if let Some(mail) = config::mail() {
+        log_task_result(
+            digests::send_digests(store, &mail)
+                .await
+                .context("failed to send email digests"),
+        );
+    }
+
+    // Receipts are signed with the server signing keys even when tokens aren't.
+    log_task_result(
+        signing::rotate_server_signing_keys(store)
+            .await
+            .context("failed to rotate server signing keys"),
+    );
+}
+
+#[event(queue)]
+async fn queue(
+    batch: MessageBatch<notifications::QueuedNotification>,
+    env: Env,
+    _ctx: Context,
+) -> worker::Result<()> {
+    console_error_panic_hook::set_once();
+
+    if let Err(err) = config::init(&env) {
+        console_error!("{}", err);
+        batch.retry_all();
+        return Ok(());
+    }
 
-    let d1 = env.d1(D1_BINDING).expect("failed to get D1 binding");
-    let kv = env.kv(KV_BINDING).expect("failed to get KV binding");
+    let store = open_store(&env)?;
+    let queue = env.queue(NOTIFICATIONS_QUEUE_BINDING)?;
 
-    let store = UnauthenticatedStore::new(d1, kv);
+    notifications::handle_batch(store.without_authenticating(), &queue, batch).await;
 
-    store
-        .without_authenticating()
-        .delete_expired_forms()
-        .await
-        .expect("failed to delete expired forms");
+    Ok(())
 }
@@ -0,0 +1,249 @@
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    extract::{MatchedPath, RawPathParams, Request},
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::{ErrorResponse, Response},
+    Extension,
+};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{auth::AccessRole, config, runtime};
+
+//
+// We write one structured log line per request. To keep secrets and identifying information out of
+// the logs, we never log request bodies, headers, or the raw URL. We log the route the request
+// matched instead of its path, and error messages are redacted unless redaction is disabled.
+//
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+const REDACTED: &str = "[redacted]";
+
+// Base64-encoded keys, signatures, and hashes are at least this long, while the words in our error
+// messages are not.
+const MIN_REDACTED_ENCODED_LEN: usize = 16;
+
+// A machine-readable reason a request failed, which is included in the log line for the request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    Internal,
+    Unauthorized,
+    Forbidden,
+    LastAdmin,
+    StaleKeyEpoch,
+    InvalidKeyEpoch,
+    StaleSubmissionKeyEpoch,
+    SchemaOutdated,
+    Unhealthy,
+    WebhookLimit,
+    InvalidWebhookUrl,
+    InvalidPushEndpoint,
+    InvalidEmail,
+    InvalidSubmissionLimit,
+    NotificationEnqueueFailed,
+    RequestTooLarge,
+    InvalidIdempotencyKey,
+    IdempotencyKeyReused,
+    IdempotencyKeyInProgress,
+    InvalidAttachment,
+    AttachmentChunkOutOfOrder,
+    AttachmentQuotaExceeded,
+    AttachmentPurgeFailed,
+    InvalidReceiptSignature,
+    StaleReceiptRevision,
+    ReplyLimit,
+    InvalidTemplateSignature,
+    StaleTemplateSignature,
+    InvalidLogRange,
+    AuditEventFailed,
+}
+
+// Handlers attach this to their error responses so the logging middleware can pick it up.
+#[derive(Debug, Clone)]
+pub struct LoggedError {
+    code: ErrorCode,
+    message: String,
+}
+
+impl LoggedError {
+    pub fn new(code: ErrorCode, message: impl fmt::Display) -> Self {
+        Self {
+            code,
+            message: message.to_string(),
+        }
+    }
+
+    pub fn into_response(self, status: axum::http::StatusCode) -> ErrorResponse {
+        (status, Extension(self)).into()
+    }
+}
+
+#[derive(Debug, Default)]
+struct RequestLogFields {
+    route: Option<String>,
+    path_params: Vec<String>,
+    role: Option<AccessRole>,
+    error: Option<LoggedError>,
+}
+
+// A handle for recording details about a request that only become known partway through handling
+// it, like the role of the access token it was authenticated with.
+#[derive(Debug, Clone, Default)]
+pub struct RequestLog(Arc<Mutex<RequestLogFields>>);
+
+impl RequestLog {
+    fn fields(&self) -> std::sync::MutexGuard<'_, RequestLogFields> {
+        self.0.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    pub fn set_role(&self, role: AccessRole) {
+        self.fields().role = Some(role);
+    }
+
+    pub fn set_error(&self, error: LoggedError) {
+        self.fields().error = Some(error);
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct LogLine<'a> {
+    request_id: Uuid,
+    method: &'a str,
+    route: &'a str,
+    status: u16,
+    latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    role: Option<AccessRole>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<ErrorCode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+}
+
+fn is_encoded_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '+' | '/' | '=' | '-' | '_')
+}
+
+fn looks_like_jwt(word: &str) -> bool {
+    let segments = word.split('.').collect::<Vec<_>>();
+    segments.len() == 3
+        && segments
+            .iter()
+            .all(|segment| !segment.is_empty() && segment.chars().all(is_encoded_char))
+}
+
+fn looks_like_encoded_secret(word: &str) -> bool {
+    word.len() >= MIN_REDACTED_ENCODED_LEN
+        && word.chars().all(is_encoded_char)
+        && word.chars().any(|c| c.is_ascii_digit())
+}
+
+// Remove anything from a message that looks like a JWT or encoded secret, along with the values of
+// the request's path parameters, which include form IDs.
+fn redact(message: &str, path_params: &[String]) -> String {
+    let redacted = message
+        .split(' ')
+        .map(|word| {
+            let trimmed = word.trim_matches(|c: char| !is_encoded_char(c) && c != '.');
+            let trimmed = trimmed.trim_end_matches('.');
+
+            if looks_like_jwt(trimmed) || looks_like_encoded_secret(trimmed) {
+                word.replace(trimmed, REDACTED)
+            } else {
+                word.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    path_params
+        .iter()
+        .filter(|param| !param.is_empty())
+        .fold(redacted, |message, param| {
+            message.replace(param.as_str(), REDACTED)
+        })
+}
+
+fn now_millis() -> u64 {
+    runtime::now_millis()
+}
+
+// This wraps the whole router, so that requests which never reach a route, like 404s and CORS
+// preflights, still get a log line and a request ID.
+pub async fn log_requests(mut req: Request, next: Next) -> Response {
+    let started_at = now_millis();
+    let request_id = Uuid::new_v4();
+    let method = req.method().to_string();
+
+    let log = RequestLog::default();
+    req.extensions_mut().insert(log.clone());
+
+    let mut resp = next.run(req).await;
+
+    if let Some(error) = resp.extensions_mut().remove::<LoggedError>() {
+        log.set_error(error);
+    }
+
+    let fields = log.fields();
+
+    let message = fields.error.as_ref().map(|error| {
+        if config::redact_logs() {
+            redact(&error.message, &fields.path_params)
+        } else {
+            error.message.clone()
+        }
+    });
+
+    let line = LogLine {
+        request_id,
+        method: &method,
+        route: fields.route.as_deref().unwrap_or_default(),
+        status: resp.status().as_u16(),
+        latency_ms: now_millis().saturating_sub(started_at),
+        role: fields.role,
+        error: fields.error.as_ref().map(|error| error.code),
+        message,
+    };
+
+    if let Ok(line) = serde_json::to_string(&line) {
+        runtime::log(&line);
+    }
+
+    if let Ok(value) = HeaderValue::from_str(&request_id.to_string()) {
+        resp.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+
+    resp
+}
+
+// The route and path parameters are only known once the request has been routed, so this runs as a
+// route layer and records them for `log_requests`.
+pub async fn record_route(
+    matched_path: Option<MatchedPath>,
+    path_params: Option<RawPathParams>,
+    req: Request,
+    next: Next,
+) -> Response {
+    if let Some(log) = req.extensions().get::<RequestLog>() {
+        let mut fields = log.fields();
+
+        fields.route = matched_path.as_ref().map(|path| path.as_str().to_string());
+        fields.path_params = path_params
+            .map(|params| {
+                params
+                    .iter()
+                    .map(|(_, value)| value.to_string())
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+    }
+
+    next.run(req).await
+}
@@ -0,0 +1,243 @@
+use std::{
+    fmt::Write,
+    mem,
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::Response,
+};
+use sha2::{Digest, Sha256};
+
+use crate::{router::AppState, runtime, store::Store};
+
+//
+// Operator metrics are counters aggregated by day and route, so they can be used for capacity
+// planning without tracking individual organizers. Routes are recorded as templates (like
+// `/forms/:form_id`), so no metric can be attributed to a particular form.
+//
+// Counts are added up in memory and written to D1 at most once per flush interval per isolate, so
+// most requests don't wait on a write. Whatever an isolate hasn't written yet when it's evicted is
+// lost, which is fine for capacity planning.
+//
+
+const METRIC_PREFIX: &str = "notwithouthelp";
+
+const METRICS_FLUSH_INTERVAL_MILLIS: u64 = 60 * 1000;
+
+#[derive(Debug)]
+struct PendingMetrics {
+    increments: Vec<MetricIncrement>,
+    flushed_at: u64,
+}
+
+impl PendingMetrics {
+    fn add(&mut self, increment: MetricIncrement) {
+        let existing = self.increments.iter_mut().find(|existing| {
+            existing.metric == increment.metric
+                && existing.route == increment.route
+                && existing.status == increment.status
+        });
+
+        match existing {
+            Some(existing) => existing.value = existing.value.saturating_add(increment.value),
+            None => self.increments.push(increment),
+        }
+    }
+}
+
+static PENDING_METRICS: Mutex<PendingMetrics> = Mutex::new(PendingMetrics {
+    increments: Vec::new(),
+    flushed_at: 0,
+});
+
+fn pending_metrics() -> std::sync::MutexGuard<'static, PendingMetrics> {
+    PENDING_METRICS
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    Requests,
+    AuthFailures,
+    RequestLatencyMs,
+}
+
+impl Metric {
+    const ALL: [Self; 3] = [Self::Requests, Self::AuthFailures, Self::RequestLatencyMs];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Requests => "requests_total",
+            Self::AuthFailures => "auth_failures_total",
+            Self::RequestLatencyMs => "request_latency_ms_total",
+        }
+    }
+
+    fn help(self) -> &'static str {
+        match self {
+            Self::Requests => "Requests handled, by day, route, and status class.",
+            Self::AuthFailures => "Requests rejected with a 401 or 403, by day and route.",
+            Self::RequestLatencyMs => "Total time spent handling requests, by day and route.",
+        }
+    }
+}
+
+// A counter for one day, metric, and set of labels.
+#[derive(Debug, Clone)]
+pub struct MetricIncrement {
+    pub metric: Metric,
+    pub route: String,
+    pub status: String,
+    pub value: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct MetricRow {
+    pub day: String,
+    pub name: String,
+    pub route: String,
+    pub status: String,
+    pub value: u64,
+}
+
+fn now_millis() -> u64 {
+    runtime::now_millis()
+}
+
+fn status_class(status: StatusCode) -> String {
+    format!("{}xx", status.as_u16() / 100)
+}
+
+pub async fn record_metrics(
+    State(state): State<Arc<AppState>>,
+    matched_path: Option<MatchedPath>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let started_at = now_millis();
+
+    let route = format!(
+        "{} {}",
+        req.method(),
+        matched_path
+            .as_ref()
+            .map(MatchedPath::as_str)
+            .unwrap_or_default()
+    );
+
+    let resp = next.run(req).await;
+
+    let status = resp.status();
+    let latency_ms = now_millis().saturating_sub(started_at);
+
+    let mut increments = vec![
+        MetricIncrement {
+            metric: Metric::Requests,
+            route: route.clone(),
+            status: status_class(status),
+            value: 1,
+        },
+        MetricIncrement {
+            metric: Metric::RequestLatencyMs,
+            route: route.clone(),
+            status: String::new(),
+            value: latency_ms,
+        },
+    ];
+
+    if matches!(status, StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN) {
+        increments.push(MetricIncrement {
+            metric: Metric::AuthFailures,
+            route,
+            status: String::new(),
+            value: 1,
+        });
+    }
+
+    let due = {
+        let mut pending = pending_metrics();
+
+        for increment in increments {
+            pending.add(increment);
+        }
+
+        let now = now_millis();
+
+        if now.saturating_sub(pending.flushed_at) >= METRICS_FLUSH_INTERVAL_MILLIS {
+            pending.flushed_at = now;
+            Some(mem::take(&mut pending.increments))
+        } else {
+            None
+        }
+    };
+
+    if let Some(increments) = due {
+        let store = state.store.without_authenticating();
+
+        // Metrics are best-effort; failing to record them shouldn't fail the request. We keep the
+        // counts for the next flush instead, including when the schema is out of date, since GET
+        // requests get past `require_current_schema`.
+        let is_written = matches!(store.is_schema_current().await, Ok(true))
+            && store.increment_metrics(&increments).await.is_ok();
+
+        if !is_written {
+            let mut pending = pending_metrics();
+
+            for increment in increments {
+                pending.add(increment);
+            }
+        }
+    }
+
+    resp
+}
+
+// Compare digests rather than the tokens themselves, so the comparison takes the same time no
+// matter how much of the token is correct.
+pub fn is_operator_token(expected: &str, actual: &str) -> bool {
+    Sha256::digest(expected.as_bytes()) == Sha256::digest(actual.as_bytes())
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+// Render metrics in the Prometheus text exposition format.
+//
+// https://prometheus.io/docs/instrumenting/exposition_formats/
+pub async fn render_metrics(store: &Store) -> anyhow::Result<String> {
+    let rows = store.list_metrics().await?;
+
+    let mut output = String::new();
+
+    for metric in Metric::ALL {
+        let name = format!("{}_{}", METRIC_PREFIX, metric.name());
+
+        writeln!(output, "# HELP {} {}", name, metric.help())?;
+        writeln!(output, "# TYPE {} counter", name)?;
+
+        for row in rows.iter().filter(|row| row.name == metric.name()) {
+            let mut labels = vec![format!("day=\"{}\"", escape_label(&row.day))];
+
+            if !row.route.is_empty() {
+                labels.push(format!("route=\"{}\"", escape_label(&row.route)));
+            }
+
+            if !row.status.is_empty() {
+                labels.push(format!("status=\"{}\"", escape_label(&row.status)));
+            }
+
+            writeln!(output, "{}{{{}}} {}", name, labels.join(","), row.value)?;
+        }
+    }
+
+    Ok(output)
+}
@@ -1,13 +1,19 @@
 use std::{fmt, str::FromStr};
 
-use chrono::{DateTime, Utc};
+use base64::prelude::*;
+use chrono::{DateTime, NaiveDate, Utc};
 use rand::distributions::{Alphanumeric, DistString};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use uuid::Uuid;
 
 use crate::{
     auth::AccessRole,
-    keys::{PublicPrimaryKey, PublicSigningKey, WrappedPrivatePrimaryKey},
+    keys::{
+        EncryptedEmailAddress, MailboxTokenHash, PublicPrimaryKey, PublicSigningKey,
+        PushSubscriptionAuth, PushSubscriptionKey, ReplyPublicKey, ServerSigningKey,
+        TemplateSignature, WebhookSecret, WrappedPrivatePrimaryKey,
+    },
 };
 
 //
@@ -59,8 +65,8 @@ impl fmt::Display for FormId {
     }
 }
 
-// As of time of writing, the submission ID isn't used anywhere. It exists only for
-// future-proofing.
+// Organizers use the submission ID to replace a submission with one re-encrypted under a new
+// public primary key.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(transparent)]
 pub struct SubmissionId(RandomId);
@@ -79,6 +85,12 @@ impl Default for SubmissionId {
     }
 }
 
+impl fmt::Display for SubmissionId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0 .0)
+    }
+}
+
 //
 // The Client Key ID is implemented as an auto-incrementing integer. It increments independently
 // for each form, meaning that:
@@ -118,13 +130,42 @@ impl Serialize for ClientKeyId {
 }
 
 // The client ID is stored in the database as an integer, so we need to be able to deserialize it
-// from an integer.
+// from an integer. Clients send it back to us in the same format we serialize it in, which is a
+// string.
 impl<'de> Deserialize<'de> for ClientKeyId {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: serde::Deserializer<'de>,
     {
-        Ok(Self(u64::deserialize(deserializer)?))
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Integer(u64),
+            String(String),
+        }
+
+        match Repr::deserialize(deserializer)? {
+            Repr::Integer(id) => Ok(Self(id)),
+            Repr::String(s) => s.parse().map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+// Each time the primary key for a form is rotated, its key epoch is incremented. Submissions are
+// tagged with the key epoch of the public primary key they were encrypted with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct KeyEpoch(u64);
+
+impl KeyEpoch {
+    pub fn next(self) -> Self {
+        Self(self.0 + 1)
+    }
+}
+
+impl fmt::Display for KeyEpoch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
     }
 }
 
@@ -158,6 +199,152 @@ impl fmt::Display for ServerKeyId {
     }
 }
 
+// Identifies a long-lived server signing key. This is distinct from a `ServerKeyId`, which
+// identifies an ephemeral server key and the session it belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ServerSigningKeyId(Uuid);
+
+impl ServerSigningKeyId {
+    pub fn new() -> Self {
+        Self(Uuid::new_v4())
+    }
+}
+
+impl Default for ServerSigningKeyId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FromStr for ServerSigningKeyId {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self(s.parse()?))
+    }
+}
+
+impl fmt::Display for ServerSigningKeyId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct WebhookId(Uuid);
+
+impl WebhookId {
+    pub fn new() -> Self {
+        Self(Uuid::new_v4())
+    }
+}
+
+impl Default for WebhookId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for WebhookId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+// Identifies a webhook event, so receivers can deduplicate events which are delivered more than
+// once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct WebhookEventId(Uuid);
+
+impl WebhookEventId {
+    pub fn new() -> Self {
+        Self(Uuid::new_v4())
+    }
+}
+
+impl Default for WebhookEventId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for WebhookEventId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct PushSubscriptionId(Uuid);
+
+impl PushSubscriptionId {
+    pub fn new() -> Self {
+        Self(Uuid::new_v4())
+    }
+}
+
+impl Default for PushSubscriptionId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for PushSubscriptionId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+// Until its submission is made, an attachment's ID is the only thing needed to upload its chunks,
+// so unlike some other IDs, it needs to be unguessable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct AttachmentId(Uuid);
+
+impl AttachmentId {
+    pub fn new() -> Self {
+        Self(Uuid::new_v4())
+    }
+}
+
+impl Default for AttachmentId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for AttachmentId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ReplyId(Uuid);
+
+impl ReplyId {
+    pub fn new() -> Self {
+        Self(Uuid::new_v4())
+    }
+}
+
+impl Default for ReplyId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for ReplyId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(transparent)]
 pub struct ChallengeId(Uuid);
@@ -185,12 +372,47 @@ impl fmt::Display for ChallengeId {
 #[serde(transparent)]
 pub struct EncryptedSubmissionBody(String);
 
+impl EncryptedSubmissionBody {
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        self.0.as_bytes()
+    }
+
+    // The base64-encoded SHA-256 hash of the ciphertext, which the respondent can compute from
+    // what they sent. This is what receipts and form logs record instead of the ciphertext.
+    pub fn hash(&self) -> String {
+        BASE64_STANDARD.encode(Sha256::digest(self.as_bytes()))
+    }
+}
+
 impl From<String> for EncryptedSubmissionBody {
     fn from(s: String) -> Self {
         Self(s)
     }
 }
 
+// This is opaque to the server, so no need to decode it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct EncryptedReplyBody(String);
+
+// The details of an audit event, which the server encrypts with the form's public primary key so
+// only the organizers can read them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct EncryptedAuditDetails(String);
+
+impl EncryptedAuditDetails {
+    pub fn seal(key: &PublicPrimaryKey, details: &impl Serialize) -> anyhow::Result<Self> {
+        let ciphertext = key.seal(&serde_json::to_vec(details)?)?;
+
+        Ok(Self(BASE64_STANDARD.encode(ciphertext)))
+    }
+}
+
 // This is opaque to the server, so no need to decode it.
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(transparent)]
@@ -222,21 +444,157 @@ pub struct FormTemplate {
 pub struct FormData {
     pub template: FormTemplate,
     pub public_primary_key: PublicPrimaryKey,
+    pub key_epoch: KeyEpoch,
     pub expires_at: Option<DateTime<Utc>>,
+    // The limit the form's admins chose, if they chose one.
+    pub max_submission_len: Option<usize>,
+    // Forms created before templates were signed don't have these.
+    pub template_signing_key: Option<PublicSigningKey>,
+    pub template_signature: Option<TemplateSignature>,
+}
+
+#[derive(Debug)]
+pub struct NewForm {
+    pub template: FormTemplate,
+    pub public_primary_key: PublicPrimaryKey,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub max_submission_len: Option<usize>,
+    pub template_signing_key: Option<PublicSigningKey>,
+    pub template_signature: Option<TemplateSignature>,
+}
+
+// Replacing a form's template signature only succeeds if the form still has the signature it had
+// when the new one was checked, so concurrent changes can't leave it signing the wrong template or
+// primary key.
+#[derive(Debug, Clone)]
+pub struct TemplateSignatureChange {
+    pub current: Option<TemplateSignature>,
+    pub new: Option<TemplateSignature>,
 }
 
 #[derive(Debug)]
 pub struct FormUpdate {
     pub template: FormTemplate,
     pub expires_at: Option<DateTime<Utc>>,
+    // If this is `None`, the form keeps its current limit.
+    pub max_submission_len: Option<usize>,
 }
 
 #[derive(Debug)]
 pub struct Submission {
+    pub id: SubmissionId,
     pub encrypted_body: EncryptedSubmissionBody,
+    pub key_epoch: KeyEpoch,
+    pub created_at: DateTime<Utc>,
+    // How many times the respondent has amended the submission.
+    pub revision: u32,
+    pub attachments: Vec<Attachment>,
+    // Present if the respondent can receive replies.
+    pub reply_public_key: Option<ReplyPublicKey>,
+}
+
+// The keys a respondent can optionally send along with a submission, which let them interact with
+// it after it's been made.
+#[derive(Debug, Clone, Default)]
+pub struct RespondentKeys {
+    // Lets the respondent withdraw or amend the submission.
+    pub receipt_public_key: Option<PublicSigningKey>,
+    // Lets organizers encrypt replies to the respondent, which they can read from the mailbox
+    // this token hash is for.
+    pub reply_public_key: Option<ReplyPublicKey>,
+    pub mailbox_token_hash: Option<MailboxTokenHash>,
+}
+
+// The public signing key a respondent chose when making a submission, which they can use to
+// withdraw or amend it, along with its current revision.
+#[derive(Debug, Clone)]
+pub struct ReceiptKey {
+    pub public_key: PublicSigningKey,
+    pub revision: u32,
+}
+
+// An entry in a form's log. The entry is the leaf data the client hashes to check it against the
+// log's tree head.
+#[derive(Debug, Clone)]
+pub struct LogEntry {
+    pub index: u64,
+    pub entry: String,
+    pub created_at: DateTime<Utc>,
+}
+
+// The changes admins can make to a form, which are recorded in its audit log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    FormEdited,
+    PrimaryKeyRotated,
+    SubmissionReplaced,
+    KeyAdded,
+    KeyUpdated,
+    KeyRevoked,
+    SessionsEnded,
+    PasswordParamsSet,
+    WebhookAdded,
+    WebhookDeleted,
+}
+
+// An entry in a form's audit log. The details are encrypted with the public primary key of
+// `key_epoch`, which may not be the current one.
+#[derive(Debug, Clone)]
+pub struct AuditEvent {
+    pub index: u64,
+    pub actor: ClientKeyId,
+    pub action: AuditAction,
+    pub key_epoch: KeyEpoch,
+    pub encrypted_details: EncryptedAuditDetails,
+    pub created_at: DateTime<Utc>,
+}
+
+// An audit event for a change an admin is about to make, which is recorded in the same transaction
+// as the change.
+#[derive(Debug, Clone)]
+pub struct NewAuditEvent {
+    pub actor: ClientKeyId,
+    pub action: AuditAction,
+    pub key_epoch: KeyEpoch,
+    pub encrypted_details: EncryptedAuditDetails,
+}
+
+impl NewAuditEvent {
+    // Encrypt the details with the public primary key of `key_epoch`.
+    pub fn seal(
+        actor: ClientKeyId,
+        action: AuditAction,
+        key_epoch: KeyEpoch,
+        key: &PublicPrimaryKey,
+        details: &impl Serialize,
+    ) -> anyhow::Result<Self> {
+        Ok(Self {
+            actor,
+            action,
+            key_epoch,
+            encrypted_details: EncryptedAuditDetails::seal(key, details)?,
+        })
+    }
+}
+
+// A reply from the organizers, encrypted to the respondent's reply public key.
+#[derive(Debug, Clone)]
+pub struct Reply {
+    pub id: ReplyId,
+    pub encrypted_body: EncryptedReplyBody,
     pub created_at: DateTime<Utc>,
 }
 
+// The chunks of an attachment are encrypted separately by the client, so they can be downloaded
+// and decrypted one at a time.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Attachment {
+    pub attachment_id: AttachmentId,
+    pub chunk_count: u32,
+    pub len: u64,
+}
+
 #[derive(Debug)]
 pub struct ClientKeys {
     pub id: ClientKeyId,
@@ -248,6 +606,22 @@ pub struct ClientKeys {
     pub accessed_at: Option<DateTime<Utc>>,
 }
 
+// A session is identified by the server key ID of the ephemeral server key its access token was
+// signed with.
+#[derive(Debug)]
+pub struct Session {
+    pub id: ServerKeyId,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+pub struct ServerSigningKeyPair {
+    pub id: ServerSigningKeyId,
+    pub signing_key: ServerSigningKey,
+    pub created_at: DateTime<Utc>,
+}
+
 #[derive(Debug)]
 pub struct SecretLinkPasswordParams {
     pub salt: SecretLinkPasswordSalt,
@@ -261,3 +635,75 @@ pub struct OrgRole {
     pub name: String,
     pub details: Vec<String>,
 }
+
+// The length of the periods that submissions are counted over in form statistics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StatsPeriod {
+    #[default]
+    Day,
+    // Weeks start on Monday.
+    Week,
+}
+
+#[derive(Debug)]
+pub struct SubmissionCount {
+    pub period_start: NaiveDate,
+    pub count: u64,
+}
+
+// Statistics about a form which can be computed without decrypting anything. Periods without any
+// submissions are omitted.
+#[derive(Debug)]
+pub struct FormStats {
+    pub submission_count: u64,
+    pub newest_submission_at: Option<DateTime<Utc>>,
+    pub submissions_per_period: Vec<SubmissionCount>,
+    pub key_count: u64,
+    pub access_count: u64,
+    pub last_accessed_at: Option<DateTime<Utc>>,
+}
+
+// The URL is where we send events, not a secret, but it can identify the organizers, so it's only
+// visible to admins.
+#[derive(Debug)]
+pub struct Webhook {
+    pub id: WebhookId,
+    pub url: String,
+    pub include_ciphertext: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+pub struct NewWebhook {
+    pub id: WebhookId,
+    pub url: String,
+    pub secret: WebhookSecret,
+    pub include_ciphertext: bool,
+}
+
+#[derive(Debug)]
+pub struct WebhookTarget {
+    pub url: String,
+    pub secret: WebhookSecret,
+}
+
+// The endpoint is a capability URL issued by the browser's push service, so anyone who knows it
+// and the keys can send notifications to the organizer.
+#[derive(Debug)]
+pub struct PushSubscription {
+    pub endpoint: String,
+    pub p256dh: PushSubscriptionKey,
+    pub auth: PushSubscriptionAuth,
+}
+
+// A secret link which has opted in to the daily email digest and has new submissions to tell its
+// organizer about.
+#[derive(Debug)]
+pub struct PendingDigest {
+    pub form_id: FormId,
+    pub client_key_id: ClientKeyId,
+    pub origin: String,
+    pub encrypted_email: EncryptedEmailAddress,
+    pub new_submission_count: u64,
+}
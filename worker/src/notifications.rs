@@ -0,0 +1,194 @@
+use std::fmt;
+
+use anyhow::anyhow;
+use chrono::DateTime;
+use serde::{Deserialize, Serialize};
+use worker::{
+    async_trait::async_trait, send::SendFuture, Message, MessageBatch, MessageBuilder, MessageExt,
+    Queue,
+};
+
+use crate::{
+    models::{EncryptedSubmissionBody, FormId, SubmissionId},
+    push::{self, PushDelivery},
+    runtime,
+    store::Store,
+    webhooks::{self, WebhookDelivery},
+};
+
+//
+// Webhook events and push notifications are sent from the consumer of a single queue, so a slow or
+// unavailable receiver doesn't hold up the request that triggered them. If a delivery fails, we
+// queue it again with an exponentially increasing delay, up to a maximum number of attempts.
+//
+
+const MAX_ATTEMPTS: u32 = 8;
+
+const INITIAL_RETRY_DELAY_SECS: u32 = 30;
+
+// The longest delay that Cloudflare Queues supports.
+const MAX_RETRY_DELAY_SECS: u32 = 12 * 60 * 60;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Notification {
+    Webhook(WebhookDelivery),
+    Push(PushDelivery),
+}
+
+impl fmt::Display for Notification {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Webhook(delivery) => write!(f, "webhook event {}", delivery.event.id),
+            Self::Push(delivery) => write!(
+                f,
+                "push notification for subscription {}",
+                delivery.subscription_id
+            ),
+        }
+    }
+}
+
+// The message we put on the queue for each attempt to deliver a notification.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueuedNotification {
+    pub notification: Notification,
+    pub attempt: u32,
+}
+
+// Where requests put notifications for the consumer to deliver. In the worker this is the
+// Cloudflare Queue; tests can collect the notifications instead.
+#[async_trait]
+pub trait NotificationQueue: Send + Sync {
+    async fn enqueue(&self, notifications: Vec<QueuedNotification>) -> anyhow::Result<()>;
+}
+
+#[async_trait]
+impl NotificationQueue for Queue {
+    async fn enqueue(&self, notifications: Vec<QueuedNotification>) -> anyhow::Result<()> {
+        SendFuture::new(self.send_batch(notifications)).await?;
+        Ok(())
+    }
+}
+
+// The delay before the given attempt, where the first attempt is zero.
+fn retry_delay_secs(attempt: u32) -> u32 {
+    INITIAL_RETRY_DELAY_SECS
+        .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+        .min(MAX_RETRY_DELAY_SECS)
+}
+
+// Queue a webhook event and push notification for everyone who should hear about a submission.
+#[worker::send]
+pub async fn enqueue_submission_created(
+    store: &Store,
+    queue: &dyn NotificationQueue,
+    form_id: &FormId,
+    submission_id: &SubmissionId,
+    encrypted_body: &EncryptedSubmissionBody,
+) -> anyhow::Result<()> {
+    let created_at = DateTime::from_timestamp_millis(runtime::now_millis() as i64)
+        .ok_or_else(|| anyhow!("Current time is out of range."))?
+        .to_rfc3339();
+
+    let webhook_deliveries =
+        webhooks::submission_created(store, form_id, submission_id, encrypted_body, &created_at)
+            .await?;
+
+    let push_deliveries = push::submission_created(store, form_id).await?;
+
+    let notifications = webhook_deliveries
+        .into_iter()
+        .map(Notification::Webhook)
+        .chain(push_deliveries.into_iter().map(Notification::Push))
+        .map(|notification| QueuedNotification {
+            notification,
+            attempt: 0,
+        })
+        .collect::<Vec<_>>();
+
+    if notifications.is_empty() {
+        return Ok(());
+    }
+
+    queue.enqueue(notifications).await?;
+
+    Ok(())
+}
+
+async fn deliver(store: &Store, notification: &Notification) -> anyhow::Result<()> {
+    match notification {
+        Notification::Webhook(delivery) => webhooks::deliver(store, delivery).await,
+        Notification::Push(delivery) => push::deliver(store, delivery).await,
+    }
+}
+
+// Try to deliver a notification. If it fails and we haven't run out of attempts, this returns the
+// next attempt, which should be queued with the returned delay in seconds.
+pub async fn attempt_delivery(
+    store: &Store,
+    queued: &QueuedNotification,
+) -> Option<(QueuedNotification, u32)> {
+    let err = match deliver(store, &queued.notification).await {
+        Ok(()) => return None,
+        Err(err) => err,
+    };
+
+    let next_attempt = queued.attempt + 1;
+
+    if next_attempt >= MAX_ATTEMPTS {
+        runtime::log_error(&format!(
+            "Error: Giving up on {} after {} attempts: {}",
+            queued.notification, next_attempt, err
+        ));
+        return None;
+    }
+
+    runtime::log_error(&format!(
+        "Error: Delivering {} failed on attempt {}: {}",
+        queued.notification, next_attempt, err
+    ));
+
+    let retry = QueuedNotification {
+        notification: queued.notification.clone(),
+        attempt: next_attempt,
+    };
+
+    Some((retry, retry_delay_secs(next_attempt)))
+}
+
+async fn handle_message(store: &Store, queue: &Queue, message: &Message<QueuedNotification>) {
+    let (retry, delay_secs) = match attempt_delivery(store, message.body()).await {
+        Some(retry) => retry,
+        None => {
+            message.ack();
+            return;
+        }
+    };
+
+    let retry = MessageBuilder::new(retry).delay_seconds(delay_secs).build();
+
+    // If we can't queue the next attempt, fall back to the queue's own retries.
+    match queue.send(retry).await {
+        Ok(()) => message.ack(),
+        Err(_) => message.retry(),
+    }
+}
+
+pub async fn handle_batch(store: &Store, queue: &Queue, batch: MessageBatch<QueuedNotification>) {
+    let messages = match batch.messages() {
+        Ok(messages) => messages,
+        Err(err) => {
+            runtime::log_error(&format!(
+                "Error: Could not decode queued notifications: {}",
+                err
+            ));
+            batch.retry_all();
+            return;
+        }
+    };
+
+    for message in &messages {
+        handle_message(store, queue, message).await;
+    }
+}
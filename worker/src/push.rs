@@ -0,0 +1,203 @@
+use aes_gcm::{aead::Aead, Aes128Gcm, KeyInit, Nonce};
+use anyhow::anyhow;
+use base64::prelude::*;
+use hkdf::Hkdf;
+use p256::{ecdh::EphemeralSecret, elliptic_curve::sec1::ToEncodedPoint};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::Sha256;
+use worker::Url;
+
+use crate::{
+    config,
+    keys::VapidSigningKey,
+    models::{FormId, PushSubscription, PushSubscriptionId},
+    runtime,
+    store::Store,
+};
+
+//
+// Organizers can subscribe a browser to Web Push notifications for their secret link. When a
+// submission is made, we queue a notification for each subscription to the form. The queue
+// consumer encrypts the message for the subscription as described in RFC 8291 and sends it to the
+// subscription's push service, identifying ourselves with a VAPID token as described in RFC 8292.
+//
+// The message never contains anything about the form or the submission, since it passes through
+// the browser vendor's push service.
+//
+
+pub const PUSH_MESSAGE: &str = "New response";
+
+// How long the push service should hold the message if the browser is offline.
+const PUSH_TTL_SECS: u32 = 24 * 60 * 60;
+
+// The most RFC 8292 allows.
+const VAPID_TOKEN_EXP_SECS: u64 = 12 * 60 * 60;
+
+// We always send a single record, so this only needs to be larger than the message.
+const RECORD_SIZE: u32 = 4096;
+
+const SALT_LEN: usize = 16;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PushDelivery {
+    pub subscription_id: PushSubscriptionId,
+}
+
+// Push services are always served over HTTPS.
+pub fn validate_endpoint(endpoint: &str) -> anyhow::Result<()> {
+    let url = Url::parse(endpoint).map_err(|err| anyhow!("Push endpoint is invalid: {}", err))?;
+
+    match url.scheme() {
+        "https" => Ok(()),
+        scheme => Err(anyhow!("Push endpoint must use HTTPS, got `{}`.", scheme)),
+    }
+}
+
+fn unix_timestamp() -> u64 {
+    runtime::now_millis() / 1000
+}
+
+// We build the JWT by hand rather than with `jsonwebtoken`, which would need to generate
+// randomness for ES256 signatures in a way that doesn't work in Workers. RFC 6979 signatures are
+// deterministic.
+fn vapid_authorization(key: &VapidSigningKey, endpoint: &str) -> anyhow::Result<String> {
+    let url = Url::parse(endpoint)?;
+
+    let header = json!({ "typ": "JWT", "alg": "ES256" });
+    let claims = json!({
+        "aud": url.origin().ascii_serialization(),
+        "exp": unix_timestamp() + VAPID_TOKEN_EXP_SECS,
+        "sub": config::default_tenant().origin,
+    });
+
+    let signing_input = format!(
+        "{}.{}",
+        BASE64_URL_SAFE_NO_PAD.encode(serde_json::to_vec(&header)?),
+        BASE64_URL_SAFE_NO_PAD.encode(serde_json::to_vec(&claims)?),
+    );
+
+    let signature = BASE64_URL_SAFE_NO_PAD.encode(key.sign(signing_input.as_bytes()));
+
+    Ok(format!(
+        "vapid t={}.{}, k={}",
+        signing_input,
+        signature,
+        key.public_key()
+    ))
+}
+
+// Encrypt the message with the `aes128gcm` content coding, as described in RFC 8291.
+fn encrypt(subscription: &PushSubscription, message: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut rng = rand::thread_rng();
+
+    let as_secret = EphemeralSecret::random(&mut rng);
+    let as_public = as_secret.public_key().to_encoded_point(false);
+    let ua_public = subscription.p256dh.to_bytes();
+
+    let ecdh_secret = as_secret.diffie_hellman(subscription.p256dh.public_key());
+
+    let mut key_info = b"WebPush: info\0".to_vec();
+    key_info.extend_from_slice(&ua_public);
+    key_info.extend_from_slice(as_public.as_bytes());
+
+    let mut ikm = [0u8; 32];
+    Hkdf::<Sha256>::new(
+        Some(subscription.auth.as_bytes()),
+        ecdh_secret.raw_secret_bytes(),
+    )
+    .expand(&key_info, &mut ikm)
+    .map_err(|err| anyhow!("Could not derive push IKM: {}", err))?;
+
+    let mut salt = [0u8; SALT_LEN];
+    rng.fill_bytes(&mut salt);
+
+    let prk = Hkdf::<Sha256>::new(Some(&salt), &ikm);
+
+    let mut cek = [0u8; 16];
+    prk.expand(b"Content-Encoding: aes128gcm\0", &mut cek)
+        .map_err(|err| anyhow!("Could not derive push content encryption key: {}", err))?;
+
+    let mut nonce = [0u8; 12];
+    prk.expand(b"Content-Encoding: nonce\0", &mut nonce)
+        .map_err(|err| anyhow!("Could not derive push nonce: {}", err))?;
+
+    // The delimiter marks this as the last (and only) record.
+    let mut plaintext = message.to_vec();
+    plaintext.push(0x02);
+
+    let ciphertext = Aes128Gcm::new_from_slice(&cek)
+        .map_err(|err| anyhow!("Invalid push content encryption key: {}", err))?
+        .encrypt(Nonce::from_slice(&nonce), plaintext.as_slice())
+        .map_err(|err| anyhow!("Could not encrypt push message: {}", err))?;
+
+    let mut body = Vec::with_capacity(SALT_LEN + 5 + as_public.len() + ciphertext.len());
+    body.extend_from_slice(&salt);
+    body.extend_from_slice(&RECORD_SIZE.to_be_bytes());
+    body.push(as_public.len() as u8);
+    body.extend_from_slice(as_public.as_bytes());
+    body.extend_from_slice(&ciphertext);
+
+    Ok(body)
+}
+
+// A notification for each push subscription to the form, unless push notifications are disabled.
+pub async fn submission_created(
+    store: &Store,
+    form_id: &FormId,
+) -> anyhow::Result<Vec<PushDelivery>> {
+    if config::vapid_signing_key().is_none() {
+        return Ok(Vec::new());
+    }
+
+    Ok(store
+        .list_push_subscription_ids(form_id)
+        .await?
+        .into_iter()
+        .map(|subscription_id| PushDelivery { subscription_id })
+        .collect())
+}
+
+pub async fn deliver(store: &Store, delivery: &PushDelivery) -> anyhow::Result<()> {
+    let key = match config::vapid_signing_key() {
+        Some(key) => key,
+        None => return Ok(()),
+    };
+
+    let subscription = match store
+        .get_push_subscription(&delivery.subscription_id)
+        .await?
+    {
+        Some(subscription) => subscription,
+        None => return Ok(()),
+    };
+
+    let body = encrypt(&subscription, PUSH_MESSAGE.as_bytes())?;
+
+    let authorization = vapid_authorization(&key, &subscription.endpoint)?;
+
+    let status = runtime::post(
+        &subscription.endpoint,
+        &[
+            ("Content-Type", "application/octet-stream"),
+            ("Content-Encoding", "aes128gcm"),
+            ("TTL", &PUSH_TTL_SECS.to_string()),
+            ("Urgency", "normal"),
+            ("Authorization", &authorization),
+        ],
+        body,
+    )
+    .await?;
+
+    match status {
+        200..=299 => Ok(()),
+        // The subscription has expired or the user has unsubscribed.
+        404 | 410 => {
+            store
+                .delete_push_subscription(&delivery.subscription_id)
+                .await
+        }
+        status => Err(anyhow!("Push service responded with status {}.", status)),
+    }
+}
@@ -0,0 +1,116 @@
+use jsonwebtoken as jwt;
+use serde::Serialize;
+
+use crate::{
+    config::Tenant,
+    keys::{PublicSigningKey, ReceiptSignature},
+    models::{EncryptedSubmissionBody, FormId, SubmissionId},
+    runtime,
+    signing::TokenSigningKey,
+    store::Store,
+};
+
+//
+// When a respondent makes or amends a submission, we give them a receipt: a JWT signed with the
+// current server signing key which records when we received the submission and a hash of its
+// encrypted body. It can be checked against the keys published at `/.well-known/jwks.json`, so the
+// respondent can prove when they submitted without trusting us to remember.
+//
+// A respondent who also sent a public signing key with their submission can later withdraw or
+// amend it by signing the request with the matching private key. The submission's revision is
+// part of the signed message and changes with every amendment, so a signed request can't be
+// replayed once it's been used.
+//
+
+const RECEIPT_TOKEN_TYPE: &str = "receipt";
+
+// Keeps a signature over one of these messages from being valid for anything else the respondent
+// might sign with the same key.
+const SIGNED_MESSAGE_PREFIX: &str = "notwithouthelp-receipt";
+
+#[derive(Debug, Serialize)]
+struct ReceiptClaims<'a> {
+    #[serde(rename = "type")]
+    token_type: &'static str,
+    iss: &'a str,
+    sub: &'a SubmissionId,
+    form_id: &'a FormId,
+    revision: u32,
+    body_hash: String,
+    iat: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(transparent)]
+pub struct SignedReceipt(String);
+
+pub async fn issue(
+    store: &Store,
+    tenant: &Tenant,
+    form_id: &FormId,
+    submission_id: &SubmissionId,
+    revision: u32,
+    body: &EncryptedSubmissionBody,
+) -> anyhow::Result<SignedReceipt> {
+    let key = TokenSigningKey::for_publication(store).await?;
+
+    let claims = ReceiptClaims {
+        token_type: RECEIPT_TOKEN_TYPE,
+        iss: &tenant.origin,
+        sub: submission_id,
+        form_id,
+        revision,
+        body_hash: body.hash(),
+        iat: runtime::now_millis() / 1000,
+    };
+
+    Ok(SignedReceipt(jwt::encode(
+        &key.header(),
+        &claims,
+        &key.encoding_key()?,
+    )?))
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum ReceiptAction<'a> {
+    Withdraw,
+    Amend(&'a EncryptedSubmissionBody),
+}
+
+// The message the respondent signs to change a submission at the given revision.
+fn signed_message(
+    form_id: &FormId,
+    submission_id: &SubmissionId,
+    revision: u32,
+    action: ReceiptAction,
+) -> String {
+    match action {
+        ReceiptAction::Withdraw => format!(
+            "{}:withdraw:{}:{}:{}",
+            SIGNED_MESSAGE_PREFIX, form_id, submission_id, revision
+        ),
+        ReceiptAction::Amend(body) => format!(
+            "{}:amend:{}:{}:{}:{}",
+            SIGNED_MESSAGE_PREFIX,
+            form_id,
+            submission_id,
+            revision,
+            body.hash()
+        ),
+    }
+}
+
+pub fn verify(
+    public_key: &PublicSigningKey,
+    form_id: &FormId,
+    submission_id: &SubmissionId,
+    revision: u32,
+    action: ReceiptAction,
+    signature: &ReceiptSignature,
+) -> bool {
+    let message = signed_message(form_id, submission_id, revision, action);
+
+    public_key
+        .verify_receipt(message.as_bytes(), signature)
+        .is_ok()
+}
@@ -1,87 +1,457 @@
-use std::sync::Arc;
+use std::{convert::Infallible, sync::Arc};
 
 use anyhow::anyhow;
 use axum::{
-    extract::{DefaultBodyLimit, Extension, Json, Path, State},
-    http::StatusCode,
-    response::{ErrorResponse, NoContent},
-    routing::{delete, get, patch, post},
+    body::Bytes,
+    extract::{DefaultBodyLimit, Extension, Json, Path, Query, Request, State},
+    http::{
+        header::{AUTHORIZATION, CONTENT_TYPE},
+        HeaderMap, HeaderName, Method, StatusCode,
+    },
+    middleware::{self, Next},
+    response::{ErrorResponse, NoContent, Response},
+    routing::{delete, get, patch, post, put},
     Router,
 };
 use chrono::DateTime;
-use worker::console_error;
+use serde_json::json;
 
 use crate::{
     api::{
-        GetApiChallengeResponse, GetFormResponse, GetKeyResponse, GetPasswordResponse,
-        ListKeysResponse, ListSubmissionsResponse, PatchFormRequest, PatchKeyRequest,
-        PostFormRequest, PostFormResponse, PostKeyRequest, PostKeyResponse, PostPasswordRequest,
-        PostSubmissionRequest, PostTokenRequest, PostTokenResponse,
+        ComponentHealth, DeleteReceiptRequest, GetApiChallengeResponse, GetConsistencyProofQuery,
+        GetConsistencyProofResponse, GetFormResponse, GetFormStatsQuery, GetFormStatsResponse,
+        GetHealthResponse, GetInclusionProofQuery, GetInclusionProofResponse, GetJwksResponse,
+        GetKeyResponse, GetLogResponse, GetMailboxResponse, GetPasswordResponse,
+        GetVapidKeyResponse, Jwk, ListAuditEventsQuery, ListAuditEventsResponse, ListKeysResponse,
+        ListLogEntriesQuery, ListLogEntriesResponse, ListSessionsResponse, ListSubmissionsResponse,
+        ListWebhooksResponse, PatchFormRequest, PatchKeyRequest, PostAttachmentResponse,
+        PostDigestSubscriptionRequest, PostFormRequest, PostFormResponse, PostKeyRequest,
+        PostKeyResponse, PostPasswordRequest, PostPrimaryKeyRequest, PostPrimaryKeyResponse,
+        PostPushSubscriptionRequest, PostRefreshTokenRequest, PostReplyRequest, PostReplyResponse,
+        PostSubmissionRequest, PostSubmissionResponse, PostTokenRequest, PostTokenResponse,
+        PostWebhookRequest, PostWebhookResponse, PutReceiptRequest, PutReceiptResponse,
+        PutSubmissionRequest,
     },
     auth::{
         auth_layer, AccessRole, ApiChallenge, ApiChallengeResponse, AuthError, AuthErrorType,
-        SignedApiAccessToken,
+        SignedApiAccessToken, ValidatedRefreshToken,
     },
-    config,
+    config::{self, Tenant},
     cors::cors_layer,
-    keys::{ApiChallengeNonce, EphemeralServerKey},
+    digests,
+    idempotency::idempotency_layer,
+    keys::{
+        ApiChallengeNonce, MailboxToken, PublicPrimaryKey, PublicSigningKey, RefreshToken,
+        TemplateSignature, WebhookSecret,
+    },
+    logging::{log_requests, record_route, ErrorCode, LoggedError, RequestLog},
+    metrics::{is_operator_token, record_metrics, render_metrics},
     models::{
-        ChallengeId, ClientKeyId, EncryptedKeyComment, FormId, FormTemplate, FormUpdate,
-        ServerKeyId, SubmissionId,
+        AttachmentId, AuditAction, ChallengeId, ClientKeyId, FormId, FormTemplate, FormUpdate,
+        NewAuditEvent, NewForm, NewWebhook, PushSubscriptionId, ReplyId, RespondentKeys,
+        ServerKeyId, SubmissionId, TemplateSignatureChange, WebhookId,
     },
-    store::{UnauthenticatedStore, FORM_TEMPLATE_CURRENT_VERSION},
+    notifications::{self, NotificationQueue},
+    push,
+    receipts::{self, ReceiptAction},
+    signing::TokenSigningKey,
+    store::{
+        ChunkOutcome, KeyChangeOutcome, ReplyOutcome, Store, UnauthenticatedStore,
+        FORM_TEMPLATE_CURRENT_VERSION, SCHEMA_VERSION,
+    },
+    templates, transparency,
+    webhooks::{self, MAX_WEBHOOKS_PER_FORM},
 };
 
+const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+const ATTACHMENT_CHUNK_CONTENT_TYPE: &str = "application/octet-stream";
+
+// Clients split attachments into encrypted chunks no longer than this, so that no single request
+// has to carry a whole file.
+const MAX_ATTACHMENT_CHUNK_LEN: usize = 1024 * 1024;
+
+// Requests to the authentication endpoints only contain a challenge and a signature, or a refresh
+// token, so they get a much smaller limit than everything else.
+const MAX_AUTH_REQUEST_BODY_LEN: usize = 2048;
+
+// Room for the fields of a submission request other than the encrypted body.
+const SUBMISSION_REQUEST_OVERHEAD_LEN: usize = 1024;
+
+// Replies are meant for a short conversation with the respondent, not as a general-purpose
+// messaging channel.
+const MAX_REPLIES_PER_SUBMISSION: u32 = 50;
+
+const DEFAULT_AUDIT_PAGE_LEN: u64 = 50;
+
+const MAX_AUDIT_PAGE_LEN: u64 = 200;
+
 fn internal_err(err: anyhow::Error) -> ErrorResponse {
-    console_error!("Error: {}", err);
-    StatusCode::INTERNAL_SERVER_ERROR.into()
+    LoggedError::new(ErrorCode::Internal, err).into_response(StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+fn unauthorized_err(err: anyhow::Error) -> ErrorResponse {
+    LoggedError::new(ErrorCode::Unauthorized, err).into_response(StatusCode::UNAUTHORIZED)
 }
 
 fn auth_err(err: AuthError) -> ErrorResponse {
-    console_error!("Error: {}", err);
     match err.kind() {
-        AuthErrorType::Unauthorized => StatusCode::UNAUTHORIZED.into(),
-        AuthErrorType::Forbidden => StatusCode::FORBIDDEN.into(),
+        AuthErrorType::Unauthorized => {
+            LoggedError::new(ErrorCode::Unauthorized, err).into_response(StatusCode::UNAUTHORIZED)
+        }
+        AuthErrorType::Forbidden => {
+            LoggedError::new(ErrorCode::Forbidden, err).into_response(StatusCode::FORBIDDEN)
+        }
+    }
+}
+
+fn submission_limit_err() -> ErrorResponse {
+    let bounds = config::max_submission_len_bounds();
+
+    LoggedError::new(
+        ErrorCode::InvalidSubmissionLimit,
+        format!(
+            "The submission length limit must be between {} and {} bytes.",
+            bounds.start(),
+            bounds.end()
+        ),
+    )
+    .into_response(StatusCode::BAD_REQUEST)
+}
+
+fn is_valid_submission_limit(max_submission_len: Option<usize>) -> bool {
+    max_submission_len.map_or(true, |len| {
+        config::max_submission_len_bounds().contains(&len)
+    })
+}
+
+// The body limit for requests containing a submission, which must allow for the largest limit any
+// form could have. The form's own limit is checked by the handler.
+fn submission_body_limit() -> DefaultBodyLimit {
+    DefaultBodyLimit::max(
+        *config::max_submission_len_bounds().end() + SUBMISSION_REQUEST_OVERHEAD_LEN,
+    )
+}
+
+fn submission_too_long_err(max_submission_len: usize) -> ErrorResponse {
+    LoggedError::new(
+        ErrorCode::RequestTooLarge,
+        format!(
+            "The submission is longer than this form's limit of {} bytes.",
+            max_submission_len
+        ),
+    )
+    .into_response(StatusCode::PAYLOAD_TOO_LARGE)
+}
+
+fn invalid_receipt_signature_err() -> ErrorResponse {
+    LoggedError::new(
+        ErrorCode::InvalidReceiptSignature,
+        "The signature does not match the submission's receipt key.",
+    )
+    .into_response(StatusCode::FORBIDDEN)
+}
+
+fn stale_receipt_revision_err() -> ErrorResponse {
+    LoggedError::new(
+        ErrorCode::StaleReceiptRevision,
+        "The submission has been amended since this request was signed.",
+    )
+    .into_response(StatusCode::CONFLICT)
+}
+
+// A form created without a template signing key can't be given a signature later, and a form
+// created with one must always have a valid signature.
+fn is_valid_template_signature(
+    signing_key: Option<&PublicSigningKey>,
+    signature: Option<&TemplateSignature>,
+    template: &FormTemplate,
+    public_primary_key: &PublicPrimaryKey,
+) -> bool {
+    match (signing_key, signature) {
+        (Some(signing_key), Some(signature)) => {
+            templates::verify(signing_key, template, public_primary_key, signature)
+        }
+        (None, None) => true,
+        _ => false,
     }
 }
 
-#[derive(Debug)]
+fn invalid_template_signature_err() -> ErrorResponse {
+    LoggedError::new(
+        ErrorCode::InvalidTemplateSignature,
+        "The template signature is missing or does not match the form's template signing key.",
+    )
+    .into_response(StatusCode::BAD_REQUEST)
+}
+
+fn invalid_log_range_err(message: &'static str) -> ErrorResponse {
+    LoggedError::new(ErrorCode::InvalidLogRange, message).into_response(StatusCode::BAD_REQUEST)
+}
+
+fn proof_too_large_err() -> ErrorResponse {
+    LoggedError::new(
+        ErrorCode::InvalidLogRange,
+        format!(
+            "Proofs are only available for trees of up to {} entries.",
+            transparency::MAX_PROOF_TREE_SIZE
+        ),
+    )
+    .into_response(StatusCode::BAD_REQUEST)
+}
+
+fn audit_event_err(err: anyhow::Error) -> ErrorResponse {
+    LoggedError::new(ErrorCode::AuditEventFailed, err)
+        .into_response(StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+fn last_admin_err() -> ErrorResponse {
+    LoggedError::new(
+        ErrorCode::LastAdmin,
+        "Refusing to remove the last admin key for a form.",
+    )
+    .into_response(StatusCode::CONFLICT)
+}
+
 pub struct AppState {
     pub store: UnauthenticatedStore,
+    pub tenant: Tenant,
+    pub notifications: Arc<dyn NotificationQueue>,
 }
 
 pub fn new(state: AppState) -> Router {
+    let cors = cors_layer(&state.tenant);
+    let state = Arc::new(state);
+
     Router::new()
         // AUTHENTICATED ENDPOINTS
         .route("/submissions/:form_id", get(list_form_submissions))
+        .route(
+            "/submissions/:form_id/:submission_id",
+            put(replace_submission).layer(submission_body_limit()),
+        )
         .route("/forms/:form_id", delete(delete_form))
         .route("/forms/:form_id", patch(edit_form))
+        .route("/forms/:form_id/stats", get(get_form_stats))
+        .route("/logs/:form_id", get(get_log))
+        .route("/logs/:form_id/entries", get(list_log_entries))
+        .route("/logs/:form_id/inclusion", get(get_inclusion_proof))
+        .route("/logs/:form_id/consistency", get(get_consistency_proof))
+        .route("/audit/:form_id", get(list_audit_events))
+        .route(
+            "/attachments/:form_id/:attachment_id/:chunk_index",
+            get(get_attachment_chunk),
+        )
+        .route("/primary-keys/:form_id", post(rotate_primary_key))
         .route("/keys/:form_id/:client_key_id", get(get_key))
         .route("/keys/:form_id", get(list_keys))
-        .route("/keys/:form_id", post(add_key))
+        .route(
+            "/keys/:form_id",
+            post(add_key).layer(middleware::from_fn_with_state(
+                Arc::clone(&state),
+                idempotency_layer,
+            )),
+        )
         .route("/keys/:form_id/:client_key_id", patch(update_key))
         .route("/keys/:form_id/:client_key_id", delete(delete_key))
+        .route("/keys/:form_id/:client_key_id/sessions", get(list_sessions))
+        .route(
+            "/keys/:form_id/:client_key_id/sessions",
+            delete(delete_sessions),
+        )
         .route(
             "/passwords/:form_id/:client_key_id",
             post(set_password_params),
         )
+        .route("/replies/:form_id/:submission_id", post(send_reply))
+        .route("/webhooks/:form_id", get(list_webhooks))
+        .route("/webhooks/:form_id", post(add_webhook))
+        .route("/webhooks/:form_id/:webhook_id", delete(delete_webhook))
+        .route("/push/:form_id/:client_key_id", post(add_push_subscription))
+        .route(
+            "/push/:form_id/:client_key_id",
+            delete(delete_push_subscriptions),
+        )
+        .route(
+            "/digests/:form_id/:client_key_id",
+            post(add_digest_subscription),
+        )
+        .route(
+            "/digests/:form_id/:client_key_id",
+            delete(delete_digest_subscription),
+        )
+        .route(
+            "/tokens/revoke",
+            post(revoke_access_token).layer(DefaultBodyLimit::max(MAX_AUTH_REQUEST_BODY_LEN)),
+        )
         .route_layer(auth_layer())
         // UNAUTHENTICATED ENDPOINTS
         .route("/forms/:form_id", get(get_form))
-        .route("/forms", post(publish_form))
-        .route("/submissions/:form_id", post(store_form_submission))
+        .route(
+            "/forms",
+            post(publish_form).layer(middleware::from_fn_with_state(
+                Arc::clone(&state),
+                idempotency_layer,
+            )),
+        )
+        .route(
+            "/submissions/:form_id",
+            post(store_form_submission)
+                .layer::<_, Infallible>(middleware::from_fn_with_state(
+                    Arc::clone(&state),
+                    idempotency_layer,
+                ))
+                .layer(submission_body_limit()),
+        )
+        .route(
+            "/receipts/:form_id/:submission_id",
+            put(amend_submission).layer(submission_body_limit()),
+        )
+        .route(
+            "/receipts/:form_id/:submission_id",
+            delete(withdraw_submission),
+        )
+        .route("/mailbox", get(get_mailbox))
+        .route("/attachments/:form_id", post(create_attachment))
+        .route(
+            "/attachments/:form_id/:attachment_id/:chunk_index",
+            put(upload_attachment_chunk).layer(DefaultBodyLimit::max(MAX_ATTACHMENT_CHUNK_LEN)),
+        )
         .route(
             "/challenges/:form_id/:client_key_id",
-            post(request_challenge),
+            post(request_challenge).layer(DefaultBodyLimit::max(MAX_AUTH_REQUEST_BODY_LEN)),
+        )
+        .route(
+            "/tokens",
+            post(request_access_token).layer(DefaultBodyLimit::max(MAX_AUTH_REQUEST_BODY_LEN)),
+        )
+        .route("/.well-known/jwks.json", get(get_jwks))
+        .route("/health", get(get_health))
+        .route("/metrics", get(get_metrics))
+        .route("/push/vapid-key", get(get_vapid_key))
+        .route(
+            "/tokens/refresh",
+            post(refresh_access_token).layer(DefaultBodyLimit::max(MAX_AUTH_REQUEST_BODY_LEN)),
         )
-        .route("/tokens", post(request_access_token))
         .route(
             "/passwords/:form_id/:client_key_id",
             get(get_password_params),
         )
-        .layer(cors_layer())
+        .route_layer(middleware::from_fn_with_state(
+            Arc::clone(&state),
+            record_metrics,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            Arc::clone(&state),
+            require_current_schema,
+        ))
+        .route_layer(middleware::from_fn(record_route))
+        .layer(cors)
         .layer(DefaultBodyLimit::max(config::max_request_body_len()))
-        .with_state(Arc::new(state))
+        .layer(middleware::from_fn(log_requests))
+        .with_state(state)
+}
+
+// If the code is deployed before the migrations it depends on are applied, writing to the database
+// could fail partway through or corrupt data, so we refuse until the migrations catch up.
+async fn require_current_schema(
+    State(state): State<Arc<AppState>>,
+    req: Request,
+    next: Next,
+) -> Result<Response, ErrorResponse> {
+    if matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS) {
+        return Ok(next.run(req).await);
+    }
+
+    let store = state.store.without_authenticating();
+
+    if !store.is_schema_current().await.map_err(internal_err)? {
+        return Err(LoggedError::new(
+            ErrorCode::SchemaOutdated,
+            "Refusing to write because the database schema is out of date.",
+        )
+        .into_response(StatusCode::SERVICE_UNAVAILABLE));
+    }
+
+    Ok(next.run(req).await)
+}
+
+#[axum::debug_handler]
+async fn get_health(
+    State(state): State<Arc<AppState>>,
+    Extension(log): Extension<RequestLog>,
+) -> (StatusCode, Json<GetHealthResponse>) {
+    let store = state.store.without_authenticating();
+
+    let applied_schema_version = store.get_schema_version().await;
+
+    let d1 = match &applied_schema_version {
+        Ok(_) => ComponentHealth::Ok,
+        Err(err) => {
+            log.set_error(LoggedError::new(ErrorCode::Unhealthy, err));
+            ComponentHealth::Error
+        }
+    };
+
+    let kv = match store.check_kv().await {
+        Ok(()) => ComponentHealth::Ok,
+        Err(err) => {
+            log.set_error(LoggedError::new(ErrorCode::Unhealthy, err));
+            ComponentHealth::Error
+        }
+    };
+
+    let applied_schema_version = applied_schema_version.ok().flatten();
+
+    let is_healthy = d1 == ComponentHealth::Ok
+        && kv == ComponentHealth::Ok
+        && applied_schema_version.is_some_and(|version| version >= SCHEMA_VERSION);
+
+    let status = if is_healthy {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (
+        status,
+        Json(GetHealthResponse {
+            version: env!("CARGO_PKG_VERSION"),
+            env: config::env(),
+            d1,
+            kv,
+            expected_schema_version: SCHEMA_VERSION,
+            applied_schema_version,
+        }),
+    )
+}
+
+// This is authenticated with the operator token rather than an access token, since it's for the
+// people running the instance rather than organizers.
+#[axum::debug_handler]
+async fn get_metrics(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<([(HeaderName, &'static str); 1], String), ErrorResponse> {
+    let expected_token = config::operator_token().ok_or(StatusCode::NOT_FOUND)?;
+
+    let actual_token = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default();
+
+    if !is_operator_token(&expected_token, actual_token) {
+        return Err(unauthorized_err(anyhow!(
+            "Operator token is missing or incorrect."
+        )));
+    }
+
+    let body = render_metrics(state.store.without_authenticating())
+        .await
+        .map_err(internal_err)?;
+
+    Ok(([(CONTENT_TYPE, PROMETHEUS_CONTENT_TYPE)], body))
 }
 
 #[axum::debug_handler]
@@ -91,6 +461,10 @@ async fn publish_form(
 ) -> Result<(StatusCode, Json<PostFormResponse>), ErrorResponse> {
     let store = state.store.without_authenticating();
 
+    if !is_valid_submission_limit(form.max_submission_len) {
+        return Err(submission_limit_err());
+    }
+
     let template = FormTemplate {
         version: FORM_TEMPLATE_CURRENT_VERSION,
         org_name: form.org_name,
@@ -99,39 +473,36 @@ async fn publish_form(
         roles: form.roles,
     };
 
+    if !is_valid_template_signature(
+        form.template_signing_key.as_ref(),
+        form.template_signature.as_ref(),
+        &template,
+        &form.public_primary_key,
+    ) {
+        return Err(invalid_template_signature_err());
+    }
+
     let form_id = FormId::new();
 
-    store
-        .put_form_template(
-            &form_id,
-            &template,
-            &form.public_primary_key,
-            match form.expires_at {
-                Some(expires_at) => Some(
-                    DateTime::parse_from_rfc3339(&expires_at)
-                        .map_err(|err| internal_err(err.into()))?
-                        .to_utc(),
-                ),
-                None => None,
-            },
-        )
-        .await
-        .map_err(internal_err)?;
+    let new_form = NewForm {
+        template,
+        public_primary_key: form.public_primary_key,
+        expires_at: match form.expires_at {
+            Some(expires_at) => Some(
+                DateTime::parse_from_rfc3339(&expires_at)
+                    .map_err(|err| internal_err(err.into()))?
+                    .to_utc(),
+            ),
+            None => None,
+        },
+        max_submission_len: form.max_submission_len,
+        template_signing_key: form.template_signing_key,
+        template_signature: form.template_signature,
+    };
 
     let client_key_id = store
-        .store_client_keys(
-            &form_id,
-            &form.public_signing_key,
-            None,
-            &EncryptedKeyComment::default(),
-            // The initial secret link will always have admin access.
-            AccessRole::Admin,
-        )
+        .create_form(&form_id, &new_form, &form.public_signing_key)
         .await
-        .map_err(internal_err)?
-        .ok_or_else(|| {
-            anyhow!("Could not find form associated with form ID, even though we just created it.")
-        })
         .map_err(internal_err)?;
 
     let response = PostFormResponse {
@@ -162,23 +533,339 @@ async fn get_form(
 #[axum::debug_handler]
 async fn store_form_submission(
     State(state): State<Arc<AppState>>,
+    Extension(log): Extension<RequestLog>,
     Path(form_id): Path<FormId>,
     Json(body): Json<PostSubmissionRequest>,
-) -> Result<StatusCode, ErrorResponse> {
+) -> Result<(StatusCode, Json<PostSubmissionResponse>), ErrorResponse> {
     let store = state.store.without_authenticating();
 
+    let form_data = store
+        .get_form_data(&form_id)
+        .await
+        .map_err(internal_err)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let max_submission_len = config::max_submission_len(form_data.max_submission_len);
+
+    if body.encrypted_body.len() > max_submission_len {
+        return Err(submission_too_long_err(max_submission_len));
+    }
+
+    // A respondent may have loaded the form just before the primary key was rotated, so older key
+    // epochs are allowed, but there's no key yet for a newer one.
+    if body
+        .key_epoch
+        .is_some_and(|key_epoch| key_epoch > form_data.key_epoch)
+    {
+        return Err(LoggedError::new(
+            ErrorCode::InvalidKeyEpoch,
+            "Submission was encrypted with a key epoch the form doesn't have yet.",
+        )
+        .into_response(StatusCode::BAD_REQUEST));
+    }
+
     let submission_id = SubmissionId::new();
 
+    let mailbox_token = body
+        .reply_public_key
+        .as_ref()
+        .map(|_| MailboxToken::generate());
+
+    let respondent_keys = RespondentKeys {
+        receipt_public_key: body.receipt_public_key,
+        reply_public_key: body.reply_public_key,
+        mailbox_token_hash: mailbox_token.as_ref().map(MailboxToken::hash),
+    };
+
+    // This is signed before the submission is stored, so that failing to sign it doesn't leave
+    // behind a submission the respondent will submit again.
+    let receipt = receipts::issue(
+        store,
+        &state.tenant,
+        &form_id,
+        &submission_id,
+        0,
+        &body.encrypted_body,
+    )
+    .await
+    .map_err(internal_err)?;
+
     let changed = store
-        .put_submission(&form_id, &submission_id, &body.encrypted_body)
+        .put_submission(
+            &form_id,
+            &submission_id,
+            &body.encrypted_body,
+            body.key_epoch,
+            &body.attachment_ids,
+            &respondent_keys,
+        )
         .await
         .map_err(internal_err)?;
 
-    if changed {
-        Ok(StatusCode::CREATED)
-    } else {
-        Ok(StatusCode::NOT_FOUND)
+    // We know the form exists, so this is most likely because of the attachments.
+    if !changed && !body.attachment_ids.is_empty() {
+        return Err(LoggedError::new(
+            ErrorCode::InvalidAttachment,
+            "Every attachment must have been uploaded to this form, have at least one chunk, and not be part of another submission.",
+        )
+        .into_response(StatusCode::BAD_REQUEST));
+    }
+
+    if !changed {
+        return Err(StatusCode::NOT_FOUND.into());
     }
+
+    // The submission is already stored, so failing to notify the organizers shouldn't fail the
+    // request.
+    if let Err(err) = notifications::enqueue_submission_created(
+        store,
+        state.notifications.as_ref(),
+        &form_id,
+        &submission_id,
+        &body.encrypted_body,
+    )
+    .await
+    {
+        log.set_error(LoggedError::new(ErrorCode::NotificationEnqueueFailed, err));
+    }
+
+    Ok((
+        StatusCode::CREATED,
+        Json(PostSubmissionResponse {
+            submission_id,
+            receipt,
+            mailbox_token,
+        }),
+    ))
+}
+
+// Respondents authenticate changes to their own submission by signing them with the key they made
+// it with, rather than with an access token.
+#[axum::debug_handler]
+async fn amend_submission(
+    State(state): State<Arc<AppState>>,
+    Path((form_id, submission_id)): Path<(FormId, SubmissionId)>,
+    Json(body): Json<PutReceiptRequest>,
+) -> Result<Json<PutReceiptResponse>, ErrorResponse> {
+    let store = state.store.without_authenticating();
+
+    let form_data = store
+        .get_form_data(&form_id)
+        .await
+        .map_err(internal_err)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let max_submission_len = config::max_submission_len(form_data.max_submission_len);
+
+    if body.encrypted_body.len() > max_submission_len {
+        return Err(submission_too_long_err(max_submission_len));
+    }
+
+    let receipt_key = store
+        .get_receipt_key(&form_id, &submission_id)
+        .await
+        .map_err(internal_err)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    if !receipts::verify(
+        &receipt_key.public_key,
+        &form_id,
+        &submission_id,
+        body.revision,
+        ReceiptAction::Amend(&body.encrypted_body),
+        &body.signature,
+    ) {
+        return Err(invalid_receipt_signature_err());
+    }
+
+    if body.revision != receipt_key.revision {
+        return Err(stale_receipt_revision_err());
+    }
+
+    let revision = body.revision + 1;
+
+    let receipt = receipts::issue(
+        store,
+        &state.tenant,
+        &form_id,
+        &submission_id,
+        revision,
+        &body.encrypted_body,
+    )
+    .await
+    .map_err(internal_err)?;
+
+    let changed = store
+        .amend_submission(
+            &form_id,
+            &submission_id,
+            &body.encrypted_body,
+            body.key_epoch,
+            body.revision,
+        )
+        .await
+        .map_err(internal_err)?;
+
+    // Another request changed the submission after we checked its revision.
+    if !changed {
+        return Err(stale_receipt_revision_err());
+    }
+
+    Ok(Json(PutReceiptResponse { revision, receipt }))
+}
+
+#[axum::debug_handler]
+async fn withdraw_submission(
+    State(state): State<Arc<AppState>>,
+    Extension(log): Extension<RequestLog>,
+    Path((form_id, submission_id)): Path<(FormId, SubmissionId)>,
+    Json(body): Json<DeleteReceiptRequest>,
+) -> Result<NoContent, ErrorResponse> {
+    let store = state.store.without_authenticating();
+
+    let receipt_key = store
+        .get_receipt_key(&form_id, &submission_id)
+        .await
+        .map_err(internal_err)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    if !receipts::verify(
+        &receipt_key.public_key,
+        &form_id,
+        &submission_id,
+        body.revision,
+        ReceiptAction::Withdraw,
+        &body.signature,
+    ) {
+        return Err(invalid_receipt_signature_err());
+    }
+
+    let attachment_ids = store
+        .withdraw_submission(&form_id, &submission_id, body.revision)
+        .await
+        .map_err(internal_err)?
+        .ok_or_else(stale_receipt_revision_err)?;
+
+    // See `delete_form` for why this doesn't fail the request.
+    if let Err(err) = store.purge_attachments(&attachment_ids).await {
+        log.set_error(LoggedError::new(ErrorCode::AttachmentPurgeFailed, err));
+    }
+
+    Ok(NoContent)
+}
+
+// The mailbox token is the only credential needed to read replies, so that respondents can check
+// for them without an account or revealing which submission is theirs in the URL.
+#[axum::debug_handler]
+async fn get_mailbox(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<GetMailboxResponse>>, ErrorResponse> {
+    let store = state.store.without_authenticating();
+
+    let token = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(MailboxToken::from)
+        .ok_or_else(|| unauthorized_err(anyhow!("Mailbox token is missing.")))?;
+
+    let replies = store
+        .list_mailbox_replies(&token.hash())
+        .await
+        .map_err(internal_err)?
+        .ok_or_else(|| unauthorized_err(anyhow!("Mailbox token is invalid.")))?;
+
+    Ok(Json(replies.into_iter().map(From::from).collect()))
+}
+
+// Respondents upload attachments before making the submission they're part of, so this isn't
+// authenticated. The attachment ID that's returned is what permits uploading its chunks.
+#[axum::debug_handler]
+async fn create_attachment(
+    State(state): State<Arc<AppState>>,
+    Path(form_id): Path<FormId>,
+) -> Result<(StatusCode, Json<PostAttachmentResponse>), ErrorResponse> {
+    let store = state.store.without_authenticating();
+
+    let attachment_id = store
+        .create_attachment(&form_id)
+        .await
+        .map_err(internal_err)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(PostAttachmentResponse { attachment_id }),
+    ))
+}
+
+#[axum::debug_handler]
+async fn upload_attachment_chunk(
+    State(state): State<Arc<AppState>>,
+    Path((form_id, attachment_id, chunk_index)): Path<(FormId, AttachmentId, u32)>,
+    body: Bytes,
+) -> Result<NoContent, ErrorResponse> {
+    let store = state.store.without_authenticating();
+
+    let outcome = store
+        .add_attachment_chunk(&form_id, &attachment_id, chunk_index, body.to_vec())
+        .await
+        .map_err(internal_err)?;
+
+    match outcome {
+        ChunkOutcome::Added => Ok(NoContent),
+        ChunkOutcome::NotFound => Err(StatusCode::NOT_FOUND.into()),
+        ChunkOutcome::OutOfOrder => Err(LoggedError::new(
+            ErrorCode::AttachmentChunkOutOfOrder,
+            "Chunks must be uploaded in order, starting from zero.",
+        )
+        .into_response(StatusCode::CONFLICT)),
+        ChunkOutcome::TooLarge => Err(LoggedError::new(
+            ErrorCode::RequestTooLarge,
+            format!(
+                "Attachments can't be longer than {} bytes.",
+                config::max_attachment_len()
+            ),
+        )
+        .into_response(StatusCode::PAYLOAD_TOO_LARGE)),
+        ChunkOutcome::OverQuota => Err(LoggedError::new(
+            ErrorCode::AttachmentQuotaExceeded,
+            format!(
+                "The attachments on this form can't be longer than {} bytes in total.",
+                config::max_form_attachments_len()
+            ),
+        )
+        .into_response(StatusCode::PAYLOAD_TOO_LARGE)),
+        ChunkOutcome::PendingOverQuota => Err(LoggedError::new(
+            ErrorCode::AttachmentQuotaExceeded,
+            format!(
+                "The attachments being uploaded to this form can't be longer than {} bytes in total.",
+                config::max_form_pending_attachments_len()
+            ),
+        )
+        .into_response(StatusCode::PAYLOAD_TOO_LARGE)),
+    }
+}
+
+#[axum::debug_handler]
+async fn get_attachment_chunk(
+    State(state): State<Arc<AppState>>,
+    Extension(token): Extension<SignedApiAccessToken>,
+    Path((form_id, attachment_id, chunk_index)): Path<(FormId, AttachmentId, u32)>,
+) -> Result<([(HeaderName, &'static str); 1], Vec<u8>), ErrorResponse> {
+    let store = token
+        .validate(&state.store, &state.tenant, &form_id, AccessRole::Read)
+        .await
+        .map_err(auth_err)?;
+
+    let chunk = store
+        .get_attachment_chunk(&form_id, &attachment_id, chunk_index)
+        .await
+        .map_err(internal_err)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(([(CONTENT_TYPE, ATTACHMENT_CHUNK_CONTENT_TYPE)], chunk))
 }
 
 #[axum::debug_handler]
@@ -189,10 +876,8 @@ async fn request_challenge(
     let store = state.store.without_authenticating();
 
     let server_key_id = ServerKeyId::new();
-    let ephemeral_server_key = EphemeralServerKey::generate();
 
-    store
-        .store_ephemeral_server_key(&server_key_id, &ephemeral_server_key)
+    let signing_key = TokenSigningKey::for_new_session(store, &server_key_id)
         .await
         .map_err(internal_err)?;
 
@@ -209,13 +894,11 @@ async fn request_challenge(
         client_key_id,
         challenge_id,
         nonce: ApiChallengeNonce::generate(),
-        origin: config::current_origin(),
+        origin: state.tenant.origin.clone(),
         exp: config::challenge_token_exp(),
     };
 
-    let signed_challenge = challenge
-        .encode(&ephemeral_server_key.encoding_key())
-        .map_err(internal_err)?;
+    let signed_challenge = challenge.encode(&signing_key).map_err(internal_err)?;
 
     Ok(Json(GetApiChallengeResponse {
         challenge: signed_challenge,
@@ -230,27 +913,125 @@ async fn request_access_token(
     let store = state.store.without_authenticating();
 
     let validated_challenge = ApiChallengeResponse::from(body)
-        .validate(store)
+        .validate(store, &state.tenant)
         .await
-        .map_err(|err| {
-            console_error!("{}", err);
-            StatusCode::UNAUTHORIZED
-        })?;
+        .map_err(unauthorized_err)?;
 
-    let ephemeral_server_key = store
-        .get_ephemeral_server_key(&validated_challenge.server_key_id())
+    let signing_key = TokenSigningKey::for_session(store, &validated_challenge.server_key_id())
         .await
         .map_err(internal_err)?
         .ok_or(StatusCode::UNAUTHORIZED)?;
 
+    let refresh_token = start_session(
+        store,
+        &state.tenant,
+        &validated_challenge.form_id(),
+        &validated_challenge.client_key_id(),
+        &validated_challenge.server_key_id(),
+    )
+    .await?;
+
     let token = validated_challenge
-        .into_access_token(
-            &ephemeral_server_key.encoding_key(),
-            config::access_token_exp(),
+        .into_access_token(&signing_key, config::access_token_exp())
+        .map_err(internal_err)?;
+
+    Ok(Json(PostTokenResponse {
+        token,
+        refresh_token,
+    }))
+}
+
+#[axum::debug_handler]
+async fn refresh_access_token(
+    State(state): State<Arc<AppState>>,
+    Json(body): Json<PostRefreshTokenRequest>,
+) -> Result<Json<PostTokenResponse>, ErrorResponse> {
+    let store = state.store.without_authenticating();
+
+    let validated_refresh_token =
+        ValidatedRefreshToken::redeem(&body.refresh_token, store, &state.tenant)
+            .await
+            .map_err(unauthorized_err)?;
+
+    let server_key_id = ServerKeyId::new();
+
+    let signing_key = TokenSigningKey::for_new_session(store, &server_key_id)
+        .await
+        .map_err(internal_err)?;
+
+    let refresh_token = start_session(
+        store,
+        &state.tenant,
+        &validated_refresh_token.form_id(),
+        &validated_refresh_token.client_key_id(),
+        &server_key_id,
+    )
+    .await?;
+
+    let token = validated_refresh_token
+        .into_access_token(&server_key_id, &signing_key, config::access_token_exp())
+        .map_err(internal_err)?;
+
+    Ok(Json(PostTokenResponse {
+        token,
+        refresh_token,
+    }))
+}
+
+// Publish the public keys for verifying tokens signed via EdDSA, so services other than this one
+// can verify our access tokens without sharing a secret.
+#[axum::debug_handler]
+async fn get_jwks(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<GetJwksResponse>, ErrorResponse> {
+    let store = state.store.without_authenticating();
+
+    let keys = store
+        .list_server_verifying_keys()
+        .await
+        .map_err(internal_err)?
+        .into_iter()
+        .map(|(key_id, verifying_key)| Jwk::new(key_id, verifying_key))
+        .collect();
+
+    Ok(Json(GetJwksResponse { keys }))
+}
+
+// Record a new session and issue the refresh token that can be used to continue it.
+async fn start_session(
+    store: &Store,
+    tenant: &Tenant,
+    form_id: &FormId,
+    client_key_id: &ClientKeyId,
+    server_key_id: &ServerKeyId,
+) -> Result<RefreshToken, ErrorResponse> {
+    let refresh_token = RefreshToken::generate();
+
+    store
+        .start_session(
+            form_id,
+            client_key_id,
+            server_key_id,
+            &refresh_token.hash(),
+            &tenant.origin,
         )
+        .await
         .map_err(internal_err)?;
 
-    Ok(Json(PostTokenResponse { token }))
+    Ok(refresh_token)
+}
+
+#[axum::debug_handler]
+async fn revoke_access_token(
+    State(state): State<Arc<AppState>>,
+    Extension(token): Extension<SignedApiAccessToken>,
+) -> Result<NoContent, ErrorResponse> {
+    token
+        .revoke(&state.store, &state.tenant)
+        .await
+        .map_err(auth_err)?;
+
+    Ok(NoContent)
 }
 
 #[axum::debug_handler]
@@ -260,7 +1041,7 @@ async fn list_form_submissions(
     Path(form_id): Path<FormId>,
 ) -> Result<Json<Vec<ListSubmissionsResponse>>, ErrorResponse> {
     let store = token
-        .validate(&state.store, &form_id, AccessRole::Read)
+        .validate(&state.store, &state.tenant, &form_id, AccessRole::Read)
         .await
         .map_err(auth_err)?;
 
@@ -272,57 +1053,468 @@ async fn list_form_submissions(
     Ok(Json(submissions.into_iter().map(From::from).collect()))
 }
 
+#[axum::debug_handler]
+async fn get_form_stats(
+    State(state): State<Arc<AppState>>,
+    Extension(token): Extension<SignedApiAccessToken>,
+    Path(form_id): Path<FormId>,
+    Query(query): Query<GetFormStatsQuery>,
+) -> Result<Json<GetFormStatsResponse>, ErrorResponse> {
+    let store = token
+        .validate(&state.store, &state.tenant, &form_id, AccessRole::Read)
+        .await
+        .map_err(auth_err)?;
+
+    let stats = store
+        .get_form_stats(&form_id, query.period)
+        .await
+        .map_err(internal_err)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(GetFormStatsResponse::new(stats, query.period)))
+}
+
+#[axum::debug_handler]
+async fn get_log(
+    State(state): State<Arc<AppState>>,
+    Extension(token): Extension<SignedApiAccessToken>,
+    Path(form_id): Path<FormId>,
+) -> Result<Json<GetLogResponse>, ErrorResponse> {
+    let store = token
+        .validate(&state.store, &state.tenant, &form_id, AccessRole::Read)
+        .await
+        .map_err(auth_err)?;
+
+    let leaves = log_leaves(store, &form_id, None).await?;
+    let root_hash = transparency::root_hash(&leaves);
+    let tree_size = leaves.len() as u64;
+
+    let started_at = store
+        .get_log_started_at(&form_id)
+        .await
+        .map_err(internal_err)?
+        .ok_or_else(|| internal_err(anyhow!("Form does not exist.")))?;
+
+    let tree_head =
+        transparency::sign_tree_head(store, &state.tenant, &form_id, tree_size, &root_hash)
+            .await
+            .map_err(internal_err)?;
+
+    Ok(Json(GetLogResponse {
+        tree_size,
+        root_hash: transparency::encode_hash(&root_hash),
+        tree_head,
+        started_at: started_at.to_rfc3339(),
+    }))
+}
+
+#[axum::debug_handler]
+async fn list_log_entries(
+    State(state): State<Arc<AppState>>,
+    Extension(token): Extension<SignedApiAccessToken>,
+    Path(form_id): Path<FormId>,
+    Query(query): Query<ListLogEntriesQuery>,
+) -> Result<Json<Vec<ListLogEntriesResponse>>, ErrorResponse> {
+    let store = token
+        .validate(&state.store, &state.tenant, &form_id, AccessRole::Read)
+        .await
+        .map_err(auth_err)?;
+
+    let entries = store
+        .list_log_entries(&form_id, query.start, query.end)
+        .await
+        .map_err(internal_err)?;
+
+    Ok(Json(entries.into_iter().map(From::from).collect()))
+}
+
+#[axum::debug_handler]
+async fn get_inclusion_proof(
+    State(state): State<Arc<AppState>>,
+    Extension(token): Extension<SignedApiAccessToken>,
+    Path(form_id): Path<FormId>,
+    Query(query): Query<GetInclusionProofQuery>,
+) -> Result<Json<GetInclusionProofResponse>, ErrorResponse> {
+    let store = token
+        .validate(&state.store, &state.tenant, &form_id, AccessRole::Read)
+        .await
+        .map_err(auth_err)?;
+
+    if query.index >= query.tree_size {
+        return Err(invalid_log_range_err(
+            "The index must be within the tree, and the tree can't be larger than the log.",
+        ));
+    }
+
+    if query.tree_size > transparency::MAX_PROOF_TREE_SIZE {
+        return Err(proof_too_large_err());
+    }
+
+    let leaves = log_leaves(store, &form_id, Some(query.tree_size)).await?;
+
+    if query.tree_size > leaves.len() as u64 {
+        return Err(invalid_log_range_err(
+            "The index must be within the tree, and the tree can't be larger than the log.",
+        ));
+    }
+
+    let proof = transparency::inclusion_proof(query.index as usize, &leaves);
+
+    Ok(Json(GetInclusionProofResponse {
+        index: query.index,
+        tree_size: query.tree_size,
+        audit_path: proof.iter().map(transparency::encode_hash).collect(),
+    }))
+}
+
+#[axum::debug_handler]
+async fn get_consistency_proof(
+    State(state): State<Arc<AppState>>,
+    Extension(token): Extension<SignedApiAccessToken>,
+    Path(form_id): Path<FormId>,
+    Query(query): Query<GetConsistencyProofQuery>,
+) -> Result<Json<GetConsistencyProofResponse>, ErrorResponse> {
+    let store = token
+        .validate(&state.store, &state.tenant, &form_id, AccessRole::Read)
+        .await
+        .map_err(auth_err)?;
+
+    if query.first == 0 || query.first > query.second {
+        return Err(invalid_log_range_err(
+            "The first tree can't be empty or larger than the second, and the second tree can't be larger than the log.",
+        ));
+    }
+
+    if query.second > transparency::MAX_PROOF_TREE_SIZE {
+        return Err(proof_too_large_err());
+    }
+
+    let leaves = log_leaves(store, &form_id, Some(query.second)).await?;
+
+    if query.second > leaves.len() as u64 {
+        return Err(invalid_log_range_err(
+            "The first tree can't be empty or larger than the second, and the second tree can't be larger than the log.",
+        ));
+    }
+
+    let proof = transparency::consistency_proof(query.first as usize, &leaves);
+
+    Ok(Json(GetConsistencyProofResponse {
+        first: query.first,
+        second: query.second,
+        proof: proof.iter().map(transparency::encode_hash).collect(),
+    }))
+}
+
+// The leaves of the first `tree_size` entries in the form's log, or of the whole log if
+// `tree_size` is unset. If the log is smaller than `tree_size`, this returns every leaf there is.
+async fn log_leaves(
+    store: &Store,
+    form_id: &FormId,
+    tree_size: Option<u64>,
+) -> Result<Vec<transparency::Hash>, ErrorResponse> {
+    Ok(store
+        .list_log_entries(form_id, 0, tree_size)
+        .await
+        .map_err(internal_err)?
+        .iter()
+        .map(|entry| transparency::leaf_hash(&entry.entry))
+        .collect())
+}
+
+// Encrypt the details of a change an admin is about to make with the form's current public primary
+// key, so the store can record the event in the same transaction as the change.
+async fn seal_audit_event(
+    store: &Store,
+    form_id: &FormId,
+    actor: ClientKeyId,
+    action: AuditAction,
+    details: serde_json::Value,
+) -> Result<NewAuditEvent, ErrorResponse> {
+    let form_data = store
+        .get_form_data(form_id)
+        .await
+        .map_err(internal_err)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    NewAuditEvent::seal(
+        actor,
+        action,
+        form_data.key_epoch,
+        &form_data.public_primary_key,
+        &details,
+    )
+    .map_err(audit_event_err)
+}
+
+#[axum::debug_handler]
+async fn list_audit_events(
+    State(state): State<Arc<AppState>>,
+    Extension(token): Extension<SignedApiAccessToken>,
+    Path(form_id): Path<FormId>,
+    Query(query): Query<ListAuditEventsQuery>,
+) -> Result<Json<ListAuditEventsResponse>, ErrorResponse> {
+    let store = token
+        .validate(&state.store, &state.tenant, &form_id, AccessRole::Admin)
+        .await
+        .map_err(auth_err)?;
+
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_AUDIT_PAGE_LEN)
+        .clamp(1, MAX_AUDIT_PAGE_LEN);
+
+    let events = store
+        .list_audit_events(&form_id, query.before, limit)
+        .await
+        .map_err(internal_err)?;
+
+    // A full page may be followed by more events, unless it ends with the first one.
+    let next_before = match events.last() {
+        Some(event) if events.len() as u64 == limit && event.index > 0 => Some(event.index),
+        _ => None,
+    };
+
+    Ok(Json(ListAuditEventsResponse {
+        events: events.into_iter().map(From::from).collect(),
+        next_before,
+    }))
+}
+
 #[axum::debug_handler]
 async fn delete_form(
     State(state): State<Arc<AppState>>,
     Extension(token): Extension<SignedApiAccessToken>,
+    Extension(log): Extension<RequestLog>,
     Path(form_id): Path<FormId>,
 ) -> Result<NoContent, ErrorResponse> {
-    let store = token
-        .validate(&state.store, &form_id, AccessRole::Admin)
+    let (store, actor) = token
+        .validate_actor(&state.store, &state.tenant, &form_id, AccessRole::Admin)
+        .await
+        .map_err(auth_err)?;
+
+    let attachment_ids = store
+        .delete_form(&form_id, &actor)
+        .await
+        .map_err(internal_err)?;
+
+    // The form is already deleted, and the scheduled tasks will try again to delete the chunks of
+    // its attachments, so this shouldn't fail the request.
+    if let Err(err) = store.purge_attachments(&attachment_ids).await {
+        log.set_error(LoggedError::new(ErrorCode::AttachmentPurgeFailed, err));
+    }
+
+    Ok(NoContent)
+}
+
+#[axum::debug_handler]
+async fn edit_form(
+    State(state): State<Arc<AppState>>,
+    Extension(token): Extension<SignedApiAccessToken>,
+    Path(form_id): Path<FormId>,
+    Json(body): Json<PatchFormRequest>,
+) -> Result<NoContent, ErrorResponse> {
+    let (store, actor) = token
+        .validate_actor(&state.store, &state.tenant, &form_id, AccessRole::Admin)
         .await
         .map_err(auth_err)?;
 
-    store.delete_form(&form_id).await.map_err(internal_err)?;
+    if !is_valid_submission_limit(body.max_submission_len) {
+        return Err(submission_limit_err());
+    }
+
+    let form_update = FormUpdate {
+        template: FormTemplate {
+            version: FORM_TEMPLATE_CURRENT_VERSION,
+            org_name: body.org_name,
+            description: body.description,
+            contact_methods: body.contact_methods,
+            roles: body.roles,
+        },
+        expires_at: match body.expires_at {
+            Some(expires_at) => Some(
+                DateTime::parse_from_rfc3339(&expires_at)
+                    .map_err(|err| internal_err(err.into()))?
+                    .to_utc(),
+            ),
+            None => None,
+        },
+        max_submission_len: body.max_submission_len,
+    };
+
+    let form_data = store
+        .get_form_data(&form_id)
+        .await
+        .map_err(internal_err)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    if !is_valid_template_signature(
+        form_data.template_signing_key.as_ref(),
+        body.template_signature.as_ref(),
+        &form_update.template,
+        &form_data.public_primary_key,
+    ) {
+        return Err(invalid_template_signature_err());
+    }
+
+    let signature = TemplateSignatureChange {
+        current: form_data.template_signature,
+        new: body.template_signature,
+    };
+
+    let audit_event = NewAuditEvent::seal(
+        actor,
+        AuditAction::FormEdited,
+        form_data.key_epoch,
+        &form_data.public_primary_key,
+        &json!({
+            "template": form_update.template,
+            "expires_at": form_update.expires_at.map(|expires_at| expires_at.to_rfc3339()),
+            "max_submission_len": form_update.max_submission_len,
+        }),
+    )
+    .map_err(audit_event_err)?;
+
+    let changed = store
+        .edit_form(&form_id, &form_update, &signature, &audit_event)
+        .await
+        .map_err(internal_err)?;
+
+    if !changed {
+        return Err(LoggedError::new(
+            ErrorCode::StaleTemplateSignature,
+            "The form was changed by another request after this one was signed.",
+        )
+        .into_response(StatusCode::CONFLICT));
+    }
+
+    Ok(NoContent)
+}
+
+#[axum::debug_handler]
+async fn rotate_primary_key(
+    State(state): State<Arc<AppState>>,
+    Extension(token): Extension<SignedApiAccessToken>,
+    Path(form_id): Path<FormId>,
+    Json(body): Json<PostPrimaryKeyRequest>,
+) -> Result<Json<PostPrimaryKeyResponse>, ErrorResponse> {
+    let (store, actor) = token
+        .validate_actor(&state.store, &state.tenant, &form_id, AccessRole::Admin)
+        .await
+        .map_err(auth_err)?;
+
+    let form_data = store
+        .get_form_data(&form_id)
+        .await
+        .map_err(internal_err)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    if !is_valid_template_signature(
+        form_data.template_signing_key.as_ref(),
+        body.template_signature.as_ref(),
+        &form_data.template,
+        &body.public_primary_key,
+    ) {
+        return Err(invalid_template_signature_err());
+    }
+
+    let signature = TemplateSignatureChange {
+        current: form_data.template_signature,
+        new: body.template_signature,
+    };
+
+    let wrapped_keys = body
+        .wrapped_keys
+        .into_iter()
+        .map(|key| (key.client_key_id, key.wrapped_private_primary_key))
+        .collect::<Vec<_>>();
+
+    // This is encrypted with the new public primary key.
+    let audit_event = NewAuditEvent::seal(
+        actor,
+        AuditAction::PrimaryKeyRotated,
+        body.key_epoch.next(),
+        &body.public_primary_key,
+        &json!({
+            "key_epoch": body.key_epoch.next(),
+            "public_primary_key": body.public_primary_key,
+        }),
+    )
+    .map_err(audit_event_err)?;
+
+    let key_epoch = store
+        .rotate_primary_key(
+            &form_id,
+            body.key_epoch,
+            &body.public_primary_key,
+            &wrapped_keys,
+            &signature,
+            &audit_event,
+        )
+        .await
+        .map_err(internal_err)?
+        .ok_or_else(|| {
+            LoggedError::new(
+                ErrorCode::StaleKeyEpoch,
+                "Primary key rotation was based on a stale key epoch, did not include every client key, or was signed for a template that has since changed.",
+            )
+            .into_response(StatusCode::CONFLICT)
+        })?;
 
-    Ok(NoContent)
+    Ok(Json(PostPrimaryKeyResponse { key_epoch }))
 }
 
 #[axum::debug_handler]
-async fn edit_form(
+async fn replace_submission(
     State(state): State<Arc<AppState>>,
     Extension(token): Extension<SignedApiAccessToken>,
-    Path(form_id): Path<FormId>,
-    Json(body): Json<PatchFormRequest>,
+    Path((form_id, submission_id)): Path<(FormId, SubmissionId)>,
+    Json(body): Json<PutSubmissionRequest>,
 ) -> Result<NoContent, ErrorResponse> {
-    let store = token
-        .validate(&state.store, &form_id, AccessRole::Admin)
+    let (store, actor) = token
+        .validate_actor(&state.store, &state.tenant, &form_id, AccessRole::Admin)
         .await
         .map_err(auth_err)?;
 
-    let form_update = FormUpdate {
-        template: FormTemplate {
-            version: FORM_TEMPLATE_CURRENT_VERSION,
-            org_name: body.org_name,
-            description: body.description,
-            contact_methods: body.contact_methods,
-            roles: body.roles,
-        },
-        expires_at: match body.expires_at {
-            Some(expires_at) => Some(
-                DateTime::parse_from_rfc3339(&expires_at)
-                    .map_err(|err| internal_err(err.into()))?
-                    .to_utc(),
-            ),
-            None => None,
-        },
-    };
+    let form_data = store
+        .get_form_data(&form_id)
+        .await
+        .map_err(internal_err)?
+        .ok_or(StatusCode::NOT_FOUND)?;
 
-    store
-        .edit_form(&form_id, &form_update)
+    // Submissions can only be re-encrypted with the current public primary key.
+    if body.key_epoch != form_data.key_epoch {
+        return Err(LoggedError::new(
+            ErrorCode::StaleSubmissionKeyEpoch,
+            "Submissions can only be re-encrypted with the current public primary key.",
+        )
+        .into_response(StatusCode::CONFLICT));
+    }
+
+    let audit_event = NewAuditEvent::seal(
+        actor,
+        AuditAction::SubmissionReplaced,
+        form_data.key_epoch,
+        &form_data.public_primary_key,
+        &json!({ "submission_id": submission_id }),
+    )
+    .map_err(audit_event_err)?;
+
+    let changed = store
+        .replace_submission(
+            &form_id,
+            &submission_id,
+            &body.encrypted_body,
+            body.key_epoch,
+            &audit_event,
+        )
         .await
         .map_err(internal_err)?;
 
+    if !changed {
+        return Err(StatusCode::NOT_FOUND.into());
+    }
+
     Ok(NoContent)
 }
 
@@ -333,7 +1525,7 @@ async fn get_key(
     Path((form_id, key_id)): Path<(FormId, ClientKeyId)>,
 ) -> Result<Json<GetKeyResponse>, ErrorResponse> {
     let store = token
-        .validate(&state.store, &form_id, AccessRole::Read)
+        .validate(&state.store, &state.tenant, &form_id, AccessRole::Read)
         .await
         .map_err(auth_err)?;
 
@@ -355,7 +1547,7 @@ async fn list_keys(
     Path(form_id): Path<FormId>,
 ) -> Result<Json<Vec<ListKeysResponse>>, ErrorResponse> {
     let store = token
-        .validate(&state.store, &form_id, AccessRole::Admin)
+        .validate(&state.store, &state.tenant, &form_id, AccessRole::Admin)
         .await
         .map_err(auth_err)?;
 
@@ -374,11 +1566,22 @@ async fn add_key(
     Path(form_id): Path<FormId>,
     Json(body): Json<PostKeyRequest>,
 ) -> Result<(StatusCode, Json<PostKeyResponse>), ErrorResponse> {
-    let store = token
-        .validate(&state.store, &form_id, AccessRole::Admin)
+    let (store, actor) = token
+        .validate_actor(&state.store, &state.tenant, &form_id, AccessRole::Admin)
         .await
         .map_err(auth_err)?;
 
+    // The client key ID isn't assigned until the key is stored, so this identifies the key by its
+    // public signing key, which is in the key's entry in the form log.
+    let audit_event = seal_audit_event(
+        store,
+        &form_id,
+        actor,
+        AuditAction::KeyAdded,
+        json!({ "public_signing_key": body.public_signing_key, "role": body.role }),
+    )
+    .await?;
+
     let client_key_id = store
         .store_client_keys(
             &form_id,
@@ -386,6 +1589,7 @@ async fn add_key(
             Some(&body.wrapped_private_primary_key),
             &body.encrypted_comment,
             body.role,
+            &audit_event,
         )
         .await
         .map_err(internal_err)?
@@ -403,22 +1607,42 @@ async fn update_key(
     Path((form_id, key_id)): Path<(FormId, ClientKeyId)>,
     Json(body): Json<PatchKeyRequest>,
 ) -> Result<NoContent, ErrorResponse> {
-    let store = token
-        .validate(&state.store, &form_id, AccessRole::Admin)
+    let (store, actor) = token
+        .validate_actor(&state.store, &state.tenant, &form_id, AccessRole::Admin)
         .await
         .map_err(auth_err)?;
 
-    store
+    let audit_event = seal_audit_event(
+        store,
+        &form_id,
+        actor,
+        AuditAction::KeyUpdated,
+        json!({
+            "client_key_id": key_id,
+            "role": body.role,
+            "wrapped_private_primary_key_changed": body.wrapped_private_primary_key.is_some(),
+            "encrypted_comment_changed": body.encrypted_comment.is_some(),
+        }),
+    )
+    .await?;
+
+    let outcome = store
         .update_client_keys(
             &form_id,
             &key_id,
             body.wrapped_private_primary_key.as_ref(),
             body.encrypted_comment.as_ref(),
+            body.role,
+            &audit_event,
         )
         .await
         .map_err(internal_err)?;
 
-    Ok(NoContent)
+    match outcome {
+        KeyChangeOutcome::Changed => Ok(NoContent),
+        KeyChangeOutcome::NotFound => Err(StatusCode::NOT_FOUND.into()),
+        KeyChangeOutcome::LastAdmin => Err(last_admin_err()),
+    }
 }
 
 #[axum::debug_handler]
@@ -427,13 +1651,74 @@ async fn delete_key(
     Extension(token): Extension<SignedApiAccessToken>,
     Path((form_id, key_id)): Path<(FormId, ClientKeyId)>,
 ) -> Result<NoContent, ErrorResponse> {
+    let (store, actor) = token
+        .validate_actor(&state.store, &state.tenant, &form_id, AccessRole::Admin)
+        .await
+        .map_err(auth_err)?;
+
+    let audit_event = seal_audit_event(
+        store,
+        &form_id,
+        actor,
+        AuditAction::KeyRevoked,
+        json!({ "client_key_id": key_id }),
+    )
+    .await?;
+
+    let outcome = store
+        .delete_client_keys(&form_id, &key_id, &audit_event)
+        .await
+        .map_err(internal_err)?;
+
+    match outcome {
+        KeyChangeOutcome::Changed => Ok(NoContent),
+        // Revoking a key that doesn't exist is not an error.
+        KeyChangeOutcome::NotFound => Ok(NoContent),
+        KeyChangeOutcome::LastAdmin => Err(last_admin_err()),
+    }
+}
+
+#[axum::debug_handler]
+async fn list_sessions(
+    State(state): State<Arc<AppState>>,
+    Extension(token): Extension<SignedApiAccessToken>,
+    Path((form_id, key_id)): Path<(FormId, ClientKeyId)>,
+) -> Result<Json<Vec<ListSessionsResponse>>, ErrorResponse> {
     let store = token
-        .validate(&state.store, &form_id, AccessRole::Admin)
+        .validate(&state.store, &state.tenant, &form_id, AccessRole::Admin)
+        .await
+        .map_err(auth_err)?;
+
+    let sessions = store
+        .list_sessions(&form_id, &key_id)
+        .await
+        .map_err(internal_err)?;
+
+    Ok(Json(sessions.into_iter().map(From::from).collect()))
+}
+
+#[axum::debug_handler]
+async fn delete_sessions(
+    State(state): State<Arc<AppState>>,
+    Extension(token): Extension<SignedApiAccessToken>,
+    Path((form_id, key_id)): Path<(FormId, ClientKeyId)>,
+) -> Result<NoContent, ErrorResponse> {
+    let (store, actor) = token
+        .validate_actor(&state.store, &state.tenant, &form_id, AccessRole::Admin)
         .await
         .map_err(auth_err)?;
 
+    let audit_event = seal_audit_event(
+        store,
+        &form_id,
+        actor,
+        AuditAction::SessionsEnded,
+        json!({ "client_key_id": key_id }),
+    )
+    .await?;
+
     store
-        .delete_client_keys(&form_id, &key_id)
+        .delete_sessions(&form_id, &key_id, &audit_event)
         .await
         .map_err(internal_err)?;
 
@@ -461,13 +1746,22 @@ async fn set_password_params(
         }
     };
 
-    let store = token
-        .validate_with(&state.store, &form_id, role_validator)
+    let (store, actor) = token
+        .validate_with(&state.store, &state.tenant, &form_id, role_validator)
         .await
         .map_err(auth_err)?;
 
+    let audit_event = seal_audit_event(
+        store,
+        &form_id,
+        actor,
+        AuditAction::PasswordParamsSet,
+        json!({ "client_key_id": key_id }),
+    )
+    .await?;
+
     store
-        .store_password_params(&form_id, &key_id, &body.salt, &body.nonce)
+        .store_password_params(&form_id, &key_id, &body.salt, &body.nonce, &audit_event)
         .await
         .map_err(internal_err)?;
 
@@ -492,3 +1786,316 @@ async fn get_password_params(
         nonce: params.nonce,
     }))
 }
+
+// Replies are encrypted by the client to the respondent's reply public key, so only the respondent
+// can read them.
+#[axum::debug_handler]
+async fn send_reply(
+    State(state): State<Arc<AppState>>,
+    Extension(token): Extension<SignedApiAccessToken>,
+    Path((form_id, submission_id)): Path<(FormId, SubmissionId)>,
+    Json(body): Json<PostReplyRequest>,
+) -> Result<(StatusCode, Json<PostReplyResponse>), ErrorResponse> {
+    let store = token
+        .validate(&state.store, &state.tenant, &form_id, AccessRole::Read)
+        .await
+        .map_err(auth_err)?;
+
+    let reply_id = ReplyId::new();
+
+    let outcome = store
+        .create_reply(
+            &form_id,
+            &submission_id,
+            &reply_id,
+            &body.encrypted_body,
+            MAX_REPLIES_PER_SUBMISSION,
+        )
+        .await
+        .map_err(internal_err)?;
+
+    match outcome {
+        ReplyOutcome::Added => Ok((StatusCode::CREATED, Json(PostReplyResponse { reply_id }))),
+        ReplyOutcome::NotFound => Err(StatusCode::NOT_FOUND.into()),
+        ReplyOutcome::LimitReached => Err(LoggedError::new(
+            ErrorCode::ReplyLimit,
+            format!(
+                "Refusing to send more than {} replies to a submission.",
+                MAX_REPLIES_PER_SUBMISSION
+            ),
+        )
+        .into_response(StatusCode::CONFLICT)),
+    }
+}
+
+#[axum::debug_handler]
+async fn list_webhooks(
+    State(state): State<Arc<AppState>>,
+    Extension(token): Extension<SignedApiAccessToken>,
+    Path(form_id): Path<FormId>,
+) -> Result<Json<Vec<ListWebhooksResponse>>, ErrorResponse> {
+    let store = token
+        .validate(&state.store, &state.tenant, &form_id, AccessRole::Admin)
+        .await
+        .map_err(auth_err)?;
+
+    let webhooks = store.list_webhooks(&form_id).await.map_err(internal_err)?;
+
+    Ok(Json(webhooks.into_iter().map(From::from).collect()))
+}
+
+#[axum::debug_handler]
+async fn add_webhook(
+    State(state): State<Arc<AppState>>,
+    Extension(token): Extension<SignedApiAccessToken>,
+    Path(form_id): Path<FormId>,
+    Json(body): Json<PostWebhookRequest>,
+) -> Result<(StatusCode, Json<PostWebhookResponse>), ErrorResponse> {
+    let (store, actor) = token
+        .validate_actor(&state.store, &state.tenant, &form_id, AccessRole::Admin)
+        .await
+        .map_err(auth_err)?;
+
+    webhooks::validate_url(&body.url, config::webhook_allow_insecure_urls()).map_err(|err| {
+        LoggedError::new(ErrorCode::InvalidWebhookUrl, err).into_response(StatusCode::BAD_REQUEST)
+    })?;
+
+    let webhook = NewWebhook {
+        id: WebhookId::new(),
+        url: body.url,
+        secret: WebhookSecret::generate(),
+        include_ciphertext: body.include_ciphertext,
+    };
+
+    let audit_event = seal_audit_event(
+        store,
+        &form_id,
+        actor,
+        AuditAction::WebhookAdded,
+        json!({ "webhook_id": webhook.id, "url": webhook.url }),
+    )
+    .await?;
+
+    let created = store
+        .create_webhook(&form_id, &webhook, MAX_WEBHOOKS_PER_FORM, &audit_event)
+        .await
+        .map_err(internal_err)?;
+
+    if !created {
+        return Err(LoggedError::new(
+            ErrorCode::WebhookLimit,
+            format!(
+                "Refusing to register more than {} webhooks for a form.",
+                MAX_WEBHOOKS_PER_FORM
+            ),
+        )
+        .into_response(StatusCode::CONFLICT));
+    }
+
+    Ok((
+        StatusCode::CREATED,
+        Json(PostWebhookResponse {
+            webhook_id: webhook.id,
+            secret: webhook.secret,
+        }),
+    ))
+}
+
+#[axum::debug_handler]
+async fn delete_webhook(
+    State(state): State<Arc<AppState>>,
+    Extension(token): Extension<SignedApiAccessToken>,
+    Path((form_id, webhook_id)): Path<(FormId, WebhookId)>,
+) -> Result<NoContent, ErrorResponse> {
+    let (store, actor) = token
+        .validate_actor(&state.store, &state.tenant, &form_id, AccessRole::Admin)
+        .await
+        .map_err(auth_err)?;
+
+    let audit_event = seal_audit_event(
+        store,
+        &form_id,
+        actor,
+        AuditAction::WebhookDeleted,
+        json!({ "webhook_id": webhook_id }),
+    )
+    .await?;
+
+    store
+        .delete_webhook(&form_id, &webhook_id, &audit_event)
+        .await
+        .map_err(internal_err)?;
+
+    Ok(NoContent)
+}
+
+#[axum::debug_handler]
+async fn get_vapid_key() -> Result<Json<GetVapidKeyResponse>, ErrorResponse> {
+    let key = config::vapid_signing_key().ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(GetVapidKeyResponse {
+        public_key: key.public_key(),
+    }))
+}
+
+#[axum::debug_handler]
+async fn add_push_subscription(
+    State(state): State<Arc<AppState>>,
+    Extension(token): Extension<SignedApiAccessToken>,
+    Path((form_id, key_id)): Path<(FormId, ClientKeyId)>,
+    Json(body): Json<PostPushSubscriptionRequest>,
+) -> Result<StatusCode, ErrorResponse> {
+    // A subscription belongs to the browser that made it, so users can only subscribe their own
+    // key, regardless of their role.
+    let role_validator = |client_key_id_from_token: ClientKeyId, _: AccessRole| {
+        if client_key_id_from_token == key_id {
+            Ok(())
+        } else {
+            Err(AuthError::forbidden(
+                "Must be the key owner to subscribe to push notifications.",
+            ))
+        }
+    };
+
+    let (store, _) = token
+        .validate_with(&state.store, &state.tenant, &form_id, role_validator)
+        .await
+        .map_err(auth_err)?;
+
+    if config::vapid_signing_key().is_none() {
+        return Err(StatusCode::NOT_FOUND.into());
+    }
+
+    push::validate_endpoint(&body.endpoint).map_err(|err| {
+        LoggedError::new(ErrorCode::InvalidPushEndpoint, err).into_response(StatusCode::BAD_REQUEST)
+    })?;
+
+    let created = store
+        .put_push_subscription(
+            &form_id,
+            &key_id,
+            &PushSubscriptionId::new(),
+            &body.endpoint,
+            &body.keys.p256dh,
+            &body.keys.auth,
+        )
+        .await
+        .map_err(internal_err)?;
+
+    if created {
+        Ok(StatusCode::CREATED)
+    } else {
+        Ok(StatusCode::NOT_FOUND)
+    }
+}
+
+#[axum::debug_handler]
+async fn delete_push_subscriptions(
+    State(state): State<Arc<AppState>>,
+    Extension(token): Extension<SignedApiAccessToken>,
+    Path((form_id, key_id)): Path<(FormId, ClientKeyId)>,
+) -> Result<NoContent, ErrorResponse> {
+    // Admin users can unsubscribe any key, but non-admin users can only unsubscribe their own key.
+    let role_validator = |client_key_id_from_token: ClientKeyId, role: AccessRole| {
+        if (role.includes(AccessRole::Admin))
+            || (role.includes(AccessRole::Read) && client_key_id_from_token == key_id)
+        {
+            Ok(())
+        } else {
+            Err(AuthError::forbidden(
+                "Must have admin role or be the key owner to unsubscribe from push notifications.",
+            ))
+        }
+    };
+
+    let (store, _) = token
+        .validate_with(&state.store, &state.tenant, &form_id, role_validator)
+        .await
+        .map_err(auth_err)?;
+
+    store
+        .delete_push_subscriptions(&form_id, &key_id)
+        .await
+        .map_err(internal_err)?;
+
+    Ok(NoContent)
+}
+
+#[axum::debug_handler]
+async fn add_digest_subscription(
+    State(state): State<Arc<AppState>>,
+    Extension(token): Extension<SignedApiAccessToken>,
+    Path((form_id, key_id)): Path<(FormId, ClientKeyId)>,
+    Json(body): Json<PostDigestSubscriptionRequest>,
+) -> Result<StatusCode, ErrorResponse> {
+    // Users can only sign up their own address, so they can only subscribe their own key,
+    // regardless of their role.
+    let role_validator = |client_key_id_from_token: ClientKeyId, _: AccessRole| {
+        if client_key_id_from_token == key_id {
+            Ok(())
+        } else {
+            Err(AuthError::forbidden(
+                "Must be the key owner to subscribe to the email digest.",
+            ))
+        }
+    };
+
+    let (store, _) = token
+        .validate_with(&state.store, &state.tenant, &form_id, role_validator)
+        .await
+        .map_err(auth_err)?;
+
+    let mail = config::mail().ok_or(StatusCode::NOT_FOUND)?;
+
+    digests::validate_email(&body.email).map_err(|err| {
+        LoggedError::new(ErrorCode::InvalidEmail, err).into_response(StatusCode::BAD_REQUEST)
+    })?;
+
+    let encrypted_email = mail
+        .encryption_key
+        .encrypt(&body.email)
+        .map_err(internal_err)?;
+
+    let created = store
+        .put_digest_subscription(&form_id, &key_id, &state.tenant.origin, &encrypted_email)
+        .await
+        .map_err(internal_err)?;
+
+    if created {
+        Ok(StatusCode::CREATED)
+    } else {
+        Ok(StatusCode::NOT_FOUND)
+    }
+}
+
+#[axum::debug_handler]
+async fn delete_digest_subscription(
+    State(state): State<Arc<AppState>>,
+    Extension(token): Extension<SignedApiAccessToken>,
+    Path((form_id, key_id)): Path<(FormId, ClientKeyId)>,
+) -> Result<NoContent, ErrorResponse> {
+    // Admin users can unsubscribe any key, but non-admin users can only unsubscribe their own key.
+    let role_validator = |client_key_id_from_token: ClientKeyId, role: AccessRole| {
+        if (role.includes(AccessRole::Admin))
+            || (role.includes(AccessRole::Read) && client_key_id_from_token == key_id)
+        {
+            Ok(())
+        } else {
+            Err(AuthError::forbidden(
+                "Must have admin role or be the key owner to unsubscribe from the email digest.",
+            ))
+        }
+    };
+
+    let (store, _) = token
+        .validate_with(&state.store, &state.tenant, &form_id, role_validator)
+        .await
+        .map_err(auth_err)?;
+
+    store
+        .delete_digest_subscription(&form_id, &key_id)
+        .await
+        .map_err(internal_err)?;
+
+    Ok(NoContent)
+}
@@ -0,0 +1,190 @@
+//
+// The few things we need from the JavaScript runtime besides the bindings. Calling into JavaScript
+// panics outside of a worker, so native builds (the standalone server and the tests) use the
+// standard library instead.
+//
+
+#[cfg(target_arch = "wasm32")]
+pub fn now_millis() -> u64 {
+    worker::Date::now().as_millis()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn now_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn log(line: &str) {
+    worker::console_log!("{}", line);
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn log(line: &str) {
+    println!("{}", line);
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn log_error(line: &str) {
+    worker::console_error!("{}", line);
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn log_error(line: &str) {
+    eprintln!("{}", line);
+}
+
+// Send a POST request to a third party, like a webhook receiver or push service, and return the
+// response status. Organizers and respondents choose these URLs, so natively we refuse to send
+// requests to the network the server is on, unless it's a test environment which receives webhook
+// events on localhost. Workers can't reach the operator's network in the first place.
+#[cfg(target_arch = "wasm32")]
+pub async fn post(url: &str, headers: &[(&str, &str)], body: Vec<u8>) -> anyhow::Result<u16> {
+    use worker::{js_sys::Uint8Array, Fetch, Headers, Method, Request, RequestInit};
+
+    let mut req_headers = Headers::new();
+
+    for (name, value) in headers {
+        req_headers.set(name, value)?;
+    }
+
+    let mut init = RequestInit::new();
+    init.with_method(Method::Post)
+        .with_headers(req_headers)
+        .with_body(Some(Uint8Array::from(body.as_slice()).into()));
+
+    let req = Request::new_with_init(url, &init)?;
+    let resp = Fetch::Request(req).send().await?;
+
+    Ok(resp.status_code())
+}
+
+#[cfg(all(not(target_arch = "wasm32"), feature = "server"))]
+pub async fn post(url: &str, headers: &[(&str, &str)], body: Vec<u8>) -> anyhow::Result<u16> {
+    use std::sync::{Arc, OnceLock};
+
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+
+    if crate::config::webhook_allow_insecure_urls() {
+        return post_to_operator(url, headers, body).await;
+    }
+
+    // Hostnames are checked when we connect, but IP addresses are never resolved.
+    let host = reqwest::Url::parse(url)?
+        .host_str()
+        .unwrap_or_default()
+        .to_string();
+
+    if crate::webhooks::is_local_host(&host) {
+        anyhow::bail!(
+            "Refusing to send a request to the local address `{}`.",
+            host
+        );
+    }
+
+    // A redirect could point anywhere, so we don't follow them.
+    let client = CLIENT.get_or_init(|| {
+        reqwest::Client::builder()
+            .timeout(POST_TIMEOUT)
+            .dns_resolver(Arc::new(PublicResolver))
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .expect("failed to build HTTP client")
+    });
+
+    send(client, url, headers, body).await
+}
+
+// Send a POST request to a URL the operator configured, like the mail relay, which may be on the
+// same network as the server.
+#[cfg(target_arch = "wasm32")]
+pub async fn post_to_operator(
+    url: &str,
+    headers: &[(&str, &str)],
+    body: Vec<u8>,
+) -> anyhow::Result<u16> {
+    post(url, headers, body).await
+}
+
+#[cfg(all(not(target_arch = "wasm32"), feature = "server"))]
+pub async fn post_to_operator(
+    url: &str,
+    headers: &[(&str, &str)],
+    body: Vec<u8>,
+) -> anyhow::Result<u16> {
+    use std::sync::OnceLock;
+
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+
+    let client = CLIENT.get_or_init(|| {
+        reqwest::Client::builder()
+            .timeout(POST_TIMEOUT)
+            .build()
+            .expect("failed to build HTTP client")
+    });
+
+    send(client, url, headers, body).await
+}
+
+// We don't want a slow receiver to hold up a delivery indefinitely.
+#[cfg(all(not(target_arch = "wasm32"), feature = "server"))]
+const POST_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+#[cfg(all(not(target_arch = "wasm32"), feature = "server"))]
+async fn send(
+    client: &reqwest::Client,
+    url: &str,
+    headers: &[(&str, &str)],
+    body: Vec<u8>,
+) -> anyhow::Result<u16> {
+    let mut req = client.post(url).body(body);
+
+    for (name, value) in headers {
+        req = req.header(*name, *value);
+    }
+
+    Ok(req.send().await?.status().as_u16())
+}
+
+// Resolves hostnames to only their public addresses. We check the addresses when we connect rather
+// than when the URL is registered, so a hostname can't be changed to point at the server's network
+// afterwards.
+#[cfg(all(not(target_arch = "wasm32"), feature = "server"))]
+struct PublicResolver;
+
+#[cfg(all(not(target_arch = "wasm32"), feature = "server"))]
+impl reqwest::dns::Resolve for PublicResolver {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        let host = name.as_str().to_string();
+
+        Box::pin(async move {
+            let addrs = tokio::net::lookup_host((host.as_str(), 0))
+                .await?
+                .filter(|addr| crate::webhooks::is_public_address(addr.ip()))
+                .collect::<Vec<_>>();
+
+            if addrs.is_empty() {
+                return Err(anyhow::anyhow!("`{}` has no public addresses.", host).into());
+            }
+
+            Ok(Box::new(addrs.into_iter()) as reqwest::dns::Addrs)
+        })
+    }
+}
+
+#[cfg(all(not(target_arch = "wasm32"), not(feature = "server")))]
+pub async fn post(_url: &str, _headers: &[(&str, &str)], _body: Vec<u8>) -> anyhow::Result<u16> {
+    anyhow::bail!("Sending requests is only supported in the worker and the standalone server.")
+}
+
+#[cfg(all(not(target_arch = "wasm32"), not(feature = "server")))]
+pub async fn post_to_operator(
+    _url: &str,
+    _headers: &[(&str, &str)],
+    _body: Vec<u8>,
+) -> anyhow::Result<u16> {
+    anyhow::bail!("Sending requests is only supported in the worker and the standalone server.")
+}
@@ -0,0 +1,142 @@
+use std::{env, path::PathBuf, sync::Arc, time::Duration};
+
+use axum::{
+    extract::Request,
+    http::{header::HOST, uri::Authority},
+    response::Response,
+    Router,
+};
+use tokio::net::TcpListener;
+use tower_service::Service;
+use worker::async_trait::async_trait;
+
+use crate::{
+    config,
+    notifications::{self, NotificationQueue, QueuedNotification},
+    router::{self, AppState},
+    run_scheduled_tasks, runtime,
+    storage::{
+        fs::FsBlobBackend,
+        sqlite::{SqliteBackend, SqliteKvBackend},
+        Blobs, Database, KeyValue,
+    },
+    store::UnauthenticatedStore,
+};
+
+//
+// The standalone server serves the same API as the worker, for groups who can't or won't use
+// Cloudflare. D1 is replaced by a local SQLite file, KV by a table in the same file, R2 by a
+// directory, the queue by background tasks, and the cron trigger by a timer. The worker's vars
+// and secrets are read from environment variables with the same names.
+//
+
+const DEFAULT_DATABASE_PATH: &str = "notwithouthelp.sqlite3";
+const DEFAULT_ATTACHMENTS_PATH: &str = "attachments";
+
+// The same address `wrangler dev` listens on, so the client's dev config works unchanged.
+const DEFAULT_LISTEN_ADDR: &str = "127.0.0.1:8787";
+
+const DAY_MILLIS: u64 = 24 * 60 * 60 * 1000;
+
+// Deliveries are retried with the same delays as the queue consumer. Unlike the queue, pending
+// retries don't survive a restart.
+#[derive(Clone)]
+struct LocalQueue {
+    store: UnauthenticatedStore,
+}
+
+impl LocalQueue {
+    async fn deliver(self, mut queued: QueuedNotification) {
+        let store = self.store.without_authenticating();
+
+        while let Some((retry, delay_secs)) = notifications::attempt_delivery(store, &queued).await
+        {
+            tokio::time::sleep(Duration::from_secs(delay_secs.into())).await;
+            queued = retry;
+        }
+    }
+}
+
+#[async_trait]
+impl NotificationQueue for LocalQueue {
+    async fn enqueue(&self, notifications: Vec<QueuedNotification>) -> anyhow::Result<()> {
+        for queued in notifications {
+            tokio::spawn(self.clone().deliver(queued));
+        }
+
+        Ok(())
+    }
+}
+
+// Run the scheduled tasks at midnight UTC, like the cron trigger in `wrangler.toml`.
+async fn run_timer(store: UnauthenticatedStore, kv: SqliteKvBackend) {
+    loop {
+        let until_midnight = DAY_MILLIS - runtime::now_millis() % DAY_MILLIS;
+        tokio::time::sleep(Duration::from_millis(until_midnight)).await;
+
+        run_scheduled_tasks(store.without_authenticating()).await;
+
+        if let Err(err) = kv.delete_expired() {
+            runtime::log_error(&format!(
+                "Error: failed to delete expired KV entries: {:#}",
+                err
+            ));
+        }
+    }
+}
+
+// Each request is routed for the tenant its `Host` header names, like in the worker.
+async fn handle(store: UnauthenticatedStore, queue: LocalQueue, req: Request) -> Response {
+    let host = req
+        .headers()
+        .get(HOST)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<Authority>().ok());
+
+    let state = AppState {
+        store,
+        tenant: config::tenant_for_host(host.as_ref().map(Authority::host)),
+        notifications: Arc::new(queue),
+    };
+
+    match router::new(state).call(req).await {
+        Ok(resp) => resp,
+        Err(infallible) => match infallible {},
+    }
+}
+
+pub async fn run() -> anyhow::Result<()> {
+    config::init_with(|name| env::var(name).ok())?;
+
+    let database_path = env::var("DATABASE_PATH")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from(DEFAULT_DATABASE_PATH));
+    let attachments_path = env::var("ATTACHMENTS_PATH")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from(DEFAULT_ATTACHMENTS_PATH));
+    let listen_addr = env::var("LISTEN_ADDR").unwrap_or_else(|_| DEFAULT_LISTEN_ADDR.to_string());
+
+    let backend = SqliteBackend::open(&database_path)?;
+
+    let store = UnauthenticatedStore::new(
+        Database::new(backend.clone()),
+        KeyValue::new(backend.kv()),
+        Blobs::new(FsBlobBackend::new(&attachments_path)),
+    );
+
+    let queue = LocalQueue {
+        store: store.clone(),
+    };
+
+    tokio::spawn(run_timer(store.clone(), backend.kv()));
+
+    let app = Router::new().fallback(move |req: Request| handle(store.clone(), queue.clone(), req));
+
+    let listener = TcpListener::bind(&listen_addr).await?;
+
+    runtime::log(&format!("Listening on http://{}", listen_addr));
+
+    axum::serve(listener, app).await?;
+
+    Ok(())
+}
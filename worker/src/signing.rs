@@ -0,0 +1,227 @@
+use std::{collections::BTreeMap, sync::Mutex};
+
+use anyhow::{anyhow, bail};
+use chrono::{DateTime, Utc};
+use jsonwebtoken as jwt;
+
+use crate::{
+    config::{self, JwtSigningAlgorithm},
+    keys::{EphemeralServerKey, ServerSigningKey, ServerVerifyingKey},
+    models::{ServerKeyId, ServerSigningKeyId},
+    runtime,
+    store::Store,
+};
+
+//
+// API challenges and access tokens can be signed in one of two ways:
+//
+// - Via HS256 with an ephemeral server key that is generated for each session and stored in KV.
+//   Verifying a token requires reading its key from KV, which is eventually consistent.
+// - Via EdDSA with a server signing key pair that is shared by every session, stored in D1, and
+//   rotated by the scheduled handler. Verifying a token's signature only requires the public key,
+//   which each isolate caches for a short time after it sees it. Since the key doesn't belong to
+//   the session, authenticated routes also check that the session still exists in D1.
+//
+// Which one we use to sign new tokens is configurable, but we accept tokens signed either way.
+//
+
+// Verifying keys never change once they've been generated, but they stop verifying tokens once
+// they're retired, so each isolate only trusts its cached copy for this long before checking that
+// the key is still current.
+const VERIFYING_KEY_CACHE_TTL_MILLIS: u64 = 5 * 60 * 1000;
+
+// Each key is cached alongside when it was fetched.
+static VERIFYING_KEYS: Mutex<BTreeMap<ServerSigningKeyId, (ServerVerifyingKey, u64)>> =
+    Mutex::new(BTreeMap::new());
+
+fn now() -> anyhow::Result<DateTime<Utc>> {
+    DateTime::from_timestamp_millis(runtime::now_millis() as i64)
+        .ok_or_else(|| anyhow!("Current time is out of range."))
+}
+
+#[derive(Debug, Clone)]
+pub enum TokenSigningKey {
+    Ephemeral {
+        server_key_id: ServerKeyId,
+        key: EphemeralServerKey,
+    },
+    Server {
+        key_id: ServerSigningKeyId,
+        key: Box<ServerSigningKey>,
+    },
+}
+
+impl TokenSigningKey {
+    // Get the key to sign the tokens for a new session with. The session is identified by
+    // `server_key_id` regardless of which kind of key is used.
+    pub async fn for_new_session(
+        store: &Store,
+        server_key_id: &ServerKeyId,
+    ) -> anyhow::Result<Self> {
+        match config::jwt_signing_algorithm() {
+            JwtSigningAlgorithm::Hs256 => {
+                let key = EphemeralServerKey::generate();
+
+                store
+                    .store_ephemeral_server_key(server_key_id, &key)
+                    .await?;
+
+                Ok(Self::Ephemeral {
+                    server_key_id: server_key_id.clone(),
+                    key,
+                })
+            }
+            JwtSigningAlgorithm::EdDsa => current_server_signing_key(store).await,
+        }
+    }
+
+    // Get the key to sign the tokens for an existing session with.
+    pub async fn for_session(
+        store: &Store,
+        server_key_id: &ServerKeyId,
+    ) -> anyhow::Result<Option<Self>> {
+        match config::jwt_signing_algorithm() {
+            JwtSigningAlgorithm::Hs256 => Ok(store
+                .get_ephemeral_server_key(server_key_id)
+                .await?
+                .map(|key| Self::Ephemeral {
+                    server_key_id: server_key_id.clone(),
+                    key,
+                })),
+            JwtSigningAlgorithm::EdDsa => Ok(Some(current_server_signing_key(store).await?)),
+        }
+    }
+
+    // Receipts and log tree heads are always signed via EdDSA, whichever algorithm is used for
+    // tokens, because only the server verifying keys are published for clients to check them with.
+    pub async fn for_publication(store: &Store) -> anyhow::Result<Self> {
+        current_server_signing_key(store).await
+    }
+
+    pub fn header(&self) -> jwt::Header {
+        match self {
+            Self::Ephemeral { server_key_id, .. } => {
+                let mut header = jwt::Header::new(jwt::Algorithm::HS256);
+                header.kid = Some(server_key_id.to_string());
+                header
+            }
+            Self::Server { key_id, .. } => {
+                let mut header = jwt::Header::new(jwt::Algorithm::EdDSA);
+                header.kid = Some(key_id.to_string());
+                header
+            }
+        }
+    }
+
+    pub fn encoding_key(&self) -> anyhow::Result<jwt::EncodingKey> {
+        match self {
+            Self::Ephemeral { key, .. } => Ok(key.encoding_key()),
+            Self::Server { key, .. } => key.encoding_key(),
+        }
+    }
+}
+
+// Look up the key to verify a token with from its header. The algorithm in the header determines
+// where we look for the key, and only that algorithm is accepted when validating the token.
+pub async fn decoding_key(store: &Store, header: &jwt::Header) -> anyhow::Result<jwt::DecodingKey> {
+    let kid = header
+        .kid
+        .as_deref()
+        .ok_or_else(|| anyhow!("Token is missing the `kid` claim."))?;
+
+    match header.alg {
+        jwt::Algorithm::HS256 => {
+            let server_key_id = kid.parse::<ServerKeyId>()?;
+
+            let ephemeral_server_key = store
+                .get_ephemeral_server_key(&server_key_id)
+                .await?
+                .ok_or_else(|| anyhow!("Ephemeral server key for token `kid` does not exist."))?;
+
+            Ok(ephemeral_server_key.decoding_key())
+        }
+        jwt::Algorithm::EdDSA => {
+            let key_id = kid.parse::<ServerSigningKeyId>()?;
+            verifying_key(store, &key_id).await?.decoding_key()
+        }
+        alg => bail!("Token is signed with an unsupported algorithm: {:?}", alg),
+    }
+}
+
+async fn verifying_key(
+    store: &Store,
+    key_id: &ServerSigningKeyId,
+) -> anyhow::Result<ServerVerifyingKey> {
+    let now = runtime::now_millis();
+
+    let cached = {
+        let mut keys = VERIFYING_KEYS.lock().unwrap_or_else(|err| err.into_inner());
+        keys.retain(|_, (_, fetched_at)| {
+            now.saturating_sub(*fetched_at) < VERIFYING_KEY_CACHE_TTL_MILLIS
+        });
+        keys.get(key_id).map(|(key, _)| key.clone())
+    };
+
+    if let Some(key) = cached {
+        return Ok(key);
+    }
+
+    let key = store
+        .get_server_verifying_key(key_id)
+        .await?
+        .ok_or_else(|| {
+            anyhow!("Server signing key for token `kid` does not exist or is retired.")
+        })?;
+
+    VERIFYING_KEYS
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .insert(*key_id, (key.clone(), now));
+
+    Ok(key)
+}
+
+async fn current_server_signing_key(store: &Store) -> anyhow::Result<TokenSigningKey> {
+    if let Some(pair) = store.get_newest_server_signing_key().await? {
+        return Ok(TokenSigningKey::Server {
+            key_id: pair.id,
+            key: Box::new(pair.signing_key),
+        });
+    }
+
+    // This only happens the first time a token is signed via EdDSA, before the scheduled handler
+    // has generated a key.
+    let key_id = ServerSigningKeyId::new();
+    let key = ServerSigningKey::generate();
+
+    store.store_server_signing_key(&key_id, &key).await?;
+
+    Ok(TokenSigningKey::Server {
+        key_id,
+        key: Box::new(key),
+    })
+}
+
+// Generate a new server signing key if the current one is due for rotation, and retire keys which
+// can no longer have signed any unexpired tokens.
+pub async fn rotate_server_signing_keys(store: &Store) -> anyhow::Result<()> {
+    let is_due = match store.get_newest_server_signing_key().await? {
+        Some(pair) => {
+            let age = (now()? - pair.created_at).to_std().unwrap_or_default();
+            age >= config::server_signing_key_rotation()
+        }
+        None => true,
+    };
+
+    if is_due {
+        store
+            .store_server_signing_key(&ServerSigningKeyId::new(), &ServerSigningKey::generate())
+            .await?;
+    }
+
+    // Refresh tokens are exchanged for new access tokens signed with the current key, so we only
+    // need to wait for access tokens signed with the old key to expire.
+    store
+        .retire_server_signing_keys(config::access_token_exp())
+        .await
+}
@@ -0,0 +1,353 @@
+use std::sync::Arc;
+
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::Value as JsonValue;
+use worker::{
+    async_trait::async_trait,
+    d1::{serde_wasm_bindgen, D1Database, D1PreparedStatement, D1Result},
+    kv::KvStore,
+    send::SendFuture,
+    Bucket,
+};
+
+#[cfg(all(not(target_arch = "wasm32"), feature = "server"))]
+pub mod fs;
+#[cfg(all(test, not(target_arch = "wasm32")))]
+pub mod memory;
+#[cfg(all(not(target_arch = "wasm32"), any(test, feature = "server")))]
+pub mod sqlite;
+
+//
+// The store talks to D1, KV, and R2 through these traits, so it can also run against native
+// backends. The API deliberately mirrors the subset of the D1 API the store uses, and every backend
+// must have the same semantics as D1, KV, and R2: foreign keys are enforced, a batch is a single
+// transaction, a KV entry disappears once its TTL has passed, and deleting a blob which doesn't
+// exist succeeds.
+//
+
+// Build a statement with its parameters bound, like `worker::query!`.
+macro_rules! query {
+    ($db:expr, $query:expr $(, $args:expr)* $(,)?) => {
+        $crate::storage::Database::prepare($db, $query)
+            .bind_serialized(vec![$(::serde_json::to_value(&$args)),*])
+    };
+}
+
+pub(crate) use query;
+
+// A statement and its positional parameters, which are bound to `?1`, `?2`, etc.
+#[derive(Debug, Clone)]
+pub struct BoundStatement {
+    pub sql: String,
+    pub params: Vec<JsonValue>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ResultMeta {
+    pub changes: Option<usize>,
+}
+
+// The rows returned by a statement, as JSON objects keyed by column name.
+#[derive(Debug, Clone, Default)]
+pub struct QueryResult {
+    pub rows: Vec<JsonValue>,
+    pub meta: Option<ResultMeta>,
+}
+
+impl QueryResult {
+    pub fn results<T: DeserializeOwned>(&self) -> anyhow::Result<Vec<T>> {
+        self.rows
+            .iter()
+            .map(|row| Ok(T::deserialize(row)?))
+            .collect()
+    }
+
+    pub fn meta(&self) -> anyhow::Result<Option<ResultMeta>> {
+        Ok(self.meta.clone())
+    }
+}
+
+#[async_trait]
+pub trait SqlBackend: Send + Sync {
+    async fn execute(&self, stmt: &BoundStatement) -> anyhow::Result<QueryResult>;
+
+    // Run the statements in a single transaction, returning a result for each one.
+    async fn batch(&self, stmts: &[BoundStatement]) -> anyhow::Result<Vec<QueryResult>>;
+}
+
+#[async_trait]
+pub trait KvBackend: Send + Sync {
+    async fn get(&self, key: &str) -> anyhow::Result<Option<String>>;
+
+    async fn put(&self, key: &str, value: &str, ttl_secs: u64) -> anyhow::Result<()>;
+
+    async fn delete(&self, key: &str) -> anyhow::Result<()>;
+}
+
+// Blobs are opaque bytes, which are too large to store in D1 or KV.
+#[async_trait]
+pub trait BlobBackend: Send + Sync {
+    async fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>>;
+
+    async fn put(&self, key: &str, value: Vec<u8>) -> anyhow::Result<()>;
+
+    async fn delete(&self, key: &str) -> anyhow::Result<()>;
+}
+
+#[derive(Clone)]
+pub struct Database(Arc<dyn SqlBackend>);
+
+impl Database {
+    pub fn new(backend: impl SqlBackend + 'static) -> Self {
+        Self(Arc::new(backend))
+    }
+
+    pub fn prepare(&self, sql: &str) -> Statement {
+        Statement {
+            db: self.clone(),
+            stmt: BoundStatement {
+                sql: sql.to_string(),
+                params: Vec::new(),
+            },
+        }
+    }
+
+    pub async fn batch(&self, statements: Vec<Statement>) -> anyhow::Result<Vec<QueryResult>> {
+        let stmts = statements
+            .into_iter()
+            .map(|statement| statement.stmt)
+            .collect::<Vec<_>>();
+
+        self.0.batch(&stmts).await
+    }
+}
+
+pub struct Statement {
+    db: Database,
+    stmt: BoundStatement,
+}
+
+impl Statement {
+    pub fn bind(mut self, params: Vec<JsonValue>) -> Self {
+        self.stmt.params = params;
+        self
+    }
+
+    // Bind parameters which may have failed to serialize, which is what `query!` passes.
+    pub fn bind_serialized(
+        self,
+        params: Vec<serde_json::Result<JsonValue>>,
+    ) -> anyhow::Result<Self> {
+        Ok(self.bind(params.into_iter().collect::<Result<Vec<_>, _>>()?))
+    }
+
+    // Return the first row, or only the given column of the first row.
+    pub async fn first<T: DeserializeOwned>(
+        &self,
+        col_name: Option<&str>,
+    ) -> anyhow::Result<Option<T>> {
+        let row = match self.db.0.execute(&self.stmt).await?.rows.into_iter().next() {
+            Some(row) => row,
+            None => return Ok(None),
+        };
+
+        let value = match col_name {
+            Some(col_name) => row
+                .get(col_name)
+                .cloned()
+                .ok_or_else(|| anyhow::anyhow!("Column not found: {}", col_name))?,
+            None => row,
+        };
+
+        Ok(Some(T::deserialize(value)?))
+    }
+
+    pub async fn run(&self) -> anyhow::Result<QueryResult> {
+        self.db.0.execute(&self.stmt).await
+    }
+
+    pub async fn all(&self) -> anyhow::Result<QueryResult> {
+        self.db.0.execute(&self.stmt).await
+    }
+}
+
+#[derive(Clone)]
+pub struct KeyValue(Arc<dyn KvBackend>);
+
+impl KeyValue {
+    pub fn new(backend: impl KvBackend + 'static) -> Self {
+        Self(Arc::new(backend))
+    }
+
+    pub async fn get(&self, key: &str) -> anyhow::Result<Option<String>> {
+        self.0.get(key).await
+    }
+
+    pub async fn put(&self, key: &str, value: &str, ttl_secs: u64) -> anyhow::Result<()> {
+        self.0.put(key, value, ttl_secs).await
+    }
+
+    pub async fn delete(&self, key: &str) -> anyhow::Result<()> {
+        self.0.delete(key).await
+    }
+}
+
+#[derive(Clone)]
+pub struct Blobs(Arc<dyn BlobBackend>);
+
+impl Blobs {
+    pub fn new(backend: impl BlobBackend + 'static) -> Self {
+        Self(Arc::new(backend))
+    }
+
+    pub async fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        self.0.get(key).await
+    }
+
+    pub async fn put(&self, key: &str, value: Vec<u8>) -> anyhow::Result<()> {
+        self.0.put(key, value).await
+    }
+
+    pub async fn delete(&self, key: &str) -> anyhow::Result<()> {
+        self.0.delete(key).await
+    }
+}
+
+//
+// D1, Workers KV, and R2
+//
+
+pub struct D1Backend(D1Database);
+
+impl D1Backend {
+    pub fn new(db: D1Database) -> Self {
+        Self(db)
+    }
+
+    fn prepare(&self, stmt: &BoundStatement) -> anyhow::Result<D1PreparedStatement> {
+        // D1 doesn't support taking in undefined values, so we translate missing values to NULL.
+        let serializer = serde_wasm_bindgen::Serializer::new().serialize_missing_as_null(true);
+
+        let bindings = stmt
+            .params
+            .iter()
+            .map(|param| serde::Serialize::serialize(param, &serializer))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| anyhow::Error::msg(err.to_string()))?;
+
+        Ok(self.0.prepare(&stmt.sql).bind(&bindings)?)
+    }
+}
+
+fn d1_query_result(result: &D1Result) -> anyhow::Result<QueryResult> {
+    let meta = result.meta()?;
+
+    Ok(QueryResult {
+        rows: result.results::<JsonValue>()?,
+        meta: meta.map(|meta| ResultMeta {
+            changes: meta.changes,
+        }),
+    })
+}
+
+#[async_trait]
+impl SqlBackend for D1Backend {
+    async fn execute(&self, stmt: &BoundStatement) -> anyhow::Result<QueryResult> {
+        SendFuture::new(async {
+            let result = self.prepare(stmt)?.all().await?;
+            d1_query_result(&result)
+        })
+        .await
+    }
+
+    async fn batch(&self, stmts: &[BoundStatement]) -> anyhow::Result<Vec<QueryResult>> {
+        SendFuture::new(async {
+            let prepared = stmts
+                .iter()
+                .map(|stmt| self.prepare(stmt))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+
+            self.0
+                .batch(prepared)
+                .await?
+                .iter()
+                .map(d1_query_result)
+                .collect()
+        })
+        .await
+    }
+}
+
+pub struct WorkersKvBackend(KvStore);
+
+impl WorkersKvBackend {
+    pub fn new(kv: KvStore) -> Self {
+        Self(kv)
+    }
+}
+
+fn wrap_kv_err(err: worker::kv::KvError) -> anyhow::Error {
+    anyhow::Error::msg(err.to_string())
+}
+
+#[async_trait]
+impl KvBackend for WorkersKvBackend {
+    async fn get(&self, key: &str) -> anyhow::Result<Option<String>> {
+        SendFuture::new(async { self.0.get(key).text().await.map_err(wrap_kv_err) }).await
+    }
+
+    async fn put(&self, key: &str, value: &str, ttl_secs: u64) -> anyhow::Result<()> {
+        SendFuture::new(async {
+            self.0
+                .put(key, value)
+                .map_err(wrap_kv_err)?
+                .expiration_ttl(ttl_secs)
+                .execute()
+                .await
+                .map_err(wrap_kv_err)
+        })
+        .await
+    }
+
+    async fn delete(&self, key: &str) -> anyhow::Result<()> {
+        SendFuture::new(async { self.0.delete(key).await.map_err(wrap_kv_err) }).await
+    }
+}
+
+pub struct R2Backend(Bucket);
+
+impl R2Backend {
+    pub fn new(bucket: Bucket) -> Self {
+        Self(bucket)
+    }
+}
+
+#[async_trait]
+impl BlobBackend for R2Backend {
+    async fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        SendFuture::new(async {
+            let object = match self.0.get(key).execute().await? {
+                Some(object) => object,
+                None => return Ok(None),
+            };
+
+            match object.body() {
+                Some(body) => Ok(Some(body.bytes().await?)),
+                None => Ok(Some(Vec::new())),
+            }
+        })
+        .await
+    }
+
+    async fn put(&self, key: &str, value: Vec<u8>) -> anyhow::Result<()> {
+        SendFuture::new(async {
+            self.0.put(key, value).execute().await?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn delete(&self, key: &str) -> anyhow::Result<()> {
+        SendFuture::new(async { Ok(self.0.delete(key).await?) }).await
+    }
+}
@@ -0,0 +1,116 @@
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use anyhow::bail;
+use worker::async_trait::async_trait;
+
+use super::BlobBackend;
+
+// Blobs stored as files in a directory, with each `/`-separated segment of the key as a path
+// component. The store only uses keys made of IDs and numbers, so we refuse any other key rather
+// than risk it escaping the directory.
+pub struct FsBlobBackend {
+    root: PathBuf,
+}
+
+impl FsBlobBackend {
+    pub fn new(root: &Path) -> Self {
+        Self {
+            root: root.to_path_buf(),
+        }
+    }
+
+    fn path(&self, key: &str) -> anyhow::Result<PathBuf> {
+        let mut path = self.root.clone();
+
+        for segment in key.split('/') {
+            let is_valid = !segment.is_empty()
+                && segment
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'));
+
+            if !is_valid {
+                bail!("Invalid blob key: {}", key);
+            }
+
+            path.push(segment);
+        }
+
+        Ok(path)
+    }
+}
+
+#[async_trait]
+impl BlobBackend for FsBlobBackend {
+    async fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        match std::fs::read(self.path(key)?) {
+            Ok(value) => Ok(Some(value)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn put(&self, key: &str, value: Vec<u8>) -> anyhow::Result<()> {
+        let path = self.path(key)?;
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        // Write to a temporary file first so a reader never sees a partially written blob.
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, value)?;
+        std::fs::rename(&tmp_path, &path)?;
+
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> anyhow::Result<()> {
+        let path = self.path(key)?;
+
+        match std::fs::remove_file(&path) {
+            Ok(()) => {}
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err.into()),
+        }
+
+        // Clean up the directory for the key's prefix once it's empty, which fails harmlessly if
+        // it isn't.
+        if let Some(parent) = path.parent() {
+            if parent != self.root {
+                let _ = std::fs::remove_dir(parent);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+    use uuid::Uuid;
+
+    use super::*;
+
+    #[test]
+    fn keys_cannot_escape_the_root() {
+        let root = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        let blobs = FsBlobBackend::new(&root);
+
+        block_on(async {
+            blobs.put("a/0", b"value".to_vec()).await.unwrap();
+            assert_eq!(blobs.get("a/0").await.unwrap(), Some(b"value".to_vec()));
+
+            blobs.delete("a/0").await.unwrap();
+            assert_eq!(blobs.get("a/0").await.unwrap(), None);
+
+            assert!(blobs.put("../escaped", Vec::new()).await.is_err());
+            assert!(blobs.get("/etc/passwd").await.is_err());
+        });
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
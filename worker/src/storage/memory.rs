@@ -0,0 +1,135 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::anyhow;
+use worker::async_trait::async_trait;
+
+use super::{BlobBackend, KvBackend};
+use crate::runtime;
+
+// A KV namespace which lives in memory. Like Workers KV, entries can't be read once their TTL has
+// passed.
+#[derive(Debug, Default)]
+pub struct MemoryKvBackend {
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    value: String,
+    expires_at_millis: u64,
+}
+
+impl MemoryKvBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> anyhow::Result<std::sync::MutexGuard<'_, HashMap<String, Entry>>> {
+        self.entries
+            .lock()
+            .map_err(|_| anyhow!("KV mutex is poisoned."))
+    }
+}
+
+#[async_trait]
+impl KvBackend for MemoryKvBackend {
+    async fn get(&self, key: &str) -> anyhow::Result<Option<String>> {
+        let mut entries = self.lock()?;
+
+        match entries.get(key) {
+            Some(entry) if entry.expires_at_millis > runtime::now_millis() => {
+                Ok(Some(entry.value.clone()))
+            }
+            Some(_) => {
+                entries.remove(key);
+                Ok(None)
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn put(&self, key: &str, value: &str, ttl_secs: u64) -> anyhow::Result<()> {
+        let expires_at_millis = runtime::now_millis().saturating_add(ttl_secs * 1000);
+
+        self.lock()?.insert(
+            key.to_string(),
+            Entry {
+                value: value.to_string(),
+                expires_at_millis,
+            },
+        );
+
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> anyhow::Result<()> {
+        self.lock()?.remove(key);
+        Ok(())
+    }
+}
+
+// A blob store which lives in memory. Clones share the same blobs, so tests can keep a handle to
+// check what's stored.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryBlobBackend {
+    blobs: Arc<Mutex<HashMap<String, Vec<u8>>>>,
+}
+
+impl MemoryBlobBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn count(&self) -> usize {
+        self.blobs
+            .lock()
+            .map(|blobs| blobs.len())
+            .unwrap_or_default()
+    }
+
+    fn lock(&self) -> anyhow::Result<std::sync::MutexGuard<'_, HashMap<String, Vec<u8>>>> {
+        self.blobs
+            .lock()
+            .map_err(|_| anyhow!("Blob store mutex is poisoned."))
+    }
+}
+
+#[async_trait]
+impl BlobBackend for MemoryBlobBackend {
+    async fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        Ok(self.lock()?.get(key).cloned())
+    }
+
+    async fn put(&self, key: &str, value: Vec<u8>) -> anyhow::Result<()> {
+        self.lock()?.insert(key.to_string(), value);
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> anyhow::Result<()> {
+        self.lock()?.remove(key);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+
+    use super::*;
+
+    #[test]
+    fn entries_expire_after_their_ttl() {
+        let kv = MemoryKvBackend::new();
+
+        block_on(async {
+            kv.put("live", "value", 60).await.unwrap();
+            kv.put("expired", "value", 0).await.unwrap();
+
+            assert_eq!(kv.get("live").await.unwrap().as_deref(), Some("value"));
+            assert_eq!(kv.get("expired").await.unwrap(), None);
+        });
+    }
+}
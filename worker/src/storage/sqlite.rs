@@ -0,0 +1,376 @@
+#[cfg(feature = "server")]
+use std::path::Path;
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use anyhow::anyhow;
+use rusqlite::{
+    types::{Value as SqlValue, ValueRef},
+    Connection,
+};
+use serde_json::{Map, Value as JsonValue};
+use worker::async_trait::async_trait;
+
+use super::{BoundStatement, KvBackend, QueryResult, ResultMeta, SqlBackend};
+use crate::runtime;
+
+// Every migration in `worker/migrations`, in order. Wrangler applies these to D1; natively, we
+// apply them ourselves when opening the database.
+const MIGRATIONS: &[(&str, &str)] = &[
+    (
+        "0001_schema.sql",
+        include_str!("../../migrations/0001_schema.sql"),
+    ),
+    (
+        "0002_form_template_version.sql",
+        include_str!("../../migrations/0002_form_template_version.sql"),
+    ),
+    (
+        "0003_secret_link_passwords.sql",
+        include_str!("../../migrations/0003_secret_link_passwords.sql"),
+    ),
+    (
+        "0004_unique_client_key_ids.sql",
+        include_str!("../../migrations/0004_unique_client_key_ids.sql"),
+    ),
+    (
+        "0005_sessions.sql",
+        include_str!("../../migrations/0005_sessions.sql"),
+    ),
+    (
+        "0006_refresh_tokens.sql",
+        include_str!("../../migrations/0006_refresh_tokens.sql"),
+    ),
+    (
+        "0007_key_epochs.sql",
+        include_str!("../../migrations/0007_key_epochs.sql"),
+    ),
+    (
+        "0008_server_signing_keys.sql",
+        include_str!("../../migrations/0008_server_signing_keys.sql"),
+    ),
+    (
+        "0009_metrics.sql",
+        include_str!("../../migrations/0009_metrics.sql"),
+    ),
+    (
+        "0010_webhooks.sql",
+        include_str!("../../migrations/0010_webhooks.sql"),
+    ),
+    (
+        "0011_push_subscriptions.sql",
+        include_str!("../../migrations/0011_push_subscriptions.sql"),
+    ),
+    (
+        "0012_digest_subscriptions.sql",
+        include_str!("../../migrations/0012_digest_subscriptions.sql"),
+    ),
+    (
+        "0013_submission_limits.sql",
+        include_str!("../../migrations/0013_submission_limits.sql"),
+    ),
+    (
+        "0014_attachments.sql",
+        include_str!("../../migrations/0014_attachments.sql"),
+    ),
+    (
+        "0015_submission_receipts.sql",
+        include_str!("../../migrations/0015_submission_receipts.sql"),
+    ),
+    (
+        "0016_submission_replies.sql",
+        include_str!("../../migrations/0016_submission_replies.sql"),
+    ),
+    (
+        "0017_template_signatures.sql",
+        include_str!("../../migrations/0017_template_signatures.sql"),
+    ),
+    (
+        "0018_form_logs.sql",
+        include_str!("../../migrations/0018_form_logs.sql"),
+    ),
+    (
+        "0019_audit_events.sql",
+        include_str!("../../migrations/0019_audit_events.sql"),
+    ),
+    (
+        "0020_form_deletions.sql",
+        include_str!("../../migrations/0020_form_deletions.sql"),
+    ),
+];
+
+// Wrangler records applied migrations in this table, and the store reads the schema version from
+// it.
+const MIGRATIONS_TABLE: &str = "
+    CREATE TABLE IF NOT EXISTS d1_migrations (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        name TEXT UNIQUE,
+        applied_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+    );
+";
+
+// This stands in for KV, so it isn't one of the D1 migrations. Expiration times are in Unix
+// milliseconds.
+const KV_TABLE: &str = "
+    CREATE TABLE IF NOT EXISTS kv (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL,
+        expires_at INTEGER NOT NULL
+    );
+";
+
+// Statements run one at a time on a single connection, the same as they do against D1.
+#[derive(Clone)]
+pub struct SqliteBackend(Arc<Mutex<Connection>>);
+
+fn lock(conn: &Mutex<Connection>) -> anyhow::Result<MutexGuard<'_, Connection>> {
+    conn.lock()
+        .map_err(|_| anyhow!("SQLite connection mutex is poisoned."))
+}
+
+impl SqliteBackend {
+    // Open the database file, creating it if it doesn't exist, and apply any migrations it's
+    // missing.
+    #[cfg(feature = "server")]
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        Self::migrate(Connection::open(path)?)
+    }
+
+    // An empty database with every migration applied.
+    #[cfg(test)]
+    pub fn open_in_memory() -> anyhow::Result<Self> {
+        Self::migrate(Connection::open_in_memory()?)
+    }
+
+    fn migrate(conn: Connection) -> anyhow::Result<Self> {
+        // D1 always enforces foreign keys, which we rely on for cascading deletes.
+        conn.pragma_update(None, "foreign_keys", true)?;
+
+        conn.execute_batch(MIGRATIONS_TABLE)?;
+        conn.execute_batch(KV_TABLE)?;
+
+        let applied = conn
+            .prepare("SELECT name FROM d1_migrations;")?
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<Result<HashSet<_>, _>>()?;
+
+        for (name, sql) in MIGRATIONS {
+            if applied.contains(*name) {
+                continue;
+            }
+
+            let tx = conn.unchecked_transaction()?;
+            tx.execute_batch(sql)?;
+            tx.execute("INSERT INTO d1_migrations (name) VALUES (?1);", [name])?;
+            tx.commit()?;
+        }
+
+        Ok(Self(Arc::new(Mutex::new(conn))))
+    }
+
+    // A KV namespace stored in the same database.
+    pub fn kv(&self) -> SqliteKvBackend {
+        SqliteKvBackend(Arc::clone(&self.0))
+    }
+
+    fn lock(&self) -> anyhow::Result<MutexGuard<'_, Connection>> {
+        lock(&self.0)
+    }
+}
+
+fn to_sql_value(value: &JsonValue) -> SqlValue {
+    match value {
+        JsonValue::Null => SqlValue::Null,
+        JsonValue::Bool(value) => SqlValue::Integer(i64::from(*value)),
+        JsonValue::Number(number) => match number.as_i64() {
+            Some(integer) => SqlValue::Integer(integer),
+            None => SqlValue::Real(number.as_f64().unwrap_or_default()),
+        },
+        JsonValue::String(value) => SqlValue::Text(value.clone()),
+        JsonValue::Array(_) | JsonValue::Object(_) => SqlValue::Text(value.to_string()),
+    }
+}
+
+fn to_json_value(value: ValueRef<'_>) -> JsonValue {
+    match value {
+        ValueRef::Null => JsonValue::Null,
+        ValueRef::Integer(integer) => JsonValue::from(integer),
+        ValueRef::Real(real) => JsonValue::from(real),
+        ValueRef::Text(text) => JsonValue::from(String::from_utf8_lossy(text).into_owned()),
+        ValueRef::Blob(blob) => JsonValue::from(blob.to_vec()),
+    }
+}
+
+fn execute(conn: &Connection, stmt: &BoundStatement) -> anyhow::Result<QueryResult> {
+    let mut prepared = conn.prepare(&stmt.sql)?;
+
+    let columns = prepared
+        .column_names()
+        .into_iter()
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    let is_readonly = prepared.readonly();
+
+    let params = stmt.params.iter().map(to_sql_value).collect::<Vec<_>>();
+    let mut rows = prepared.query(rusqlite::params_from_iter(params))?;
+
+    let mut results = Vec::new();
+
+    while let Some(row) = rows.next()? {
+        let mut object = Map::new();
+
+        for (index, column) in columns.iter().enumerate() {
+            object.insert(column.clone(), to_json_value(row.get_ref(index)?));
+        }
+
+        results.push(JsonValue::Object(object));
+    }
+
+    // Like D1, this doesn't count rows changed by triggers or foreign key actions.
+    let changes = if is_readonly {
+        0
+    } else {
+        conn.changes() as usize
+    };
+
+    Ok(QueryResult {
+        rows: results,
+        meta: Some(ResultMeta {
+            changes: Some(changes),
+        }),
+    })
+}
+
+#[async_trait]
+impl SqlBackend for SqliteBackend {
+    async fn execute(&self, stmt: &BoundStatement) -> anyhow::Result<QueryResult> {
+        execute(&*self.lock()?, stmt)
+    }
+
+    async fn batch(&self, stmts: &[BoundStatement]) -> anyhow::Result<Vec<QueryResult>> {
+        let conn = self.lock()?;
+        let tx = conn.unchecked_transaction()?;
+
+        let results = stmts
+            .iter()
+            .map(|stmt| execute(&tx, stmt))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        tx.commit()?;
+
+        Ok(results)
+    }
+}
+
+// Like Workers KV, entries can't be read once their TTL has passed. Expired entries are only
+// deleted by `delete_expired`, which the server runs on a timer.
+pub struct SqliteKvBackend(Arc<Mutex<Connection>>);
+
+impl SqliteKvBackend {
+    pub fn delete_expired(&self) -> anyhow::Result<usize> {
+        Ok(lock(&self.0)?.execute(
+            "DELETE FROM kv WHERE expires_at <= ?1;",
+            [runtime::now_millis() as i64],
+        )?)
+    }
+}
+
+#[async_trait]
+impl KvBackend for SqliteKvBackend {
+    async fn get(&self, key: &str) -> anyhow::Result<Option<String>> {
+        let conn = lock(&self.0)?;
+
+        let mut stmt = conn.prepare("SELECT value FROM kv WHERE key = ?1 AND expires_at > ?2;")?;
+        let mut rows = stmt.query(rusqlite::params![key, runtime::now_millis() as i64])?;
+
+        match rows.next()? {
+            Some(row) => Ok(Some(row.get(0)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn put(&self, key: &str, value: &str, ttl_secs: u64) -> anyhow::Result<()> {
+        let expires_at = runtime::now_millis().saturating_add(ttl_secs * 1000);
+
+        lock(&self.0)?.execute(
+            "
+            INSERT INTO kv (key, value, expires_at)
+            VALUES (?1, ?2, ?3)
+            ON CONFLICT (key) DO UPDATE SET
+                value = excluded.value,
+                expires_at = excluded.expires_at;
+            ",
+            rusqlite::params![key, value, expires_at as i64],
+        )?;
+
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> anyhow::Result<()> {
+        lock(&self.0)?.execute("DELETE FROM kv WHERE key = ?1;", [key])?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+    use serde_json::json;
+
+    use super::*;
+    use crate::store::SCHEMA_VERSION;
+
+    fn stmt(sql: &str, params: Vec<JsonValue>) -> BoundStatement {
+        BoundStatement {
+            sql: sql.to_string(),
+            params,
+        }
+    }
+
+    #[test]
+    fn migrations_are_current() {
+        let (name, _) = MIGRATIONS.last().unwrap();
+        let number = name.split('_').next().unwrap().parse::<u32>().unwrap();
+
+        assert_eq!(number, SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn failed_batch_is_rolled_back() {
+        let db = SqliteBackend::open_in_memory().unwrap();
+
+        let insert = stmt(
+            "INSERT INTO forms (form_id, template, public_primary_key) VALUES (?1, '{}', '');",
+            vec![json!("form")],
+        );
+
+        block_on(async {
+            // The second insert violates the unique constraint on `form_id`.
+            assert!(db.batch(&[insert.clone(), insert.clone()]).await.is_err());
+
+            let count = db
+                .execute(&stmt("SELECT count(*) AS count FROM forms;", Vec::new()))
+                .await
+                .unwrap();
+
+            assert_eq!(count.rows[0]["count"], json!(0));
+        });
+    }
+
+    #[test]
+    fn kv_entries_expire_after_their_ttl() {
+        let kv = SqliteBackend::open_in_memory().unwrap().kv();
+
+        block_on(async {
+            kv.put("live", "value", 60).await.unwrap();
+            kv.put("expired", "value", 0).await.unwrap();
+
+            assert_eq!(kv.get("live").await.unwrap().as_deref(), Some("value"));
+            assert_eq!(kv.get("expired").await.unwrap(), None);
+        });
+
+        assert_eq!(kv.delete_expired().unwrap(), 1);
+    }
+}
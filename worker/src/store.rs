@@ -1,19 +1,37 @@
-use std::fmt;
+use std::{
+    fmt,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
 
-use chrono::{DateTime, NaiveDateTime, Utc};
+use anyhow::{anyhow, bail};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use futures::future;
 use secrecy::ExposeSecret;
 use serde::Deserialize;
-use worker::{d1::D1Database, kv::KvStore, query};
 
 use crate::{
     auth::AccessRole,
     config,
-    keys::{EphemeralServerKey, PublicPrimaryKey, PublicSigningKey, WrappedPrivatePrimaryKey},
+    idempotency::IdempotencyRecord,
+    keys::{
+        EncryptedEmailAddress, EncryptedServerSigningKey, EphemeralServerKey, MailboxTokenHash,
+        PublicPrimaryKey, PublicSigningKey, PushSubscriptionAuth, PushSubscriptionKey,
+        RefreshTokenHash, ReplyPublicKey, ServerSigningKey, ServerVerifyingKey, TemplateSignature,
+        WebhookSecret, WrappedPrivatePrimaryKey,
+    },
+    metrics::{MetricIncrement, MetricRow},
     models::{
-        ChallengeId, ClientKeyId, ClientKeys, EncryptedKeyComment, EncryptedSubmissionBody,
-        FormData, FormId, FormTemplate, FormUpdate, SecretLinkPasswordNonce,
-        SecretLinkPasswordParams, SecretLinkPasswordSalt, ServerKeyId, Submission, SubmissionId,
+        Attachment, AttachmentId, AuditAction, AuditEvent, ChallengeId, ClientKeyId, ClientKeys,
+        EncryptedAuditDetails, EncryptedKeyComment, EncryptedReplyBody, EncryptedSubmissionBody,
+        FormData, FormId, FormStats, FormTemplate, FormUpdate, KeyEpoch, LogEntry, NewAuditEvent,
+        NewForm, NewWebhook, PendingDigest, PushSubscription, PushSubscriptionId, ReceiptKey,
+        Reply, ReplyId, RespondentKeys, SecretLinkPasswordNonce, SecretLinkPasswordParams,
+        SecretLinkPasswordSalt, ServerKeyId, ServerSigningKeyId, ServerSigningKeyPair, Session,
+        StatsPeriod, Submission, SubmissionCount, SubmissionId, TemplateSignatureChange, Webhook,
+        WebhookId, WebhookTarget,
     },
+    storage::{query, Blobs, Database, KeyValue, QueryResult, Statement},
 };
 
 // SQLite natively understands datetime strings with this format; it uses the format when
@@ -26,6 +44,17 @@ const SQLITE_DATETIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
 // https://sqlite.org/json1.html
 pub const FORM_TEMPLATE_CURRENT_VERSION: u32 = 1;
 
+// This must be the number of the latest migration in `worker/migrations`. We refuse writes if the
+// migrations applied to the database are older than this, since the code may depend on them.
+pub const SCHEMA_VERSION: u32 = 20;
+
+// Migrations are only ever applied, never reverted, so once we've seen that the schema is current,
+// we don't need to check again for the life of the isolate.
+static SCHEMA_IS_CURRENT: AtomicBool = AtomicBool::new(false);
+
+// This key is never written; reading it is enough to show that KV is reachable.
+const HEALTH_CHECK_KEY: &str = "health";
+
 fn server_key_ttl() -> u64 {
     config::access_token_exp().as_secs() * 2
 }
@@ -42,16 +71,75 @@ fn challenge_key(challenge_id: &ChallengeId) -> String {
     format!("challenge:{}", challenge_id)
 }
 
-fn wrap_kv_err(err: worker::kv::KvError) -> anyhow::Error {
-    anyhow::Error::msg(err.to_string())
+fn idempotency_key(scope: &str) -> String {
+    format!("idempotency:{}", scope)
+}
+
+fn attachment_chunk_key(attachment_id: &AttachmentId, chunk_index: u32) -> String {
+    format!("attachments/{}/{}", attachment_id, chunk_index)
+}
+
+// The attachment IDs selected by a statement in a batch, when the statement after it might delete
+// them.
+fn attachment_ids_from(result: Option<&QueryResult>) -> anyhow::Result<Vec<AttachmentId>> {
+    #[derive(Debug, Deserialize)]
+    struct Row {
+        attachment_id: AttachmentId,
+    }
+
+    Ok(result
+        .map(QueryResult::results::<Row>)
+        .transpose()?
+        .unwrap_or_default()
+        .into_iter()
+        .map(|row| row.attachment_id)
+        .collect())
+}
+
+// The number of rows a statement in a batch changed.
+fn changes_from(result: Option<&QueryResult>) -> anyhow::Result<usize> {
+    Ok(match result {
+        Some(result) => result.meta()?.and_then(|meta| meta.changes).unwrap_or(0),
+        None => 0,
+    })
+}
+
+// The result of an operation on a client key which is not allowed to leave a form without any keys
+// that have the admin role.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyChangeOutcome {
+    Changed,
+    NotFound,
+    LastAdmin,
+}
+
+// The result of adding a chunk to an attachment. Chunks must be added in order, and only until the
+// attachment is made part of a submission.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkOutcome {
+    Added,
+    NotFound,
+    OutOfOrder,
+    TooLarge,
+    OverQuota,
+    PendingOverQuota,
+}
+
+// The result of sending a reply to a respondent. Only submissions made with a reply public key can
+// be replied to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplyOutcome {
+    Added,
+    NotFound,
+    LimitReached,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct UnauthenticatedStore(Store);
 
 impl UnauthenticatedStore {
-    pub fn new(db: D1Database, kv: KvStore) -> Self {
-        Self(Store { db, kv })
+    pub fn new(db: Database, kv: KeyValue, blobs: Blobs) -> Self {
+        Self(Store { db, kv, blobs })
     }
 
     // If we want to access the store without authenticating, we need to be explicit about it.
@@ -60,9 +148,11 @@ impl UnauthenticatedStore {
     }
 }
 
+#[derive(Clone)]
 pub struct Store {
-    db: D1Database,
-    kv: KvStore,
+    db: Database,
+    kv: KeyValue,
+    blobs: Blobs,
 }
 
 impl fmt::Debug for Store {
@@ -72,6 +162,20 @@ impl fmt::Debug for Store {
 }
 
 impl Store {
+    // Run the statements in a single D1 batch, which is a transaction, so they either all succeed
+    // or all fail. Anything which writes with more than one statement should go through here, so
+    // that a failure partway through can't leave the database in an inconsistent state.
+    async fn batch(&self, statements: Vec<Statement>) -> anyhow::Result<Vec<QueryResult>> {
+        let expected = statements.len();
+        let results = self.db.batch(statements).await?;
+
+        if results.len() != expected {
+            bail!("Expected a result for each statement in the batch.");
+        }
+
+        Ok(results)
+    }
+
     #[worker::send]
     pub async fn get_form_data(&self, form_id: &FormId) -> anyhow::Result<Option<FormData>> {
         let stmt = query!(
@@ -80,7 +184,11 @@ impl Store {
             SELECT
                 template,
                 public_primary_key,
-                expires_at
+                key_epoch,
+                expires_at,
+                max_submission_len,
+                template_signing_key,
+                template_signature
             FROM forms
             WHERE form_id = ?1;
             ",
@@ -91,7 +199,11 @@ impl Store {
         struct Row {
             template: String,
             public_primary_key: PublicPrimaryKey,
+            key_epoch: KeyEpoch,
             expires_at: Option<String>,
+            max_submission_len: Option<usize>,
+            template_signing_key: Option<PublicSigningKey>,
+            template_signature: Option<TemplateSignature>,
         }
 
         stmt.first::<Row>(None)
@@ -100,44 +212,110 @@ impl Store {
                 Ok(FormData {
                     template: serde_json::from_str::<FormTemplate>(&raw.template)?,
                     public_primary_key: raw.public_primary_key,
+                    key_epoch: raw.key_epoch,
                     expires_at: raw
                         .expires_at
                         .map(|s| NaiveDateTime::parse_from_str(&s, SQLITE_DATETIME_FORMAT))
                         .transpose()?
                         .map(|dt| dt.and_utc()),
+                    max_submission_len: raw.max_submission_len,
+                    template_signing_key: raw.template_signing_key,
+                    template_signature: raw.template_signature,
                 })
             })
             .transpose()
     }
 
+    // The form and its initial client key are created together, so a failure can't leave behind
+    // a form which nobody has the keys to use or delete.
     #[worker::send]
-    pub async fn put_form_template(
+    pub async fn create_form(
         &self,
         form_id: &FormId,
-        template: &FormTemplate,
-        public_primary_key: &PublicPrimaryKey,
-        expires_at: Option<DateTime<Utc>>,
-    ) -> anyhow::Result<()> {
-        let stmt = query!(
+        form: &NewForm,
+        public_signing_key: &PublicSigningKey,
+    ) -> anyhow::Result<ClientKeyId> {
+        let form_stmt = query!(
             &self.db,
             "
-            INSERT INTO forms (form_id, template, public_primary_key, expires_at)
-            VALUES (?1, ?2, ?3, ?4);
+            INSERT INTO forms (
+                form_id,
+                template,
+                public_primary_key,
+                expires_at,
+                max_submission_len,
+                template_signing_key,
+                template_signature
+            )
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7);
             ",
             form_id,
-            serde_json::to_string(&template)?,
-            public_primary_key,
-            expires_at.map(|dt| dt.format(SQLITE_DATETIME_FORMAT).to_string()),
+            serde_json::to_string(&form.template)?,
+            form.public_primary_key,
+            form.expires_at
+                .map(|dt| dt.format(SQLITE_DATETIME_FORMAT).to_string()),
+            form.max_submission_len,
+            form.template_signing_key,
+            form.template_signature,
         )?;
 
-        stmt.run().await?.meta()?;
+        // The initial secret link will always have admin access.
+        let keys_stmt = self.insert_client_keys_stmt(
+            form_id,
+            public_signing_key,
+            None,
+            &EncryptedKeyComment::default(),
+            AccessRole::Admin,
+        )?;
 
-        Ok(())
+        let results = self.batch(vec![form_stmt, keys_stmt]).await?;
+
+        #[derive(Debug, Deserialize)]
+        struct Row {
+            key_index: ClientKeyId,
+        }
+
+        results
+            .last()
+            .map(QueryResult::results::<Row>)
+            .transpose()?
+            .and_then(|rows| rows.into_iter().next())
+            .map(|row| row.key_index)
+            .ok_or_else(|| anyhow!("Creating the form did not return its initial client key."))
     }
 
+    // Returns the IDs of the form's attachments, so the caller can purge their chunks. The form's
+    // audit log is deleted along with it, so the deletion is recorded separately.
     #[worker::send]
-    pub async fn delete_form(&self, form_id: &FormId) -> anyhow::Result<()> {
-        let stmt = query!(
+    pub async fn delete_form(
+        &self,
+        form_id: &FormId,
+        actor: &ClientKeyId,
+    ) -> anyhow::Result<Vec<AttachmentId>> {
+        let deletion_stmt = query!(
+            &self.db,
+            "
+            INSERT INTO form_deletions (form_id, actor)
+            SELECT forms.form_id, ?2
+            FROM forms
+            WHERE forms.form_id = ?1;
+            ",
+            form_id,
+            actor,
+        )?;
+
+        let attachments_stmt = query!(
+            &self.db,
+            "
+            SELECT attachments.attachment_id
+            FROM attachments
+            JOIN forms ON attachments.form = forms.id
+            WHERE forms.form_id = ?1;
+            ",
+            form_id,
+        )?;
+
+        let form_stmt = query!(
             &self.db,
             "
             DELETE FROM forms
@@ -146,31 +324,221 @@ impl Store {
             form_id,
         )?;
 
-        stmt.run().await?.meta()?;
+        let results = self
+            .batch(vec![deletion_stmt, attachments_stmt, form_stmt])
+            .await?;
 
-        Ok(())
+        attachment_ids_from(results.get(1))
     }
 
+    // Returns `false` if the form's template signature changed since `signature` was checked.
     #[worker::send]
-    pub async fn edit_form(&self, form_id: &FormId, data: &FormUpdate) -> anyhow::Result<()> {
-        let stmt = query!(
+    pub async fn edit_form(
+        &self,
+        form_id: &FormId,
+        data: &FormUpdate,
+        signature: &TemplateSignatureChange,
+        audit_event: &NewAuditEvent,
+    ) -> anyhow::Result<bool> {
+        let form_stmt = query!(
             &self.db,
             "
             UPDATE forms
             SET
                 template = ?2,
-                expires_at = ?3
-            WHERE form_id = ?1;
+                expires_at = ?3,
+                max_submission_len = COALESCE(?4, max_submission_len),
+                template_signature = ?6
+            WHERE
+                form_id = ?1
+                AND template_signature IS ?5;
             ",
             form_id,
             serde_json::to_string(&data.template)?,
             data.expires_at
                 .map(|dt| dt.format(SQLITE_DATETIME_FORMAT).to_string()),
+            data.max_submission_len,
+            signature.current,
+            signature.new,
         )?;
 
-        stmt.run().await?.meta()?;
+        let audit_stmt = self.audit_event_stmt(form_id, audit_event, true)?;
 
-        Ok(())
+        let results = self.batch(vec![form_stmt, audit_stmt]).await?;
+
+        Ok(changes_from(results.first())? > 0)
+    }
+
+    // Get the number of the latest migration applied to the database. Wrangler records applied
+    // migrations in this table by their file name, which starts with the migration number.
+    #[worker::send]
+    pub async fn get_schema_version(&self) -> anyhow::Result<Option<u32>> {
+        let stmt = query!(
+            &self.db,
+            "
+            SELECT name
+            FROM d1_migrations
+            ORDER BY id DESC
+            LIMIT 1;
+            ",
+        )?;
+
+        #[derive(Debug, Deserialize)]
+        struct Row {
+            name: String,
+        }
+
+        stmt.first::<Row>(None)
+            .await?
+            .map(|row| {
+                let number = row.name.split('_').next().unwrap_or_default();
+                number
+                    .parse::<u32>()
+                    .map_err(|_| anyhow!("Could not parse migration name: {}", row.name))
+            })
+            .transpose()
+    }
+
+    #[worker::send]
+    pub async fn is_schema_current(&self) -> anyhow::Result<bool> {
+        if SCHEMA_IS_CURRENT.load(Ordering::Relaxed) {
+            return Ok(true);
+        }
+
+        let is_current = self
+            .get_schema_version()
+            .await?
+            .is_some_and(|version| version >= SCHEMA_VERSION);
+
+        if is_current {
+            SCHEMA_IS_CURRENT.store(true, Ordering::Relaxed);
+        }
+
+        Ok(is_current)
+    }
+
+    // Both queries run in the same batch so the counts are consistent with each other.
+    #[worker::send]
+    pub async fn get_form_stats(
+        &self,
+        form_id: &FormId,
+        period: StatsPeriod,
+    ) -> anyhow::Result<Option<FormStats>> {
+        // SQLite date modifiers which round a timestamp down to the start of its period.
+        let (round_modifier, offset_modifier) = match period {
+            StatsPeriod::Day => ("start of day", "+0 days"),
+            StatsPeriod::Week => ("weekday 0", "-6 days"),
+        };
+
+        let summary_stmt = query!(
+            &self.db,
+            "
+            SELECT
+                (
+                    SELECT COUNT(submissions.id)
+                    FROM submissions
+                    WHERE submissions.form = forms.id
+                ) AS submission_count,
+                (
+                    SELECT MAX(submissions.created_at)
+                    FROM submissions
+                    WHERE submissions.form = forms.id
+                ) AS newest_submission_at,
+                (
+                    SELECT COUNT(keys.id)
+                    FROM keys
+                    WHERE keys.form = forms.id
+                ) AS key_count,
+                (
+                    SELECT COUNT(access_log.id)
+                    FROM access_log
+                    JOIN keys ON access_log.key = keys.id
+                    WHERE keys.form = forms.id
+                ) AS access_count,
+                (
+                    SELECT MAX(access_log.accessed_at)
+                    FROM access_log
+                    JOIN keys ON access_log.key = keys.id
+                    WHERE keys.form = forms.id
+                ) AS last_accessed_at
+            FROM forms
+            WHERE forms.form_id = ?1;
+            ",
+            form_id,
+        )?;
+
+        let periods_stmt = query!(
+            &self.db,
+            "
+            SELECT
+                date(submissions.created_at, ?2, ?3) AS period_start,
+                COUNT(submissions.id) AS count
+            FROM submissions
+            JOIN forms ON submissions.form = forms.id
+            WHERE forms.form_id = ?1
+            GROUP BY period_start
+            ORDER BY period_start;
+            ",
+            form_id,
+            round_modifier,
+            offset_modifier,
+        )?;
+
+        let results = self.batch(vec![summary_stmt, periods_stmt]).await?;
+
+        #[derive(Debug, Deserialize)]
+        struct SummaryRow {
+            submission_count: u64,
+            newest_submission_at: Option<String>,
+            key_count: u64,
+            access_count: u64,
+            last_accessed_at: Option<String>,
+        }
+
+        #[derive(Debug, Deserialize)]
+        struct PeriodRow {
+            period_start: String,
+            count: u64,
+        }
+
+        let (summary, periods) = match results.as_slice() {
+            [summary, periods] => (
+                summary.results::<SummaryRow>()?,
+                periods.results::<PeriodRow>()?,
+            ),
+            _ => bail!("Expected a result for each statement in the batch."),
+        };
+
+        let summary = match summary.into_iter().next() {
+            Some(summary) => summary,
+            None => return Ok(None),
+        };
+
+        let parse_datetime = |value: Option<String>| {
+            value
+                .map(|value| {
+                    NaiveDateTime::parse_from_str(&value, SQLITE_DATETIME_FORMAT)
+                        .map(|datetime| datetime.and_utc())
+                })
+                .transpose()
+        };
+
+        Ok(Some(FormStats {
+            submission_count: summary.submission_count,
+            newest_submission_at: parse_datetime(summary.newest_submission_at)?,
+            submissions_per_period: periods
+                .into_iter()
+                .map(|row| {
+                    Ok(SubmissionCount {
+                        period_start: NaiveDate::parse_from_str(&row.period_start, "%Y-%m-%d")?,
+                        count: row.count,
+                    })
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?,
+            key_count: summary.key_count,
+            access_count: summary.access_count,
+            last_accessed_at: parse_datetime(summary.last_accessed_at)?,
+        }))
     }
 
     #[worker::send]
@@ -193,7 +561,22 @@ impl Store {
         let stmt = query!(
             &self.db,
             "
-            SELECT submissions.encrypted_body, submissions.created_at
+            SELECT
+                submissions.submission_id,
+                submissions.encrypted_body,
+                submissions.key_epoch,
+                submissions.created_at,
+                submissions.revision,
+                submissions.reply_public_key,
+                (
+                    SELECT json_group_array(json_object(
+                        'attachment_id', attachments.attachment_id,
+                        'chunk_count', attachments.chunk_count,
+                        'len', attachments.len
+                    ))
+                    FROM attachments
+                    WHERE attachments.submission = submissions.id
+                ) AS attachments
             FROM submissions
             JOIN forms ON submissions.form = forms.id
             WHERE forms.form_id = ?1
@@ -204,8 +587,13 @@ impl Store {
 
         #[derive(Debug, Deserialize)]
         struct Row {
+            submission_id: SubmissionId,
             encrypted_body: EncryptedSubmissionBody,
+            key_epoch: KeyEpoch,
             created_at: String,
+            revision: u32,
+            reply_public_key: Option<ReplyPublicKey>,
+            attachments: String,
         }
 
         stmt.all()
@@ -214,248 +602,2117 @@ impl Store {
             .into_iter()
             .map(|row| {
                 Ok(Submission {
+                    id: row.submission_id,
                     encrypted_body: row.encrypted_body,
+                    key_epoch: row.key_epoch,
                     created_at: NaiveDateTime::parse_from_str(
                         &row.created_at,
                         SQLITE_DATETIME_FORMAT,
                     )?
                     .and_utc(),
+                    revision: row.revision,
+                    attachments: serde_json::from_str::<Vec<Attachment>>(&row.attachments)?,
+                    reply_public_key: row.reply_public_key,
                 })
             })
             .collect::<anyhow::Result<Vec<_>>>()
     }
 
+    // If no key epoch is given, the submission is assumed to be encrypted with the current public
+    // primary key. The submission is only stored if every one of the attachments exists for the
+    // form, has at least one chunk, and isn't already part of another submission.
     #[worker::send]
     pub async fn put_submission(
         &self,
         form_id: &FormId,
         submission_id: &SubmissionId,
         encrypted_submission: &EncryptedSubmissionBody,
+        key_epoch: Option<KeyEpoch>,
+        attachment_ids: &[AttachmentId],
+        respondent_keys: &RespondentKeys,
     ) -> anyhow::Result<bool> {
-        let stmt = query!(
+        let attachment_ids = serde_json::to_string(attachment_ids)?;
+
+        let submission_stmt = query!(
             &self.db,
             "
-            INSERT INTO submissions (form, submission_id, encrypted_body)
-            SELECT forms.id, ?1, ?2
+            INSERT INTO submissions (
+                form,
+                submission_id,
+                encrypted_body,
+                key_epoch,
+                receipt_public_key,
+                reply_public_key,
+                mailbox_token_hash,
+                body_hash
+            )
+            SELECT forms.id, ?1, ?2, COALESCE(?4, forms.key_epoch), ?6, ?7, ?8, ?9
             FROM forms
-            WHERE forms.form_id = ?3;
+            WHERE
+                forms.form_id = ?3
+                AND (?4 IS NULL OR ?4 <= forms.key_epoch)
+                AND (
+                    SELECT COUNT(attachments.id)
+                    FROM attachments
+                    WHERE
+                        attachments.form = forms.id
+                        AND attachments.submission IS NULL
+                        AND attachments.chunk_count > 0
+                        AND attachments.attachment_id IN (SELECT value FROM json_each(?5))
+                ) = json_array_length(?5);
             ",
             submission_id,
             encrypted_submission,
             form_id,
+            key_epoch,
+            attachment_ids,
+            respondent_keys.receipt_public_key.as_ref(),
+            respondent_keys.reply_public_key.as_ref(),
+            respondent_keys.mailbox_token_hash.as_ref(),
+            encrypted_submission.hash(),
         )?;
 
-        let meta = stmt.run().await?.meta()?;
+        // This only applies if the statement above did, since otherwise there's no submission with
+        // this ID in the form.
+        let attachments_stmt = query!(
+            &self.db,
+            "
+            UPDATE attachments
+            SET submission = (
+                SELECT submissions.id
+                FROM submissions
+                WHERE submissions.submission_id = ?1
+            )
+            WHERE
+                attachments.form = (
+                    SELECT submissions.form
+                    FROM submissions
+                    WHERE submissions.submission_id = ?1
+                )
+                AND attachments.submission IS NULL
+                AND attachments.attachment_id IN (SELECT value FROM json_each(?2));
+            ",
+            submission_id,
+            attachment_ids,
+        )?;
 
-        if let Some(meta) = meta {
-            Ok(meta.changed_db.unwrap_or(false))
-        } else {
-            Ok(false)
-        }
+        let results = self.batch(vec![submission_stmt, attachments_stmt]).await?;
+
+        let changes = match results.first() {
+            Some(result) => result.meta()?.and_then(|meta| meta.changes).unwrap_or(0),
+            None => 0,
+        };
+
+        Ok(changes > 0)
     }
 
+    // Returns `None` if the submission doesn't exist or the respondent didn't give a public signing
+    // key when making it.
     #[worker::send]
-    pub async fn get_client_keys(
+    pub async fn get_receipt_key(
         &self,
         form_id: &FormId,
-        key_id: &ClientKeyId,
-    ) -> anyhow::Result<Option<ClientKeys>> {
+        submission_id: &SubmissionId,
+    ) -> anyhow::Result<Option<ReceiptKey>> {
         let stmt = query!(
             &self.db,
             "
             SELECT
-                keys.public_signing_key,
-                keys.wrapped_private_primary_key,
-                keys.encrypted_comment,
-                keys.role,
-                EXISTS(
-                    SELECT id
-                    FROM passwords
-                    WHERE passwords.key = keys.id
-                ) AS protected,
-                (
-                    SELECT MAX(access_log.accessed_at)
-                    FROM access_log
-                    WHERE access_log.key = keys.id
-                    GROUP BY access_log.key
-                ) AS accessed_at
-            FROM keys
-            JOIN forms ON keys.form = forms.id
-            WHERE forms.form_id = ?1 AND keys.key_index = ?2;
+                submissions.receipt_public_key,
+                submissions.revision
+            FROM submissions
+            JOIN forms ON submissions.form = forms.id
+            WHERE
+                forms.form_id = ?1
+                AND submissions.submission_id = ?2
+                AND submissions.receipt_public_key IS NOT NULL;
             ",
             form_id,
-            key_id,
+            submission_id,
         )?;
 
         #[derive(Debug, Deserialize)]
         struct Row {
-            public_signing_key: PublicSigningKey,
-            wrapped_private_primary_key: Option<WrappedPrivatePrimaryKey>,
-            encrypted_comment: EncryptedKeyComment,
-            role: AccessRole,
-            protected: i32,
-            accessed_at: Option<String>,
+            receipt_public_key: PublicSigningKey,
+            revision: u32,
         }
 
-        let row = stmt.first::<Row>(None).await?;
-
-        row.map(|row| -> anyhow::Result<_> {
-            Ok(ClientKeys {
-                id: key_id.to_owned(),
-                public_signing_key: row.public_signing_key,
-                wrapped_private_primary_key: row.wrapped_private_primary_key,
-                encrypted_comment: row.encrypted_comment,
-                role: row.role,
-                protected: row.protected != 0,
-                accessed_at: row
-                    .accessed_at
-                    .map(|s| NaiveDateTime::parse_from_str(&s, SQLITE_DATETIME_FORMAT))
-                    .transpose()?
-                    .map(|dt| dt.and_utc()),
-            })
-        })
-        .transpose()
+        Ok(stmt.first::<Row>(None).await?.map(|row| ReceiptKey {
+            public_key: row.receipt_public_key,
+            revision: row.revision,
+        }))
     }
 
+    // Replace the body of a submission on behalf of the respondent. This only succeeds if the
+    // submission is still at the given revision, so two amendments signed for the same revision
+    // can't both apply.
     #[worker::send]
-    pub async fn list_client_keys(&self, form_id: &FormId) -> anyhow::Result<Vec<ClientKeys>> {
+    pub async fn amend_submission(
+        &self,
+        form_id: &FormId,
+        submission_id: &SubmissionId,
+        encrypted_submission: &EncryptedSubmissionBody,
+        key_epoch: Option<KeyEpoch>,
+        revision: u32,
+    ) -> anyhow::Result<bool> {
         let stmt = query!(
             &self.db,
             "
-            SELECT
-                keys.key_index,
-                keys.public_signing_key,
-                keys.wrapped_private_primary_key,
-                keys.encrypted_comment,
-                keys.role,
-                EXISTS(
-                    SELECT id
-                    FROM passwords
-                    WHERE passwords.key = keys.id
-                ) AS protected,
-                (
-                    SELECT MAX(access_log.accessed_at)
-                    FROM access_log
-                    WHERE access_log.key = keys.id
-                    GROUP BY access_log.key
-                ) AS accessed_at
-            FROM keys
-            JOIN forms ON keys.form = forms.id
-            WHERE forms.form_id = ?1
-            ORDER BY keys.key_index ASC;
+            UPDATE submissions
+            SET
+                encrypted_body = ?3,
+                key_epoch = COALESCE(
+                    ?4,
+                    (SELECT forms.key_epoch FROM forms WHERE forms.id = submissions.form)
+                ),
+                revision = submissions.revision + 1,
+                body_hash = ?6
+            WHERE
+                submissions.form = (
+                    SELECT forms.id
+                    FROM forms
+                    WHERE forms.form_id = ?1
+                )
+                AND submissions.submission_id = ?2
+                AND submissions.revision = ?5;
             ",
             form_id,
+            submission_id,
+            encrypted_submission,
+            key_epoch,
+            revision,
+            encrypted_submission.hash(),
         )?;
 
-        #[derive(Debug, Deserialize)]
-        struct Row {
-            key_index: ClientKeyId,
-            public_signing_key: PublicSigningKey,
-            wrapped_private_primary_key: Option<WrappedPrivatePrimaryKey>,
-            encrypted_comment: EncryptedKeyComment,
-            role: AccessRole,
-            protected: i32,
-            accessed_at: Option<String>,
-        }
+        let meta = stmt.run().await?.meta()?;
+
+        Ok(meta.and_then(|meta| meta.changes).unwrap_or(0) > 0)
+    }
+
+    // Delete a submission on behalf of the respondent. Like `amend_submission`, this only succeeds
+    // if the submission is still at the given revision.
+    #[worker::send]
+    pub async fn withdraw_submission(
+        &self,
+        form_id: &FormId,
+        submission_id: &SubmissionId,
+        revision: u32,
+    ) -> anyhow::Result<Option<Vec<AttachmentId>>> {
+        let attachments_stmt = query!(
+            &self.db,
+            "
+            SELECT attachments.attachment_id
+            FROM attachments
+            JOIN submissions ON attachments.submission = submissions.id
+            JOIN forms ON submissions.form = forms.id
+            WHERE
+                forms.form_id = ?1
+                AND submissions.submission_id = ?2
+                AND submissions.revision = ?3;
+            ",
+            form_id,
+            submission_id,
+            revision,
+        )?;
+
+        let submission_stmt = query!(
+            &self.db,
+            "
+            DELETE FROM submissions
+            WHERE
+                submissions.form = (
+                    SELECT forms.id
+                    FROM forms
+                    WHERE forms.form_id = ?1
+                )
+                AND submissions.submission_id = ?2
+                AND submissions.revision = ?3;
+            ",
+            form_id,
+            submission_id,
+            revision,
+        )?;
+
+        let results = self.batch(vec![attachments_stmt, submission_stmt]).await?;
+
+        let changes = match results.get(1) {
+            Some(result) => result.meta()?.and_then(|meta| meta.changes).unwrap_or(0),
+            None => 0,
+        };
+
+        if changes == 0 {
+            return Ok(None);
+        }
+
+        attachment_ids_from(results.first()).map(Some)
+    }
+
+    // Organizers can reply to a submission up to `max_replies` times.
+    #[worker::send]
+    pub async fn create_reply(
+        &self,
+        form_id: &FormId,
+        submission_id: &SubmissionId,
+        reply_id: &ReplyId,
+        encrypted_body: &EncryptedReplyBody,
+        max_replies: u32,
+    ) -> anyhow::Result<ReplyOutcome> {
+        let stmt = query!(
+            &self.db,
+            "
+            INSERT INTO replies (submission, reply_id, encrypted_body)
+            SELECT submissions.id, ?3, ?4
+            FROM submissions
+            JOIN forms ON submissions.form = forms.id
+            WHERE
+                forms.form_id = ?1
+                AND submissions.submission_id = ?2
+                AND submissions.reply_public_key IS NOT NULL
+                AND (
+                    SELECT COUNT(replies.id)
+                    FROM replies
+                    WHERE replies.submission = submissions.id
+                ) < ?5;
+            ",
+            form_id,
+            submission_id,
+            reply_id,
+            encrypted_body,
+            max_replies,
+        )?;
+
+        let meta = stmt.run().await?.meta()?;
+
+        if meta.and_then(|meta| meta.changes).unwrap_or(0) > 0 {
+            return Ok(ReplyOutcome::Added);
+        }
+
+        let stmt = query!(
+            &self.db,
+            "
+            SELECT submissions.id
+            FROM submissions
+            JOIN forms ON submissions.form = forms.id
+            WHERE
+                forms.form_id = ?1
+                AND submissions.submission_id = ?2
+                AND submissions.reply_public_key IS NOT NULL;
+            ",
+            form_id,
+            submission_id,
+        )?;
+
+        match stmt.first::<u64>(Some("id")).await? {
+            Some(_) => Ok(ReplyOutcome::LimitReached),
+            None => Ok(ReplyOutcome::NotFound),
+        }
+    }
+
+    // Returns `None` if no submission has a mailbox with this token. This deliberately doesn't need
+    // the form or submission ID, so a respondent checking for replies doesn't have to reveal them.
+    #[worker::send]
+    pub async fn list_mailbox_replies(
+        &self,
+        token_hash: &MailboxTokenHash,
+    ) -> anyhow::Result<Option<Vec<Reply>>> {
+        let stmt = query!(
+            &self.db,
+            "
+            SELECT
+                (
+                    SELECT json_group_array(json_object(
+                        'reply_id', replies.reply_id,
+                        'encrypted_body', replies.encrypted_body,
+                        'created_at', replies.created_at
+                    ))
+                    FROM (
+                        SELECT *
+                        FROM replies
+                        WHERE replies.submission = submissions.id
+                        ORDER BY replies.id
+                    ) AS replies
+                ) AS replies
+            FROM submissions
+            WHERE submissions.mailbox_token_hash = ?1;
+            ",
+            token_hash,
+        )?;
+
+        #[derive(Debug, Deserialize)]
+        struct ReplyRow {
+            reply_id: ReplyId,
+            encrypted_body: EncryptedReplyBody,
+            created_at: String,
+        }
+
+        let replies = match stmt.first::<String>(Some("replies")).await? {
+            Some(replies) => replies,
+            None => return Ok(None),
+        };
+
+        serde_json::from_str::<Vec<ReplyRow>>(&replies)?
+            .into_iter()
+            .map(|row| {
+                Ok(Reply {
+                    id: row.reply_id,
+                    encrypted_body: row.encrypted_body,
+                    created_at: NaiveDateTime::parse_from_str(
+                        &row.created_at,
+                        SQLITE_DATETIME_FORMAT,
+                    )?
+                    .and_utc(),
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()
+            .map(Some)
+    }
+
+    // The entries in the form's log from `start` up to but not including `end`, in the order they
+    // were added. If `end` is unset, this returns every entry from `start` onward. The log is only
+    // ever appended to, by triggers on the tables it records changes to.
+    #[worker::send]
+    pub async fn list_log_entries(
+        &self,
+        form_id: &FormId,
+        start: u64,
+        end: Option<u64>,
+    ) -> anyhow::Result<Vec<LogEntry>> {
+        let stmt = query!(
+            &self.db,
+            "
+            SELECT
+                log_entries.entry_index,
+                log_entries.entry,
+                log_entries.created_at
+            FROM log_entries
+            JOIN forms ON log_entries.form = forms.id
+            WHERE forms.form_id = ?1
+                AND log_entries.entry_index >= ?2
+                AND (?3 IS NULL OR log_entries.entry_index < ?3)
+            ORDER BY log_entries.entry_index;
+            ",
+            form_id,
+            start,
+            end,
+        )?;
+
+        #[derive(Debug, Deserialize)]
+        struct Row {
+            entry_index: u64,
+            entry: String,
+            created_at: String,
+        }
+
+        stmt.all()
+            .await?
+            .results::<Row>()?
+            .into_iter()
+            .map(|row| {
+                Ok(LogEntry {
+                    index: row.entry_index,
+                    entry: row.entry,
+                    created_at: NaiveDateTime::parse_from_str(
+                        &row.created_at,
+                        SQLITE_DATETIME_FORMAT,
+                    )?
+                    .and_utc(),
+                })
+            })
+            .collect()
+    }
+
+    // When the form's log started recording changes. Every change to the form's submissions and
+    // keys since then is in its log. This is when the form was created, unless the form predates
+    // the log.
+    #[worker::send]
+    pub async fn get_log_started_at(
+        &self,
+        form_id: &FormId,
+    ) -> anyhow::Result<Option<DateTime<Utc>>> {
+        let stmt = query!(
+            &self.db,
+            "
+            SELECT COALESCE(log_started_at, created_at) AS started_at
+            FROM forms
+            WHERE form_id = ?1;
+            ",
+            form_id,
+        )?;
+
+        #[derive(Debug, Deserialize)]
+        struct Row {
+            started_at: String,
+        }
+
+        stmt.first::<Row>(None)
+            .await?
+            .map(|row| {
+                Ok(
+                    NaiveDateTime::parse_from_str(&row.started_at, SQLITE_DATETIME_FORMAT)?
+                        .and_utc(),
+                )
+            })
+            .transpose()
+    }
+
+    // Record an audit event in the same batch as the change it describes, so the change is rolled
+    // back if the event can't be recorded. If `only_if_changed` is set, this must come right after
+    // the statement that makes the change, and the event is only recorded if that statement changed
+    // something.
+    fn audit_event_stmt(
+        &self,
+        form_id: &FormId,
+        event: &NewAuditEvent,
+        only_if_changed: bool,
+    ) -> anyhow::Result<Statement> {
+        query!(
+            &self.db,
+            "
+            INSERT INTO audit_events (form, event_index, actor, action, key_epoch, encrypted_details)
+            SELECT
+                forms.id,
+                (
+                    SELECT COUNT(audit_events.id)
+                    FROM audit_events
+                    WHERE audit_events.form = forms.id
+                ),
+                ?2,
+                ?3,
+                ?4,
+                ?5
+            FROM forms
+            WHERE forms.form_id = ?1 AND (?6 = 0 OR changes() > 0);
+            ",
+            form_id,
+            event.actor,
+            event.action,
+            event.key_epoch,
+            event.encrypted_details,
+            only_if_changed as u8,
+        )
+    }
+
+    // Up to `limit` of the form's audit events, newest first, starting before the event at index
+    // `before` if it's set.
+    #[worker::send]
+    pub async fn list_audit_events(
+        &self,
+        form_id: &FormId,
+        before: Option<u64>,
+        limit: u64,
+    ) -> anyhow::Result<Vec<AuditEvent>> {
+        let stmt = query!(
+            &self.db,
+            "
+            SELECT
+                audit_events.event_index,
+                audit_events.actor,
+                audit_events.action,
+                audit_events.key_epoch,
+                audit_events.encrypted_details,
+                audit_events.created_at
+            FROM audit_events
+            JOIN forms ON audit_events.form = forms.id
+            WHERE forms.form_id = ?1 AND (?2 IS NULL OR audit_events.event_index < ?2)
+            ORDER BY audit_events.event_index DESC
+            LIMIT ?3;
+            ",
+            form_id,
+            before,
+            limit,
+        )?;
+
+        #[derive(Debug, Deserialize)]
+        struct Row {
+            event_index: u64,
+            actor: ClientKeyId,
+            action: AuditAction,
+            key_epoch: KeyEpoch,
+            encrypted_details: EncryptedAuditDetails,
+            created_at: String,
+        }
+
+        stmt.all()
+            .await?
+            .results::<Row>()?
+            .into_iter()
+            .map(|row| {
+                Ok(AuditEvent {
+                    index: row.event_index,
+                    actor: row.actor,
+                    action: row.action,
+                    key_epoch: row.key_epoch,
+                    encrypted_details: row.encrypted_details,
+                    created_at: NaiveDateTime::parse_from_str(
+                        &row.created_at,
+                        SQLITE_DATETIME_FORMAT,
+                    )?
+                    .and_utc(),
+                })
+            })
+            .collect()
+    }
+
+    // Returns `None` if the form doesn't exist.
+    #[worker::send]
+    pub async fn create_attachment(
+        &self,
+        form_id: &FormId,
+    ) -> anyhow::Result<Option<AttachmentId>> {
+        let attachment_id = AttachmentId::new();
+
+        let stmt = query!(
+            &self.db,
+            "
+            INSERT INTO attachments (form, attachment_id)
+            SELECT forms.id, ?2
+            FROM forms
+            WHERE forms.form_id = ?1;
+            ",
+            form_id,
+            attachment_id,
+        )?;
+
+        let meta = stmt.run().await?.meta()?;
+
+        if meta.and_then(|meta| meta.changes).unwrap_or(0) > 0 {
+            Ok(Some(attachment_id))
+        } else {
+            Ok(None)
+        }
+    }
+
+    // Room for the chunk is reserved before it's stored, and the size limits are checked in the
+    // same statement as the reservation, so concurrent uploads can't together exceed the form's
+    // quotas.
+    #[worker::send]
+    pub async fn add_attachment_chunk(
+        &self,
+        form_id: &FormId,
+        attachment_id: &AttachmentId,
+        chunk_index: u32,
+        chunk: Vec<u8>,
+    ) -> anyhow::Result<ChunkOutcome> {
+        let chunk_len = chunk.len() as u64;
+
+        let stmt = query!(
+            &self.db,
+            "
+            UPDATE attachments
+            SET
+                chunk_count = attachments.chunk_count + 1,
+                len = attachments.len + ?4
+            WHERE
+                attachments.form = (
+                    SELECT forms.id
+                    FROM forms
+                    WHERE forms.form_id = ?1
+                )
+                AND attachments.attachment_id = ?2
+                AND attachments.submission IS NULL
+                AND attachments.chunk_count = ?3
+                AND attachments.len + ?4 <= ?5
+                AND (
+                    SELECT SUM(other.len)
+                    FROM attachments AS other
+                    WHERE other.form = attachments.form
+                ) + ?4 <= ?6
+                AND (
+                    SELECT SUM(other.len)
+                    FROM attachments AS other
+                    WHERE
+                        other.form = attachments.form
+                        AND other.submission IS NULL
+                ) + ?4 <= ?7;
+            ",
+            form_id,
+            attachment_id,
+            chunk_index,
+            chunk_len,
+            config::max_attachment_len() as u64,
+            config::max_form_attachments_len() as u64,
+            config::max_form_pending_attachments_len() as u64,
+        )?;
+
+        let meta = stmt.run().await?.meta()?;
+
+        if meta.and_then(|meta| meta.changes).unwrap_or(0) == 0 {
+            return self
+                .chunk_outcome(form_id, attachment_id, chunk_index, chunk_len)
+                .await;
+        }
+
+        if let Err(err) = self
+            .blobs
+            .put(&attachment_chunk_key(attachment_id, chunk_index), chunk)
+            .await
+        {
+            // Give back the room we reserved, so the client can retry the chunk.
+            let stmt = query!(
+                &self.db,
+                "
+                UPDATE attachments
+                SET
+                    chunk_count = attachments.chunk_count - 1,
+                    len = attachments.len - ?3
+                WHERE
+                    attachments.attachment_id = ?1
+                    AND attachments.submission IS NULL
+                    AND attachments.chunk_count = ?2 + 1;
+                ",
+                attachment_id,
+                chunk_index,
+                chunk_len,
+            )?;
+
+            stmt.run().await?.meta()?;
+
+            return Err(err);
+        }
+
+        Ok(ChunkOutcome::Added)
+    }
+
+    // If the guarded statement in `add_attachment_chunk` didn't change anything, work out which of
+    // its conditions wasn't met.
+    async fn chunk_outcome(
+        &self,
+        form_id: &FormId,
+        attachment_id: &AttachmentId,
+        chunk_index: u32,
+        chunk_len: u64,
+    ) -> anyhow::Result<ChunkOutcome> {
+        let stmt = query!(
+            &self.db,
+            "
+            SELECT
+                attachments.chunk_count,
+                attachments.len,
+                (
+                    SELECT SUM(other.len)
+                    FROM attachments AS other
+                    WHERE other.form = attachments.form
+                ) AS form_len,
+                (
+                    SELECT SUM(other.len)
+                    FROM attachments AS other
+                    WHERE
+                        other.form = attachments.form
+                        AND other.submission IS NULL
+                ) AS pending_len
+            FROM attachments
+            JOIN forms ON attachments.form = forms.id
+            WHERE
+                forms.form_id = ?1
+                AND attachments.attachment_id = ?2
+                AND attachments.submission IS NULL;
+            ",
+            form_id,
+            attachment_id,
+        )?;
+
+        #[derive(Debug, Deserialize)]
+        struct Row {
+            chunk_count: u32,
+            len: u64,
+            form_len: u64,
+            pending_len: u64,
+        }
+
+        let row = match stmt.first::<Row>(None).await? {
+            Some(row) => row,
+            None => return Ok(ChunkOutcome::NotFound),
+        };
+
+        if row.chunk_count != chunk_index {
+            Ok(ChunkOutcome::OutOfOrder)
+        } else if row.len + chunk_len > config::max_attachment_len() as u64 {
+            Ok(ChunkOutcome::TooLarge)
+        } else if row.form_len + chunk_len > config::max_form_attachments_len() as u64 {
+            Ok(ChunkOutcome::OverQuota)
+        } else if row.pending_len + chunk_len > config::max_form_pending_attachments_len() as u64 {
+            Ok(ChunkOutcome::PendingOverQuota)
+        } else {
+            // The attachment changed between the two statements; the client can retry the chunk.
+            Ok(ChunkOutcome::OutOfOrder)
+        }
+    }
+
+    // Only chunks of attachments which are part of a submission can be downloaded.
+    #[worker::send]
+    pub async fn get_attachment_chunk(
+        &self,
+        form_id: &FormId,
+        attachment_id: &AttachmentId,
+        chunk_index: u32,
+    ) -> anyhow::Result<Option<Vec<u8>>> {
+        let stmt = query!(
+            &self.db,
+            "
+            SELECT attachments.id
+            FROM attachments
+            JOIN forms ON attachments.form = forms.id
+            WHERE
+                forms.form_id = ?1
+                AND attachments.attachment_id = ?2
+                AND attachments.submission IS NOT NULL
+                AND attachments.chunk_count > ?3;
+            ",
+            form_id,
+            attachment_id,
+            chunk_index,
+        )?;
+
+        if stmt.first::<u64>(Some("id")).await?.is_none() {
+            return Ok(None);
+        }
+
+        self.blobs
+            .get(&attachment_chunk_key(attachment_id, chunk_index))
+            .await
+    }
+
+    // Attachments which were never made part of a submission.
+    #[worker::send]
+    pub async fn delete_abandoned_attachments(&self, older_than: Duration) -> anyhow::Result<()> {
+        let stmt = query!(
+            &self.db,
+            "
+            DELETE FROM attachments
+            WHERE
+                attachments.submission IS NULL
+                AND attachments.created_at < datetime(CURRENT_TIMESTAMP, ?1);
+            ",
+            format!("-{} seconds", older_than.as_secs()),
+        )?;
+
+        stmt.run().await?.meta()?;
+
+        Ok(())
+    }
+
+    // Deleting an attachment's row, including when its submission or form is deleted, leaves a
+    // record of it behind so that we can delete its chunks here. The record is only removed once
+    // every chunk is gone, so a failure partway through is picked up again next time.
+    #[worker::send]
+    pub async fn purge_deleted_attachments(&self) -> anyhow::Result<()> {
+        let stmt = query!(
+            &self.db,
+            "
+            SELECT id, attachment_id, chunk_count
+            FROM deleted_attachments;
+            ",
+        )?;
+
+        self.purge_deleted_attachment_rows(stmt).await
+    }
+
+    // Like `purge_deleted_attachments`, but only for the given attachments, so a request which
+    // deletes a form or submission doesn't also pay for purging everyone else's.
+    #[worker::send]
+    pub async fn purge_attachments(&self, attachment_ids: &[AttachmentId]) -> anyhow::Result<()> {
+        if attachment_ids.is_empty() {
+            return Ok(());
+        }
+
+        let stmt = query!(
+            &self.db,
+            "
+            SELECT id, attachment_id, chunk_count
+            FROM deleted_attachments
+            WHERE attachment_id IN (SELECT value FROM json_each(?1));
+            ",
+            serde_json::to_string(attachment_ids)?,
+        )?;
+
+        self.purge_deleted_attachment_rows(stmt).await
+    }
+
+    async fn purge_deleted_attachment_rows(&self, stmt: Statement) -> anyhow::Result<()> {
+        #[derive(Debug, Deserialize)]
+        struct Row {
+            id: u64,
+            attachment_id: AttachmentId,
+            chunk_count: u32,
+        }
+
+        for row in stmt.all().await?.results::<Row>()? {
+            for chunk_index in 0..row.chunk_count {
+                self.blobs
+                    .delete(&attachment_chunk_key(&row.attachment_id, chunk_index))
+                    .await?;
+            }
+
+            let stmt = query!(
+                &self.db,
+                "
+                DELETE FROM deleted_attachments
+                WHERE id = ?1;
+                ",
+                row.id,
+            )?;
+
+            stmt.run().await?.meta()?;
+        }
+
+        Ok(())
+    }
+
+    // Replace a submission with one that has been re-encrypted with the public primary key for the
+    // given key epoch. This only succeeds if that is the current key epoch for the form.
+    #[worker::send]
+    pub async fn replace_submission(
+        &self,
+        form_id: &FormId,
+        submission_id: &SubmissionId,
+        encrypted_submission: &EncryptedSubmissionBody,
+        key_epoch: KeyEpoch,
+        audit_event: &NewAuditEvent,
+    ) -> anyhow::Result<bool> {
+        let submission_stmt = query!(
+            &self.db,
+            "
+            UPDATE submissions
+            SET
+                encrypted_body = ?3,
+                key_epoch = ?4,
+                body_hash = ?5
+            WHERE
+                submissions.form = (
+                    SELECT forms.id
+                    FROM forms
+                    WHERE forms.form_id = ?1 AND forms.key_epoch = ?4
+                )
+                AND submissions.submission_id = ?2;
+            ",
+            form_id,
+            submission_id,
+            encrypted_submission,
+            key_epoch,
+            encrypted_submission.hash(),
+        )?;
+
+        let audit_stmt = self.audit_event_stmt(form_id, audit_event, true)?;
+
+        let results = self.batch(vec![submission_stmt, audit_stmt]).await?;
+
+        Ok(changes_from(results.first())? > 0)
+    }
+
+    #[worker::send]
+    pub async fn get_client_keys(
+        &self,
+        form_id: &FormId,
+        key_id: &ClientKeyId,
+    ) -> anyhow::Result<Option<ClientKeys>> {
+        let stmt = query!(
+            &self.db,
+            "
+            SELECT
+                keys.public_signing_key,
+                keys.wrapped_private_primary_key,
+                keys.encrypted_comment,
+                keys.role,
+                EXISTS(
+                    SELECT id
+                    FROM passwords
+                    WHERE passwords.key = keys.id
+                ) AS protected,
+                (
+                    SELECT MAX(access_log.accessed_at)
+                    FROM access_log
+                    WHERE access_log.key = keys.id
+                    GROUP BY access_log.key
+                ) AS accessed_at
+            FROM keys
+            JOIN forms ON keys.form = forms.id
+            WHERE forms.form_id = ?1 AND keys.key_index = ?2;
+            ",
+            form_id,
+            key_id,
+        )?;
+
+        #[derive(Debug, Deserialize)]
+        struct Row {
+            public_signing_key: PublicSigningKey,
+            wrapped_private_primary_key: Option<WrappedPrivatePrimaryKey>,
+            encrypted_comment: EncryptedKeyComment,
+            role: AccessRole,
+            protected: i32,
+            accessed_at: Option<String>,
+        }
+
+        let row = stmt.first::<Row>(None).await?;
+
+        row.map(|row| -> anyhow::Result<_> {
+            Ok(ClientKeys {
+                id: key_id.to_owned(),
+                public_signing_key: row.public_signing_key,
+                wrapped_private_primary_key: row.wrapped_private_primary_key,
+                encrypted_comment: row.encrypted_comment,
+                role: row.role,
+                protected: row.protected != 0,
+                accessed_at: row
+                    .accessed_at
+                    .map(|s| NaiveDateTime::parse_from_str(&s, SQLITE_DATETIME_FORMAT))
+                    .transpose()?
+                    .map(|dt| dt.and_utc()),
+            })
+        })
+        .transpose()
+    }
+
+    #[worker::send]
+    pub async fn list_client_keys(&self, form_id: &FormId) -> anyhow::Result<Vec<ClientKeys>> {
+        let stmt = query!(
+            &self.db,
+            "
+            SELECT
+                keys.key_index,
+                keys.public_signing_key,
+                keys.wrapped_private_primary_key,
+                keys.encrypted_comment,
+                keys.role,
+                EXISTS(
+                    SELECT id
+                    FROM passwords
+                    WHERE passwords.key = keys.id
+                ) AS protected,
+                (
+                    SELECT MAX(access_log.accessed_at)
+                    FROM access_log
+                    WHERE access_log.key = keys.id
+                    GROUP BY access_log.key
+                ) AS accessed_at
+            FROM keys
+            JOIN forms ON keys.form = forms.id
+            WHERE forms.form_id = ?1
+            ORDER BY keys.key_index ASC;
+            ",
+            form_id,
+        )?;
+
+        #[derive(Debug, Deserialize)]
+        struct Row {
+            key_index: ClientKeyId,
+            public_signing_key: PublicSigningKey,
+            wrapped_private_primary_key: Option<WrappedPrivatePrimaryKey>,
+            encrypted_comment: EncryptedKeyComment,
+            role: AccessRole,
+            protected: i32,
+            accessed_at: Option<String>,
+        }
+
+        let rows = stmt.all().await?.results::<Row>()?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(ClientKeys {
+                    id: row.key_index,
+                    public_signing_key: row.public_signing_key,
+                    wrapped_private_primary_key: row.wrapped_private_primary_key,
+                    encrypted_comment: row.encrypted_comment,
+                    role: row.role,
+                    protected: row.protected != 0,
+                    accessed_at: row
+                        .accessed_at
+                        .map(|s| NaiveDateTime::parse_from_str(&s, SQLITE_DATETIME_FORMAT))
+                        .transpose()?
+                        .map(|dt| dt.and_utc()),
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()
+    }
+
+    fn insert_client_keys_stmt(
+        &self,
+        form_id: &FormId,
+        public_signing_key: &PublicSigningKey,
+        wrapped_private_primary_key: Option<&WrappedPrivatePrimaryKey>,
+        encrypted_comment: &EncryptedKeyComment,
+        role: AccessRole,
+    ) -> anyhow::Result<Statement> {
+        query!(
+            &self.db,
+            "
+            INSERT INTO keys (
+                form,
+                key_index,
+                public_signing_key,
+                wrapped_private_primary_key,
+                encrypted_comment,
+                role
+            )
+            SELECT
+                forms.id,
+                forms.next_key_index,
+                ?2,
+                ?3,
+                ?4,
+                ?5
+            FROM forms
+            WHERE forms.form_id = ?1
+            RETURNING keys.key_index;
+            ",
+            form_id,
+            public_signing_key,
+            wrapped_private_primary_key,
+            encrypted_comment,
+            role,
+        )
+    }
+
+    #[worker::send]
+    pub async fn store_client_keys(
+        &self,
+        form_id: &FormId,
+        public_signing_key: &PublicSigningKey,
+        wrapped_private_primary_key: Option<&WrappedPrivatePrimaryKey>,
+        encrypted_comment: &EncryptedKeyComment,
+        role: AccessRole,
+        audit_event: &NewAuditEvent,
+    ) -> anyhow::Result<Option<ClientKeyId>> {
+        let keys_stmt = self.insert_client_keys_stmt(
+            form_id,
+            public_signing_key,
+            wrapped_private_primary_key,
+            encrypted_comment,
+            role,
+        )?;
+
+        let audit_stmt = self.audit_event_stmt(form_id, audit_event, true)?;
+
+        let results = self.batch(vec![keys_stmt, audit_stmt]).await?;
+
+        #[derive(Debug, Deserialize)]
+        struct Row {
+            key_index: ClientKeyId,
+        }
+
+        Ok(results
+            .first()
+            .map(QueryResult::results::<Row>)
+            .transpose()?
+            .and_then(|rows| rows.into_iter().next())
+            .map(|row| row.key_index))
+    }
+
+    #[worker::send]
+    pub async fn update_client_keys(
+        &self,
+        form_id: &FormId,
+        key_id: &ClientKeyId,
+        wrapped_private_primary_key: Option<&WrappedPrivatePrimaryKey>,
+        encrypted_comment: Option<&EncryptedKeyComment>,
+        role: Option<AccessRole>,
+        audit_event: &NewAuditEvent,
+    ) -> anyhow::Result<KeyChangeOutcome> {
+        // The last-admin check happens in the same statement as the update so that two concurrent
+        // requests can't each demote one of the last two admin keys.
+        let keys_stmt = query!(
+            &self.db,
+            "
+            UPDATE keys
+            SET
+                wrapped_private_primary_key = COALESCE(?3, keys.wrapped_private_primary_key),
+                encrypted_comment = COALESCE(?4, keys.encrypted_comment),
+                role = COALESCE(?5, keys.role)
+            WHERE
+                keys.form = (
+                    SELECT forms.id
+                    FROM forms
+                    WHERE forms.form_id = ?1
+                )
+                AND keys.key_index = ?2
+                AND (
+                    COALESCE(?5, keys.role) = 'admin'
+                    OR keys.role != 'admin'
+                    OR EXISTS(
+                        SELECT other.id
+                        FROM keys AS other
+                        WHERE
+                            other.form = keys.form
+                            AND other.role = 'admin'
+                            AND other.id != keys.id
+                    )
+                );
+            ",
+            form_id,
+            key_id,
+            wrapped_private_primary_key,
+            encrypted_comment,
+            role,
+        )?;
+
+        let audit_stmt = self.audit_event_stmt(form_id, audit_event, true)?;
+
+        let results = self.batch(vec![keys_stmt, audit_stmt]).await?;
+
+        self.key_change_outcome(form_id, key_id, results.first())
+            .await
+    }
+
+    #[worker::send]
+    pub async fn delete_client_keys(
+        &self,
+        form_id: &FormId,
+        key_id: &ClientKeyId,
+        audit_event: &NewAuditEvent,
+    ) -> anyhow::Result<KeyChangeOutcome> {
+        // See `update_client_keys` for why the last-admin check is part of this statement.
+        let keys_stmt = query!(
+            &self.db,
+            "
+            DELETE FROM keys
+            WHERE keys.id IN (
+                SELECT keys.id
+                FROM keys
+                JOIN forms ON keys.form = forms.id
+                WHERE
+                    forms.form_id = ?1
+                    AND keys.key_index = ?2
+                    AND (
+                        keys.role != 'admin'
+                        OR EXISTS(
+                            SELECT other.id
+                            FROM keys AS other
+                            WHERE
+                                other.form = keys.form
+                                AND other.role = 'admin'
+                                AND other.id != keys.id
+                        )
+                    )
+            );
+            ",
+            form_id,
+            key_id,
+        )?;
+
+        let audit_stmt = self.audit_event_stmt(form_id, audit_event, true)?;
+
+        let results = self.batch(vec![keys_stmt, audit_stmt]).await?;
+
+        self.key_change_outcome(form_id, key_id, results.first())
+            .await
+    }
+
+    // If a guarded statement didn't change anything, it's either because the key doesn't exist or
+    // because the change would have left the form without an admin key.
+    async fn key_change_outcome(
+        &self,
+        form_id: &FormId,
+        key_id: &ClientKeyId,
+        result: Option<&QueryResult>,
+    ) -> anyhow::Result<KeyChangeOutcome> {
+        if changes_from(result)? > 0 {
+            return Ok(KeyChangeOutcome::Changed);
+        }
+
+        if self.get_client_keys(form_id, key_id).await?.is_some() {
+            Ok(KeyChangeOutcome::LastAdmin)
+        } else {
+            Ok(KeyChangeOutcome::NotFound)
+        }
+    }
+
+    // Replace the public primary key for a form and the wrapped private primary key for every one
+    // of its client keys, all in a single transaction. This returns `None` if the form's key epoch
+    // is not `current_epoch` or if `wrapped_keys` does not contain exactly the client keys that
+    // currently exist for the form, or if its template signature changed since `signature` was
+    // checked.
+    #[worker::send]
+    pub async fn rotate_primary_key(
+        &self,
+        form_id: &FormId,
+        current_epoch: KeyEpoch,
+        public_primary_key: &PublicPrimaryKey,
+        wrapped_keys: &[(ClientKeyId, WrappedPrivatePrimaryKey)],
+        signature: &TemplateSignatureChange,
+        audit_event: &NewAuditEvent,
+    ) -> anyhow::Result<Option<KeyEpoch>> {
+        let next_epoch = current_epoch.next();
+
+        let key_ids = format!(
+            "[{}]",
+            wrapped_keys
+                .iter()
+                .map(|(key_id, _)| key_id.to_string())
+                .collect::<Vec<_>>()
+                .join(",")
+        );
+
+        let mut statements = vec![query!(
+            &self.db,
+            "
+            UPDATE forms
+            SET
+                public_primary_key = ?3,
+                key_epoch = ?4,
+                template_signature = ?7
+            WHERE
+                forms.form_id = ?1
+                AND forms.key_epoch = ?2
+                AND forms.template_signature IS ?6
+                AND NOT EXISTS(
+                    SELECT keys.id
+                    FROM keys
+                    WHERE
+                        keys.form = forms.id
+                        AND keys.key_index NOT IN (SELECT value FROM json_each(?5))
+                )
+                AND (
+                    SELECT COUNT(keys.id)
+                    FROM keys
+                    WHERE keys.form = forms.id
+                ) = json_array_length(?5);
+            ",
+            form_id,
+            current_epoch,
+            public_primary_key,
+            next_epoch,
+            key_ids,
+            signature.current,
+            signature.new,
+        )?];
+
+        statements.push(self.audit_event_stmt(form_id, audit_event, true)?);
+
+        // These only apply if the statement above did, because the new public primary key is
+        // freshly generated and can't match a key set by any other rotation.
+        for (key_id, wrapped_private_primary_key) in wrapped_keys {
+            statements.push(query!(
+                &self.db,
+                "
+                UPDATE keys
+                SET wrapped_private_primary_key = ?3
+                WHERE
+                    keys.form = (
+                        SELECT forms.id
+                        FROM forms
+                        WHERE
+                            forms.form_id = ?1
+                            AND forms.key_epoch = ?4
+                            AND forms.public_primary_key = ?5
+                    )
+                    AND keys.key_index = ?2;
+                ",
+                form_id,
+                key_id,
+                wrapped_private_primary_key,
+                next_epoch,
+                public_primary_key,
+            )?);
+        }
+
+        let results = self.batch(statements).await?;
+
+        if changes_from(results.first())? > 0 {
+            Ok(Some(next_epoch))
+        } else {
+            Ok(None)
+        }
+    }
+
+    #[worker::send]
+    pub async fn store_ephemeral_server_key(
+        &self,
+        key_id: &ServerKeyId,
+        key: &EphemeralServerKey,
+    ) -> anyhow::Result<()> {
+        self.kv
+            .put(
+                &server_key_key(key_id),
+                key.expose_secret(),
+                server_key_ttl(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    #[worker::send]
+    pub async fn get_ephemeral_server_key(
+        &self,
+        key_id: &ServerKeyId,
+    ) -> anyhow::Result<Option<EphemeralServerKey>> {
+        Ok(self
+            .kv
+            .get(&server_key_key(key_id))
+            .await?
+            .map(|s| s.parse())
+            .transpose()?)
+    }
+
+    #[worker::send]
+    pub async fn has_challenge_id(&self, challenge_id: &ChallengeId) -> anyhow::Result<bool> {
+        Ok(self.kv.get(&challenge_key(challenge_id)).await?.is_some())
+    }
+
+    // Read a key from KV. The health check is unauthenticated, so it mustn't write anything, or
+    // anyone could use up our KV write quota.
+    #[worker::send]
+    pub async fn check_kv(&self) -> anyhow::Result<()> {
+        self.kv.get(HEALTH_CHECK_KEY).await?;
+
+        Ok(())
+    }
+
+    #[worker::send]
+    pub async fn get_idempotency_record(
+        &self,
+        scope: &str,
+    ) -> anyhow::Result<Option<IdempotencyRecord>> {
+        Ok(self
+            .kv
+            .get(&idempotency_key(scope))
+            .await?
+            .map(|s| serde_json::from_str(&s))
+            .transpose()?)
+    }
+
+    #[worker::send]
+    pub async fn store_idempotency_record(
+        &self,
+        scope: &str,
+        record: &IdempotencyRecord,
+        ttl: Duration,
+    ) -> anyhow::Result<()> {
+        self.kv
+            .put(
+                &idempotency_key(scope),
+                &serde_json::to_string(record)?,
+                ttl.as_secs(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    #[worker::send]
+    pub async fn delete_idempotency_record(&self, scope: &str) -> anyhow::Result<()> {
+        self.kv.delete(&idempotency_key(scope)).await?;
+
+        Ok(())
+    }
+
+    #[worker::send]
+    pub async fn store_challenge_id(&self, challenge_id: &ChallengeId) -> anyhow::Result<()> {
+        self.kv
+            .put(&challenge_key(challenge_id), "", challenge_ttl())
+            .await?;
+
+        Ok(())
+    }
+
+    #[worker::send]
+    pub async fn delete_challenge_id(&self, challenge_id: &ChallengeId) -> anyhow::Result<()> {
+        self.kv.delete(&challenge_key(challenge_id)).await?;
+
+        Ok(())
+    }
+
+    #[worker::send]
+    pub async fn delete_ephemeral_server_key(&self, key_id: &ServerKeyId) -> anyhow::Result<()> {
+        self.kv.delete(&server_key_key(key_id)).await?;
+
+        Ok(())
+    }
+
+    #[worker::send]
+    pub async fn get_newest_server_signing_key(
+        &self,
+    ) -> anyhow::Result<Option<ServerSigningKeyPair>> {
+        let stmt = query!(
+            &self.db,
+            "
+            SELECT key_id, private_key, created_at
+            FROM server_signing_keys
+            WHERE private_key IS NOT NULL
+            ORDER BY id DESC
+            LIMIT 1;
+            ",
+        )?;
+
+        #[derive(Debug, Deserialize)]
+        struct Row {
+            key_id: ServerSigningKeyId,
+            private_key: EncryptedServerSigningKey,
+            created_at: String,
+        }
+
+        stmt.first::<Row>(None)
+            .await?
+            .map(|row| -> anyhow::Result<_> {
+                Ok(ServerSigningKeyPair {
+                    id: row.key_id,
+                    signing_key: config::server_key_encryption_key().decrypt(&row.private_key)?,
+                    created_at: NaiveDateTime::parse_from_str(
+                        &row.created_at,
+                        SQLITE_DATETIME_FORMAT,
+                    )?
+                    .and_utc(),
+                })
+            })
+            .transpose()
+    }
+
+    #[worker::send]
+    pub async fn store_server_signing_key(
+        &self,
+        key_id: &ServerSigningKeyId,
+        key: &ServerSigningKey,
+    ) -> anyhow::Result<()> {
+        let stmt = query!(
+            &self.db,
+            "
+            INSERT INTO server_signing_keys (key_id, private_key, public_key)
+            VALUES (?1, ?2, ?3);
+            ",
+            key_id,
+            config::server_key_encryption_key().encrypt(key)?,
+            key.verifying_key(),
+        )?;
+
+        stmt.run().await?.meta()?;
+
+        Ok(())
+    }
+
+    // Retired keys are left out, since they can't have signed any unexpired tokens.
+    #[worker::send]
+    pub async fn get_server_verifying_key(
+        &self,
+        key_id: &ServerSigningKeyId,
+    ) -> anyhow::Result<Option<ServerVerifyingKey>> {
+        let stmt = query!(
+            &self.db,
+            "
+            SELECT public_key
+            FROM server_signing_keys
+            WHERE key_id = ?1 AND private_key IS NOT NULL;
+            ",
+            key_id,
+        )?;
+
+        stmt.first::<ServerVerifyingKey>(Some("public_key")).await
+    }
+
+    // Retired keys are included, since receipts and tree heads signed with them are still valid.
+    #[worker::send]
+    pub async fn list_server_verifying_keys(
+        &self,
+    ) -> anyhow::Result<Vec<(ServerSigningKeyId, ServerVerifyingKey)>> {
+        let stmt = query!(
+            &self.db,
+            "
+            SELECT key_id, public_key
+            FROM server_signing_keys
+            ORDER BY id DESC;
+            ",
+        )?;
+
+        #[derive(Debug, Deserialize)]
+        struct Row {
+            key_id: ServerSigningKeyId,
+            public_key: ServerVerifyingKey,
+        }
+
+        Ok(stmt
+            .all()
+            .await?
+            .results::<Row>()?
+            .into_iter()
+            .map(|row| (row.key_id, row.public_key))
+            .collect())
+    }
+
+    // A server signing key is retired once a newer key has been signing tokens for longer than
+    // `grace_period`, at which point no unexpired token can have been signed with it. We delete the
+    // private key, but keep the public key so that what it signed can still be verified.
+    #[worker::send]
+    pub async fn retire_server_signing_keys(&self, grace_period: Duration) -> anyhow::Result<()> {
+        let stmt = query!(
+            &self.db,
+            "
+            UPDATE server_signing_keys
+            SET private_key = NULL
+            WHERE
+                private_key IS NOT NULL
+                AND EXISTS(
+                    SELECT successor.id
+                    FROM server_signing_keys AS successor
+                    WHERE
+                        successor.id > server_signing_keys.id
+                        AND successor.created_at < datetime(CURRENT_TIMESTAMP, ?1)
+                );
+            ",
+            format!("-{} seconds", grace_period.as_secs()),
+        )?;
+
+        stmt.run().await?.meta()?;
+
+        Ok(())
+    }
+
+    // A session and the refresh token that continues it are stored together, so there's never a
+    // refresh token without a session that can be revoked.
+    #[worker::send]
+    pub async fn start_session(
+        &self,
+        form_id: &FormId,
+        key_id: &ClientKeyId,
+        server_key_id: &ServerKeyId,
+        token_hash: &RefreshTokenHash,
+        origin: &str,
+    ) -> anyhow::Result<()> {
+        let session_stmt = query!(
+            &self.db,
+            "
+            INSERT INTO sessions (key, server_key_id, expires_at)
+            SELECT keys.id, ?3, datetime(CURRENT_TIMESTAMP, ?4)
+            FROM keys
+            JOIN forms ON keys.form = forms.id
+            WHERE forms.form_id = ?1 AND keys.key_index = ?2;
+            ",
+            form_id,
+            key_id,
+            server_key_id,
+            format!("+{} seconds", config::access_token_exp().as_secs()),
+        )?;
+
+        let refresh_token_stmt = query!(
+            &self.db,
+            "
+            INSERT INTO refresh_tokens (key, token_hash, server_key_id, origin, expires_at)
+            SELECT keys.id, ?3, ?4, ?5, datetime(CURRENT_TIMESTAMP, ?6)
+            FROM keys
+            JOIN forms ON keys.form = forms.id
+            WHERE forms.form_id = ?1 AND keys.key_index = ?2;
+            ",
+            form_id,
+            key_id,
+            token_hash,
+            server_key_id,
+            origin,
+            format!("+{} seconds", config::refresh_token_exp().as_secs()),
+        )?;
+
+        self.batch(vec![session_stmt, refresh_token_stmt]).await?;
+
+        Ok(())
+    }
+
+    #[worker::send]
+    pub async fn list_sessions(
+        &self,
+        form_id: &FormId,
+        key_id: &ClientKeyId,
+    ) -> anyhow::Result<Vec<Session>> {
+        let stmt = query!(
+            &self.db,
+            "
+            SELECT sessions.server_key_id, sessions.created_at, sessions.expires_at
+            FROM sessions
+            JOIN keys ON sessions.key = keys.id
+            JOIN forms ON keys.form = forms.id
+            WHERE
+                forms.form_id = ?1
+                AND keys.key_index = ?2
+                AND sessions.expires_at > CURRENT_TIMESTAMP
+            ORDER BY sessions.created_at DESC;
+            ",
+            form_id,
+            key_id,
+        )?;
+
+        #[derive(Debug, Deserialize)]
+        struct Row {
+            server_key_id: ServerKeyId,
+            created_at: String,
+            expires_at: String,
+        }
+
+        stmt.all()
+            .await?
+            .results::<Row>()?
+            .into_iter()
+            .map(|row| {
+                Ok(Session {
+                    id: row.server_key_id,
+                    created_at: NaiveDateTime::parse_from_str(
+                        &row.created_at,
+                        SQLITE_DATETIME_FORMAT,
+                    )?
+                    .and_utc(),
+                    expires_at: NaiveDateTime::parse_from_str(
+                        &row.expires_at,
+                        SQLITE_DATETIME_FORMAT,
+                    )?
+                    .and_utc(),
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()
+    }
+
+    #[worker::send]
+    pub async fn has_session(&self, server_key_id: &ServerKeyId) -> anyhow::Result<bool> {
+        let stmt = query!(
+            &self.db,
+            "
+            SELECT EXISTS(
+                SELECT sessions.id
+                FROM sessions
+                WHERE sessions.server_key_id = ?1
+            ) AS present;
+            ",
+            server_key_id,
+        )?;
+
+        Ok(stmt.first::<i32>(Some("present")).await?.unwrap_or(0) != 0)
+    }
+
+    // Ending a session deletes its ephemeral server key, so neither its access token nor any
+    // outstanding challenge signed with it can be validated anymore. Deletes from KV may take some
+    // time to propagate to every location.
+    #[worker::send]
+    pub async fn delete_session(&self, server_key_id: &ServerKeyId) -> anyhow::Result<()> {
+        self.delete_ephemeral_server_key(server_key_id).await?;
+
+        self.batch(self.delete_session_stmts(server_key_id)?)
+            .await?;
+
+        Ok(())
+    }
+
+    fn delete_session_stmts(&self, server_key_id: &ServerKeyId) -> anyhow::Result<Vec<Statement>> {
+        Ok(vec![
+            query!(
+                &self.db,
+                "
+                DELETE FROM sessions
+                WHERE sessions.server_key_id = ?1;
+                ",
+                server_key_id,
+            )?,
+            // Otherwise, the session could be resumed with its refresh token.
+            query!(
+                &self.db,
+                "
+                DELETE FROM refresh_tokens
+                WHERE refresh_tokens.server_key_id = ?1;
+                ",
+                server_key_id,
+            )?,
+        ])
+    }
+
+    #[worker::send]
+    pub async fn delete_sessions(
+        &self,
+        form_id: &FormId,
+        key_id: &ClientKeyId,
+        audit_event: &NewAuditEvent,
+    ) -> anyhow::Result<()> {
+        let sessions = self.list_sessions(form_id, key_id).await?;
+
+        future::try_join_all(
+            sessions
+                .iter()
+                .map(|session| self.delete_ephemeral_server_key(&session.id)),
+        )
+        .await?;
+
+        let sessions_stmt = query!(
+            &self.db,
+            "
+            DELETE FROM sessions
+            WHERE sessions.key IN (
+                SELECT keys.id
+                FROM keys
+                JOIN forms ON keys.form = forms.id
+                WHERE forms.form_id = ?1 AND keys.key_index = ?2
+            );
+            ",
+            form_id,
+            key_id,
+        )?;
+
+        // This also catches refresh tokens which outlive the session they were issued with.
+        let refresh_tokens_stmt = query!(
+            &self.db,
+            "
+            DELETE FROM refresh_tokens
+            WHERE refresh_tokens.key IN (
+                SELECT keys.id
+                FROM keys
+                JOIN forms ON keys.form = forms.id
+                WHERE forms.form_id = ?1 AND keys.key_index = ?2
+            );
+            ",
+            form_id,
+            key_id,
+        )?;
+
+        // Ending a key's sessions is recorded even if it had none.
+        let audit_stmt = self.audit_event_stmt(form_id, audit_event, false)?;
+
+        self.batch(vec![sessions_stmt, refresh_tokens_stmt, audit_stmt])
+            .await?;
+
+        Ok(())
+    }
+
+    // Sessions are only deleted once the ephemeral server keys for them have expired from KV on
+    // their own, which happens `server_key_ttl` after the session starts rather than when it
+    // expires.
+    #[worker::send]
+    pub async fn delete_expired_sessions(&self) -> anyhow::Result<()> {
+        let stmt = query!(
+            &self.db,
+            "
+            DELETE FROM sessions
+            WHERE datetime(sessions.expires_at, ?1) < CURRENT_TIMESTAMP;
+            ",
+            format!(
+                "+{} seconds",
+                server_key_ttl() - config::access_token_exp().as_secs()
+            ),
+        )?;
+
+        stmt.run().await?.meta()?;
+
+        Ok(())
+    }
+
+    // Returns `false` if the form already has the maximum number of webhooks.
+    #[worker::send]
+    pub async fn create_webhook(
+        &self,
+        form_id: &FormId,
+        webhook: &NewWebhook,
+        max_webhooks: u32,
+        audit_event: &NewAuditEvent,
+    ) -> anyhow::Result<bool> {
+        let webhook_stmt = query!(
+            &self.db,
+            "
+            INSERT INTO webhooks (form, webhook_id, url, secret, include_ciphertext)
+            SELECT forms.id, ?2, ?3, ?4, ?5
+            FROM forms
+            WHERE
+                forms.form_id = ?1
+                AND (
+                    SELECT COUNT(webhooks.id)
+                    FROM webhooks
+                    WHERE webhooks.form = forms.id
+                ) < ?6;
+            ",
+            form_id,
+            webhook.id,
+            webhook.url,
+            webhook.secret.expose_secret(),
+            webhook.include_ciphertext as u8,
+            max_webhooks,
+        )?;
+
+        let audit_stmt = self.audit_event_stmt(form_id, audit_event, true)?;
+
+        let results = self.batch(vec![webhook_stmt, audit_stmt]).await?;
+
+        Ok(changes_from(results.first())? > 0)
+    }
+
+    #[worker::send]
+    pub async fn list_webhooks(&self, form_id: &FormId) -> anyhow::Result<Vec<Webhook>> {
+        let stmt = query!(
+            &self.db,
+            "
+            SELECT
+                webhooks.webhook_id,
+                webhooks.url,
+                webhooks.include_ciphertext,
+                webhooks.created_at
+            FROM webhooks
+            JOIN forms ON webhooks.form = forms.id
+            WHERE forms.form_id = ?1
+            ORDER BY webhooks.id;
+            ",
+            form_id,
+        )?;
+
+        #[derive(Debug, Deserialize)]
+        struct Row {
+            webhook_id: WebhookId,
+            url: String,
+            include_ciphertext: u8,
+            created_at: String,
+        }
+
+        stmt.all()
+            .await?
+            .results::<Row>()?
+            .into_iter()
+            .map(|row| {
+                Ok(Webhook {
+                    id: row.webhook_id,
+                    url: row.url,
+                    include_ciphertext: row.include_ciphertext != 0,
+                    created_at: NaiveDateTime::parse_from_str(
+                        &row.created_at,
+                        SQLITE_DATETIME_FORMAT,
+                    )?
+                    .and_utc(),
+                })
+            })
+            .collect()
+    }
+
+    #[worker::send]
+    pub async fn delete_webhook(
+        &self,
+        form_id: &FormId,
+        webhook_id: &WebhookId,
+        audit_event: &NewAuditEvent,
+    ) -> anyhow::Result<()> {
+        let webhook_stmt = query!(
+            &self.db,
+            "
+            DELETE FROM webhooks
+            WHERE
+                webhooks.webhook_id = ?2
+                AND webhooks.form = (SELECT forms.id FROM forms WHERE forms.form_id = ?1);
+            ",
+            form_id,
+            webhook_id,
+        )?;
+
+        let audit_stmt = self.audit_event_stmt(form_id, audit_event, true)?;
+
+        self.batch(vec![webhook_stmt, audit_stmt]).await?;
+
+        Ok(())
+    }
+
+    // Returns `None` if the webhook has been deleted since the event was queued.
+    #[worker::send]
+    pub async fn get_webhook_target(
+        &self,
+        webhook_id: &WebhookId,
+    ) -> anyhow::Result<Option<WebhookTarget>> {
+        let stmt = query!(
+            &self.db,
+            "
+            SELECT url, secret
+            FROM webhooks
+            WHERE webhook_id = ?1;
+            ",
+            webhook_id,
+        )?;
+
+        #[derive(Debug, Deserialize)]
+        struct Row {
+            url: String,
+            secret: WebhookSecret,
+        }
+
+        Ok(stmt.first::<Row>(None).await?.map(|row| WebhookTarget {
+            url: row.url,
+            secret: row.secret,
+        }))
+    }
+
+    // If the browser already registered this endpoint, possibly for a different secret link, it
+    // moves to this one, since the endpoint belongs to whoever is currently using that browser.
+    //
+    // Returns `false` if the key doesn't exist.
+    #[worker::send]
+    pub async fn put_push_subscription(
+        &self,
+        form_id: &FormId,
+        key_id: &ClientKeyId,
+        subscription_id: &PushSubscriptionId,
+        endpoint: &str,
+        p256dh: &PushSubscriptionKey,
+        auth: &PushSubscriptionAuth,
+    ) -> anyhow::Result<bool> {
+        let stmt = query!(
+            &self.db,
+            "
+            INSERT INTO push_subscriptions (key, subscription_id, endpoint, p256dh, auth)
+            SELECT keys.id, ?3, ?4, ?5, ?6
+            FROM keys
+            JOIN forms ON keys.form = forms.id
+            WHERE
+                forms.form_id = ?1
+                AND keys.key_index = ?2
+            ON CONFLICT (endpoint) DO UPDATE
+            SET
+                key = excluded.key,
+                p256dh = excluded.p256dh,
+                auth = excluded.auth;
+            ",
+            form_id,
+            key_id,
+            subscription_id,
+            endpoint,
+            p256dh,
+            auth,
+        )?;
+
+        let meta = stmt.run().await?.meta()?;
+
+        Ok(meta.and_then(|meta| meta.changes).unwrap_or(0) > 0)
+    }
+
+    #[worker::send]
+    pub async fn delete_push_subscriptions(
+        &self,
+        form_id: &FormId,
+        key_id: &ClientKeyId,
+    ) -> anyhow::Result<()> {
+        let stmt = query!(
+            &self.db,
+            "
+            DELETE FROM push_subscriptions
+            WHERE push_subscriptions.key = (
+                SELECT keys.id
+                FROM keys
+                JOIN forms ON keys.form = forms.id
+                WHERE
+                    forms.form_id = ?1
+                    AND keys.key_index = ?2
+            );
+            ",
+            form_id,
+            key_id,
+        )?;
 
-        let rows = stmt.all().await?.results::<Row>()?;
+        stmt.run().await?.meta()?;
 
-        rows.into_iter()
-            .map(|row| {
-                Ok(ClientKeys {
-                    id: row.key_index,
-                    public_signing_key: row.public_signing_key,
-                    wrapped_private_primary_key: row.wrapped_private_primary_key,
-                    encrypted_comment: row.encrypted_comment,
-                    role: row.role,
-                    protected: row.protected != 0,
-                    accessed_at: row
-                        .accessed_at
-                        .map(|s| NaiveDateTime::parse_from_str(&s, SQLITE_DATETIME_FORMAT))
-                        .transpose()?
-                        .map(|dt| dt.and_utc()),
-                })
-            })
-            .collect::<anyhow::Result<Vec<_>>>()
+        Ok(())
     }
 
     #[worker::send]
-    pub async fn store_client_keys(
+    pub async fn list_push_subscription_ids(
         &self,
         form_id: &FormId,
-        public_signing_key: &PublicSigningKey,
-        wrapped_private_primary_key: Option<&WrappedPrivatePrimaryKey>,
-        encrypted_comment: &EncryptedKeyComment,
-        role: AccessRole,
-    ) -> anyhow::Result<Option<ClientKeyId>> {
+    ) -> anyhow::Result<Vec<PushSubscriptionId>> {
         let stmt = query!(
             &self.db,
             "
-            INSERT INTO keys (
-                form,
-                key_index,
-                public_signing_key,
-                wrapped_private_primary_key,
-                encrypted_comment,
-                role
-            )
-            SELECT
-                forms.id,
-                forms.next_key_index,
-                ?2,
-                ?3,
-                ?4,
-                ?5
-            FROM forms
-            WHERE forms.form_id = ?1
-            RETURNING keys.key_index;
+            SELECT push_subscriptions.subscription_id
+            FROM push_subscriptions
+            JOIN keys ON push_subscriptions.key = keys.id
+            JOIN forms ON keys.form = forms.id
+            WHERE forms.form_id = ?1;
             ",
             form_id,
-            public_signing_key,
-            wrapped_private_primary_key,
-            encrypted_comment,
-            role,
         )?;
 
-        Ok(stmt.first::<ClientKeyId>(Some("key_index")).await?)
+        #[derive(Debug, Deserialize)]
+        struct Row {
+            subscription_id: PushSubscriptionId,
+        }
+
+        Ok(stmt
+            .all()
+            .await?
+            .results::<Row>()?
+            .into_iter()
+            .map(|row| row.subscription_id)
+            .collect())
     }
 
+    // Returns `None` if the subscription has been removed since the notification was queued, such
+    // as because its secret link was revoked.
     #[worker::send]
-    pub async fn update_client_keys(
+    pub async fn get_push_subscription(
+        &self,
+        subscription_id: &PushSubscriptionId,
+    ) -> anyhow::Result<Option<PushSubscription>> {
+        let stmt = query!(
+            &self.db,
+            "
+            SELECT endpoint, p256dh, auth
+            FROM push_subscriptions
+            WHERE subscription_id = ?1;
+            ",
+            subscription_id,
+        )?;
+
+        #[derive(Debug, Deserialize)]
+        struct Row {
+            endpoint: String,
+            p256dh: PushSubscriptionKey,
+            auth: PushSubscriptionAuth,
+        }
+
+        Ok(stmt.first::<Row>(None).await?.map(|row| PushSubscription {
+            endpoint: row.endpoint,
+            p256dh: row.p256dh,
+            auth: row.auth,
+        }))
+    }
+
+    // Push services tell us when a subscription has expired or been revoked by the user.
+    #[worker::send]
+    pub async fn delete_push_subscription(
+        &self,
+        subscription_id: &PushSubscriptionId,
+    ) -> anyhow::Result<()> {
+        let stmt = query!(
+            &self.db,
+            "
+            DELETE FROM push_subscriptions
+            WHERE subscription_id = ?1;
+            ",
+            subscription_id,
+        )?;
+
+        stmt.run().await?.meta()?;
+
+        Ok(())
+    }
+
+    // Subscribing again replaces the address without resetting when the last digest was sent.
+    //
+    // Returns `false` if the key doesn't exist.
+    #[worker::send]
+    pub async fn put_digest_subscription(
         &self,
         form_id: &FormId,
         key_id: &ClientKeyId,
-        wrapped_private_primary_key: Option<&WrappedPrivatePrimaryKey>,
-        encrypted_comment: Option<&EncryptedKeyComment>,
-    ) -> anyhow::Result<()> {
+        origin: &str,
+        encrypted_email: &EncryptedEmailAddress,
+    ) -> anyhow::Result<bool> {
         let stmt = query!(
             &self.db,
             "
-            UPDATE keys
-            SET
-                wrapped_private_primary_key = COALESCE(?3, keys.wrapped_private_primary_key),
-                encrypted_comment = COALESCE(?4, keys.encrypted_comment)
+            INSERT INTO digest_subscriptions (key, origin, encrypted_email)
+            SELECT keys.id, ?3, ?4
+            FROM keys
+            JOIN forms ON keys.form = forms.id
             WHERE
-                keys.form = (
-                    SELECT forms.id
-                    FROM forms
-                    WHERE forms.form_id = ?1
-                )
-                AND keys.key_index = ?2;
+                forms.form_id = ?1
+                AND keys.key_index = ?2
+            ON CONFLICT (key) DO UPDATE
+            SET
+                origin = excluded.origin,
+                encrypted_email = excluded.encrypted_email;
             ",
             form_id,
             key_id,
-            wrapped_private_primary_key,
-            encrypted_comment,
+            origin,
+            encrypted_email,
         )?;
 
-        stmt.run().await?.meta()?;
+        let meta = stmt.run().await?.meta()?;
 
-        Ok(())
+        Ok(meta.and_then(|meta| meta.changes).unwrap_or(0) > 0)
     }
 
     #[worker::send]
-    pub async fn delete_client_keys(
+    pub async fn delete_digest_subscription(
         &self,
         form_id: &FormId,
         key_id: &ClientKeyId,
@@ -463,12 +2720,14 @@ impl Store {
         let stmt = query!(
             &self.db,
             "
-            DELETE FROM keys
-            WHERE keys.id IN (
+            DELETE FROM digest_subscriptions
+            WHERE digest_subscriptions.key = (
                 SELECT keys.id
                 FROM keys
                 JOIN forms ON keys.form = forms.id
-                WHERE forms.form_id = ?1 AND keys.key_index = ?2
+                WHERE
+                    forms.form_id = ?1
+                    AND keys.key_index = ?2
             );
             ",
             form_id,
@@ -480,68 +2739,243 @@ impl Store {
         Ok(())
     }
 
+    // Count the submissions made since the last digest, up to but not including the cutoff. We
+    // exclude the cutoff so the same submission is never counted by two digests, since the
+    // timestamps only have a resolution of one second.
     #[worker::send]
-    pub async fn store_ephemeral_server_key(
+    pub async fn list_pending_digests(
         &self,
-        key_id: &ServerKeyId,
-        key: &EphemeralServerKey,
+        cutoff: DateTime<Utc>,
+        limit: u32,
+    ) -> anyhow::Result<Vec<PendingDigest>> {
+        let stmt = query!(
+            &self.db,
+            "
+            SELECT
+                forms.form_id,
+                keys.key_index,
+                digest_subscriptions.origin,
+                digest_subscriptions.encrypted_email,
+                COUNT(submissions.id) AS new_submission_count
+            FROM digest_subscriptions
+            JOIN keys ON digest_subscriptions.key = keys.id
+            JOIN forms ON keys.form = forms.id
+            JOIN submissions ON submissions.form = forms.id
+            WHERE
+                submissions.created_at >= digest_subscriptions.last_sent_at
+                AND submissions.created_at < ?1
+            GROUP BY digest_subscriptions.id
+            ORDER BY digest_subscriptions.last_sent_at, digest_subscriptions.id
+            LIMIT ?2;
+            ",
+            cutoff.format(SQLITE_DATETIME_FORMAT).to_string(),
+            limit,
+        )?;
+
+        #[derive(Debug, Deserialize)]
+        struct Row {
+            form_id: FormId,
+            key_index: ClientKeyId,
+            origin: String,
+            encrypted_email: EncryptedEmailAddress,
+            new_submission_count: u64,
+        }
+
+        Ok(stmt
+            .all()
+            .await?
+            .results::<Row>()?
+            .into_iter()
+            .map(|row| PendingDigest {
+                form_id: row.form_id,
+                client_key_id: row.key_index,
+                origin: row.origin,
+                encrypted_email: row.encrypted_email,
+                new_submission_count: row.new_submission_count,
+            })
+            .collect())
+    }
+
+    #[worker::send]
+    pub async fn mark_digest_sent(
+        &self,
+        form_id: &FormId,
+        key_id: &ClientKeyId,
+        cutoff: DateTime<Utc>,
     ) -> anyhow::Result<()> {
-        self.kv
-            .put(&server_key_key(key_id), key.expose_secret())
-            .map_err(wrap_kv_err)?
-            .expiration_ttl(server_key_ttl())
-            .execute()
-            .await
-            .map_err(wrap_kv_err)?;
+        let stmt = query!(
+            &self.db,
+            "
+            UPDATE digest_subscriptions
+            SET last_sent_at = ?3
+            WHERE digest_subscriptions.key = (
+                SELECT keys.id
+                FROM keys
+                JOIN forms ON keys.form = forms.id
+                WHERE
+                    forms.form_id = ?1
+                    AND keys.key_index = ?2
+            );
+            ",
+            form_id,
+            key_id,
+            cutoff.format(SQLITE_DATETIME_FORMAT).to_string(),
+        )?;
+
+        stmt.run().await?.meta()?;
 
         Ok(())
     }
 
     #[worker::send]
-    pub async fn get_ephemeral_server_key(
-        &self,
-        key_id: &ServerKeyId,
-    ) -> anyhow::Result<Option<EphemeralServerKey>> {
-        Ok(self
-            .kv
-            .get(&server_key_key(key_id))
-            .text()
-            .await
-            .map_err(wrap_kv_err)?
-            .map(|s| s.parse())
-            .transpose()?)
+    pub async fn increment_metrics(&self, increments: &[MetricIncrement]) -> anyhow::Result<()> {
+        if increments.is_empty() {
+            return Ok(());
+        }
+
+        let statements = increments
+            .iter()
+            .map(|increment| {
+                query!(
+                    &self.db,
+                    "
+                    INSERT INTO metrics (day, name, route, status, value)
+                    VALUES (date(CURRENT_TIMESTAMP), ?1, ?2, ?3, ?4)
+                    ON CONFLICT (day, name, route, status) DO UPDATE
+                    SET value = metrics.value + excluded.value;
+                    ",
+                    increment.metric.name(),
+                    increment.route,
+                    increment.status,
+                    increment.value,
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        self.batch(statements).await?;
+
+        Ok(())
     }
 
     #[worker::send]
-    pub async fn has_challenge_id(&self, challenge_id: &ChallengeId) -> anyhow::Result<bool> {
-        Ok(self
-            .kv
-            .get(&challenge_key(challenge_id))
-            .text()
-            .await
-            .map_err(wrap_kv_err)?
-            .is_some())
+    pub async fn list_metrics(&self) -> anyhow::Result<Vec<MetricRow>> {
+        let stmt = query!(
+            &self.db,
+            "
+            SELECT day, name, route, status, value
+            FROM metrics
+            ORDER BY day, name, route, status;
+            ",
+        )?;
+
+        #[derive(Debug, Deserialize)]
+        struct Row {
+            day: String,
+            name: String,
+            route: String,
+            status: String,
+            value: u64,
+        }
+
+        Ok(stmt
+            .all()
+            .await?
+            .results::<Row>()?
+            .into_iter()
+            .map(|row| MetricRow {
+                day: row.day,
+                name: row.name,
+                route: row.route,
+                status: row.status,
+                value: row.value,
+            })
+            .collect())
     }
 
     #[worker::send]
-    pub async fn store_challenge_id(&self, challenge_id: &ChallengeId) -> anyhow::Result<()> {
-        self.kv
-            .put(&challenge_key(challenge_id), "")
-            .map_err(wrap_kv_err)?
-            .expiration_ttl(challenge_ttl())
-            .execute()
-            .await
-            .map_err(wrap_kv_err)?;
+    pub async fn delete_old_metrics(&self, retention_days: u32) -> anyhow::Result<()> {
+        let stmt = query!(
+            &self.db,
+            "
+            DELETE FROM metrics
+            WHERE metrics.day < date(CURRENT_TIMESTAMP, ?1);
+            ",
+            format!("-{} days", retention_days),
+        )?;
+
+        stmt.run().await?.meta()?;
 
         Ok(())
     }
 
+    // Returns the form and client key a refresh token was issued for, deleting it in the process.
+    // If two requests try to consume the same refresh token concurrently, only one of them will
+    // see a row deleted.
+    //
+    // Refresh tokens issued before we recorded their origin belong to the default tenant.
     #[worker::send]
-    pub async fn delete_challenge_id(&self, challenge_id: &ChallengeId) -> anyhow::Result<()> {
-        self.kv
-            .delete(&challenge_key(challenge_id))
-            .await
-            .map_err(wrap_kv_err)?;
+    pub async fn consume_refresh_token(
+        &self,
+        token_hash: &RefreshTokenHash,
+        origin: &str,
+    ) -> anyhow::Result<Option<(FormId, ClientKeyId)>> {
+        let stmt = query!(
+            &self.db,
+            "
+            SELECT forms.form_id, keys.key_index
+            FROM refresh_tokens
+            JOIN keys ON refresh_tokens.key = keys.id
+            JOIN forms ON keys.form = forms.id
+            WHERE
+                refresh_tokens.token_hash = ?1
+                AND IFNULL(refresh_tokens.origin, ?3) = ?2
+                AND refresh_tokens.expires_at > CURRENT_TIMESTAMP;
+            ",
+            token_hash,
+            origin,
+            config::default_tenant().origin,
+        )?;
+
+        #[derive(Debug, Deserialize)]
+        struct Row {
+            form_id: FormId,
+            key_index: ClientKeyId,
+        }
+
+        let row = match stmt.first::<Row>(None).await? {
+            Some(row) => row,
+            None => return Ok(None),
+        };
+
+        let stmt = query!(
+            &self.db,
+            "
+            DELETE FROM refresh_tokens
+            WHERE refresh_tokens.token_hash = ?1;
+            ",
+            token_hash,
+        )?;
+
+        let meta = stmt.run().await?.meta()?;
+
+        if meta.and_then(|meta| meta.changes).unwrap_or(0) == 0 {
+            return Ok(None);
+        }
+
+        Ok(Some((row.form_id, row.key_index)))
+    }
+
+    #[worker::send]
+    pub async fn delete_expired_refresh_tokens(&self) -> anyhow::Result<()> {
+        let stmt = query!(
+            &self.db,
+            "
+            DELETE FROM refresh_tokens
+            WHERE refresh_tokens.expires_at < CURRENT_TIMESTAMP;
+            ",
+        )?;
+
+        stmt.run().await?.meta()?;
 
         Ok(())
     }
@@ -573,8 +3007,9 @@ impl Store {
         key_id: &ClientKeyId,
         salt: &SecretLinkPasswordSalt,
         nonce: &SecretLinkPasswordNonce,
+        audit_event: &NewAuditEvent,
     ) -> anyhow::Result<()> {
-        let stmt = query!(
+        let passwords_stmt = query!(
             &self.db,
             "
             INSERT INTO passwords (key, salt, nonce)
@@ -590,7 +3025,9 @@ impl Store {
             nonce,
         )?;
 
-        stmt.run().await?.meta()?;
+        let audit_stmt = self.audit_event_stmt(form_id, audit_event, true)?;
+
+        self.batch(vec![passwords_stmt, audit_stmt]).await?;
 
         Ok(())
     }
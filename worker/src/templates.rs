@@ -0,0 +1,79 @@
+use serde::Serialize;
+
+use crate::{
+    keys::{PublicPrimaryKey, PublicSigningKey, TemplateSignature},
+    models::FormTemplate,
+};
+
+//
+// The primary key fingerprint in a sharing link only lets respondents check the public primary key
+// they're given, not what the form says about who's collecting it. So organizers can also sign the
+// form's template together with its public primary key, using a template signing key whose
+// fingerprint is in the sharing link, and respondents can check that nothing has been changed by
+// anyone but the organizers.
+//
+// The signed message is this prefix, a colon, and then the fields below serialized as JSON with no
+// whitespace and the keys of every object in lexicographic order.
+//
+
+const SIGNED_MESSAGE_PREFIX: &str = "notwithouthelp-template";
+
+// The fields of these structs are declared in lexicographic order, which is the order they're
+// serialized in.
+
+#[derive(Debug, Serialize)]
+struct SignedRole<'a> {
+    details: &'a [String],
+    id: &'a str,
+    name: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+struct SignedTemplate<'a> {
+    contact_methods: &'a [String],
+    description: &'a str,
+    org_name: &'a str,
+    public_primary_key: &'a PublicPrimaryKey,
+    roles: Vec<SignedRole<'a>>,
+}
+
+fn signed_message(
+    template: &FormTemplate,
+    public_primary_key: &PublicPrimaryKey,
+) -> serde_json::Result<String> {
+    let signed = SignedTemplate {
+        contact_methods: &template.contact_methods,
+        description: &template.description,
+        org_name: &template.org_name,
+        public_primary_key,
+        roles: template
+            .roles
+            .iter()
+            .map(|role| SignedRole {
+                details: &role.details,
+                id: &role.id,
+                name: &role.name,
+            })
+            .collect(),
+    };
+
+    Ok(format!(
+        "{}:{}",
+        SIGNED_MESSAGE_PREFIX,
+        serde_json::to_string(&signed)?
+    ))
+}
+
+pub fn verify(
+    signing_key: &PublicSigningKey,
+    template: &FormTemplate,
+    public_primary_key: &PublicPrimaryKey,
+    signature: &TemplateSignature,
+) -> bool {
+    match signed_message(template, public_primary_key) {
+        Ok(message) => signing_key
+            .verify_template(message.as_bytes(), signature)
+            .is_ok(),
+        Err(_) => false,
+    }
+}
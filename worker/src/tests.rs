@@ -0,0 +1,1922 @@
+//
+// These tests run the router natively, against SQLite and an in-memory KV namespace instead of D1
+// and Workers KV, so they don't need wrangler. The end-to-end tests in the `tests` crate cover the
+// same API against a real worker.
+//
+
+use std::sync::{Arc, Mutex};
+
+use axum::{
+    body::{self, Body},
+    http::{header, HeaderMap, Method, Request, StatusCode},
+    Router,
+};
+use base64::prelude::*;
+use ed25519_dalek::{Signer, SigningKey};
+use futures::executor::block_on;
+use jsonwebtoken as jwt;
+use serde_json::{json, Value as JsonValue};
+use sha2::{Digest, Sha256};
+use tower_service::Service;
+use worker::async_trait::async_trait;
+
+use crate::{
+    config,
+    notifications::{Notification, NotificationQueue, QueuedNotification},
+    router::{self, AppState},
+    signing::{self, TokenSigningKey},
+    storage::{
+        memory::{MemoryBlobBackend, MemoryKvBackend},
+        sqlite::SqliteBackend,
+        Blobs, Database, KeyValue,
+    },
+    store::UnauthenticatedStore,
+    transparency,
+};
+
+#[derive(Debug, Default)]
+struct CollectedNotifications(Mutex<Vec<QueuedNotification>>);
+
+#[async_trait]
+impl NotificationQueue for CollectedNotifications {
+    async fn enqueue(&self, notifications: Vec<QueuedNotification>) -> anyhow::Result<()> {
+        self.0.lock().unwrap().extend(notifications);
+        Ok(())
+    }
+}
+
+struct TestApp {
+    router: Router,
+    // For tests which need to set up state the API can't, such as tokens signed via EdDSA.
+    store: UnauthenticatedStore,
+    db: Database,
+    blobs: MemoryBlobBackend,
+    notifications: Arc<CollectedNotifications>,
+}
+
+struct TestForm {
+    form_id: String,
+    client_key_id: String,
+    signing_key: SigningKey,
+}
+
+// The server encrypts audit events with the form's public primary key, so it must be a valid key.
+const PUBLIC_PRIMARY_KEY: &str = "AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=";
+
+fn test_config_var(name: &str) -> Option<String> {
+    match name {
+        "ENV" => Some("dev"),
+        "ORIGINS" => Some(
+            "http://localhost:8787 http://localhost:5173\nhttp://127.0.0.1:8787 http://127.0.0.1:5173",
+        ),
+        "SERVER_KEY_ENCRYPTION_KEY" => Some("qYBKX5+AnF4/7DvI7a1Bx4vqt7stQyvzjzR6ouNG8Rs="),
+        _ => None,
+    }
+    .map(str::to_string)
+}
+
+fn init_config() {
+    config::init_with(test_config_var).expect("invalid test config");
+}
+
+// Validate the test config with some vars changed, and return every problem with it.
+fn config_problems(vars: &[(&str, Option<&str>)]) -> Option<String> {
+    config::validate(|name| match vars.iter().find(|(var, _)| *var == name) {
+        Some((_, value)) => value.map(str::to_string),
+        None => test_config_var(name),
+    })
+    .err()
+    .map(|err| err.to_string())
+}
+
+fn new_signing_key() -> SigningKey {
+    SigningKey::from_bytes(&rand::random())
+}
+
+// Sign a respondent's request to change their submission, the same way the client does.
+fn sign_receipt_request(
+    signing_key: &SigningKey,
+    form_id: &str,
+    submission_id: &str,
+    revision: u32,
+    encrypted_body: Option<&str>,
+) -> String {
+    let message = match encrypted_body {
+        Some(body) => format!(
+            "notwithouthelp-receipt:amend:{}:{}:{}:{}",
+            form_id,
+            submission_id,
+            revision,
+            BASE64_STANDARD.encode(Sha256::digest(body.as_bytes()))
+        ),
+        None => format!(
+            "notwithouthelp-receipt:withdraw:{}:{}:{}",
+            form_id, submission_id, revision
+        ),
+    };
+
+    BASE64_STANDARD.encode(signing_key.sign(message.as_bytes()).to_bytes())
+}
+
+// Sign a form's template the same way the client does, with the fields and roles from
+// `signed_form_request`.
+fn sign_template(signing_key: &SigningKey, description: &str, public_primary_key: &str) -> String {
+    let message = format!(
+        concat!(
+            "notwithouthelp-template:",
+            r#"{{"contact_methods":["<contact_method>"],"description":"{}","org_name":"<org_name>","#,
+            r#""public_primary_key":"{}","roles":[{{"details":["<detail>"],"id":"<id>","name":"<name>"}}]}}"#,
+        ),
+        description, public_primary_key,
+    );
+
+    BASE64_STANDARD.encode(signing_key.sign(message.as_bytes()).to_bytes())
+}
+
+fn signed_form_request(signing_key: &SigningKey, template_signing_key: &SigningKey) -> JsonValue {
+    json!({
+        "public_primary_key": PUBLIC_PRIMARY_KEY,
+        "public_signing_key": BASE64_STANDARD.encode(signing_key.verifying_key().to_bytes()),
+        "org_name": "<org_name>",
+        "description": "<description>",
+        "contact_methods": ["<contact_method>"],
+        "roles": [{ "id": "<id>", "name": "<name>", "details": ["<detail>"] }],
+        "template_signing_key": BASE64_STANDARD.encode(template_signing_key.verifying_key().to_bytes()),
+        "template_signature": sign_template(template_signing_key, "<description>", PUBLIC_PRIMARY_KEY),
+    })
+}
+
+fn form_request(signing_key: &SigningKey) -> JsonValue {
+    json!({
+        "public_primary_key": PUBLIC_PRIMARY_KEY,
+        "public_signing_key": BASE64_STANDARD.encode(signing_key.verifying_key().to_bytes()),
+        "org_name": "<org_name>",
+        "description": "<description>",
+        "contact_methods": ["<contact_method>"],
+        "roles": [],
+    })
+}
+
+impl TestApp {
+    fn new() -> Self {
+        init_config();
+
+        let notifications = Arc::new(CollectedNotifications::default());
+        let db = Database::new(SqliteBackend::open_in_memory().unwrap());
+        let blobs = MemoryBlobBackend::new();
+
+        let store = UnauthenticatedStore::new(
+            db.clone(),
+            KeyValue::new(MemoryKvBackend::new()),
+            Blobs::new(blobs.clone()),
+        );
+
+        let state = AppState {
+            store: store.clone(),
+            tenant: config::tenant_for_host(Some("localhost")),
+            notifications: Arc::clone(&notifications) as Arc<dyn NotificationQueue>,
+        };
+
+        Self {
+            router: router::new(state),
+            store,
+            db,
+            blobs,
+            notifications,
+        }
+    }
+
+    // The same app, with the same data, as served to requests for another host.
+    fn for_host(&self, host: &str) -> Self {
+        let state = AppState {
+            store: self.store.clone(),
+            tenant: config::tenant_for_host(Some(host)),
+            notifications: Arc::clone(&self.notifications) as Arc<dyn NotificationQueue>,
+        };
+
+        Self {
+            router: router::new(state),
+            store: self.store.clone(),
+            db: self.db.clone(),
+            blobs: self.blobs.clone(),
+            notifications: Arc::clone(&self.notifications),
+        }
+    }
+
+    fn request(
+        &self,
+        method: Method,
+        path: &str,
+        token: Option<&str>,
+        body: Option<JsonValue>,
+    ) -> (StatusCode, JsonValue) {
+        let (status, _, body) = self.request_with_headers(method, path, token, &[], body);
+        (status, body)
+    }
+
+    fn request_with_headers(
+        &self,
+        method: Method,
+        path: &str,
+        token: Option<&str>,
+        headers: &[(&str, &str)],
+        body: Option<JsonValue>,
+    ) -> (StatusCode, HeaderMap, JsonValue) {
+        let mut req = Request::builder().method(method).uri(path);
+
+        if let Some(token) = token {
+            req = req.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+
+        for (name, value) in headers {
+            req = req.header(*name, *value);
+        }
+
+        let req = match body {
+            Some(body) => req
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string())),
+            None => req.body(Body::empty()),
+        }
+        .unwrap();
+
+        block_on(async {
+            let resp = self.router.clone().call(req).await.unwrap();
+            let status = resp.status();
+            let headers = resp.headers().clone();
+            let bytes = body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+
+            (
+                status,
+                headers,
+                serde_json::from_slice(&bytes).unwrap_or(JsonValue::Null),
+            )
+        })
+    }
+
+    // For endpoints which take or return raw bytes rather than JSON.
+    fn request_bytes(
+        &self,
+        method: Method,
+        path: &str,
+        token: Option<&str>,
+        body: Vec<u8>,
+    ) -> (StatusCode, Vec<u8>) {
+        let mut req = Request::builder().method(method).uri(path);
+
+        if let Some(token) = token {
+            req = req.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+
+        let req = req
+            .header(header::CONTENT_TYPE, "application/octet-stream")
+            .body(Body::from(body))
+            .unwrap();
+
+        block_on(async {
+            let resp = self.router.clone().call(req).await.unwrap();
+            let status = resp.status();
+            let bytes = body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+
+            (status, bytes.to_vec())
+        })
+    }
+
+    fn count_rows(&self, table: &str) -> u64 {
+        block_on(
+            self.db
+                .prepare(&format!("SELECT COUNT(*) AS count FROM {};", table))
+                .first::<u64>(Some("count")),
+        )
+        .unwrap()
+        .unwrap()
+    }
+
+    fn post_form(&self, signing_key: &SigningKey) -> (StatusCode, JsonValue) {
+        self.request(
+            Method::POST,
+            "/forms",
+            None,
+            Some(form_request(signing_key)),
+        )
+    }
+
+    fn create_form(&self) -> TestForm {
+        let signing_key = new_signing_key();
+
+        let (status, body) = self.post_form(&signing_key);
+
+        assert_eq!(status, StatusCode::CREATED);
+
+        TestForm {
+            form_id: body["form_id"].as_str().unwrap().to_string(),
+            client_key_id: body["client_key_id"].as_str().unwrap().to_string(),
+            signing_key,
+        }
+    }
+
+    fn challenge_response(
+        &self,
+        form_id: &str,
+        client_key_id: &str,
+        signing_key: &SigningKey,
+    ) -> JsonValue {
+        let (status, body) = self.request(
+            Method::POST,
+            &format!("/challenges/{}/{}", form_id, client_key_id),
+            None,
+            None,
+        );
+
+        assert_eq!(status, StatusCode::OK);
+
+        let challenge = body["challenge"].as_str().unwrap();
+        let payload = challenge.split('.').nth(1).unwrap();
+        let claims =
+            serde_json::from_slice::<JsonValue>(&BASE64_URL_SAFE_NO_PAD.decode(payload).unwrap())
+                .unwrap();
+        let nonce = BASE64_STANDARD
+            .decode(claims["nonce"].as_str().unwrap())
+            .unwrap();
+
+        json!({
+            "challenge": challenge,
+            "signature": BASE64_STANDARD.encode(signing_key.sign(&nonce).to_bytes()),
+        })
+    }
+
+    fn authenticate(&self, form_id: &str, client_key_id: &str, signing_key: &SigningKey) -> String {
+        let challenge_response = self.challenge_response(form_id, client_key_id, signing_key);
+
+        let (status, body) = self.request(Method::POST, "/tokens", None, Some(challenge_response));
+
+        assert_eq!(status, StatusCode::OK);
+
+        body["token"].as_str().unwrap().to_string()
+    }
+
+    fn upload_attachment(&self, form_id: &str, chunks: &[&[u8]]) -> String {
+        let (status, body) = self.request(
+            Method::POST,
+            &format!("/attachments/{}", form_id),
+            None,
+            None,
+        );
+
+        assert_eq!(status, StatusCode::CREATED);
+
+        let attachment_id = body["attachment_id"].as_str().unwrap().to_string();
+
+        for (index, chunk) in chunks.iter().enumerate() {
+            let (status, _) = self.request_bytes(
+                Method::PUT,
+                &format!("/attachments/{}/{}/{}", form_id, attachment_id, index),
+                None,
+                chunk.to_vec(),
+            );
+
+            assert_eq!(status, StatusCode::NO_CONTENT);
+        }
+
+        attachment_id
+    }
+
+    // Rotate the server signing keys as if the current one had been signing tokens for long enough
+    // to be retired.
+    fn retire_server_signing_keys(&self) {
+        let store = self.store.without_authenticating();
+
+        block_on(async {
+            self.db
+                .prepare("UPDATE server_signing_keys SET created_at = datetime('now', '-30 days');")
+                .run()
+                .await
+                .unwrap();
+
+            signing::rotate_server_signing_keys(store).await.unwrap();
+
+            self.db
+                .prepare("UPDATE server_signing_keys SET created_at = datetime('now', '-1 day');")
+                .run()
+                .await
+                .unwrap();
+
+            signing::rotate_server_signing_keys(store).await.unwrap();
+        });
+
+        assert_eq!(
+            block_on(
+                self.db
+                    .prepare(
+                        "SELECT COUNT(*) AS count FROM server_signing_keys WHERE private_key IS NULL;"
+                    )
+                    .first::<u64>(Some("count")),
+            )
+            .unwrap(),
+            Some(1)
+        );
+    }
+
+    // Verify a JWT signed with a server signing key against the published JWK Set, and return its
+    // claims.
+    fn verify_published(&self, token: &str) -> JsonValue {
+        let kid = jwt::decode_header(token).unwrap().kid.unwrap();
+
+        let (status, jwks) = self.request(Method::GET, "/.well-known/jwks.json", None, None);
+
+        assert_eq!(status, StatusCode::OK);
+
+        let jwk = jwks["keys"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|key| key["kid"] == json!(kid))
+            .unwrap();
+
+        let mut validation = jwt::Validation::new(jwt::Algorithm::EdDSA);
+        validation.required_spec_claims.clear();
+        validation.validate_exp = false;
+
+        jwt::decode::<JsonValue>(
+            token,
+            &jwt::DecodingKey::from_ed_components(jwk["x"].as_str().unwrap()).unwrap(),
+            &validation,
+        )
+        .unwrap()
+        .claims
+    }
+
+    fn add_key(&self, form_id: &str, token: &str) -> String {
+        let (status, body) = self.request(
+            Method::POST,
+            &format!("/keys/{}", form_id),
+            Some(token),
+            Some(json!({
+                "public_signing_key": BASE64_STANDARD.encode(new_signing_key().verifying_key().to_bytes()),
+                "wrapped_private_primary_key": "<wrapped_private_primary_key>",
+                "encrypted_comment": "<encrypted_comment>",
+                "role": "read",
+            })),
+        );
+
+        assert_eq!(status, StatusCode::CREATED);
+
+        body["client_key_id"].as_str().unwrap().to_string()
+    }
+}
+
+#[test]
+fn authenticated_key_can_access_its_form() {
+    let app = TestApp::new();
+    let form = app.create_form();
+
+    let token = app.authenticate(&form.form_id, &form.client_key_id, &form.signing_key);
+
+    let (status, body) = app.request(
+        Method::GET,
+        &format!("/keys/{}", form.form_id),
+        Some(&token),
+        None,
+    );
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body.as_array().map(Vec::len), Some(1));
+}
+
+#[test]
+fn failing_to_store_initial_key_does_not_create_form() {
+    let app = TestApp::new();
+
+    block_on(
+        app.db
+            .prepare(
+                "
+                CREATE TRIGGER fail_key_insert BEFORE INSERT ON keys
+                BEGIN
+                    SELECT RAISE(ABORT, 'key insert failed');
+                END;
+                ",
+            )
+            .run(),
+    )
+    .unwrap();
+
+    let (status, _) = app.post_form(&new_signing_key());
+
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(app.count_rows("forms"), 0);
+}
+
+#[test]
+fn request_without_token_is_unauthorized() {
+    let app = TestApp::new();
+    let form = app.create_form();
+
+    let (status, _) = app.request(Method::GET, &format!("/keys/{}", form.form_id), None, None);
+
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[test]
+fn token_is_only_valid_for_its_own_form() {
+    let app = TestApp::new();
+    let form = app.create_form();
+    let other_form = app.create_form();
+
+    let token = app.authenticate(&form.form_id, &form.client_key_id, &form.signing_key);
+
+    let (status, _) = app.request(
+        Method::GET,
+        &format!("/keys/{}", other_form.form_id),
+        Some(&token),
+        None,
+    );
+
+    assert_eq!(status, StatusCode::FORBIDDEN);
+}
+
+#[test]
+fn challenge_can_only_be_used_once() {
+    let app = TestApp::new();
+    let form = app.create_form();
+
+    let challenge_response =
+        app.challenge_response(&form.form_id, &form.client_key_id, &form.signing_key);
+
+    let (status, _) = app.request(
+        Method::POST,
+        "/tokens",
+        None,
+        Some(challenge_response.clone()),
+    );
+
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = app.request(Method::POST, "/tokens", None, Some(challenge_response));
+
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[test]
+fn challenge_signed_by_wrong_key_is_unauthorized() {
+    let app = TestApp::new();
+    let form = app.create_form();
+
+    let challenge_response =
+        app.challenge_response(&form.form_id, &form.client_key_id, &new_signing_key());
+
+    let (status, _) = app.request(Method::POST, "/tokens", None, Some(challenge_response));
+
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[test]
+fn eddsa_access_token_is_rejected_once_its_session_ends() {
+    let app = TestApp::new();
+    let form = app.create_form();
+
+    let token = app.authenticate(&form.form_id, &form.client_key_id, &form.signing_key);
+
+    // The tests sign tokens via HS256, so re-sign this one's claims via EdDSA, as the server does
+    // when it's configured to.
+    let payload = token.split('.').nth(1).unwrap();
+    let claims =
+        serde_json::from_slice::<JsonValue>(&BASE64_URL_SAFE_NO_PAD.decode(payload).unwrap())
+            .unwrap();
+    let key = block_on(TokenSigningKey::for_publication(
+        app.store.without_authenticating(),
+    ))
+    .unwrap();
+    let token = jwt::encode(&key.header(), &claims, &key.encoding_key().unwrap()).unwrap();
+
+    let path = format!("/keys/{}", form.form_id);
+
+    let (status, _) = app.request(Method::GET, &path, Some(&token), None);
+
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = app.request(Method::POST, "/tokens/revoke", Some(&token), None);
+
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let (status, _) = app.request(Method::GET, &path, Some(&token), None);
+
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[test]
+fn test_config_is_valid() {
+    assert_eq!(config_problems(&[]), None);
+}
+
+#[test]
+fn missing_config_reports_every_required_var() {
+    let problems = config_problems(&[
+        ("ENV", None),
+        ("ORIGINS", None),
+        ("SERVER_KEY_ENCRYPTION_KEY", None),
+    ])
+    .unwrap();
+
+    assert!(problems.contains("`ENV` is required"));
+    assert!(problems.contains("`ORIGINS` is required"));
+    assert!(problems.contains("`SERVER_KEY_ENCRYPTION_KEY` is required"));
+}
+
+#[test]
+fn unparseable_config_is_rejected() {
+    let problems = config_problems(&[
+        ("ENV", Some("staging")),
+        ("ORIGINS", Some("http://localhost:8787")),
+        ("ACCESS_TOKEN_EXP", Some("soon")),
+        ("MAX_ATTACHMENT_LEN", Some("0")),
+        ("REDACT_LOGS", Some("yes")),
+        ("SERVER_KEY_ENCRYPTION_KEY", Some("<key>")),
+    ])
+    .unwrap();
+
+    assert!(problems.contains("`ENV` must be `dev` or `prod`"));
+    assert!(problems.contains("has no CORS allowed origins"));
+    assert!(problems.contains("`ACCESS_TOKEN_EXP` must be a number of seconds"));
+    assert!(problems.contains("`MAX_ATTACHMENT_LEN` must be greater than zero"));
+    assert!(problems.contains("`REDACT_LOGS` must be `true` or `false`"));
+    assert!(problems.contains("`SERVER_KEY_ENCRYPTION_KEY` is invalid"));
+}
+
+#[test]
+fn inconsistent_config_is_rejected() {
+    let problems = config_problems(&[
+        ("ACCESS_TOKEN_EXP", Some("600")),
+        ("CHALLENGE_TOKEN_EXP", Some("600")),
+        ("MAX_SUBMISSION_LEN_FLOOR", Some("2048")),
+        ("MAX_SUBMISSION_LEN_CEILING", Some("1024")),
+        ("IDEMPOTENCY_KEY_TTL", Some("30")),
+        ("MAIL_RELAY_URL", Some("https://relay.example.com")),
+    ])
+    .unwrap();
+
+    assert!(problems.contains("`CHALLENGE_TOKEN_EXP` must be shorter than `ACCESS_TOKEN_EXP`"));
+    assert!(problems.contains(
+        "`MAX_SUBMISSION_LEN_FLOOR` must not be greater than `MAX_SUBMISSION_LEN_CEILING`"
+    ));
+    assert!(problems.contains("`IDEMPOTENCY_KEY_TTL` must be at least 60 seconds"));
+    assert!(problems.contains("`EMAIL_ENCRYPTION_KEY` is required when `MAIL_RELAY_URL` is set"));
+}
+
+#[test]
+fn invalid_config_is_served_as_unavailable() {
+    let err = config::validate(|name| match name {
+        "ENV" => None,
+        _ => test_config_var(name),
+    })
+    .unwrap_err();
+
+    let req = Request::builder()
+        .method(Method::GET)
+        .uri("/forms")
+        .header(header::ORIGIN, "https://example.com")
+        .body(Body::empty())
+        .unwrap();
+
+    let (status, headers, body) = block_on(async {
+        let resp = crate::config_error_router(&err).call(req).await.unwrap();
+        let status = resp.status();
+        let headers = resp.headers().clone();
+        let bytes = body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+
+        (
+            status,
+            headers,
+            serde_json::from_slice::<JsonValue>(&bytes).unwrap(),
+        )
+    });
+
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert!(body["diagnostic_id"].is_string());
+
+    // The response doesn't say what's wrong with the config.
+    assert!(!body.to_string().contains("ENV"));
+
+    // The frontend can read the response whichever origin it's on.
+    assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+}
+
+#[test]
+fn tenant_is_chosen_by_host() {
+    init_config();
+
+    assert_eq!(
+        config::tenant_for_host(Some("127.0.0.1")).origin,
+        "http://127.0.0.1:8787"
+    );
+
+    // Unknown hosts are served as the first tenant.
+    assert_eq!(
+        config::tenant_for_host(Some("example.com")).origin,
+        "http://localhost:8787"
+    );
+    assert_eq!(
+        config::tenant_for_host(None).origin,
+        "http://localhost:8787"
+    );
+}
+
+#[test]
+fn cors_only_allows_the_tenants_own_frontend() {
+    let app = TestApp::new();
+
+    let preflight = |app: &TestApp, origin: &str| {
+        let (_, headers, _) = app.request_with_headers(
+            Method::OPTIONS,
+            "/forms",
+            None,
+            &[
+                ("origin", origin),
+                ("access-control-request-method", "POST"),
+            ],
+            None,
+        );
+
+        headers
+            .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .map(|value| value.to_str().unwrap().to_string())
+    };
+
+    let other_tenant = app.for_host("127.0.0.1");
+    let unknown_host = app.for_host("example.com");
+
+    assert_eq!(
+        preflight(&app, "http://localhost:5173").as_deref(),
+        Some("http://localhost:5173")
+    );
+    assert_eq!(preflight(&app, "http://127.0.0.1:5173"), None);
+    assert_eq!(
+        preflight(&other_tenant, "http://127.0.0.1:5173").as_deref(),
+        Some("http://127.0.0.1:5173")
+    );
+    assert_eq!(preflight(&other_tenant, "http://localhost:5173"), None);
+    assert_eq!(
+        preflight(&unknown_host, "http://localhost:5173").as_deref(),
+        Some("http://localhost:5173")
+    );
+}
+
+#[test]
+fn token_is_only_valid_for_the_tenant_which_issued_it() {
+    let app = TestApp::new();
+    let other_tenant = app.for_host("127.0.0.1");
+    let form = app.create_form();
+
+    let token = app.authenticate(&form.form_id, &form.client_key_id, &form.signing_key);
+    let path = format!("/keys/{}", form.form_id);
+
+    let (status, _) = app.request(Method::GET, &path, Some(&token), None);
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = other_tenant.request(Method::GET, &path, Some(&token), None);
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let token = other_tenant.authenticate(&form.form_id, &form.client_key_id, &form.signing_key);
+
+    let (status, _) = other_tenant.request(Method::GET, &path, Some(&token), None);
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[test]
+fn deleted_client_key_ids_are_not_reused() {
+    let app = TestApp::new();
+    let form = app.create_form();
+
+    let token = app.authenticate(&form.form_id, &form.client_key_id, &form.signing_key);
+
+    let first_key_id = app.add_key(&form.form_id, &token);
+
+    let (status, _) = app.request(
+        Method::DELETE,
+        &format!("/keys/{}/{}", form.form_id, first_key_id),
+        Some(&token),
+        None,
+    );
+
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let second_key_id = app.add_key(&form.form_id, &token);
+
+    assert_ne!(first_key_id, second_key_id);
+}
+
+#[test]
+fn deleting_form_deletes_its_keys() {
+    let app = TestApp::new();
+    let form = app.create_form();
+
+    let token = app.authenticate(&form.form_id, &form.client_key_id, &form.signing_key);
+
+    let (status, _) = app.request(
+        Method::DELETE,
+        &format!("/forms/{}", form.form_id),
+        Some(&token),
+        None,
+    );
+
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let (status, _) = app.request(Method::GET, &format!("/forms/{}", form.form_id), None, None);
+
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let challenge_response =
+        app.challenge_response(&form.form_id, &form.client_key_id, &form.signing_key);
+
+    let (status, _) = app.request(Method::POST, "/tokens", None, Some(challenge_response));
+
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(app.count_rows("form_deletions"), 1);
+}
+
+#[test]
+fn submission_queues_webhook_event() {
+    let app = TestApp::new();
+    let form = app.create_form();
+
+    let token = app.authenticate(&form.form_id, &form.client_key_id, &form.signing_key);
+
+    let (status, _) = app.request(
+        Method::POST,
+        &format!("/webhooks/{}", form.form_id),
+        Some(&token),
+        Some(json!({ "url": "https://example.com/webhook" })),
+    );
+
+    assert_eq!(status, StatusCode::CREATED);
+
+    let (status, _) = app.request(
+        Method::POST,
+        &format!("/submissions/{}", form.form_id),
+        None,
+        Some(json!({ "encrypted_body": "<encrypted_body>" })),
+    );
+
+    assert_eq!(status, StatusCode::CREATED);
+
+    let notifications = app.notifications.0.lock().unwrap();
+
+    assert!(matches!(
+        notifications.as_slice(),
+        [QueuedNotification {
+            notification: Notification::Webhook(_),
+            attempt: 0,
+        }]
+    ));
+}
+
+#[test]
+fn webhook_cannot_point_to_local_address() {
+    let app = TestApp::new();
+    let form = app.create_form();
+
+    let token = app.authenticate(&form.form_id, &form.client_key_id, &form.signing_key);
+
+    for url in [
+        "https://localhost/webhook",
+        "https://127.0.0.1/webhook",
+        "https://10.0.0.1/webhook",
+        "https://169.254.169.254/latest/meta-data",
+        "https://[::1]/webhook",
+        "https://[::ffff:192.168.0.1]/webhook",
+        "https://[fd00::1]/webhook",
+    ] {
+        let (status, _) = app.request(
+            Method::POST,
+            &format!("/webhooks/{}", form.form_id),
+            Some(&token),
+            Some(json!({ "url": url })),
+        );
+
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", url);
+    }
+
+    assert_eq!(app.count_rows("webhooks"), 0);
+}
+
+#[test]
+fn retried_submission_is_only_stored_once() {
+    let app = TestApp::new();
+    let form = app.create_form();
+
+    let submit = || {
+        app.request_with_headers(
+            Method::POST,
+            &format!("/submissions/{}", form.form_id),
+            None,
+            &[("Idempotency-Key", "<idempotency_key>")],
+            Some(json!({ "encrypted_body": "<encrypted_body>" })),
+        )
+    };
+
+    let (status, headers, first_body) = submit();
+
+    assert_eq!(status, StatusCode::CREATED);
+    assert!(!headers.contains_key("idempotent-replayed"));
+
+    let (status, headers, second_body) = submit();
+
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(headers["idempotent-replayed"], "true");
+    assert_eq!(first_body, second_body);
+    assert_eq!(app.count_rows("submissions"), 1);
+}
+
+#[test]
+fn idempotency_key_cannot_be_reused_with_different_body() {
+    let app = TestApp::new();
+    let form = app.create_form();
+
+    let submit = |encrypted_body: &str| {
+        let (status, _, _) = app.request_with_headers(
+            Method::POST,
+            &format!("/submissions/{}", form.form_id),
+            None,
+            &[("Idempotency-Key", "<idempotency_key>")],
+            Some(json!({ "encrypted_body": encrypted_body })),
+        );
+
+        status
+    };
+
+    assert_eq!(submit("<first_encrypted_body>"), StatusCode::CREATED);
+    assert_eq!(
+        submit("<second_encrypted_body>"),
+        StatusCode::UNPROCESSABLE_ENTITY
+    );
+    assert_eq!(app.count_rows("submissions"), 1);
+}
+
+#[test]
+fn idempotency_key_is_scoped_to_client_key() {
+    let app = TestApp::new();
+    let form = app.create_form();
+
+    let token = app.authenticate(&form.form_id, &form.client_key_id, &form.signing_key);
+
+    let other_signing_key = new_signing_key();
+    let (status, body) = app.request(
+        Method::POST,
+        &format!("/keys/{}", form.form_id),
+        Some(&token),
+        Some(json!({
+            "public_signing_key": BASE64_STANDARD.encode(other_signing_key.verifying_key().to_bytes()),
+            "wrapped_private_primary_key": "<wrapped_private_primary_key>",
+            "encrypted_comment": "<encrypted_comment>",
+            "role": "admin",
+        })),
+    );
+    assert_eq!(status, StatusCode::CREATED);
+
+    let other_client_key_id = body["client_key_id"].as_str().unwrap();
+    let other_token = app.authenticate(&form.form_id, other_client_key_id, &other_signing_key);
+
+    let request = json!({
+        "public_signing_key": BASE64_STANDARD.encode(new_signing_key().verifying_key().to_bytes()),
+        "wrapped_private_primary_key": "<wrapped_private_primary_key>",
+        "encrypted_comment": "<encrypted_comment>",
+        "role": "read",
+    });
+
+    let (status, _, first) = app.request_with_headers(
+        Method::POST,
+        &format!("/keys/{}", form.form_id),
+        Some(&token),
+        &[("idempotency-key", "<idempotency_key>")],
+        Some(request.clone()),
+    );
+    assert_eq!(status, StatusCode::CREATED);
+
+    let (status, headers, second) = app.request_with_headers(
+        Method::POST,
+        &format!("/keys/{}", form.form_id),
+        Some(&other_token),
+        &[("idempotency-key", "<idempotency_key>")],
+        Some(request),
+    );
+    assert_eq!(status, StatusCode::CREATED);
+    assert!(!headers.contains_key("idempotent-replayed"));
+    assert_ne!(first["client_key_id"], second["client_key_id"]);
+}
+
+#[test]
+fn submission_longer_than_form_limit_is_rejected() {
+    let app = TestApp::new();
+
+    let mut form_request = form_request(&new_signing_key());
+    form_request["max_submission_len"] = json!(2048);
+
+    let (status, body) = app.request(Method::POST, "/forms", None, Some(form_request));
+
+    assert_eq!(status, StatusCode::CREATED);
+
+    let form_id = body["form_id"].as_str().unwrap();
+
+    let (status, body) = app.request(Method::GET, &format!("/forms/{}", form_id), None, None);
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["max_submission_len"], json!(2048));
+
+    let submit = |len: usize| {
+        let (status, _) = app.request(
+            Method::POST,
+            &format!("/submissions/{}", form_id),
+            None,
+            Some(json!({ "encrypted_body": "a".repeat(len) })),
+        );
+
+        status
+    };
+
+    assert_eq!(submit(2048), StatusCode::CREATED);
+    assert_eq!(submit(2049), StatusCode::PAYLOAD_TOO_LARGE);
+}
+
+#[test]
+fn submission_with_future_key_epoch_is_rejected() {
+    let app = TestApp::new();
+    let form = app.create_form();
+
+    let submit = |key_epoch: u64| {
+        let (status, _) = app.request(
+            Method::POST,
+            &format!("/submissions/{}", form.form_id),
+            None,
+            Some(json!({ "encrypted_body": "<encrypted_body>", "key_epoch": key_epoch })),
+        );
+
+        status
+    };
+
+    assert_eq!(submit(1), StatusCode::BAD_REQUEST);
+    assert_eq!(submit(0), StatusCode::CREATED);
+}
+
+#[test]
+fn form_without_limit_gets_default_limit() {
+    let app = TestApp::new();
+    let form = app.create_form();
+
+    let (status, body) = app.request(Method::GET, &format!("/forms/{}", form.form_id), None, None);
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["max_submission_len"], json!(5120));
+}
+
+#[test]
+fn submission_limit_must_be_within_bounds() {
+    let app = TestApp::new();
+
+    let mut form_request = form_request(&new_signing_key());
+    form_request["max_submission_len"] = json!(1024 * 1024);
+
+    let (status, _) = app.request(Method::POST, "/forms", None, Some(form_request));
+
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(app.count_rows("forms"), 0);
+}
+
+#[test]
+fn auth_endpoints_have_smaller_body_limit() {
+    let app = TestApp::new();
+
+    let (status, _) = app.request(
+        Method::POST,
+        "/tokens",
+        None,
+        Some(json!({ "challenge": "a".repeat(4096), "signature": "" })),
+    );
+
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+}
+
+#[test]
+fn attachments_can_be_downloaded_once_submitted() {
+    let app = TestApp::new();
+    let form = app.create_form();
+    let token = app.authenticate(&form.form_id, &form.client_key_id, &form.signing_key);
+
+    let attachment_id = app.upload_attachment(&form.form_id, &[b"first", b"second"]);
+    let chunk_path = format!("/attachments/{}/{}/1", form.form_id, attachment_id);
+
+    // Until it's part of a submission, the attachment is still being uploaded.
+    let (status, _) = app.request_bytes(Method::GET, &chunk_path, Some(&token), Vec::new());
+
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _) = app.request(
+        Method::POST,
+        &format!("/submissions/{}", form.form_id),
+        None,
+        Some(json!({
+            "encrypted_body": "<encrypted_body>",
+            "attachment_ids": [attachment_id],
+        })),
+    );
+
+    assert_eq!(status, StatusCode::CREATED);
+
+    let (status, body) = app.request(
+        Method::GET,
+        &format!("/submissions/{}", form.form_id),
+        Some(&token),
+        None,
+    );
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        body[0]["attachments"],
+        json!([{ "attachment_id": attachment_id, "chunk_count": 2, "len": 11 }])
+    );
+
+    let (status, chunk) = app.request_bytes(Method::GET, &chunk_path, Some(&token), Vec::new());
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(chunk, b"second");
+
+    let (status, _) = app.request_bytes(Method::GET, &chunk_path, None, Vec::new());
+
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    // Once it's submitted, the attachment can't be changed.
+    let (status, _) = app.request_bytes(
+        Method::PUT,
+        &format!("/attachments/{}/{}/2", form.form_id, attachment_id),
+        None,
+        b"third".to_vec(),
+    );
+
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn attachment_chunks_must_be_in_order_and_within_limit() {
+    let app = TestApp::new();
+    let form = app.create_form();
+
+    let attachment_id = app.upload_attachment(&form.form_id, &[]);
+    let upload = |index: usize, len: usize| {
+        let (status, _) = app.request_bytes(
+            Method::PUT,
+            &format!("/attachments/{}/{}/{}", form.form_id, attachment_id, index),
+            None,
+            vec![0; len],
+        );
+
+        status
+    };
+
+    assert_eq!(upload(1, 1), StatusCode::CONFLICT);
+
+    // The default limit is 10 chunks of the largest size.
+    for index in 0..10 {
+        assert_eq!(upload(index, 1024 * 1024), StatusCode::NO_CONTENT);
+    }
+
+    assert_eq!(upload(10, 1), StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(upload(10, 1024 * 1024 + 1), StatusCode::PAYLOAD_TOO_LARGE);
+}
+
+#[test]
+fn abandoned_attachments_cannot_fill_form_quota() {
+    let app = TestApp::new();
+    let form = app.create_form();
+
+    // The default limit for pending uploads is two attachments of the largest size.
+    let chunk = vec![0u8; 1024 * 1024];
+    let chunks = vec![chunk.as_slice(); 10];
+    let attachment_id = app.upload_attachment(&form.form_id, &chunks);
+    app.upload_attachment(&form.form_id, &chunks);
+
+    let pending_id = app.upload_attachment(&form.form_id, &[]);
+    let (status, _) = app.request_bytes(
+        Method::PUT,
+        &format!("/attachments/{}/{}/0", form.form_id, pending_id),
+        None,
+        vec![0; 1],
+    );
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+
+    let (status, _) = app.request(
+        Method::POST,
+        &format!("/submissions/{}", form.form_id),
+        None,
+        Some(json!({
+            "encrypted_body": "<encrypted_body>",
+            "attachment_ids": [attachment_id],
+        })),
+    );
+    assert_eq!(status, StatusCode::CREATED);
+
+    let (status, _) = app.request_bytes(
+        Method::PUT,
+        &format!("/attachments/{}/{}/0", form.form_id, pending_id),
+        None,
+        vec![0; 1],
+    );
+    assert_eq!(status, StatusCode::NO_CONTENT);
+}
+
+#[test]
+fn submission_must_only_reference_uploaded_attachments() {
+    let app = TestApp::new();
+    let form = app.create_form();
+    let other_form = app.create_form();
+
+    let empty_attachment_id = app.upload_attachment(&form.form_id, &[]);
+    let other_attachment_id = app.upload_attachment(&other_form.form_id, &[b"chunk"]);
+
+    for attachment_id in [empty_attachment_id, other_attachment_id] {
+        let (status, _) = app.request(
+            Method::POST,
+            &format!("/submissions/{}", form.form_id),
+            None,
+            Some(json!({
+                "encrypted_body": "<encrypted_body>",
+                "attachment_ids": [attachment_id],
+            })),
+        );
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    assert_eq!(app.count_rows("submissions"), 0);
+}
+
+#[test]
+fn deleting_form_deletes_its_attachments() {
+    let app = TestApp::new();
+    let form = app.create_form();
+    let token = app.authenticate(&form.form_id, &form.client_key_id, &form.signing_key);
+
+    let attachment_id = app.upload_attachment(&form.form_id, &[b"first", b"second"]);
+    app.upload_attachment(&form.form_id, &[b"abandoned"]);
+
+    let (status, _) = app.request(
+        Method::POST,
+        &format!("/submissions/{}", form.form_id),
+        None,
+        Some(json!({
+            "encrypted_body": "<encrypted_body>",
+            "attachment_ids": [attachment_id],
+        })),
+    );
+
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(app.blobs.count(), 3);
+
+    let (status, _) = app.request(
+        Method::DELETE,
+        &format!("/forms/{}", form.form_id),
+        Some(&token),
+        None,
+    );
+
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert_eq!(app.blobs.count(), 0);
+    assert_eq!(app.count_rows("attachments"), 0);
+    assert_eq!(app.count_rows("deleted_attachments"), 0);
+}
+
+#[test]
+fn submission_receipt_is_signed_with_published_key() {
+    let app = TestApp::new();
+    let form = app.create_form();
+
+    let (status, body) = app.request(
+        Method::POST,
+        &format!("/submissions/{}", form.form_id),
+        None,
+        Some(json!({ "encrypted_body": "<encrypted_body>" })),
+    );
+
+    assert_eq!(status, StatusCode::CREATED);
+
+    let claims = app.verify_published(body["receipt"].as_str().unwrap());
+
+    assert_eq!(claims["type"], json!("receipt"));
+    assert_eq!(claims["sub"], body["submission_id"]);
+    assert_eq!(claims["form_id"], json!(form.form_id));
+    assert_eq!(claims["revision"], json!(0));
+    assert_eq!(
+        claims["body_hash"],
+        json!(BASE64_STANDARD.encode(Sha256::digest(b"<encrypted_body>")))
+    );
+}
+
+#[test]
+fn receipt_can_be_verified_after_key_is_retired() {
+    let app = TestApp::new();
+    let form = app.create_form();
+
+    let (status, body) = app.request(
+        Method::POST,
+        &format!("/submissions/{}", form.form_id),
+        None,
+        Some(json!({ "encrypted_body": "<encrypted_body>" })),
+    );
+
+    assert_eq!(status, StatusCode::CREATED);
+
+    let receipt = body["receipt"].as_str().unwrap().to_string();
+
+    app.retire_server_signing_keys();
+
+    let claims = app.verify_published(&receipt);
+
+    assert_eq!(claims["sub"], body["submission_id"]);
+
+    // New receipts are signed with the new key.
+    let (status, body) = app.request(
+        Method::POST,
+        &format!("/submissions/{}", form.form_id),
+        None,
+        Some(json!({ "encrypted_body": "<encrypted_body>" })),
+    );
+
+    assert_eq!(status, StatusCode::CREATED);
+
+    let new_receipt = body["receipt"].as_str().unwrap();
+
+    assert_ne!(
+        jwt::decode_header(new_receipt).unwrap().kid,
+        jwt::decode_header(&receipt).unwrap().kid
+    );
+
+    app.verify_published(new_receipt);
+}
+
+#[test]
+fn respondent_can_amend_and_withdraw_submission() {
+    let app = TestApp::new();
+    let form = app.create_form();
+    let token = app.authenticate(&form.form_id, &form.client_key_id, &form.signing_key);
+    let receipt_key = new_signing_key();
+
+    let (status, body) = app.request(
+        Method::POST,
+        &format!("/submissions/{}", form.form_id),
+        None,
+        Some(json!({
+            "encrypted_body": "<encrypted_body>",
+            "receipt_public_key": BASE64_STANDARD.encode(receipt_key.verifying_key().to_bytes()),
+        })),
+    );
+
+    assert_eq!(status, StatusCode::CREATED);
+
+    let submission_id = body["submission_id"].as_str().unwrap();
+    let receipt_path = format!("/receipts/{}/{}", form.form_id, submission_id);
+
+    let amend = |signing_key: &SigningKey, revision: u32| {
+        let (status, body) = app.request(
+            Method::PUT,
+            &receipt_path,
+            None,
+            Some(json!({
+                "encrypted_body": "<amended_body>",
+                "revision": revision,
+                "signature": sign_receipt_request(
+                    signing_key,
+                    &form.form_id,
+                    submission_id,
+                    revision,
+                    Some("<amended_body>"),
+                ),
+            })),
+        );
+
+        (status, body)
+    };
+
+    assert_eq!(amend(&new_signing_key(), 0).0, StatusCode::FORBIDDEN);
+
+    let (status, body) = amend(&receipt_key, 0);
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["revision"], json!(1));
+
+    // The same signed request can't be used again.
+    assert_eq!(amend(&receipt_key, 0).0, StatusCode::CONFLICT);
+
+    let (status, body) = app.request(
+        Method::GET,
+        &format!("/submissions/{}", form.form_id),
+        Some(&token),
+        None,
+    );
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body[0]["encrypted_body"], json!("<amended_body>"));
+    assert_eq!(body[0]["revision"], json!(1));
+
+    let (status, _) = app.request(
+        Method::DELETE,
+        &receipt_path,
+        None,
+        Some(json!({
+            "revision": 1,
+            "signature": sign_receipt_request(&receipt_key, &form.form_id, submission_id, 1, None),
+        })),
+    );
+
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert_eq!(app.count_rows("submissions"), 0);
+}
+
+#[test]
+fn submission_without_receipt_key_cannot_be_withdrawn() {
+    let app = TestApp::new();
+    let form = app.create_form();
+
+    let (status, body) = app.request(
+        Method::POST,
+        &format!("/submissions/{}", form.form_id),
+        None,
+        Some(json!({ "encrypted_body": "<encrypted_body>" })),
+    );
+
+    assert_eq!(status, StatusCode::CREATED);
+
+    let submission_id = body["submission_id"].as_str().unwrap();
+
+    let (status, _) = app.request(
+        Method::DELETE,
+        &format!("/receipts/{}/{}", form.form_id, submission_id),
+        None,
+        Some(json!({
+            "revision": 0,
+            "signature": sign_receipt_request(
+                &new_signing_key(),
+                &form.form_id,
+                submission_id,
+                0,
+                None,
+            ),
+        })),
+    );
+
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(app.count_rows("submissions"), 1);
+}
+
+#[test]
+fn respondent_can_read_replies_with_mailbox_token() {
+    let app = TestApp::new();
+    let form = app.create_form();
+    let token = app.authenticate(&form.form_id, &form.client_key_id, &form.signing_key);
+
+    let (status, body) = app.request(
+        Method::POST,
+        &format!("/submissions/{}", form.form_id),
+        None,
+        Some(json!({
+            "encrypted_body": "<encrypted_body>",
+            "reply_public_key": "<reply_public_key>",
+        })),
+    );
+
+    assert_eq!(status, StatusCode::CREATED);
+
+    let submission_id = body["submission_id"].as_str().unwrap();
+    let mailbox_token = body["mailbox_token"].as_str().unwrap();
+
+    let (status, body) = app.request(
+        Method::GET,
+        &format!("/submissions/{}", form.form_id),
+        Some(&token),
+        None,
+    );
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body[0]["reply_public_key"], json!("<reply_public_key>"));
+
+    let (status, body) = app.request(Method::GET, "/mailbox", Some(mailbox_token), None);
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, json!([]));
+
+    let (status, body) = app.request(
+        Method::POST,
+        &format!("/replies/{}/{}", form.form_id, submission_id),
+        Some(&token),
+        Some(json!({ "encrypted_body": "<encrypted_reply>" })),
+    );
+
+    assert_eq!(status, StatusCode::CREATED);
+
+    let reply_id = body["reply_id"].clone();
+
+    let (status, body) = app.request(Method::GET, "/mailbox", Some(mailbox_token), None);
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body.as_array().unwrap().len(), 1);
+    assert_eq!(body[0]["reply_id"], reply_id);
+    assert_eq!(body[0]["encrypted_body"], json!("<encrypted_reply>"));
+
+    let (status, _) = app.request(Method::GET, "/mailbox", Some("<wrong_token>"), None);
+
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[test]
+fn submission_without_reply_key_cannot_be_replied_to() {
+    let app = TestApp::new();
+    let form = app.create_form();
+    let token = app.authenticate(&form.form_id, &form.client_key_id, &form.signing_key);
+
+    let (status, body) = app.request(
+        Method::POST,
+        &format!("/submissions/{}", form.form_id),
+        None,
+        Some(json!({ "encrypted_body": "<encrypted_body>" })),
+    );
+
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(body["mailbox_token"], JsonValue::Null);
+
+    let (status, _) = app.request(
+        Method::POST,
+        &format!(
+            "/replies/{}/{}",
+            form.form_id,
+            body["submission_id"].as_str().unwrap()
+        ),
+        Some(&token),
+        Some(json!({ "encrypted_body": "<encrypted_reply>" })),
+    );
+
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(app.count_rows("replies"), 0);
+}
+
+#[test]
+fn signed_template_must_be_signed_again_when_edited() {
+    let app = TestApp::new();
+    let signing_key = new_signing_key();
+    let template_signing_key = new_signing_key();
+
+    let (status, body) = app.request(
+        Method::POST,
+        "/forms",
+        None,
+        Some(signed_form_request(&signing_key, &template_signing_key)),
+    );
+
+    assert_eq!(status, StatusCode::CREATED);
+
+    let form_id = body["form_id"].as_str().unwrap();
+    let client_key_id = body["client_key_id"].as_str().unwrap();
+    let token = app.authenticate(form_id, client_key_id, &signing_key);
+
+    let (status, body) = app.request(Method::GET, &format!("/forms/{}", form_id), None, None);
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        body["template_signature"],
+        json!(sign_template(
+            &template_signing_key,
+            "<description>",
+            PUBLIC_PRIMARY_KEY
+        ))
+    );
+
+    let edit = |template_signature: Option<String>| {
+        app.request(
+            Method::PATCH,
+            &format!("/forms/{}", form_id),
+            Some(&token),
+            Some(json!({
+                "org_name": "<org_name>",
+                "description": "<new_description>",
+                "contact_methods": ["<contact_method>"],
+                "roles": [{ "id": "<id>", "name": "<name>", "details": ["<detail>"] }],
+                "template_signature": template_signature,
+            })),
+        )
+        .0
+    };
+
+    assert_eq!(edit(None), StatusCode::BAD_REQUEST);
+
+    // The old signature is for the old description.
+    assert_eq!(
+        edit(Some(sign_template(
+            &template_signing_key,
+            "<description>",
+            PUBLIC_PRIMARY_KEY
+        ))),
+        StatusCode::BAD_REQUEST
+    );
+
+    let new_signature = sign_template(
+        &template_signing_key,
+        "<new_description>",
+        PUBLIC_PRIMARY_KEY,
+    );
+
+    assert_eq!(edit(Some(new_signature.clone())), StatusCode::NO_CONTENT);
+
+    let (status, body) = app.request(Method::GET, &format!("/forms/{}", form_id), None, None);
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["description"], json!("<new_description>"));
+    assert_eq!(body["template_signature"], json!(new_signature));
+}
+
+#[test]
+fn form_with_invalid_template_signature_is_not_created() {
+    let app = TestApp::new();
+    let signing_key = new_signing_key();
+
+    let mut request = signed_form_request(&signing_key, &new_signing_key());
+    request["template_signing_key"] =
+        json!(BASE64_STANDARD.encode(new_signing_key().verifying_key().to_bytes()));
+
+    let (status, _) = app.request(Method::POST, "/forms", None, Some(request));
+
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(app.count_rows("forms"), 0);
+}
+
+#[test]
+fn form_log_records_submissions_and_key_changes() {
+    let app = TestApp::new();
+    let form = app.create_form();
+    let token = app.authenticate(&form.form_id, &form.client_key_id, &form.signing_key);
+
+    let (status, body) = app.request(
+        Method::POST,
+        &format!("/submissions/{}", form.form_id),
+        None,
+        Some(json!({ "encrypted_body": "<encrypted_body>" })),
+    );
+
+    assert_eq!(status, StatusCode::CREATED);
+
+    let submission_id = body["submission_id"].as_str().unwrap().to_string();
+    let client_key_id = app.add_key(&form.form_id, &token);
+
+    let (status, _) = app.request(
+        Method::DELETE,
+        &format!("/keys/{}/{}", form.form_id, client_key_id),
+        Some(&token),
+        None,
+    );
+
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let (status, body) = app.request(
+        Method::GET,
+        &format!("/logs/{}/entries", form.form_id),
+        Some(&token),
+        None,
+    );
+
+    assert_eq!(status, StatusCode::OK);
+
+    let entries = body
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|entry| entry["entry"].as_str().unwrap().to_string())
+        .collect::<Vec<_>>();
+
+    assert_eq!(entries.len(), 4);
+    assert!(entries[0].starts_with(&format!("key_added:{}:", form.client_key_id)));
+    assert_eq!(
+        entries[1],
+        format!(
+            "submission_added:{}:{}",
+            submission_id,
+            BASE64_STANDARD.encode(Sha256::digest(b"<encrypted_body>"))
+        )
+    );
+    assert!(entries[2].starts_with(&format!("key_added:{}:", client_key_id)));
+    assert_eq!(entries[3], format!("key_revoked:{}", client_key_id));
+
+    let (status, body) = app.request(
+        Method::GET,
+        &format!("/logs/{}/entries?start=1&end=3", form.form_id),
+        Some(&token),
+        None,
+    );
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        body.as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| entry["index"].clone())
+            .collect::<Vec<_>>(),
+        vec![json!(1), json!(2)]
+    );
+
+    let leaf = |entry: &str| Sha256::digest([&[0u8], entry.as_bytes()].concat());
+    let node = |left: &[u8], right: &[u8]| Sha256::digest([&[1u8], left, right].concat());
+
+    let root = node(
+        &node(&leaf(&entries[0]), &leaf(&entries[1])),
+        &node(&leaf(&entries[2]), &leaf(&entries[3])),
+    );
+
+    let (status, body) = app.request(
+        Method::GET,
+        &format!("/logs/{}", form.form_id),
+        Some(&token),
+        None,
+    );
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["tree_size"], json!(4));
+    assert_eq!(body["root_hash"], json!(BASE64_STANDARD.encode(root)));
+    assert!(body["started_at"].as_str().is_some());
+
+    let claims = app.verify_published(body["tree_head"].as_str().unwrap());
+
+    assert_eq!(claims["type"], json!("tree_head"));
+    assert_eq!(claims["form_id"], json!(form.form_id));
+    assert_eq!(claims["root_hash"], body["root_hash"]);
+
+    let (status, body) = app.request(
+        Method::GET,
+        &format!("/logs/{}/inclusion?index=1&tree_size=4", form.form_id),
+        Some(&token),
+        None,
+    );
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        body["audit_path"],
+        json!([
+            BASE64_STANDARD.encode(leaf(&entries[0])),
+            BASE64_STANDARD.encode(node(&leaf(&entries[2]), &leaf(&entries[3]))),
+        ])
+    );
+
+    let (status, _) = app.request(
+        Method::GET,
+        &format!("/logs/{}/consistency?first=2&second=5", form.form_id),
+        Some(&token),
+        None,
+    );
+
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, _) = app.request(
+        Method::GET,
+        &format!(
+            "/logs/{}/inclusion?index=1&tree_size={}",
+            form.form_id,
+            transparency::MAX_PROOF_TREE_SIZE + 1
+        ),
+        Some(&token),
+        None,
+    );
+
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[test]
+fn tree_head_can_be_verified_after_key_is_retired() {
+    let app = TestApp::new();
+    let form = app.create_form();
+    let token = app.authenticate(&form.form_id, &form.client_key_id, &form.signing_key);
+
+    let (status, body) = app.request(
+        Method::GET,
+        &format!("/logs/{}", form.form_id),
+        Some(&token),
+        None,
+    );
+
+    assert_eq!(status, StatusCode::OK);
+
+    let tree_head = body["tree_head"].as_str().unwrap().to_string();
+
+    app.retire_server_signing_keys();
+
+    let claims = app.verify_published(&tree_head);
+
+    assert_eq!(claims["type"], json!("tree_head"));
+    assert_eq!(claims["root_hash"], body["root_hash"]);
+}
+
+#[test]
+fn admin_changes_are_recorded_in_encrypted_audit_log() {
+    let app = TestApp::new();
+    let signing_key = new_signing_key();
+    let primary_key = crypto_box::SecretKey::generate(&mut rand::thread_rng());
+
+    let mut request = form_request(&signing_key);
+    request["public_primary_key"] = json!(BASE64_STANDARD.encode(primary_key.public_key()));
+
+    let (status, body) = app.request(Method::POST, "/forms", None, Some(request));
+
+    assert_eq!(status, StatusCode::CREATED);
+
+    let form_id = body["form_id"].as_str().unwrap().to_string();
+    let admin_key_id = body["client_key_id"].as_str().unwrap().to_string();
+    let token = app.authenticate(&form_id, &admin_key_id, &signing_key);
+
+    let client_key_id = app.add_key(&form_id, &token);
+
+    let (status, _) = app.request(
+        Method::PATCH,
+        &format!("/keys/{}/{}", form_id, client_key_id),
+        Some(&token),
+        Some(json!({ "role": "admin" })),
+    );
+
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let (status, _) = app.request(
+        Method::DELETE,
+        &format!("/keys/{}/{}", form_id, client_key_id),
+        Some(&token),
+        None,
+    );
+
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    // Changes which are refused aren't recorded.
+    let (status, _) = app.request(
+        Method::PATCH,
+        &format!("/keys/{}/{}", form_id, admin_key_id),
+        Some(&token),
+        Some(json!({ "role": "read" })),
+    );
+
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let (status, body) = app.request(
+        Method::GET,
+        &format!("/audit/{}?limit=2", form_id),
+        Some(&token),
+        None,
+    );
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["events"][0]["action"], json!("key_revoked"));
+    assert_eq!(body["events"][0]["actor"], json!(admin_key_id));
+    assert_eq!(body["events"][1]["action"], json!("key_updated"));
+    assert_eq!(body["next_before"], json!(1));
+
+    let ciphertext = BASE64_STANDARD
+        .decode(body["events"][0]["encrypted_details"].as_str().unwrap())
+        .unwrap();
+    let details =
+        serde_json::from_slice::<JsonValue>(&primary_key.unseal(&ciphertext).unwrap()).unwrap();
+
+    assert_eq!(details["client_key_id"], json!(client_key_id));
+
+    let (status, body) = app.request(
+        Method::GET,
+        &format!("/audit/{}?limit=2&before=1", form_id),
+        Some(&token),
+        None,
+    );
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["events"].as_array().unwrap().len(), 1);
+    assert_eq!(body["events"][0]["action"], json!("key_added"));
+    assert_eq!(body["next_before"], JsonValue::Null);
+}
+
+#[test]
+fn failing_to_record_audit_event_does_not_change_form() {
+    let app = TestApp::new();
+    let form = app.create_form();
+
+    let token = app.authenticate(&form.form_id, &form.client_key_id, &form.signing_key);
+    let client_key_id = app.add_key(&form.form_id, &token);
+
+    block_on(
+        app.db
+            .prepare(
+                "
+                CREATE TRIGGER fail_audit_event_insert BEFORE INSERT ON audit_events
+                BEGIN
+                    SELECT RAISE(ABORT, 'audit event insert failed');
+                END;
+                ",
+            )
+            .run(),
+    )
+    .unwrap();
+
+    let (status, _) = app.request(
+        Method::DELETE,
+        &format!("/keys/{}/{}", form.form_id, client_key_id),
+        Some(&token),
+        None,
+    );
+
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(app.count_rows("keys"), 2);
+}
@@ -0,0 +1,323 @@
+use base64::prelude::*;
+use jsonwebtoken as jwt;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::{config::Tenant, models::FormId, runtime, signing::TokenSigningKey, store::Store};
+
+//
+// Each form has an append-only log of the changes to its submissions and keys, so organizers don't
+// have to trust that the server never silently drops a submission or adds a key. The entries are
+// the leaves of a Merkle tree, which is hashed the same way as in Certificate Transparency (RFC
+// 9162): a leaf is hashed as `SHA-256(0x00 || entry)` and an interior node as
+// `SHA-256(0x01 || left || right)`.
+//
+// Clients can fetch a tree head signed with the current server signing key, and prove that an
+// entry is in the tree (an inclusion proof) or that an older tree head they saw is a prefix of a
+// newer one (a consistency proof). If the server ever shows them a history which doesn't extend
+// what they saw before, the proofs won't verify, and the signed tree heads prove it.
+//
+
+const TREE_HEAD_TOKEN_TYPE: &str = "tree_head";
+
+// We don't store the interior nodes of the tree, so a proof is computed from every leaf up to its
+// tree size. This bounds how much of the log one request can make us read and hash.
+pub const MAX_PROOF_TREE_SIZE: u64 = 1 << 16;
+
+const LEAF_PREFIX: u8 = 0x00;
+
+const NODE_PREFIX: u8 = 0x01;
+
+pub type Hash = [u8; 32];
+
+#[derive(Debug, Serialize)]
+struct TreeHeadClaims<'a> {
+    #[serde(rename = "type")]
+    token_type: &'static str,
+    iss: &'a str,
+    form_id: &'a FormId,
+    tree_size: u64,
+    root_hash: String,
+    iat: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(transparent)]
+pub struct SignedTreeHead(String);
+
+pub fn encode_hash(hash: &Hash) -> String {
+    BASE64_STANDARD.encode(hash)
+}
+
+pub fn leaf_hash(entry: &str) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update([LEAF_PREFIX]);
+    hasher.update(entry.as_bytes());
+    hasher.finalize().into()
+}
+
+fn node_hash(left: &Hash, right: &Hash) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update([NODE_PREFIX]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+// The largest power of two smaller than `n`, which is where the tree of `n` leaves is split.
+fn split_point(n: usize) -> usize {
+    let mut k = 1;
+
+    while k * 2 < n {
+        k *= 2;
+    }
+
+    k
+}
+
+// The hash of the tree with these leaves.
+pub fn root_hash(leaves: &[Hash]) -> Hash {
+    match leaves.len() {
+        0 => Sha256::digest([]).into(),
+        1 => leaves[0],
+        n => {
+            let k = split_point(n);
+            node_hash(&root_hash(&leaves[..k]), &root_hash(&leaves[k..]))
+        }
+    }
+}
+
+// The hashes needed to get from the leaf at `index` to the root of the tree with these leaves.
+// The caller must make sure `index` is within the tree.
+pub fn inclusion_proof(index: usize, leaves: &[Hash]) -> Vec<Hash> {
+    let n = leaves.len();
+
+    if n <= 1 {
+        return Vec::new();
+    }
+
+    let k = split_point(n);
+
+    if index < k {
+        let mut proof = inclusion_proof(index, &leaves[..k]);
+        proof.push(root_hash(&leaves[k..]));
+        proof
+    } else {
+        let mut proof = inclusion_proof(index - k, &leaves[k..]);
+        proof.push(root_hash(&leaves[..k]));
+        proof
+    }
+}
+
+// The hashes needed to prove that the tree with the first `old_size` of these leaves is a prefix
+// of the tree with all of them. The caller must make sure `old_size` is within the tree and not
+// zero.
+pub fn consistency_proof(old_size: usize, leaves: &[Hash]) -> Vec<Hash> {
+    subproof(old_size, leaves, true)
+}
+
+fn subproof(m: usize, leaves: &[Hash], is_complete_subtree: bool) -> Vec<Hash> {
+    let n = leaves.len();
+
+    if m == n {
+        return if is_complete_subtree {
+            Vec::new()
+        } else {
+            vec![root_hash(leaves)]
+        };
+    }
+
+    let k = split_point(n);
+
+    if m <= k {
+        let mut proof = subproof(m, &leaves[..k], is_complete_subtree);
+        proof.push(root_hash(&leaves[k..]));
+        proof
+    } else {
+        let mut proof = subproof(m - k, &leaves[k..], false);
+        proof.push(root_hash(&leaves[..k]));
+        proof
+    }
+}
+
+pub async fn sign_tree_head(
+    store: &Store,
+    tenant: &Tenant,
+    form_id: &FormId,
+    tree_size: u64,
+    root_hash: &Hash,
+) -> anyhow::Result<SignedTreeHead> {
+    let key = TokenSigningKey::for_publication(store).await?;
+
+    let claims = TreeHeadClaims {
+        token_type: TREE_HEAD_TOKEN_TYPE,
+        iss: &tenant.origin,
+        form_id,
+        tree_size,
+        root_hash: encode_hash(root_hash),
+        iat: runtime::now_millis() / 1000,
+    };
+
+    Ok(SignedTreeHead(jwt::encode(
+        &key.header(),
+        &claims,
+        &key.encoding_key()?,
+    )?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // These verify proofs the way a client does, following RFC 9162.
+
+    fn verify_inclusion(
+        index: usize,
+        tree_size: usize,
+        leaf: &Hash,
+        proof: &[Hash],
+        root: &Hash,
+    ) -> bool {
+        if index >= tree_size {
+            return false;
+        }
+
+        let (mut f, mut s) = (index, tree_size - 1);
+        let mut r = *leaf;
+
+        for p in proof {
+            if s == 0 {
+                return false;
+            }
+
+            if f & 1 == 1 || f == s {
+                r = node_hash(p, &r);
+
+                while f & 1 == 0 && f != 0 {
+                    f >>= 1;
+                    s >>= 1;
+                }
+            } else {
+                r = node_hash(&r, p);
+            }
+
+            f >>= 1;
+            s >>= 1;
+        }
+
+        s == 0 && r == *root
+    }
+
+    fn verify_consistency(
+        old_size: usize,
+        new_size: usize,
+        old_root: &Hash,
+        new_root: &Hash,
+        proof: &[Hash],
+    ) -> bool {
+        if old_size == new_size {
+            return proof.is_empty() && old_root == new_root;
+        }
+
+        let mut proof = proof.to_vec();
+
+        if old_size.is_power_of_two() {
+            proof.insert(0, *old_root);
+        }
+
+        let (mut f, mut s) = (old_size - 1, new_size - 1);
+
+        while f & 1 == 1 {
+            f >>= 1;
+            s >>= 1;
+        }
+
+        let Some((first, rest)) = proof.split_first() else {
+            return false;
+        };
+
+        let (mut fr, mut sr) = (*first, *first);
+
+        for c in rest {
+            if s == 0 {
+                return false;
+            }
+
+            if f & 1 == 1 || f == s {
+                fr = node_hash(c, &fr);
+                sr = node_hash(c, &sr);
+
+                while f & 1 == 0 && f != 0 {
+                    f >>= 1;
+                    s >>= 1;
+                }
+            } else {
+                sr = node_hash(&sr, c);
+            }
+
+            f >>= 1;
+            s >>= 1;
+        }
+
+        s == 0 && fr == *old_root && sr == *new_root
+    }
+
+    fn leaves(n: usize) -> Vec<Hash> {
+        (0..n).map(|i| leaf_hash(&format!("entry:{}", i))).collect()
+    }
+
+    #[test]
+    fn root_of_two_leaves_is_their_node() {
+        let leaves = leaves(2);
+
+        assert_eq!(root_hash(&leaves), node_hash(&leaves[0], &leaves[1]));
+    }
+
+    #[test]
+    fn inclusion_proofs_verify() {
+        for size in 1..=17 {
+            let leaves = leaves(size);
+            let root = root_hash(&leaves);
+
+            for index in 0..size {
+                let proof = inclusion_proof(index, &leaves);
+
+                assert!(verify_inclusion(index, size, &leaves[index], &proof, &root));
+
+                let wrong_leaf = leaf_hash("wrong");
+                assert!(!verify_inclusion(index, size, &wrong_leaf, &proof, &root));
+            }
+        }
+    }
+
+    #[test]
+    fn consistency_proofs_verify() {
+        for new_size in 1..=17 {
+            let leaves = leaves(new_size);
+            let new_root = root_hash(&leaves);
+
+            for old_size in 1..=new_size {
+                let old_root = root_hash(&leaves[..old_size]);
+                let proof = consistency_proof(old_size, &leaves);
+
+                assert!(verify_consistency(
+                    old_size, new_size, &old_root, &new_root, &proof
+                ));
+
+                // A history that was rewritten shouldn't be consistent with the old one.
+                let mut rewritten = leaves.clone();
+                rewritten[old_size - 1] = leaf_hash("wrong");
+                let rewritten_root = root_hash(&rewritten);
+                let proof = consistency_proof(old_size, &rewritten);
+
+                assert!(!verify_consistency(
+                    old_size,
+                    new_size,
+                    &old_root,
+                    &rewritten_root,
+                    &proof
+                ));
+            }
+        }
+    }
+}
@@ -0,0 +1,183 @@
+use std::net::IpAddr;
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use worker::Url;
+
+use crate::{
+    models::{EncryptedSubmissionBody, FormId, SubmissionId, WebhookEventId, WebhookId},
+    runtime,
+    store::Store,
+};
+
+//
+// When a submission is made, we queue an event for each of the form's webhooks. The queue consumer
+// sends each event to its webhook, signed with the webhook's secret. See the `notifications`
+// module for how failed deliveries are retried.
+//
+// Events contain the form ID, submission ID, and timestamp, but only contain the submission's
+// ciphertext if the organizers asked for it when registering the webhook.
+//
+
+pub const MAX_WEBHOOKS_PER_FORM: u32 = 5;
+
+pub const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+pub const TIMESTAMP_HEADER: &str = "X-Webhook-Timestamp";
+pub const EVENT_ID_HEADER: &str = "X-Webhook-Id";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WebhookEventType {
+    #[serde(rename = "submission.created")]
+    SubmissionCreated,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookEvent {
+    pub id: WebhookEventId,
+    #[serde(rename = "type")]
+    pub event_type: WebhookEventType,
+    pub form_id: FormId,
+    pub submission_id: SubmissionId,
+    pub created_at: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encrypted_body: Option<EncryptedSubmissionBody>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookDelivery {
+    pub webhook_id: WebhookId,
+    pub event: WebhookEvent,
+}
+
+// Whether an address is reachable from the internet, as opposed to only from the network the server
+// is on.
+pub fn is_public_address(addr: IpAddr) -> bool {
+    match addr {
+        IpAddr::V4(addr) => {
+            let [first, second, ..] = addr.octets();
+
+            !(addr.is_private()
+                || addr.is_loopback()
+                || addr.is_link_local()
+                || addr.is_unspecified()
+                || addr.is_broadcast()
+                || addr.is_documentation()
+                || first == 0
+                // The shared address space used for carrier-grade NAT.
+                || (first == 100 && second & 0xc0 == 64))
+        }
+        IpAddr::V6(addr) => match addr.to_ipv4_mapped() {
+            Some(addr) => is_public_address(IpAddr::V4(addr)),
+            None => {
+                let first = addr.segments()[0];
+
+                // Unique local addresses are in `fc00::/7`, and link-local ones in `fe80::/10`.
+                !(addr.is_loopback()
+                    || addr.is_unspecified()
+                    || first & 0xfe00 == 0xfc00
+                    || first & 0xffc0 == 0xfe80)
+            }
+        },
+    }
+}
+
+// Whether a URL's host is an address on the network the server is on, or a name which always
+// resolves to one. Other names have to be checked when they're resolved.
+pub fn is_local_host(host: &str) -> bool {
+    // IPv6 hosts are in brackets.
+    match host
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse::<IpAddr>()
+    {
+        Ok(addr) => !is_public_address(addr),
+        Err(_) => host.is_empty() || host == "localhost" || host.ends_with(".localhost"),
+    }
+}
+
+// Events aren't encrypted unless the organizers ask for the ciphertext, so we require HTTPS unless
+// this is a test environment. We also don't let organizers point us at the network the server is
+// on, unless this is a test environment which receives events on localhost.
+pub fn validate_url(url: &str, allow_insecure: bool) -> anyhow::Result<()> {
+    let url = Url::parse(url).map_err(|err| anyhow!("Webhook URL is invalid: {}", err))?;
+
+    match url.scheme() {
+        "https" => {}
+        "http" if allow_insecure => {}
+        scheme => return Err(anyhow!("Webhook URL must use HTTPS, got `{}`.", scheme)),
+    }
+
+    let host = url.host_str().unwrap_or_default();
+
+    if is_local_host(host) && !allow_insecure {
+        return Err(anyhow!(
+            "Webhook URL must not point to a local or private address, got `{}`.",
+            host
+        ));
+    }
+
+    Ok(())
+}
+
+fn unix_timestamp() -> u64 {
+    runtime::now_millis() / 1000
+}
+
+// An event for each of the form's webhooks.
+pub async fn submission_created(
+    store: &Store,
+    form_id: &FormId,
+    submission_id: &SubmissionId,
+    encrypted_body: &EncryptedSubmissionBody,
+    created_at: &str,
+) -> anyhow::Result<Vec<WebhookDelivery>> {
+    let webhooks = store.list_webhooks(form_id).await?;
+
+    Ok(webhooks
+        .into_iter()
+        .map(|webhook| WebhookDelivery {
+            webhook_id: webhook.id,
+            event: WebhookEvent {
+                id: WebhookEventId::new(),
+                event_type: WebhookEventType::SubmissionCreated,
+                form_id: form_id.clone(),
+                submission_id: submission_id.clone(),
+                created_at: created_at.to_string(),
+                encrypted_body: webhook.include_ciphertext.then(|| encrypted_body.clone()),
+            },
+        })
+        .collect())
+}
+
+pub async fn deliver(store: &Store, delivery: &WebhookDelivery) -> anyhow::Result<()> {
+    let target = match store.get_webhook_target(&delivery.webhook_id).await? {
+        Some(target) => target,
+        None => return Ok(()),
+    };
+
+    let body = serde_json::to_string(&delivery.event)?;
+    let timestamp = unix_timestamp();
+
+    let event_id = delivery.event.id.to_string();
+    let signature = format!("sha256={}", target.secret.sign(timestamp, &body));
+
+    let status = runtime::post(
+        &target.url,
+        &[
+            ("Content-Type", "application/json"),
+            (EVENT_ID_HEADER, &event_id),
+            (TIMESTAMP_HEADER, &timestamp.to_string()),
+            (SIGNATURE_HEADER, &signature),
+        ],
+        body.into_bytes(),
+    )
+    .await?;
+
+    match status {
+        200..=299 => Ok(()),
+        status => Err(anyhow!(
+            "Webhook receiver responded with status {}.",
+            status
+        )),
+    }
+}